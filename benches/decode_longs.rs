@@ -0,0 +1,61 @@
+//! Decoding a synthetic 1M-value long column: the block-at-a-time fast
+//! path against reading one value at a time, on raw values and through
+//! a full LZ4 column.
+//!
+//! Run with `cargo bench --bench decode_longs`.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use druid_datafusion_bridge::column::compressed_longs::{
+    CompressedColumnarLongs, CompressedColumnarLongsWriter,
+};
+use druid_datafusion_bridge::column::long_encoding::{decode_longs, decode_longs_scalar};
+use druid_datafusion_bridge::segment::column_descriptor::ByteOrder;
+
+const VALUES: usize = 1_000_000;
+
+fn values() -> Vec<i64> {
+    (0..VALUES as i64)
+        .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64) >> 20)
+        .collect()
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let values = values();
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+
+    let mut group = c.benchmark_group("decode_longs");
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(VALUES);
+            decode_longs_scalar(black_box(&bytes), VALUES, ByteOrder::BigEndian, &mut out).unwrap();
+            out
+        })
+    });
+    group.bench_function("strided", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(VALUES);
+            decode_longs(black_box(&bytes), ByteOrder::BigEndian, &mut out);
+            out
+        })
+    });
+    group.finish();
+
+    let column = CompressedColumnarLongsWriter::new().write(&values).unwrap();
+    let mut group = c.benchmark_group("decompress_all");
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("lz4", |b| {
+        b.iter(|| {
+            CompressedColumnarLongs::from_bytes(black_box(&column))
+                .unwrap()
+                .decompress_all()
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
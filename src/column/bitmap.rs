@@ -1,34 +1,41 @@
+use std::ops::Range;
+
+use arrow::buffer::{BooleanBuffer, NullBuffer};
 use roaring::RoaringBitmap;
 
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::metadata::BitmapSerdeFactory;
 
 /// Bitmap type markers used by Druid.
 const BITMAP_TYPE_ROARING: u8 = 0x01;
 const BITMAP_TYPE_CONCISE: u8 = 0x00;
 
+/// Cookies at the start of a portable Roaring serialization (little-endian),
+/// without and with run containers respectively.
+const ROARING_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+const ROARING_COOKIE: u16 = 12347;
+
 /// Read a bitmap from Druid's serialized format.
 ///
 /// Druid serializes bitmaps with a type byte prefix:
 /// - 0x00 = Concise bitmap (legacy, not yet supported)
 /// - 0x01 = Roaring bitmap
 ///
-/// The Roaring bitmap format follows the standard Roaring serialization.
+/// Bitmaps written by `RoaringBitmapSerdeFactory` carry no type byte and
+/// start directly with the portable Roaring cookie; those are recognized
+/// and deserialized as-is.
 pub fn read_bitmap(data: &[u8]) -> Result<RoaringBitmap> {
     if data.is_empty() {
         return Ok(RoaringBitmap::new());
     }
 
+    if has_roaring_cookie(data) {
+        return deserialize_roaring(data);
+    }
+
     let bitmap_type = data[0];
     match bitmap_type {
-        BITMAP_TYPE_ROARING => {
-            // Standard Roaring bitmap deserialization
-            RoaringBitmap::deserialize_from(&data[1..]).map_err(|e| {
-                DruidSegmentError::InvalidData(format!(
-                    "Failed to deserialize Roaring bitmap: {}",
-                    e
-                ))
-            })
-        }
+        BITMAP_TYPE_ROARING => deserialize_roaring(&data[1..]),
         BITMAP_TYPE_CONCISE => Err(DruidSegmentError::UnsupportedColumnType(
             "Concise bitmap format not yet supported".into(),
         )),
@@ -39,6 +46,16 @@ pub fn read_bitmap(data: &[u8]) -> Result<RoaringBitmap> {
     }
 }
 
+/// Serialize a bitmap as `RoaringBitmapSerdeFactory` does: the portable
+/// Roaring format, with no type byte.
+pub fn write_bitmap(bitmap: &RoaringBitmap) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(bitmap.serialized_size());
+    bitmap
+        .serialize_into(&mut buf)
+        .expect("writing to a Vec cannot fail");
+    buf
+}
+
 /// Read a null bitmap and return the set of null row indices.
 /// If the data is empty, returns an empty bitmap (no nulls).
 pub fn read_null_bitmap(data: &[u8]) -> Result<RoaringBitmap> {
@@ -47,3 +64,66 @@ pub fn read_null_bitmap(data: &[u8]) -> Result<RoaringBitmap> {
     }
     read_bitmap(data)
 }
+
+/// Read a bitmap written by a known serde factory.
+///
+/// Unlike [`read_bitmap`], the first byte is never taken for a type marker:
+/// a `roaring` factory's bitmaps are decoded as portable Roaring as they
+/// stand. Only an [`Unknown`](BitmapSerdeFactory::Unknown) factory falls
+/// back to sniffing the format.
+pub fn read_bitmap_with_factory(data: &[u8], factory: BitmapSerdeFactory) -> Result<RoaringBitmap> {
+    if data.is_empty() {
+        return Ok(RoaringBitmap::new());
+    }
+    match factory {
+        BitmapSerdeFactory::Roaring => deserialize_roaring(data),
+        BitmapSerdeFactory::Concise => Err(DruidSegmentError::UnsupportedColumnType(
+            "Concise bitmap format not yet supported".into(),
+        )),
+        BitmapSerdeFactory::Unknown => read_bitmap(data),
+    }
+}
+
+/// Build an Arrow validity buffer for `len` rows from a bitmap of null rows.
+/// Returns `None` when no row is null, so arrays carry no null buffer at all.
+pub fn to_null_buffer(nulls: &RoaringBitmap, len: usize) -> Result<Option<NullBuffer>> {
+    if nulls.is_empty() {
+        return Ok(None);
+    }
+    if let Some(max) = nulls.max().filter(|&max| max as usize >= len) {
+        return Err(DruidSegmentError::InvalidData(format!(
+            "Null bitmap references row {} but the column has {} rows",
+            max, len
+        )));
+    }
+    let validity = BooleanBuffer::collect_bool(len, |i| !nulls.contains(i as u32));
+    Ok(Some(NullBuffer::new(validity)))
+}
+
+/// Restrict a validity buffer to the rows in `range`, dropping it if none
+/// of those rows is null.
+pub fn null_range(nulls: Option<NullBuffer>, range: Range<usize>) -> Option<NullBuffer> {
+    nulls
+        .map(|n| {
+            if range.start == 0 && n.len() == range.len() {
+                n
+            } else {
+                n.slice(range.start, range.len())
+            }
+        })
+        .filter(|n| n.null_count() > 0)
+}
+
+fn has_roaring_cookie(data: &[u8]) -> bool {
+    if data.len() < 4 {
+        return false;
+    }
+    let cookie = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    cookie == ROARING_COOKIE_NO_RUNCONTAINER || cookie as u16 == ROARING_COOKIE
+}
+
+fn deserialize_roaring(data: &[u8]) -> Result<RoaringBitmap> {
+    RoaringBitmap::deserialize_from(data).map_err(|e| {
+        DruidSegmentError::InvalidData(format!("Failed to deserialize Roaring bitmap: {}", e))
+    })
+}
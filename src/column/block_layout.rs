@@ -0,0 +1,225 @@
+use super::generic_indexed::GenericIndexedV1;
+use crate::compression::CompressionStrategy;
+use crate::error::{DruidSegmentError, Result};
+
+/// The block structure of a compressed columnar value section, read from
+/// its header and the offsets of its GenericIndexed of blocks without
+/// decompressing anything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockLayout {
+    /// Format version byte.
+    pub version: u8,
+    /// Total number of values.
+    pub total_size: usize,
+    /// Values per block; the last block may hold fewer.
+    pub size_per: usize,
+    /// Compression applied to each block.
+    pub compression: CompressionStrategy,
+    /// Compressed size in bytes of each block.
+    pub block_sizes: Vec<usize>,
+}
+
+impl BlockLayout {
+    pub(crate) fn read(
+        version: u8,
+        total_size: usize,
+        size_per: usize,
+        compression: CompressionStrategy,
+        blocks: &GenericIndexedV1<'_>,
+    ) -> Result<Self> {
+        let block_sizes = (0..blocks.len())
+            .map(|i| block_compressed_size(blocks, i))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            version,
+            total_size,
+            size_per,
+            compression,
+            block_sizes,
+        })
+    }
+
+    /// Number of compressed blocks.
+    pub fn block_count(&self) -> usize {
+        self.block_sizes.len()
+    }
+
+    /// Compressed size in bytes of block `i`.
+    pub fn block_compressed_size(&self, i: usize) -> Option<usize> {
+        self.block_sizes.get(i).copied()
+    }
+
+    /// Number of values stored in block `i`.
+    pub fn block_value_count(&self, i: usize) -> Option<usize> {
+        block_value_count(self.total_size, self.size_per, self.block_count(), i)
+    }
+
+    /// Compressed size in bytes of all blocks.
+    pub fn compressed_size(&self) -> usize {
+        self.block_sizes.iter().sum()
+    }
+}
+
+/// Where a compressed columnar section keeps its values.
+///
+/// Druid writes the `none` strategy with its "entire layout": no blocks,
+/// just the raw values straight after the header, whose `size_per` is 0.
+/// Every other strategy, `uncompressed` included, compresses blocks of
+/// `size_per` values one by one into a GenericIndexed. An entire layout is
+/// read as a single block holding every value.
+pub(crate) enum ValueBlocks<'a> {
+    Blocks(GenericIndexedV1<'a>),
+    Entire(&'a [u8]),
+}
+
+impl<'a> ValueBlocks<'a> {
+    /// Parse the values following a header. `entire_size` is the number of
+    /// bytes the values take in an entire layout; any bytes past it are
+    /// not the values'.
+    pub(crate) fn parse(
+        compression: CompressionStrategy,
+        data: &'a [u8],
+        entire_size: usize,
+    ) -> Result<Self> {
+        match compression {
+            CompressionStrategy::None => Ok(Self::Entire(&data[..entire_size.min(data.len())])),
+            _ => Ok(Self::Blocks(GenericIndexedV1::from_bytes(data)?)),
+        }
+    }
+
+    /// Number of blocks.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Blocks(blocks) => blocks.len(),
+            Self::Entire(_) => 1,
+        }
+    }
+
+    /// The bytes of block `i`, or `None` for a null block.
+    pub(crate) fn get(&self, i: usize) -> Result<Option<&'a [u8]>> {
+        match self {
+            Self::Blocks(blocks) => blocks.get(i),
+            Self::Entire(values) if i == 0 => Ok(Some(values)),
+            Self::Entire(_) => Err(DruidSegmentError::InvalidData(format!(
+                "Block index {} out of range for an entire layout",
+                i
+            ))),
+        }
+    }
+
+    /// Size in bytes of block `i`.
+    pub(crate) fn compressed_size(&self, i: usize) -> Result<usize> {
+        match self {
+            Self::Blocks(blocks) => block_compressed_size(blocks, i),
+            Self::Entire(_) => Ok(self.get(i)?.map_or(0, <[u8]>::len)),
+        }
+    }
+
+    /// The header fields and block sizes.
+    pub(crate) fn layout(
+        &self,
+        version: u8,
+        total_size: usize,
+        size_per: usize,
+        compression: CompressionStrategy,
+    ) -> Result<BlockLayout> {
+        match self {
+            Self::Blocks(blocks) => {
+                BlockLayout::read(version, total_size, size_per, compression, blocks)
+            }
+            Self::Entire(values) => Ok(BlockLayout {
+                version,
+                total_size,
+                size_per,
+                compression,
+                block_sizes: vec![values.len()],
+            }),
+        }
+    }
+
+    /// Values per block: `size_per` from the header, or every value for an
+    /// entire layout.
+    pub(crate) fn size_per(&self, size_per: usize, total_size: usize) -> usize {
+        match self {
+            Self::Blocks(_) => size_per,
+            Self::Entire(_) => total_size,
+        }
+    }
+}
+
+/// Compressed size in bytes of block `i`, from the GenericIndexed offsets.
+pub(crate) fn block_compressed_size(blocks: &GenericIndexedV1<'_>, i: usize) -> Result<usize> {
+    let block = blocks
+        .get(i)?
+        .ok_or_else(|| DruidSegmentError::InvalidData(format!("Compressed block {} is null", i)))?;
+    Ok(block.len())
+}
+
+/// Check that uncompressed block `i` of a `reader` holds exactly `expected`
+/// bytes. Compressed blocks are checked by their decompressor instead.
+pub(crate) fn check_uncompressed_block(
+    reader: &str,
+    compression: CompressionStrategy,
+    i: usize,
+    block: &[u8],
+    expected: usize,
+) -> Result<()> {
+    if compression.is_uncompressed() && block.len() != expected {
+        return Err(DruidSegmentError::InvalidData(format!(
+            "{}: uncompressed block {} holds {} bytes, expected {}",
+            reader,
+            i,
+            block.len(),
+            expected
+        )));
+    }
+    Ok(())
+}
+
+/// Number of values in block `i` of `block_count` blocks holding
+/// `total_size` values, `size_per` to a block.
+pub(crate) fn block_value_count(
+    total_size: usize,
+    size_per: usize,
+    block_count: usize,
+    i: usize,
+) -> Option<usize> {
+    (i < block_count).then(|| {
+        total_size
+            .saturating_sub(i.saturating_mul(size_per))
+            .min(size_per)
+    })
+}
+
+/// Index of the first value of block `i` and the number of values in it,
+/// as for [`block_value_count`].
+pub(crate) fn block_value_range(
+    total_size: usize,
+    size_per: usize,
+    block_count: usize,
+    i: usize,
+) -> Option<(usize, usize)> {
+    let count = block_value_count(total_size, size_per, block_count, i)?;
+    Some((i * size_per, count))
+}
+
+/// The error for asking a `reader` for block `i` of its `block_count`.
+pub(crate) fn block_out_of_range(reader: &str, i: usize, block_count: usize) -> DruidSegmentError {
+    DruidSegmentError::InvalidData(format!(
+        "{}: block {} out of range (have {} blocks)",
+        reader, i, block_count
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_value_count() {
+        assert_eq!(block_value_count(10, 4, 3, 0), Some(4));
+        assert_eq!(block_value_count(10, 4, 3, 2), Some(2));
+        assert_eq!(block_value_count(10, 4, 3, 3), None);
+        assert_eq!(block_value_count(8, 4, 2, 1), Some(4));
+    }
+}
@@ -0,0 +1,175 @@
+use std::marker::PhantomData;
+
+use super::generic_indexed::GenericIndexedWriter;
+use crate::compression::{CompressionStrategy, compress_block};
+use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+
+/// Bytes Druid compresses per block: one 64 KiB buffer of values.
+const BLOCK_BYTES: usize = 0x10000;
+
+/// A fixed-width value stored in compressed columnar blocks.
+pub trait BlockValue: Copy {
+    /// Name of the format the values are written in, for errors.
+    const FORMAT: &'static str;
+    /// Size of one value in bytes.
+    const WIDTH: usize;
+
+    /// Append the value's bytes in `byte_order` to `out`.
+    fn put(self, byte_order: ByteOrder, out: &mut Vec<u8>);
+}
+
+impl BlockValue for i64 {
+    const FORMAT: &'static str = "CompressedColumnarLongs";
+    const WIDTH: usize = 8;
+
+    fn put(self, byte_order: ByteOrder, out: &mut Vec<u8>) {
+        match byte_order {
+            ByteOrder::BigEndian => out.extend_from_slice(&self.to_be_bytes()),
+            ByteOrder::LittleEndian => out.extend_from_slice(&self.to_le_bytes()),
+        }
+    }
+}
+
+impl BlockValue for f64 {
+    const FORMAT: &'static str = "CompressedColumnarDoubles";
+    const WIDTH: usize = 8;
+
+    fn put(self, byte_order: ByteOrder, out: &mut Vec<u8>) {
+        match byte_order {
+            ByteOrder::BigEndian => out.extend_from_slice(&self.to_be_bytes()),
+            ByteOrder::LittleEndian => out.extend_from_slice(&self.to_le_bytes()),
+        }
+    }
+}
+
+impl BlockValue for f32 {
+    const FORMAT: &'static str = "CompressedColumnarFloats";
+    const WIDTH: usize = 4;
+
+    fn put(self, byte_order: ByteOrder, out: &mut Vec<u8>) {
+        match byte_order {
+            ByteOrder::BigEndian => out.extend_from_slice(&self.to_be_bytes()),
+            ByteOrder::LittleEndian => out.extend_from_slice(&self.to_le_bytes()),
+        }
+    }
+}
+
+/// Writer for version 0x02 compressed columnar values, the layout shared
+/// by CompressedColumnarLongs (with plain, unencoded longs), Doubles and
+/// Floats:
+///
+/// ```text
+/// [version: u8 = 0x02]
+/// [total_size: i32]
+/// [size_per: i32]
+/// [compression: u8]
+/// [GenericIndexed<ByteBuffer>]  -- compressed blocks
+/// ```
+///
+/// Values are split into blocks of `size_per` values, the last possibly
+/// shorter, and each block is compressed on its own. The `none` strategy
+/// writes Druid's entire layout instead: a `size_per` of 0 and the values
+/// as they are in place of the blocks. By default blocks
+/// hold 64 KiB of big-endian values and are LZ4-compressed, as in the
+/// columns Druid writes.
+#[derive(Debug, Clone)]
+pub struct CompressedBlockWriter<T> {
+    size_per: usize,
+    compression: CompressionStrategy,
+    byte_order: ByteOrder,
+    values: PhantomData<T>,
+}
+
+impl<T: BlockValue> Default for CompressedBlockWriter<T> {
+    fn default() -> Self {
+        Self {
+            size_per: BLOCK_BYTES / T::WIDTH,
+            compression: CompressionStrategy::Lz4,
+            byte_order: ByteOrder::BigEndian,
+            values: PhantomData,
+        }
+    }
+}
+
+impl<T: BlockValue> CompressedBlockWriter<T> {
+    /// A writer of LZ4 blocks of 64 KiB of big-endian values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of values per block.
+    pub fn with_size_per(mut self, size_per: usize) -> Self {
+        self.size_per = size_per;
+        self
+    }
+
+    /// Set the block compression; only LZ4, uncompressed and none are
+    /// supported.
+    pub fn with_compression(mut self, compression: CompressionStrategy) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the byte order of the values inside each block, which the
+    /// column's part serde must declare for readers.
+    pub fn with_byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Serialize `values`.
+    pub fn write(&self, values: &[T]) -> Result<Vec<u8>> {
+        let total_size = header_count::<T>(values.len(), "values")?;
+        if self.compression == CompressionStrategy::None {
+            // Druid's entire layout: no blocks and a size_per of 0
+            let mut buf = Vec::with_capacity(10 + values.len() * T::WIDTH);
+            buf.push(0x02);
+            buf.extend_from_slice(&total_size.to_be_bytes());
+            buf.extend_from_slice(&0i32.to_be_bytes());
+            buf.push(self.compression.id());
+            for &value in values {
+                value.put(self.byte_order, &mut buf);
+            }
+            return Ok(buf);
+        }
+        if self.size_per == 0 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "{}: zero values per block",
+                T::FORMAT
+            )));
+        }
+        let size_per = header_count::<T>(self.size_per, "values per block")?;
+
+        let mut blocks = GenericIndexedWriter::new(false);
+        let mut block = Vec::with_capacity(self.size_per.min(values.len()) * T::WIDTH);
+        for chunk in values.chunks(self.size_per) {
+            block.clear();
+            for &value in chunk {
+                value.put(self.byte_order, &mut block);
+            }
+            blocks.push(Some(&compress_block(self.compression, &block)?))?;
+        }
+        let blocks = blocks.finish()?;
+
+        let mut buf = Vec::with_capacity(10 + blocks.len());
+        buf.push(0x02);
+        buf.extend_from_slice(&total_size.to_be_bytes());
+        buf.extend_from_slice(&size_per.to_be_bytes());
+        buf.push(self.compression.id());
+        buf.extend_from_slice(&blocks);
+        Ok(buf)
+    }
+}
+
+/// A count for the header's i32 fields.
+fn header_count<T: BlockValue>(count: usize, what: &str) -> Result<i32> {
+    i32::try_from(count).map_err(|_| {
+        DruidSegmentError::InvalidData(format!(
+            "{}: {} {} exceed the format's limit",
+            T::FORMAT,
+            count,
+            what
+        ))
+    })
+}
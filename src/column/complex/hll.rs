@@ -0,0 +1,281 @@
+use arrow::array::{Array, BinaryArray, Float64Array};
+
+use crate::error::{DruidSegmentError, Result};
+
+/// Reader for Druid's HyperLogLogCollector, the object stored in each row
+/// of a `hyperUnique` column.
+///
+/// A collector has 2048 four-bit registers, two per payload byte (the
+/// first register in the upper nibble). Register values are stored minus
+/// `register_offset`. Layout of version 1:
+/// ```text
+/// [version: u8 = 0x01]
+/// [register_offset: u8]
+/// [num_non_zero_registers: u16]
+/// [max_overflow_value: u8]     -- a register value too large for 4 bits
+/// [max_overflow_register: u16] -- the register it belongs to
+/// [payload]
+/// ```
+/// Version 0 has only `[register_offset: u8][num_non_zero_registers: u16]`.
+/// The payload is either dense (all 1024 bytes) or sparse (3-byte entries
+/// of `[byte position: u16][byte: u8]` for the non-zero bytes only). All
+/// values are big-endian.
+#[derive(Debug, Clone, Copy)]
+pub struct HyperLogLogCollector<'a> {
+    register_offset: u8,
+    max_overflow_value: u8,
+    max_overflow_register: u16,
+    payload: &'a [u8],
+}
+
+const NUM_BUCKETS: usize = 2048;
+const NUM_BYTES_FOR_BUCKETS: usize = NUM_BUCKETS / 2;
+const HEADER_SIZE_V0: usize = 3;
+const HEADER_SIZE_V1: usize = 7;
+const VERSION_V1: u8 = 0x01;
+
+const ALPHA: f64 = 0.7213 / (1.0 + 1.079 / NUM_BUCKETS as f64);
+const CORRECTION_PARAMETER: f64 = ALPHA * (NUM_BUCKETS * NUM_BUCKETS) as f64;
+const LOW_CORRECTION_THRESHOLD: f64 = (5 * NUM_BUCKETS) as f64 / 2.0;
+const TWO_TO_THE_SIXTY_FOUR: f64 = 18446744073709551616.0;
+const HIGH_CORRECTION_THRESHOLD: f64 = TWO_TO_THE_SIXTY_FOUR / 30.0;
+
+impl<'a> HyperLogLogCollector<'a> {
+    /// Parse a serialized collector.
+    ///
+    /// As in Druid, the version is told apart by size: version 0 sizes are
+    /// a multiple of 3 (sparse) or 1027 (dense). An empty buffer is an
+    /// empty collector.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Ok(Self {
+                register_offset: 0,
+                max_overflow_value: 0,
+                max_overflow_register: 0,
+                payload: &[],
+            });
+        }
+
+        let is_v0 =
+            data.len().is_multiple_of(3) || data.len() == HEADER_SIZE_V0 + NUM_BYTES_FOR_BUCKETS;
+        let collector = if is_v0 {
+            Self {
+                register_offset: data[0],
+                max_overflow_value: 0,
+                max_overflow_register: 0,
+                payload: &data[HEADER_SIZE_V0..],
+            }
+        } else {
+            if data.len() < HEADER_SIZE_V1 || data[0] != VERSION_V1 {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "HyperLogLogCollector: unsupported layout (version {:#x}, {} bytes)",
+                    data[0],
+                    data.len()
+                )));
+            }
+            Self {
+                register_offset: data[1],
+                max_overflow_value: data[4],
+                max_overflow_register: u16::from_be_bytes([data[5], data[6]]),
+                payload: &data[HEADER_SIZE_V1..],
+            }
+        };
+
+        if !collector.is_dense() && !collector.payload.len().is_multiple_of(3) {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "HyperLogLogCollector: sparse payload of {} bytes is not a multiple of 3",
+                collector.payload.len()
+            )));
+        }
+        Ok(collector)
+    }
+
+    fn is_dense(&self) -> bool {
+        self.payload.len() == NUM_BYTES_FOR_BUCKETS
+    }
+
+    /// Estimate the number of distinct values added to the collector,
+    /// matching Druid's `HyperLogLogCollector.estimateCardinality`.
+    pub fn estimate(&self) -> Result<f64> {
+        let mut sum = 0.0;
+        let mut zero_count = 0;
+        let mut add_byte = |position: usize, byte: u8| {
+            for (register, nibble) in [(position * 2, byte >> 4), (position * 2 + 1, byte & 0x0f)] {
+                let mut value = nibble as u32 + self.register_offset as u32;
+                if self.max_overflow_value != 0 && register == self.max_overflow_register as usize {
+                    value = value.max(self.max_overflow_value as u32);
+                }
+                sum += 2f64.powi(-(value as i32));
+                if value == 0 {
+                    zero_count += 1;
+                }
+            }
+        };
+
+        if self.is_dense() {
+            for (position, &byte) in self.payload.iter().enumerate() {
+                add_byte(position, byte);
+            }
+        } else {
+            for entry in self.payload.chunks_exact(3) {
+                let position = u16::from_be_bytes([entry[0], entry[1]]) as usize;
+                if position >= NUM_BYTES_FOR_BUCKETS {
+                    return Err(DruidSegmentError::InvalidData(format!(
+                        "HyperLogLogCollector: sparse position {} out of range",
+                        position
+                    )));
+                }
+                add_byte(position, entry[2]);
+            }
+            // Bytes missing from a sparse payload hold two registers at
+            // the offset; as in Druid, the overflow only applies to listed
+            // bytes
+            let missing = 2 * (NUM_BYTES_FOR_BUCKETS - self.payload.len() / 3);
+            sum += missing as f64 * 2f64.powi(-(self.register_offset as i32));
+            if self.register_offset == 0 {
+                zero_count += missing;
+            }
+        }
+
+        Ok(apply_correction(sum, zero_count))
+    }
+}
+
+/// Turn the harmonic sum of the registers into an estimate, with the
+/// small- and large-range corrections Druid applies.
+fn apply_correction(sum: f64, zero_count: usize) -> f64 {
+    let estimate = CORRECTION_PARAMETER / sum;
+    if estimate <= LOW_CORRECTION_THRESHOLD && zero_count != 0 {
+        return NUM_BUCKETS as f64 * (NUM_BUCKETS as f64 / zero_count as f64).ln();
+    }
+    if estimate > HIGH_CORRECTION_THRESHOLD {
+        let ratio = estimate / TWO_TO_THE_SIXTY_FOUR;
+        if ratio >= 1.0 {
+            return f64::MAX;
+        }
+        return -TWO_TO_THE_SIXTY_FOUR * (1.0 - ratio).ln();
+    }
+    estimate
+}
+
+/// Estimate the cardinality of one serialized collector.
+pub fn estimate(data: &[u8]) -> Result<f64> {
+    HyperLogLogCollector::from_bytes(data)?.estimate()
+}
+
+/// Estimate the cardinality of every collector in a `hyperUnique` column
+/// read as binary. Null sketches stay null.
+pub fn estimate_array(sketches: &BinaryArray) -> Result<Float64Array> {
+    (0..sketches.len())
+        .map(|i| {
+            if sketches.is_null(i) {
+                Ok(None)
+            } else {
+                estimate(sketches.value(i)).map(Some)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A version 1 collector with the given header fields and payload.
+    fn v1(register_offset: u8, overflow: (u8, u16), payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![VERSION_V1, register_offset, 0, 0, overflow.0];
+        buf.extend_from_slice(&overflow.1.to_be_bytes());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    /// The sparse payload listing the non-zero bytes of `dense`.
+    fn sparse(dense: &[u8]) -> Vec<u8> {
+        dense
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b != 0)
+            .flat_map(|(i, &b)| {
+                let [hi, lo] = (i as u16).to_be_bytes();
+                [hi, lo, b]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_collector() {
+        assert_eq!(estimate(&[]).unwrap(), 0.0);
+        assert_eq!(estimate(&v1(0, (0, 0), &[])).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_single_register() {
+        // One register at 1 and 2047 at 0 is the small-range estimate
+        // 2048 * ln(2048 / 2047), about one distinct value.
+        let mut dense = vec![0u8; NUM_BYTES_FOR_BUCKETS];
+        dense[10] = 0x10;
+        let expected = 2048.0 * (2048.0f64 / 2047.0).ln();
+        assert!((estimate(&v1(0, (0, 0), &dense)).unwrap() - expected).abs() < 1e-9);
+        assert!((estimate(&v1(0, (0, 0), &sparse(&dense))).unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_dense_sparse_and_v0_agree() {
+        let dense: Vec<u8> = (0..NUM_BYTES_FOR_BUCKETS)
+            .map(|i| {
+                if i % 3 == 0 {
+                    ((i % 5) as u8) << 4 | (i % 7) as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let from_dense = estimate(&v1(0, (0, 0), &dense)).unwrap();
+        let from_sparse = estimate(&v1(0, (0, 0), &sparse(&dense))).unwrap();
+        assert!(from_dense > 0.0);
+        assert!((from_dense - from_sparse).abs() < 1e-9);
+
+        let mut v0 = vec![0, 0, 0];
+        v0.extend_from_slice(&dense);
+        assert!((estimate(&v0).unwrap() - from_dense).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_large_estimate_uses_registers() {
+        // Every register at offset 8 + 2: no zero registers, so no
+        // small-range correction.
+        let dense = vec![0x22u8; NUM_BYTES_FOR_BUCKETS];
+        let expected = CORRECTION_PARAMETER / (NUM_BUCKETS as f64 * 2f64.powi(-10));
+        assert!((estimate(&v1(8, (0, 0), &dense)).unwrap() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_overflow_register() {
+        let dense = vec![0x22u8; NUM_BYTES_FOR_BUCKETS];
+        let base = estimate(&v1(0, (0, 0), &dense)).unwrap();
+        // Register 5 overflowed to 20, which lowers the harmonic sum
+        let overflowed = estimate(&v1(0, (20, 5), &dense)).unwrap();
+        let sum = (NUM_BUCKETS - 1) as f64 * 0.25 + 2f64.powi(-20);
+        assert!((overflowed - CORRECTION_PARAMETER / sum).abs() < 1e-6);
+        assert!(overflowed > base);
+    }
+
+    #[test]
+    fn test_estimate_array_keeps_nulls() {
+        let mut dense = vec![0u8; NUM_BYTES_FOR_BUCKETS];
+        dense[0] = 0x11;
+        let sketch = v1(0, (0, 0), &dense);
+        let sketches = BinaryArray::from(vec![Some(sketch.as_slice()), None, Some(&[][..])]);
+        let estimates = estimate_array(&sketches).unwrap();
+        assert!(estimates.value(0) > 1.9 && estimates.value(0) < 2.1);
+        assert!(estimates.is_null(1));
+        assert_eq!(estimates.value(2), 0.0);
+    }
+
+    #[test]
+    fn test_invalid_layouts() {
+        assert!(estimate(&[0x02, 0, 0, 0, 0, 0, 0, 1]).is_err());
+        // Sparse position beyond the 1024 payload bytes
+        assert!(estimate(&v1(0, (0, 0), &[0x04, 0x00, 0x11])).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+pub mod hll;
+pub mod nested;
+pub mod quantiles;
+mod smile;
+
+use arrow::array::BinaryArray;
+
+use super::generic_indexed::GenericIndexedV1;
+use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::{ColumnDescriptor, ValueType};
+use crate::segment::read_options::ReadOptions;
+
+/// Complex type name of HyperLogLog metric columns.
+pub const HYPER_UNIQUE: &str = "hyperUnique";
+
+/// Complex type name of DataSketches quantiles metric columns.
+pub const QUANTILES_DOUBLES_SKETCH: &str = "quantilesDoublesSketch";
+
+/// Complex type name of nested columns written by Druid's `json` column
+/// type before the common nested format.
+pub const NESTED_JSON: &str = "json";
+
+/// Part serde type of nested (`auto` and `json`) columns written by Druid
+/// 26 and later, whatever their value type.
+pub const NESTED_COMMON_FORMAT: &str = "nestedCommonFormat";
+
+/// Logical type, in a `nestedCommonFormat` part serde, of nested columns
+/// holding JSON values, which [`nested::read_json_column`] reads.
+pub const NESTED_JSON_LOGICAL_TYPE: &str = "COMPLEX<json>";
+
+/// Field metadata key holding the `type` of a column's part serdes,
+/// comma-separated, e.g. `complex` or `longV2`.
+pub const SERDE_TYPE_KEY: &str = "druid.serde_type";
+
+/// Field metadata key holding a complex column's `typeName`, e.g.
+/// `thetaSketch`, which identifies how its bytes are serialized.
+pub const COMPLEX_TYPE_KEY: &str = "druid.complex_type";
+
+/// Field metadata key holding an Arrow extension type's name.
+pub const EXTENSION_NAME_KEY: &str = "ARROW:extension:name";
+
+/// Arrow extension name marking a Binary field that holds serialized
+/// HyperLogLog collectors, set under [`EXTENSION_NAME_KEY`].
+pub const HLL_EXTENSION_NAME: &str = "druid.hyperUnique";
+
+/// Arrow extension name marking a Binary field that holds serialized
+/// DataSketches `DoublesSketch`es.
+pub const QUANTILES_EXTENSION_NAME: &str = "druid.quantilesDoublesSketch";
+
+/// Arrow's canonical extension name for a Utf8 field of JSON text, marking
+/// nested `COMPLEX<json>` columns.
+pub const JSON_EXTENSION_NAME: &str = "arrow.json";
+
+/// The Arrow extension name for a supported complex type.
+pub fn extension_name(type_name: &str) -> Option<&'static str> {
+    match type_name {
+        HYPER_UNIQUE => Some(HLL_EXTENSION_NAME),
+        QUANTILES_DOUBLES_SKETCH => Some(QUANTILES_EXTENSION_NAME),
+        _ => None,
+    }
+}
+
+/// The complex type name of a column, from the `typeName` of its
+/// `complex` part serde, or from the `COMPLEX<...>` logical type of its
+/// `nestedCommonFormat` one.
+pub fn complex_type_name(descriptor: &ColumnDescriptor) -> Option<&str> {
+    descriptor
+        .parts
+        .iter()
+        .find_map(|p| match p.serde_type.as_str() {
+            "complex" => p.extra.get("typeName")?.as_str(),
+            NESTED_COMMON_FORMAT => p
+                .extra
+                .get("logicalType")?
+                .as_str()?
+                .strip_prefix("COMPLEX<")?
+                .strip_suffix('>'),
+            _ => None,
+        })
+}
+
+/// Whether a column uses one of Druid's nested data formats, which store
+/// a field dictionary, global value dictionaries and a column per field in
+/// separate logical files rather than in the column's own data.
+pub fn is_nested(descriptor: &ColumnDescriptor) -> bool {
+    descriptor
+        .parts
+        .iter()
+        .any(|p| p.serde_type == NESTED_COMMON_FORMAT)
+        || complex_type_name(descriptor) == Some(NESTED_JSON)
+}
+
+/// Whether a column is a nested `COMPLEX<json>` column in the common
+/// nested format, the kind of nested column that can be read.
+pub fn is_nested_json(descriptor: &ColumnDescriptor) -> bool {
+    descriptor.value_type == ValueType::Complex
+        && descriptor.parts.iter().any(|p| {
+            p.serde_type == NESTED_COMMON_FORMAT
+                && p.extra.get("logicalType").and_then(|t| t.as_str())
+                    == Some(NESTED_JSON_LOGICAL_TYPE)
+        })
+}
+
+/// Read a complex column as the serialized bytes of each row's object.
+///
+/// Complex columns written through Druid's generic serde store one object
+/// per row in a GenericIndexed, each serialized by the type's
+/// ObjectStrategy, so any complex type can be read as bytes. The bytes of
+/// `hyperUnique` and `quantilesDoublesSketch` columns can be passed to
+/// [`hll::estimate`] and [`quantiles::summary`] respectively.
+///
+/// Fails with [`DruidSegmentError::UnsupportedColumnType`] if the column
+/// does not use that layout.
+pub fn read_complex_column(
+    descriptor: &ColumnDescriptor,
+    data: &[u8],
+    options: &ReadOptions,
+) -> Result<BinaryArray> {
+    read_objects(data, options).map_err(|e| {
+        DruidSegmentError::UnsupportedColumnType(format!(
+            "Complex<{}> ({})",
+            complex_type_name(descriptor).unwrap_or("unknown"),
+            e
+        ))
+    })
+}
+
+/// Bytes taken by a complex column's GenericIndexed of objects.
+pub(crate) fn part_size(data: &[u8]) -> Result<usize> {
+    GenericIndexedV1::from_bytes(data)?.total_size()
+}
+
+/// Read the rows of a GenericIndexed of objects that fills `data`.
+fn read_objects(data: &[u8], options: &ReadOptions) -> Result<BinaryArray> {
+    let objects = GenericIndexedV1::from_bytes(data)?;
+    let size = objects.total_size()?;
+    if size > data.len() {
+        return Err(DruidSegmentError::InvalidData(format!(
+            "GenericIndexed of {} bytes exceeds the column's {} bytes",
+            size,
+            data.len()
+        )));
+    }
+    options
+        .row_range(objects.len())
+        .map(|i| objects.get(i))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    const HLL_DESCRIPTOR: &str = r#"{"valueType":"COMPLEX","hasMultipleValues":false,"parts":[{"type":"complex","typeName":"hyperUnique"}]}"#;
+
+    /// A GenericIndexed V1 of objects, `None` written as a null marker.
+    fn build_objects(objects: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for object in objects {
+            match object {
+                Some(bytes) => {
+                    body.write_i32::<BigEndian>(0).unwrap();
+                    body.extend_from_slice(bytes);
+                }
+                None => body.write_i32::<BigEndian>(-1).unwrap(),
+            }
+            offsets.push(body.len() as i32);
+        }
+        let mut buf = vec![0x01, 0x00];
+        buf.write_i32::<BigEndian>((offsets.len() * 4 + body.len() + 4) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(objects.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    fn build_column(descriptor: &str, binary: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_i32::<BigEndian>(descriptor.len() as i32).unwrap();
+        buf.extend_from_slice(descriptor.as_bytes());
+        buf.extend_from_slice(binary);
+        buf
+    }
+
+    #[test]
+    fn test_read_hyper_unique_column() {
+        // Version 1 sparse collector with two registers at 1
+        let sketch: &[u8] = &[0x01, 0, 0, 2, 0, 0, 0, 0x00, 0x00, 0x11];
+        let data = build_column(
+            HLL_DESCRIPTOR,
+            &build_objects(&[Some(sketch), None, Some(&[])]),
+        );
+        let (descriptor, array) = crate::column::read_column("unique_users", &data).unwrap();
+        assert_eq!(complex_type_name(&descriptor), Some(HYPER_UNIQUE));
+
+        let sketches = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+        assert_eq!(sketches.len(), 3);
+        assert_eq!(sketches.value(0), sketch);
+        assert!(sketches.is_null(1));
+
+        let estimates = hll::estimate_array(sketches).unwrap();
+        assert!((estimates.value(0) - 2.0).abs() < 0.01);
+        assert!(estimates.is_null(1));
+        assert_eq!(estimates.value(2), 0.0);
+
+        let options = ReadOptions::default().with_offset(1).with_limit(1);
+        let (_, array) =
+            crate::column::read_column_with_options("unique_users", &data, &options).unwrap();
+        assert_eq!(array.len(), 1);
+        assert!(array.is_null(0));
+    }
+
+    #[test]
+    fn test_read_quantiles_column() {
+        let mut sketch = vec![2, 3, 8, 0x18, 128, 0, 0, 0];
+        sketch.extend_from_slice(&4u64.to_le_bytes());
+        sketch.extend_from_slice(&1.0f64.to_le_bytes());
+        sketch.extend_from_slice(&9.0f64.to_le_bytes());
+        let descriptor = HLL_DESCRIPTOR.replace(HYPER_UNIQUE, QUANTILES_DOUBLES_SKETCH);
+        let data = build_column(&descriptor, &build_objects(&[Some(&sketch), None]));
+
+        let (_, array) = crate::column::read_column("latency", &data).unwrap();
+        let sketches = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+        assert_eq!(sketches.value(0), sketch.as_slice());
+        assert!(sketches.is_null(1));
+        let summary = quantiles::summarize_array(sketches).unwrap();
+        assert_eq!(
+            (summary.n, summary.min, summary.max),
+            (4, Some(1.0), Some(9.0))
+        );
+    }
+
+    #[test]
+    fn test_other_complex_types_read_as_bytes() {
+        let descriptor = HLL_DESCRIPTOR.replace("hyperUnique", "thetaSketch");
+        let data = build_column(&descriptor, &build_objects(&[Some(&[1, 2]), None]));
+        let (_, array) = crate::column::read_column("sketch", &data).unwrap();
+        let objects = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+        assert_eq!(objects.value(0), &[1, 2]);
+        assert!(objects.is_null(1));
+    }
+
+    #[test]
+    fn test_nested_columns_unsupported() {
+        for descriptor in [
+            HLL_DESCRIPTOR.replace(HYPER_UNIQUE, NESTED_JSON),
+            // Auto columns holding only longs keep the nested format
+            r#"{"valueType":"LONG","parts":[{"type":"nestedCommonFormat","logicalType":"LONG"}]}"#
+                .to_string(),
+        ] {
+            let data = build_column(&descriptor, &build_objects(&[Some(&[1, 2])]));
+            let err = crate::column::read_column("attributes", &data).unwrap_err();
+            assert!(
+                matches!(&err, DruidSegmentError::ColumnReadError { source, .. }
+                if matches!(&**source, DruidSegmentError::UnsupportedColumnType(t) if t.contains("nested"))),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_unparseable_complex_column() {
+        let descriptor = HLL_DESCRIPTOR.replace("hyperUnique", "thetaSketch");
+        // Not a GenericIndexed: unknown version byte
+        let data = build_column(&descriptor, &[0x07; 16]);
+        let err = crate::column::read_column("sketch", &data).unwrap_err();
+        assert!(
+            matches!(&err, DruidSegmentError::ColumnReadError { source, .. }
+                if matches!(&**source, DruidSegmentError::UnsupportedColumnType(t) if t.starts_with("Complex<thetaSketch>"))),
+            "{}",
+            err
+        );
+
+        // Offsets pointing past the end of the column
+        let mut objects = build_objects(&[Some(&[1, 2, 3])]);
+        objects.truncate(objects.len() - 2);
+        let data = build_column(&descriptor, &objects);
+        assert!(crate::column::read_column("sketch", &data).is_err());
+    }
+}
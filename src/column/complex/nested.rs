@@ -0,0 +1,560 @@
+//! Nested `COMPLEX<json>` columns in Druid's common nested format, as the
+//! `auto` and `json` column types write them since Druid 26.
+//!
+//! The column's own file holds only a header; everything else is in
+//! internal files named `<column>.<file>`:
+//!
+//! ```text
+//! [version: u8 = 0x00]
+//! [column name length: VByte][column name: UTF-8]
+//! [GenericIndexed<String>]   -- paths of the nested literal fields
+//! [field types: u8 * fields]
+//! ```
+//!
+//! Besides the global value dictionaries and a column per field, the
+//! internal files include `__raw`: every row's whole value serialized as
+//! Smile, in compressed blocks. Rows are read from it as JSON text.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use arrow::array::StringArray;
+use serde_json::Value;
+
+use super::smile;
+use crate::column::generic_indexed::{GenericIndexed, GenericIndexedWriter};
+use crate::compression::{CompressionStrategy, compress_block, decompress_block};
+use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::{ByteOrder, ColumnDescriptor, ColumnPartSerde, ValueType};
+use crate::segment::read_options::ReadOptions;
+use crate::segment::smoosh::SmooshReader;
+
+/// Version byte of the common nested format's column header.
+const VERSION: u8 = 0x00;
+
+/// Version byte of the compressed blob and block formats.
+const BLOCK_VERSION: u8 = 0x01;
+
+/// Internal file holding the header of the raw values.
+const RAW_FILE_NAME: &str = "__raw";
+
+/// Bytes Druid puts in each block of raw values before compressing it.
+const BLOCK_BYTES: usize = 0x10000;
+
+/// Read a nested `COMPLEX<json>` column from the start of `data`, the
+/// part's section of the column file, as the JSON text of each row. Rows
+/// without a value are null.
+///
+/// Returns the values and the number of bytes the header takes. The raw
+/// values are read from the column's internal files in `smoosh`, which is
+/// required.
+pub fn read_json_column(
+    part: &ColumnPartSerde,
+    data: &[u8],
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+) -> Result<(StringArray, usize)> {
+    let smoosh = smoosh.ok_or_else(|| {
+        DruidSegmentError::InvalidData(
+            "nested column: values are stored in other smoosh files, which are not available"
+                .into(),
+        )
+    })?;
+    let header = NestedHeader::parse(data, smoosh)?;
+    let byte_order = part.byte_order()?.unwrap_or_default();
+
+    let raw_name = internal_file_name(header.column_name, RAW_FILE_NAME);
+    let raw = smoosh.map_file(&raw_name)?;
+    let num_rows = match raw {
+        [BLOCK_VERSION, rest @ ..] if rest.len() >= 4 => {
+            crate::column::read_len(&mut &rest[..4], "nested column: row count")?
+        }
+        _ => {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "nested column: unsupported raw values header in '{}'",
+                raw_name
+            )));
+        }
+    };
+    let offsets = smoosh.map_file(&format!("{}_offsets", raw_name))?;
+    let offsets = CompressedBlocks::parse(offsets, byte_order)?;
+    let blobs = smoosh.map_file(&format!("{}_compressed", raw_name))?;
+    let blobs = CompressedBlocks::parse(blobs, byte_order)?;
+
+    let rows = options.row_range(num_rows);
+    // Offsets hold where each row's value ends, which is where the next
+    // row's starts
+    let ends = offsets.longs(rows.start.saturating_sub(1)..rows.end, byte_order)?;
+    let mut start = match rows.start {
+        0 => 0,
+        _ => ends[0],
+    };
+    let ends = &ends[ends.len() - rows.len()..];
+    let mut values = Vec::with_capacity(rows.len());
+    for (row, &end) in rows.clone().zip(ends) {
+        if row % 1024 == 0 {
+            options.check_cancelled()?;
+        }
+        let range = usize::try_from(start)
+            .ok()
+            .zip(usize::try_from(end).ok())
+            .filter(|(start, end)| start <= end)
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!(
+                    "nested column: row {} spans bytes {}..{}",
+                    row, start, end
+                ))
+            })?;
+        let bytes = blobs.bytes(range.0..range.1)?;
+        if bytes.is_empty() {
+            values.push(None);
+        } else {
+            let json = smile::to_json(&bytes).map_err(|e| {
+                DruidSegmentError::InvalidData(format!("nested column: row {}: {}", row, e))
+            })?;
+            values.push(Some(json));
+        }
+        start = end;
+    }
+    Ok((StringArray::from(values), header.size))
+}
+
+/// The logical files of a nested column, from [`write_json_column`].
+#[derive(Debug, Clone)]
+pub struct NestedColumnFiles {
+    /// The column's own file, stored under the column's name.
+    pub column: Vec<u8>,
+    /// The internal files the column's header refers to, with their names.
+    pub internal_files: Vec<(String, Vec<u8>)>,
+}
+
+/// Serialize rows of JSON values as a nested `COMPLEX<json>` column named
+/// `name`, the inverse of [`read_json_column`].
+///
+/// Only the raw values are written: the field list is empty and there are
+/// no field columns or value dictionaries, which is enough for readers of
+/// the raw values such as this crate's. Raw value blocks are compressed
+/// with `compression`, which must be LZ4 or uncompressed.
+pub fn write_json_column(
+    name: &str,
+    rows: &[Option<Value>],
+    compression: CompressionStrategy,
+    byte_order: ByteOrder,
+) -> Result<NestedColumnFiles> {
+    let mut header = vec![VERSION];
+    write_vbyte(name.len(), &mut header);
+    header.extend_from_slice(name.as_bytes());
+    header.extend(GenericIndexedWriter::write_strings([], true)?);
+
+    let mut blobs = Vec::new();
+    let mut ends = Vec::with_capacity(rows.len() * 8);
+    for row in rows {
+        if let Some(value) = row {
+            blobs.extend(smile::from_json(value));
+        }
+        let end = blobs.len() as i64;
+        match byte_order {
+            ByteOrder::BigEndian => ends.extend_from_slice(&end.to_be_bytes()),
+            ByteOrder::LittleEndian => ends.extend_from_slice(&end.to_le_bytes()),
+        }
+    }
+
+    let descriptor = ColumnDescriptor {
+        value_type: ValueType::Complex,
+        has_multiple_values: false,
+        parts: vec![ColumnPartSerde {
+            serde_type: super::NESTED_COMMON_FORMAT.into(),
+            extra: serde_json::json!({
+                "logicalType": super::NESTED_JSON_LOGICAL_TYPE,
+                "hasNulls": rows.iter().any(Option::is_none),
+                "byteOrder": byte_order,
+            }),
+        }],
+    };
+    let column = crate::column::write_column_file(&descriptor, &header)?;
+
+    let raw_name = internal_file_name(name, RAW_FILE_NAME);
+    let mut raw = vec![BLOCK_VERSION];
+    raw.extend_from_slice(&(rows.len() as i32).to_be_bytes());
+    let internal_files = vec![
+        (
+            format!("{}_offsets", raw_name),
+            write_blocks(&ends, compression, byte_order)?,
+        ),
+        (
+            format!("{}_compressed", raw_name),
+            write_blocks(&blobs, compression, byte_order)?,
+        ),
+        (raw_name, raw),
+    ];
+    Ok(NestedColumnFiles {
+        column,
+        internal_files,
+    })
+}
+
+/// The name of a nested column's internal file.
+fn internal_file_name(column: &str, file: &str) -> String {
+    format!("{}.{}", column, file)
+}
+
+/// The header in a nested column's own file.
+struct NestedHeader<'a> {
+    /// The name the internal files are named after.
+    column_name: &'a str,
+    /// Bytes the header takes.
+    size: usize,
+}
+
+impl<'a> NestedHeader<'a> {
+    fn parse(data: &'a [u8], smoosh: &'a SmooshReader) -> Result<Self> {
+        let truncated = || DruidSegmentError::InvalidData("nested column: header truncated".into());
+        match data.first() {
+            Some(&VERSION) => {}
+            Some(&other) => {
+                return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                    "nested column version {:#x}",
+                    other
+                )));
+            }
+            None => return Err(truncated()),
+        }
+        let (name_len, vbyte_len) = read_vbyte(&data[1..])?;
+        let name_start = 1 + vbyte_len;
+        let name = data
+            .get(name_start..)
+            .and_then(|rest| rest.get(..name_len))
+            .ok_or_else(truncated)?;
+        let column_name = std::str::from_utf8(name).map_err(|e| {
+            DruidSegmentError::InvalidData(format!("nested column: name is not UTF-8: {}", e))
+        })?;
+
+        let fields_start = name_start + name_len;
+        let fields = GenericIndexed::from_bytes(&data[fields_start..], Some(smoosh))?;
+        // One byte per field of the types it holds
+        let size = fields_start + fields.total_size()? + fields.len();
+        if size > data.len() {
+            return Err(truncated());
+        }
+        Ok(Self { column_name, size })
+    }
+}
+
+/// Read a Druid VByte integer: 7 bits per byte, least significant first,
+/// with the high bit set on the last byte. Returns it and its length.
+fn read_vbyte(data: &[u8]) -> Result<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, &b) in data.iter().take(5).enumerate() {
+        value |= ((b & 0x7F) as usize) << (7 * i);
+        if b & 0x80 != 0 {
+            return Ok((value, i + 1));
+        }
+    }
+    Err(DruidSegmentError::InvalidData(
+        "nested column: invalid VByte integer".into(),
+    ))
+}
+
+fn write_vbyte(mut value: usize, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value & 0x7F) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8 | 0x80);
+}
+
+/// Reader for Druid's compressed block format, which holds a run of bytes
+/// in blocks compressed on their own:
+///
+/// ```text
+/// [version: u8 = 0x01]
+/// [compression: u8]
+/// [block size: i32]            -- bytes in every block but the last
+/// [block count: i32]
+/// [end offsets: i32 * count]   -- in the column's byte order
+/// [compressed blocks]
+/// ```
+///
+/// The raw values of a nested column are stored this way, and so are the
+/// offsets at which each row's value ends, as longs.
+struct CompressedBlocks<'a> {
+    compression: CompressionStrategy,
+    block_size: usize,
+    /// Where each compressed block ends in `blocks`.
+    ends: Vec<usize>,
+    blocks: &'a [u8],
+}
+
+impl<'a> CompressedBlocks<'a> {
+    fn parse(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
+        let invalid = |message: &str| {
+            DruidSegmentError::InvalidData(format!("compressed blocks: {}", message))
+        };
+        let [version, compression, rest @ ..] = data else {
+            return Err(invalid("data too short"));
+        };
+        if *version != BLOCK_VERSION {
+            return Err(invalid(&format!("unsupported version {:#x}", version)));
+        }
+        let compression = CompressionStrategy::from_id(*compression)?;
+        let mut cursor = rest;
+        let block_size = crate::column::read_len(&mut cursor, "compressed blocks: block size")?;
+        let count = crate::column::read_len(&mut cursor, "compressed blocks: block count")?;
+        if block_size == 0 && count > 0 {
+            return Err(invalid("block size is 0"));
+        }
+        let offsets = cursor
+            .get(..count.saturating_mul(4))
+            .ok_or_else(|| invalid("offsets truncated"))?;
+        let blocks = &cursor[offsets.len()..];
+        let ends = offsets
+            .chunks_exact(4)
+            .map(|end| {
+                let end = end.try_into().expect("4-byte chunk");
+                match byte_order {
+                    ByteOrder::BigEndian => i32::from_be_bytes(end),
+                    ByteOrder::LittleEndian => i32::from_le_bytes(end),
+                }
+            })
+            .map(|end| usize::try_from(end).ok().filter(|&end| end <= blocks.len()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("block offsets out of range"))?;
+        if ends.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid("block offsets out of order"));
+        }
+        Ok(Self {
+            compression,
+            block_size,
+            ends,
+            blocks,
+        })
+    }
+
+    fn block(&self, i: usize) -> Result<Cow<'a, [u8]>> {
+        let end = *self.ends.get(i).ok_or_else(|| {
+            DruidSegmentError::InvalidData(format!(
+                "compressed blocks: block {} of {} out of range",
+                i,
+                self.ends.len()
+            ))
+        })?;
+        let start = i.checked_sub(1).map_or(0, |prev| self.ends[prev]);
+        decompress_block(self.compression, &self.blocks[start..end], self.block_size)
+    }
+
+    /// The bytes at `range` of the decompressed run, decompressing only the
+    /// blocks it overlaps.
+    fn bytes(&self, range: Range<usize>) -> Result<Cow<'a, [u8]>> {
+        if range.is_empty() {
+            return Ok(Cow::Borrowed(&[]));
+        }
+        let first = range.start / self.block_size;
+        let last = (range.end - 1) / self.block_size;
+        let mut out = Vec::with_capacity(range.len());
+        for i in first..=last {
+            let block = self.block(i)?;
+            let block_start = i * self.block_size;
+            let from = range.start.max(block_start) - block_start;
+            let to = range.end.min(block_start + self.block_size) - block_start;
+            out.extend_from_slice(block.get(from..to).ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!(
+                    "compressed blocks: block {} holds {} bytes, need {}",
+                    i,
+                    block.len(),
+                    to
+                ))
+            })?);
+        }
+        Ok(Cow::Owned(out))
+    }
+
+    /// The longs at `rows` of the decompressed run, in `byte_order`.
+    fn longs(&self, rows: Range<usize>, byte_order: ByteOrder) -> Result<Vec<i64>> {
+        let bytes = self.bytes(rows.start * 8..rows.end * 8)?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|long| {
+                let long = long.try_into().expect("8-byte chunk");
+                match byte_order {
+                    ByteOrder::BigEndian => i64::from_be_bytes(long),
+                    ByteOrder::LittleEndian => i64::from_le_bytes(long),
+                }
+            })
+            .collect())
+    }
+}
+
+/// Write `bytes` in the compressed block format read by
+/// [`CompressedBlocks`].
+fn write_blocks(
+    bytes: &[u8],
+    compression: CompressionStrategy,
+    byte_order: ByteOrder,
+) -> Result<Vec<u8>> {
+    let mut ends = Vec::new();
+    let mut blocks = Vec::new();
+    for block in bytes.chunks(BLOCK_BYTES) {
+        blocks.extend_from_slice(&compress_block(compression, block)?);
+        ends.push(blocks.len() as i32);
+    }
+    let mut out = vec![BLOCK_VERSION, compression.id()];
+    out.extend_from_slice(&(BLOCK_BYTES as i32).to_be_bytes());
+    out.extend_from_slice(&(ends.len() as i32).to_be_bytes());
+    for end in ends {
+        match byte_order {
+            ByteOrder::BigEndian => out.extend_from_slice(&end.to_be_bytes()),
+            ByteOrder::LittleEndian => out.extend_from_slice(&end.to_le_bytes()),
+        }
+    }
+    out.extend(blocks);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::DruidSegment;
+    use crate::testing::SegmentFixtureBuilder;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::DataType;
+    use serde_json::json;
+
+    fn rows() -> Vec<Option<Value>> {
+        vec![
+            Some(json!({"user": {"name": "ada", "langs": ["en", "fr"]}, "n": 1})),
+            None,
+            Some(json!({"user": {"name": "bob"}, "n": 2.5})),
+            Some(json!([1, "two", null])),
+            Some(json!("scalar")),
+        ]
+    }
+
+    fn read(segment: &DruidSegment, options: &ReadOptions) -> Vec<Option<Value>> {
+        let batch = segment
+            .read_columns_with_options(&["attrs"], options)
+            .unwrap();
+        let json = batch.column(0).as_string::<i32>();
+        json.iter()
+            .map(|row| row.map(|row| serde_json::from_str(row).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_read_json_column() {
+        let segment = SegmentFixtureBuilder::new()
+            .with_json_column("attrs", rows())
+            .build()
+            .unwrap();
+        let descriptor = segment.column_descriptor("attrs").unwrap();
+        assert!(super::super::is_nested_json(&descriptor));
+        assert_eq!(read(&segment, &ReadOptions::default()), rows());
+
+        let options = ReadOptions::default().with_offset(1).with_limit(3);
+        assert_eq!(read(&segment, &options), rows()[1..4]);
+        let options = ReadOptions::default().with_offset(5);
+        assert!(read(&segment, &options).is_empty());
+
+        // Strings read as dictionaries include nested JSON
+        let options = ReadOptions::default().with_strings_as_dictionary(true);
+        let batch = segment
+            .read_columns_with_options(&["attrs"], &options)
+            .unwrap();
+        assert!(matches!(
+            batch.column(0).data_type(),
+            DataType::Dictionary(_, _)
+        ));
+        assert_eq!(batch.column(0).null_count(), 1);
+    }
+
+    #[test]
+    fn test_values_across_blocks() {
+        // About 200 KiB of raw values, in four blocks, with values split
+        // across block boundaries
+        let rows: Vec<Option<Value>> = (0..3000)
+            .map(|i| Some(json!({"i": i, "pad": "x".repeat(60)})))
+            .collect();
+        for byte_order in [ByteOrder::BigEndian, ByteOrder::LittleEndian] {
+            let files =
+                write_json_column("attrs", &rows, CompressionStrategy::Lz4, byte_order).unwrap();
+            let blobs = CompressedBlocks::parse(&files.internal_files[1].1, byte_order).unwrap();
+            assert_eq!(blobs.ends.len(), 4);
+        }
+
+        let segment = SegmentFixtureBuilder::new()
+            .with_json_column("attrs", rows.clone())
+            .build()
+            .unwrap();
+        assert_eq!(read(&segment, &ReadOptions::default()), rows);
+        let options = ReadOptions::default().with_offset(1000).with_limit(1000);
+        assert_eq!(read(&segment, &options), rows[1000..2000]);
+    }
+
+    #[test]
+    fn test_invalid_nested_column() {
+        let NestedColumnFiles {
+            column,
+            internal_files: files,
+        } = write_json_column(
+            "attrs",
+            &rows(),
+            CompressionStrategy::Uncompressed,
+            ByteOrder::LittleEndian,
+        )
+        .unwrap();
+        let (descriptor, data) = crate::column::parse_column_header(&column).unwrap();
+        let part = &descriptor.parts[0];
+        let options = ReadOptions::default();
+
+        // The raw values are in other files
+        let err = read_json_column(part, data, None, &options).unwrap_err();
+        assert!(err.to_string().contains("not available"), "{}", err);
+
+        let segment = |files: &[(String, Vec<u8>)]| {
+            let mut writer = crate::segment::writer::SegmentWriter::new()
+                .with_column_file("attrs", column.clone());
+            for (name, file) in files {
+                writer = writer.with_internal_file(name.clone(), file.clone());
+            }
+            SegmentFixtureBuilder::new()
+                .with_writer(writer)
+                .build()
+                .unwrap()
+        };
+        assert!(segment(&files[..2]).read_columns(&["attrs"]).is_err());
+
+        // Offsets pointing past the raw values
+        let mut corrupt = files.clone();
+        let offsets = &mut corrupt[0].1;
+        let last = offsets.len() - 8;
+        offsets[last..].copy_from_slice(&1_000_000i64.to_le_bytes());
+        assert!(segment(&corrupt).read_columns(&["attrs"]).is_err());
+
+        // Raw values that are not Smile: a reserved token where the last
+        // row's string starts
+        let mut corrupt = files.clone();
+        let blobs = &mut corrupt[1].1;
+        let last = blobs.len() - 1 - "scalar".len();
+        blobs[last] = 0x27;
+        let err = segment(&corrupt).read_columns(&["attrs"]).unwrap_err();
+        assert!(err.to_string().contains("Smile"), "{}", err);
+
+        let segment = segment(&files);
+        assert!(NestedHeader::parse(&data[..3], segment.smoosh()).is_err());
+        let mut future = data.to_vec();
+        future[0] = 0x01;
+        assert!(matches!(
+            read_json_column(part, &future, Some(segment.smoosh()), &options),
+            Err(DruidSegmentError::UnsupportedColumnType(_))
+        ));
+    }
+
+    #[test]
+    fn test_vbyte() {
+        for value in [0, 1, 127, 128, 300, 1 << 21, i32::MAX as usize] {
+            let mut out = Vec::new();
+            write_vbyte(value, &mut out);
+            assert_eq!(read_vbyte(&out).unwrap(), (value, out.len()));
+        }
+        assert!(read_vbyte(&[0x01, 0x02]).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use arrow::array::{Array, BinaryArray};
+
+use crate::error::{DruidSegmentError, Result};
+
+/// The plain fields of a DataSketches `DoublesSketch` preamble, the
+/// object stored in each row of a `quantilesDoublesSketch` column.
+///
+/// Only the preamble is read; quantiles themselves are not computed.
+/// Layout (little-endian):
+/// ```text
+/// [pre_longs: u8]      -- 1 when empty, 2 otherwise
+/// [ser_ver: u8]        -- 1, 2 or 3
+/// [family: u8 = 8]     -- quantiles
+/// [flags: u8]          -- bit 2 set when empty
+/// [k: u16][unused: u16]
+/// [n: u64]             -- values fed to the sketch; absent when empty
+/// [min: f64][max: f64] -- absent when empty
+/// [combined buffer...]
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DoublesSketchSummary {
+    /// Number of values fed to the sketch.
+    pub n: u64,
+    /// Smallest value seen, `None` for an empty sketch.
+    pub min: Option<f64>,
+    /// Largest value seen, `None` for an empty sketch.
+    pub max: Option<f64>,
+}
+
+const FAMILY_QUANTILES: u8 = 8;
+const FLAG_BIG_ENDIAN: u8 = 0x01;
+const FLAG_EMPTY: u8 = 0x04;
+const PREAMBLE_SIZE: usize = 32;
+
+impl DoublesSketchSummary {
+    /// Read the summary from a serialized sketch. An empty buffer is an
+    /// empty sketch.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let empty = Self {
+            n: 0,
+            min: None,
+            max: None,
+        };
+        if data.is_empty() {
+            return Ok(empty);
+        }
+        if data.len() < 8 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "DoublesSketch: {} bytes is too short for a preamble",
+                data.len()
+            )));
+        }
+
+        let (ser_ver, family, flags) = (data[1], data[2], data[3]);
+        if family != FAMILY_QUANTILES {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "DoublesSketch: family {} is not quantiles ({})",
+                family, FAMILY_QUANTILES
+            )));
+        }
+        if !(1..=3).contains(&ser_ver) {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "DoublesSketch: unsupported serialization version {}",
+                ser_ver
+            )));
+        }
+        if flags & FLAG_BIG_ENDIAN != 0 {
+            return Err(DruidSegmentError::InvalidData(
+                "DoublesSketch: big-endian sketches are not supported".into(),
+            ));
+        }
+        if flags & FLAG_EMPTY != 0 {
+            return Ok(empty);
+        }
+
+        if data.len() < PREAMBLE_SIZE {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "DoublesSketch: {} bytes is too short for a non-empty preamble",
+                data.len()
+            )));
+        }
+        let f64_at = |offset: usize| {
+            f64::from_le_bytes(data[offset..offset + 8].try_into().expect("8-byte slice"))
+        };
+        Ok(Self {
+            n: u64::from_le_bytes(data[8..16].try_into().expect("8-byte slice")),
+            min: Some(f64_at(16)),
+            max: Some(f64_at(24)),
+        })
+    }
+
+    /// The summary of a sketch that merged both inputs.
+    pub fn merge(&self, other: &Self) -> Self {
+        let pick = |a: Option<f64>, b: Option<f64>, f: fn(f64, f64) -> f64| match (a, b) {
+            (Some(a), Some(b)) => Some(f(a, b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            n: self.n + other.n,
+            min: pick(self.min, other.min, f64::min),
+            max: pick(self.max, other.max, f64::max),
+        }
+    }
+}
+
+/// Read the summary of one serialized sketch.
+pub fn summary(data: &[u8]) -> Result<DoublesSketchSummary> {
+    DoublesSketchSummary::from_bytes(data)
+}
+
+/// Merge the summaries of every non-null sketch in a
+/// `quantilesDoublesSketch` column read as binary.
+pub fn summarize_array(sketches: &BinaryArray) -> Result<DoublesSketchSummary> {
+    let mut total = DoublesSketchSummary::from_bytes(&[])?;
+    for i in 0..sketches.len() {
+        if sketches.is_valid(i) {
+            total = total.merge(&summary(sketches.value(i))?);
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A serialization version 3 compact sketch with `k = 128`, the given
+    /// preamble fields, and `items` as its combined buffer.
+    fn sketch(n: u64, min: f64, max: f64, items: &[f64]) -> Vec<u8> {
+        let mut buf = vec![2, 3, FAMILY_QUANTILES, 0x08 | 0x10];
+        buf.extend_from_slice(&128u16.to_le_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&n.to_le_bytes());
+        buf.extend_from_slice(&min.to_le_bytes());
+        buf.extend_from_slice(&max.to_le_bytes());
+        for item in items {
+            buf.extend_from_slice(&item.to_le_bytes());
+        }
+        buf
+    }
+
+    fn empty_sketch() -> Vec<u8> {
+        let mut buf = vec![1, 3, FAMILY_QUANTILES, FLAG_EMPTY | 0x08];
+        buf.extend_from_slice(&128u16.to_le_bytes());
+        buf.extend_from_slice(&[0, 0]);
+        buf
+    }
+
+    #[test]
+    fn test_summary() {
+        let data = sketch(3, -1.5, 8.25, &[-1.5, 2.0, 8.25]);
+        let summary = summary(&data).unwrap();
+        assert_eq!(summary.n, 3);
+        assert_eq!(summary.min, Some(-1.5));
+        assert_eq!(summary.max, Some(8.25));
+    }
+
+    #[test]
+    fn test_empty_sketches() {
+        for data in [empty_sketch(), Vec::new()] {
+            let summary = summary(&data).unwrap();
+            assert_eq!(summary.n, 0);
+            assert_eq!(summary.min, None);
+            assert_eq!(summary.max, None);
+        }
+    }
+
+    #[test]
+    fn test_summarize_array() {
+        let a = sketch(3, -1.5, 8.25, &[-1.5, 2.0, 8.25]);
+        let b = sketch(2, 0.5, 10.0, &[0.5, 10.0]);
+        let empty = empty_sketch();
+        let sketches = BinaryArray::from(vec![
+            Some(a.as_slice()),
+            None,
+            Some(empty.as_slice()),
+            Some(b.as_slice()),
+        ]);
+        let total = summarize_array(&sketches).unwrap();
+        assert_eq!(total.n, 5);
+        assert_eq!(total.min, Some(-1.5));
+        assert_eq!(total.max, Some(10.0));
+    }
+
+    #[test]
+    fn test_invalid_preambles() {
+        let mut data = sketch(1, 1.0, 1.0, &[1.0]);
+        data[2] = 3; // theta family
+        assert!(summary(&data).is_err());
+        assert!(summary(&sketch(1, 1.0, 1.0, &[])[..20]).is_err());
+        assert!(summary(&[2, 3, FAMILY_QUANTILES]).is_err());
+    }
+}
@@ -0,0 +1,470 @@
+//! The subset of the Smile binary JSON format Druid writes the raw values
+//! of nested columns in: Jackson's `SmileGenerator` with shared property
+//! names, one document per row.
+
+use serde_json::Value;
+
+use crate::error::{DruidSegmentError, Result};
+
+/// Bytes every document starts with, before the flags byte.
+const HEADER: [u8; 3] = [b':', b')', b'\n'];
+
+/// Header flag: property names may refer back to earlier ones.
+const FLAG_SHARED_NAMES: u8 = 0x01;
+
+/// Header flag: short string values may refer back to earlier ones.
+const FLAG_SHARED_VALUES: u8 = 0x02;
+
+/// Size at which the tables of shared names and values start over.
+const MAX_SHARED: usize = 1024;
+
+/// Longest string, in bytes, that goes in a table of shared strings.
+const MAX_SHARED_BYTES: usize = 64;
+
+/// Deepest nesting of arrays and objects decoded, so corrupt data cannot
+/// overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Decode a Smile document into JSON text, keeping the order of object
+/// properties.
+///
+/// Big integers, big decimals and binary values cannot be decoded and
+/// fail with [`DruidSegmentError::InvalidData`], as does malformed data.
+pub(crate) fn to_json(data: &[u8]) -> Result<String> {
+    let mut decoder = Decoder::new(data)?;
+    let mut out = String::new();
+    let token = decoder.byte()?;
+    decoder.value(token, &mut out, 0)?;
+    match decoder.data.get(decoder.pos..) {
+        Some([] | [0xFF]) => Ok(out),
+        _ => Err(smile_error("trailing bytes after the value")),
+    }
+}
+
+/// Encode `value` as a Smile document with a header and shared property
+/// names, as Jackson writes it by default.
+pub(crate) fn from_json(value: &Value) -> Vec<u8> {
+    let mut out = HEADER.to_vec();
+    out.push(FLAG_SHARED_NAMES);
+    let mut names = Vec::new();
+    encode(value, &mut out, &mut names);
+    out
+}
+
+fn smile_error(message: impl std::fmt::Display) -> DruidSegmentError {
+    DruidSegmentError::InvalidData(format!("Smile: {}", message))
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    shared_names: Option<Vec<String>>,
+    shared_values: Option<Vec<String>>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        // Without a header Jackson assumes shared names only
+        let (flags, pos) = match data {
+            [a, b, c, flags, ..] if [*a, *b, *c] == HEADER => {
+                if flags >> 4 != 0 {
+                    return Err(smile_error(format!("unsupported version {}", flags >> 4)));
+                }
+                (*flags, 4)
+            }
+            _ => (FLAG_SHARED_NAMES, 0),
+        };
+        Ok(Self {
+            data,
+            pos,
+            shared_names: (flags & FLAG_SHARED_NAMES != 0).then(Vec::new),
+            shared_values: (flags & FLAG_SHARED_VALUES != 0).then(Vec::new),
+        })
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| smile_error("unexpected end of data"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| smile_error("unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'a str> {
+        std::str::from_utf8(self.bytes(len)?).map_err(smile_error)
+    }
+
+    /// A string running up to the 0xFC end-of-string marker.
+    fn terminated_str(&mut self) -> Result<&'a str> {
+        let rest = self.data.get(self.pos..).unwrap_or_default();
+        let len = rest
+            .iter()
+            .position(|&b| b == 0xFC)
+            .ok_or_else(|| smile_error("unterminated long string"))?;
+        let s = self.str(len)?;
+        self.pos += 1;
+        Ok(s)
+    }
+
+    /// An unsigned variable-length integer: 7 bits per byte, most
+    /// significant first, and 6 in the last byte, which has its high bit
+    /// set.
+    fn vint(&mut self) -> Result<u64> {
+        let mut value: u64 = 0;
+        for _ in 0..10 {
+            let b = self.byte()?;
+            if b & 0x80 != 0 {
+                return Ok((value << 6) | (b & 0x3F) as u64);
+            }
+            value = (value << 7) | b as u64;
+        }
+        Err(smile_error("variable-length integer too long"))
+    }
+
+    /// `n` bytes of 7 bits each, most significant first.
+    fn bits7(&mut self, n: usize) -> Result<u64> {
+        self.bytes(n)?.iter().try_fold(0u64, |value, &b| match b {
+            0..=0x7F => Ok((value << 7) | b as u64),
+            _ => Err(smile_error("invalid 7-bit encoded number")),
+        })
+    }
+
+    fn value(&mut self, token: u8, out: &mut String, depth: usize) -> Result<()> {
+        match token {
+            0x01..=0x1F => {
+                let s = self.shared_value((token - 1) as usize)?;
+                push_json(out, &s);
+            }
+            0x20 => out.push_str("\"\""),
+            0x21 => out.push_str("null"),
+            0x22 => out.push_str("false"),
+            0x23 => out.push_str("true"),
+            0x24 | 0x25 => {
+                let n = self.vint()?;
+                out.push_str(&zigzag(n).to_string());
+            }
+            0x28 => {
+                let bits = self.bits7(5)? as u32;
+                push_json(out, &f32::from_bits(bits));
+            }
+            0x29 => {
+                let bits = self.bits7(10)?;
+                push_json(out, &f64::from_bits(bits));
+            }
+            0x40..=0x7F => {
+                let len = (token & 0x1F) as usize + if token < 0x60 { 1 } else { 33 };
+                self.short_value(len, out)?;
+            }
+            0x80..=0xBF => {
+                let len = (token & 0x1F) as usize + if token < 0xA0 { 2 } else { 34 };
+                self.short_value(len, out)?;
+            }
+            0xC0..=0xDF => out.push_str(&zigzag((token & 0x1F) as u64).to_string()),
+            0xE0 | 0xE4 => {
+                let s = self.terminated_str()?;
+                push_json(out, s);
+            }
+            0xEC..=0xEF => {
+                let index = ((token as usize & 0x03) << 8) | self.byte()? as usize;
+                let s = self.shared_value(index)?;
+                push_json(out, &s);
+            }
+            0xF8 => {
+                check_depth(depth)?;
+                out.push('[');
+                let mut first = true;
+                loop {
+                    let token = self.byte()?;
+                    if token == 0xF9 {
+                        break;
+                    }
+                    if !first {
+                        out.push(',');
+                    }
+                    first = false;
+                    self.value(token, out, depth + 1)?;
+                }
+                out.push(']');
+            }
+            0xFA => {
+                check_depth(depth)?;
+                out.push('{');
+                let mut first = true;
+                loop {
+                    let token = self.byte()?;
+                    if token == 0xFB {
+                        break;
+                    }
+                    if !first {
+                        out.push(',');
+                    }
+                    first = false;
+                    let key = self.key(token)?;
+                    push_json(out, &key);
+                    out.push(':');
+                    let token = self.byte()?;
+                    self.value(token, out, depth + 1)?;
+                }
+                out.push('}');
+            }
+            0x26 => return Err(smile_error("big integers are not supported")),
+            0x2A => return Err(smile_error("big decimals are not supported")),
+            0xE8 | 0xFD => return Err(smile_error("binary values are not supported")),
+            other => {
+                return Err(smile_error(format!(
+                    "unexpected value token {:#04x}",
+                    other
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// A tiny or short string value of `len` bytes, which is shared if the
+    /// document shares values.
+    fn short_value(&mut self, len: usize, out: &mut String) -> Result<()> {
+        let s = self.str(len)?;
+        push_json(out, s);
+        if let Some(values) = &mut self.shared_values {
+            share(values, s);
+        }
+        Ok(())
+    }
+
+    fn shared_value(&self, index: usize) -> Result<String> {
+        self.shared_values
+            .as_ref()
+            .and_then(|values| values.get(index))
+            .cloned()
+            .ok_or_else(|| smile_error(format!("unknown shared value {}", index)))
+    }
+
+    fn key(&mut self, token: u8) -> Result<String> {
+        let len = match token {
+            0x20 => return Ok(String::new()),
+            0x30..=0x33 => {
+                let index = ((token as usize & 0x03) << 8) | self.byte()? as usize;
+                return self.shared_name(index);
+            }
+            0x34 => return Ok(self.terminated_str()?.to_string()),
+            0x40..=0x7F => return self.shared_name((token & 0x3F) as usize),
+            0x80..=0xBF => (token & 0x3F) as usize + 1,
+            0xC0..=0xF7 => (token & 0x3F) as usize + 2,
+            other => return Err(smile_error(format!("unexpected key token {:#04x}", other))),
+        };
+        let key = self.str(len)?;
+        if let Some(names) = &mut self.shared_names {
+            share(names, key);
+        }
+        Ok(key.to_string())
+    }
+
+    fn shared_name(&self, index: usize) -> Result<String> {
+        self.shared_names
+            .as_ref()
+            .and_then(|names| names.get(index))
+            .cloned()
+            .ok_or_else(|| smile_error(format!("unknown shared name {}", index)))
+    }
+}
+
+/// Add `s` to a table of shared strings, which starts over once full.
+fn share(table: &mut Vec<String>, s: &str) {
+    if s.len() > MAX_SHARED_BYTES {
+        return;
+    }
+    if table.len() == MAX_SHARED {
+        table.clear();
+    }
+    table.push(s.to_string());
+}
+
+fn check_depth(depth: usize) -> Result<()> {
+    if depth >= MAX_DEPTH {
+        return Err(smile_error(format!("nested deeper than {}", MAX_DEPTH)));
+    }
+    Ok(())
+}
+
+fn zigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// Append `value` as JSON; non-finite floats become `null`.
+fn push_json<T: serde::Serialize + ?Sized>(out: &mut String, value: &T) {
+    out.push_str(&serde_json::to_string(value).expect("strings and floats serialize"));
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>, names: &mut Vec<String>) {
+    match value {
+        Value::Null => out.push(0x21),
+        Value::Bool(false) => out.push(0x22),
+        Value::Bool(true) => out.push(0x23),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => encode_int(i, out),
+            None => {
+                let bits = n.as_f64().unwrap_or(f64::NAN).to_bits();
+                out.push(0x29);
+                out.extend((0..10).rev().map(|i| ((bits >> (7 * i)) & 0x7F) as u8));
+            }
+        },
+        Value::String(s) => encode_str(s, out),
+        Value::Array(values) => {
+            out.push(0xF8);
+            for value in values {
+                encode(value, out, names);
+            }
+            out.push(0xF9);
+        }
+        Value::Object(map) => {
+            out.push(0xFA);
+            for (key, value) in map {
+                encode_key(key, out, names);
+                encode(value, out, names);
+            }
+            out.push(0xFB);
+        }
+    }
+}
+
+fn encode_int(i: i64, out: &mut Vec<u8>) {
+    let zigzag = ((i << 1) ^ (i >> 63)) as u64;
+    if (-16..16).contains(&i) {
+        out.push(0xC0 | zigzag as u8);
+        return;
+    }
+    out.push(if i32::try_from(i).is_ok() { 0x24 } else { 0x25 });
+    let groups = (64 - zigzag.leading_zeros() as usize)
+        .saturating_sub(6)
+        .div_ceil(7);
+    for g in (1..=groups).rev() {
+        out.push(((zigzag >> (6 + 7 * (g - 1))) & 0x7F) as u8);
+    }
+    out.push(0x80 | (zigzag & 0x3F) as u8);
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    let len = s.len();
+    match (s.is_ascii(), len) {
+        (_, 0) => return out.push(0x20),
+        (true, 1..=32) => out.push(0x40 | (len - 1) as u8),
+        (true, 33..=64) => out.push(0x60 | (len - 33) as u8),
+        (false, 2..=33) => out.push(0x80 | (len - 2) as u8),
+        (false, 34..=64) => out.push(0xA0 | (len - 34) as u8),
+        (ascii, _) => {
+            out.push(if ascii { 0xE0 } else { 0xE4 });
+            out.extend_from_slice(s.as_bytes());
+            return out.push(0xFC);
+        }
+    }
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn encode_key(key: &str, out: &mut Vec<u8>, names: &mut Vec<String>) {
+    if let Some(index) = names.iter().position(|name| name == key) {
+        if index < 64 {
+            out.push(0x40 | index as u8);
+        } else {
+            out.push(0x30 | (index >> 8) as u8);
+            out.push(index as u8);
+        }
+        return;
+    }
+    let len = key.len();
+    match (key.is_ascii(), len) {
+        (_, 0) => return out.push(0x20),
+        (true, 1..=64) => out.push(0x80 | (len - 1) as u8),
+        (false, 2..=57) => out.push(0xC0 | (len - 2) as u8),
+        _ => {
+            out.push(0x34);
+            out.extend_from_slice(key.as_bytes());
+            return out.push(0xFC);
+        }
+    }
+    out.extend_from_slice(key.as_bytes());
+    share(names, key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let long_key = "k".repeat(70);
+        let value = json!({
+            "a": 1,
+            "b": [-16, 15, -17, 1000, i64::MAX, i64::MIN, 1.5, -0.25],
+            "c": {"a": null, "d": true, "e": false},
+            "s": ["", "x", "é", "y".repeat(40), "ü".repeat(20), "z".repeat(100)],
+            long_key.clone(): {long_key: "repeated long key"},
+            "": [{"a": 2}, {"a": 3}],
+        });
+        let smile = from_json(&value);
+        assert_eq!(&smile[..4], b":)\n\x01");
+        let json = to_json(&smile).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_shared_names() {
+        // {"ab":{"ab":1}} with the inner key referring back to the outer
+        let smile = [
+            b':', b')', b'\n', 0x01, 0xFA, 0x81, b'a', b'b', 0xFA, 0x40, 0xC2, 0xFB, 0xFB,
+        ];
+        assert_eq!(to_json(&smile).unwrap(), r#"{"ab":{"ab":1}}"#);
+        assert_eq!(from_json(&json!({"ab": {"ab": 1}})), smile);
+    }
+
+    #[test]
+    fn test_shared_values_and_property_order() {
+        // {"z":"hi","a":"hi"} with the second value shared, and no header
+        // flag for shared names
+        let smile = [
+            b':', b')', b'\n', 0x02, 0xFA, 0x80, b'z', 0x41, b'h', b'i', 0x80, b'a', 0x01, 0xFB,
+        ];
+        assert_eq!(to_json(&smile).unwrap(), r#"{"z":"hi","a":"hi"}"#);
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(to_json(&from_json(&json!("a\"b"))).unwrap(), r#""a\"b""#);
+        assert_eq!(to_json(&[0x21]).unwrap(), "null");
+        // Float 0.5 as five 7-bit groups, with an end marker
+        let bits = 0.5f32.to_bits();
+        let mut smile = vec![0x28];
+        smile.extend((0..5).rev().map(|i| ((bits >> (7 * i)) & 0x7F) as u8));
+        smile.push(0xFF);
+        assert_eq!(to_json(&smile).unwrap(), "0.5");
+    }
+
+    #[test]
+    fn test_malformed() {
+        for smile in [
+            &[][..],
+            &[0xFA, 0x80, b'a'],
+            &[0xFA, 0x45, 0x21, 0xFB],
+            &[0x01],
+            &[0xF8, 0x21],
+            &[0x24, 0x00],
+            &[0x21, 0x21],
+            &[0x26, 0x81, 0x00],
+            &[0xE0, b'a'],
+        ] {
+            assert!(to_json(smile).is_err(), "{:?}", smile);
+        }
+        assert!(to_json(&[0xF8; MAX_DEPTH + 1]).is_err());
+    }
+}
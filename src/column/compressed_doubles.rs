@@ -1,10 +1,18 @@
 use std::io::Cursor;
+use std::ops::Range;
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 
-use super::generic_indexed::GenericIndexedV1;
+use super::block_layout::{
+    BlockLayout, ValueBlocks, block_out_of_range, block_value_count, block_value_range,
+    check_uncompressed_block,
+};
+use super::block_writer::CompressedBlockWriter;
+use super::read_len;
 use crate::compression::{CompressionStrategy, decompress_block};
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+use crate::segment::read_options::{CancellationToken, check_cancelled};
 
 /// Reader for Druid's CompressedColumnarDoubles format.
 ///
@@ -18,17 +26,28 @@ use crate::error::{DruidSegmentError, Result};
 /// [compression: u8]     -- CompressionStrategy ID
 /// [GenericIndexed<ByteBuffer>]  -- compressed blocks
 /// ```
+///
+/// With the `none` strategy the values follow the header as they are,
+/// with no blocks: Druid's "entire layout", read as a single block.
 pub struct CompressedColumnarDoubles<'a> {
+    version: u8,
     total_size: usize,
     size_per: usize,
     compression: CompressionStrategy,
-    blocks: GenericIndexedV1<'a>,
+    byte_order: ByteOrder,
+    blocks: ValueBlocks<'a>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl<'a> CompressedColumnarDoubles<'a> {
-    /// Parse from raw bytes.
+    /// Parse from raw bytes, assuming big-endian values.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
-        if data.len() < 11 {
+        Self::from_bytes_with_order(data, ByteOrder::BigEndian)
+    }
+
+    /// Parse from raw bytes whose decompressed values use `byte_order`.
+    pub fn from_bytes_with_order(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
+        if data.len() < 10 {
             return Err(DruidSegmentError::InvalidData(
                 "CompressedColumnarDoubles: data too short".into(),
             ));
@@ -43,20 +62,31 @@ impl<'a> CompressedColumnarDoubles<'a> {
         }
 
         let mut cursor = Cursor::new(&data[1..]);
-        let total_size = cursor.read_i32::<BigEndian>()? as usize;
-        let size_per = cursor.read_i32::<BigEndian>()? as usize;
+        let total_size = read_len(&mut cursor, "CompressedColumnarDoubles: total size")?;
+        let size_per = read_len(&mut cursor, "CompressedColumnarDoubles: values per block")?;
 
         let compression = CompressionStrategy::from_id(data[9])?;
-        let blocks = GenericIndexedV1::from_bytes(&data[10..])?;
+        let blocks = ValueBlocks::parse(compression, &data[10..], total_size.saturating_mul(8))?;
+        let size_per = blocks.size_per(size_per, total_size);
 
         Ok(Self {
+            version,
             total_size,
             size_per,
             compression,
+            byte_order,
             blocks,
+            cancellation: None,
         })
     }
 
+    /// Check `token` before decompressing each block, returning
+    /// `Err(Cancelled)` once it has been cancelled.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
     /// Total number of double values.
     pub fn len(&self) -> usize {
         self.total_size
@@ -67,12 +97,75 @@ impl<'a> CompressedColumnarDoubles<'a> {
         self.total_size == 0
     }
 
+    /// Number of compressed blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Compressed size in bytes of block `i`, from the GenericIndexed
+    /// offsets.
+    pub fn block_compressed_size(&self, i: usize) -> Result<usize> {
+        self.blocks.compressed_size(i)
+    }
+
+    /// Number of values stored in block `i`.
+    pub fn block_value_count(&self, i: usize) -> Option<usize> {
+        block_value_count(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// Index of the first value of block `i` and the number of values it
+    /// holds, or `None` past the last block.
+    pub fn block_value_range(&self, i: usize) -> Option<(usize, usize)> {
+        block_value_range(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// The header fields and block sizes, read without decompressing any
+    /// block.
+    pub fn layout(&self) -> Result<BlockLayout> {
+        self.blocks.layout(
+            self.version,
+            self.total_size,
+            self.size_per,
+            self.compression,
+        )
+    }
+
     /// Decompress all values into a Vec<f64>.
     pub fn decompress_all(&self) -> Result<Vec<f64>> {
-        let mut result = Vec::with_capacity(self.total_size);
-        let num_blocks = self.blocks.len();
+        self.decompress_prefix(self.total_size)
+    }
+
+    /// Decompress only the first `max_values` values, stopping after the
+    /// block that contains the last one.
+    pub fn decompress_prefix(&self, max_values: usize) -> Result<Vec<f64>> {
+        self.decompress_range(0..max_values)
+    }
+
+    /// Decompress block `i` alone, e.g. to decode blocks in parallel.
+    pub fn decompress_block(&self, i: usize) -> Result<Vec<f64>> {
+        let (start, len) = self.block_value_range(i).ok_or_else(|| {
+            block_out_of_range("CompressedColumnarDoubles", i, self.block_count())
+        })?;
+        self.decompress_range(start..start + len)
+    }
+
+    /// Decompress the values in `range`, skipping the blocks before it and
+    /// stopping after the block that contains its last value.
+    pub fn decompress_range(&self, range: Range<usize>) -> Result<Vec<f64>> {
+        let end = range.end.min(self.total_size);
+        let start = range.start.min(end);
+        let mut result = Vec::with_capacity(end - start);
+        if start == end {
+            return Ok(result);
+        }
+        if self.size_per == 0 {
+            return Err(DruidSegmentError::InvalidData(
+                "CompressedColumnarDoubles: zero values per block".into(),
+            ));
+        }
 
-        for block_idx in 0..num_blocks {
+        for block_idx in start / self.size_per..end.div_ceil(self.size_per) {
+            check_cancelled(self.cancellation.as_ref())?;
             let block_data = self.blocks.get(block_idx)?.ok_or_else(|| {
                 DruidSegmentError::InvalidData(format!(
                     "CompressedColumnarDoubles: null block at index {}",
@@ -80,16 +173,31 @@ impl<'a> CompressedColumnarDoubles<'a> {
                 ))
             })?;
 
-            let remaining = self.total_size - result.len();
-            let values_in_block = remaining.min(self.size_per);
+            let block_start = block_idx * self.size_per;
+            let values_in_block = (self.total_size - block_start).min(self.size_per);
+            // Values of this block that fall inside the range
+            let skip = start.saturating_sub(block_start);
+            let take = (end - block_start).min(values_in_block);
             let decompressed_size = values_in_block * 8; // 8 bytes per f64
 
+            check_uncompressed_block(
+                "CompressedColumnarDoubles",
+                self.compression,
+                block_idx,
+                block_data,
+                decompressed_size,
+            )?;
             let decompressed = decompress_block(self.compression, block_data, decompressed_size)?;
 
             let mut cursor = Cursor::new(&decompressed);
-            for _ in 0..values_in_block {
-                let value = cursor.read_f64::<BigEndian>()?;
-                result.push(value);
+            for i in 0..take {
+                let value = match self.byte_order {
+                    ByteOrder::BigEndian => cursor.read_f64::<BigEndian>()?,
+                    ByteOrder::LittleEndian => cursor.read_f64::<LittleEndian>()?,
+                };
+                if i >= skip {
+                    result.push(value);
+                }
             }
         }
 
@@ -97,20 +205,34 @@ impl<'a> CompressedColumnarDoubles<'a> {
     }
 }
 
+/// Writer for version 0x02 CompressedColumnarDoubles.
+pub type CompressedColumnarDoublesWriter = CompressedBlockWriter<f64>;
+
+/// Writer for version 0x02 CompressedColumnarFloats.
+pub type CompressedColumnarFloatsWriter = CompressedBlockWriter<f32>;
+
 /// Reader for Druid's CompressedColumnarFloats format.
 ///
 /// Same structure as doubles but with f32 values.
 pub struct CompressedColumnarFloats<'a> {
+    version: u8,
     total_size: usize,
     size_per: usize,
     compression: CompressionStrategy,
-    blocks: GenericIndexedV1<'a>,
+    byte_order: ByteOrder,
+    blocks: ValueBlocks<'a>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl<'a> CompressedColumnarFloats<'a> {
-    /// Parse from raw bytes.
+    /// Parse from raw bytes, assuming big-endian values.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
-        if data.len() < 11 {
+        Self::from_bytes_with_order(data, ByteOrder::BigEndian)
+    }
+
+    /// Parse from raw bytes whose decompressed values use `byte_order`.
+    pub fn from_bytes_with_order(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
+        if data.len() < 10 {
             return Err(DruidSegmentError::InvalidData(
                 "CompressedColumnarFloats: data too short".into(),
             ));
@@ -125,20 +247,31 @@ impl<'a> CompressedColumnarFloats<'a> {
         }
 
         let mut cursor = Cursor::new(&data[1..]);
-        let total_size = cursor.read_i32::<BigEndian>()? as usize;
-        let size_per = cursor.read_i32::<BigEndian>()? as usize;
+        let total_size = read_len(&mut cursor, "CompressedColumnarFloats: total size")?;
+        let size_per = read_len(&mut cursor, "CompressedColumnarFloats: values per block")?;
 
         let compression = CompressionStrategy::from_id(data[9])?;
-        let blocks = GenericIndexedV1::from_bytes(&data[10..])?;
+        let blocks = ValueBlocks::parse(compression, &data[10..], total_size.saturating_mul(4))?;
+        let size_per = blocks.size_per(size_per, total_size);
 
         Ok(Self {
+            version,
             total_size,
             size_per,
             compression,
+            byte_order,
             blocks,
+            cancellation: None,
         })
     }
 
+    /// Check `token` before decompressing each block, returning
+    /// `Err(Cancelled)` once it has been cancelled.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
     /// Total number of float values.
     pub fn len(&self) -> usize {
         self.total_size
@@ -149,12 +282,75 @@ impl<'a> CompressedColumnarFloats<'a> {
         self.total_size == 0
     }
 
+    /// Number of compressed blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Compressed size in bytes of block `i`, from the GenericIndexed
+    /// offsets.
+    pub fn block_compressed_size(&self, i: usize) -> Result<usize> {
+        self.blocks.compressed_size(i)
+    }
+
+    /// Number of values stored in block `i`.
+    pub fn block_value_count(&self, i: usize) -> Option<usize> {
+        block_value_count(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// Index of the first value of block `i` and the number of values it
+    /// holds, or `None` past the last block.
+    pub fn block_value_range(&self, i: usize) -> Option<(usize, usize)> {
+        block_value_range(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// The header fields and block sizes, read without decompressing any
+    /// block.
+    pub fn layout(&self) -> Result<BlockLayout> {
+        self.blocks.layout(
+            self.version,
+            self.total_size,
+            self.size_per,
+            self.compression,
+        )
+    }
+
     /// Decompress all values into a Vec<f32>.
     pub fn decompress_all(&self) -> Result<Vec<f32>> {
-        let mut result = Vec::with_capacity(self.total_size);
-        let num_blocks = self.blocks.len();
+        self.decompress_prefix(self.total_size)
+    }
 
-        for block_idx in 0..num_blocks {
+    /// Decompress only the first `max_values` values, stopping after the
+    /// block that contains the last one.
+    pub fn decompress_prefix(&self, max_values: usize) -> Result<Vec<f32>> {
+        self.decompress_range(0..max_values)
+    }
+
+    /// Decompress block `i` alone, e.g. to decode blocks in parallel.
+    pub fn decompress_block(&self, i: usize) -> Result<Vec<f32>> {
+        let (start, len) = self
+            .block_value_range(i)
+            .ok_or_else(|| block_out_of_range("CompressedColumnarFloats", i, self.block_count()))?;
+        self.decompress_range(start..start + len)
+    }
+
+    /// Decompress the values in `range`, skipping the blocks before it and
+    /// stopping after the block that contains its last value.
+    pub fn decompress_range(&self, range: Range<usize>) -> Result<Vec<f32>> {
+        let end = range.end.min(self.total_size);
+        let start = range.start.min(end);
+        let mut result = Vec::with_capacity(end - start);
+        if start == end {
+            return Ok(result);
+        }
+        if self.size_per == 0 {
+            return Err(DruidSegmentError::InvalidData(
+                "CompressedColumnarFloats: zero values per block".into(),
+            ));
+        }
+
+        for block_idx in start / self.size_per..end.div_ceil(self.size_per) {
+            check_cancelled(self.cancellation.as_ref())?;
             let block_data = self.blocks.get(block_idx)?.ok_or_else(|| {
                 DruidSegmentError::InvalidData(format!(
                     "CompressedColumnarFloats: null block at index {}",
@@ -162,19 +358,238 @@ impl<'a> CompressedColumnarFloats<'a> {
                 ))
             })?;
 
-            let remaining = self.total_size - result.len();
-            let values_in_block = remaining.min(self.size_per);
+            let block_start = block_idx * self.size_per;
+            let values_in_block = (self.total_size - block_start).min(self.size_per);
+            // Values of this block that fall inside the range
+            let skip = start.saturating_sub(block_start);
+            let take = (end - block_start).min(values_in_block);
             let decompressed_size = values_in_block * 4; // 4 bytes per f32
 
+            check_uncompressed_block(
+                "CompressedColumnarFloats",
+                self.compression,
+                block_idx,
+                block_data,
+                decompressed_size,
+            )?;
             let decompressed = decompress_block(self.compression, block_data, decompressed_size)?;
 
             let mut cursor = Cursor::new(&decompressed);
-            for _ in 0..values_in_block {
-                let value = cursor.read_f32::<BigEndian>()?;
-                result.push(value);
+            for i in 0..take {
+                let value = match self.byte_order {
+                    ByteOrder::BigEndian => cursor.read_f32::<BigEndian>()?,
+                    ByteOrder::LittleEndian => cursor.read_f32::<LittleEndian>()?,
+                };
+                if i >= skip {
+                    result.push(value);
+                }
             }
         }
 
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    /// Build a CompressedColumnarDoubles/Floats whose blocks of big-endian
+    /// values (written by `write`) are stored uncompressed.
+    fn build_uncompressed<T: Copy>(
+        blocks: &[&[T]],
+        size_per: usize,
+        write: impl Fn(&mut Vec<u8>, T),
+    ) -> Vec<u8> {
+        let total: usize = blocks.iter().map(|b| b.len()).sum();
+        let mut buf = vec![0x02];
+        buf.write_i32::<BigEndian>(total as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push(0xFF);
+
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for block in blocks {
+            body.write_i32::<BigEndian>(0).unwrap();
+            for &v in *block {
+                write(&mut body, v);
+            }
+            offsets.push(body.len() as i32);
+        }
+        buf.extend_from_slice(&[0x01, 0x00]);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(blocks.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    fn write_f64(buf: &mut Vec<u8>, v: f64) {
+        buf.write_f64::<BigEndian>(v).unwrap();
+    }
+
+    #[test]
+    fn test_uncompressed_doubles_with_partial_block() {
+        let data = build_uncompressed(&[&[1.5, -2.0], &[3.25]], 2, write_f64);
+        let doubles = CompressedColumnarDoubles::from_bytes(&data).unwrap();
+        assert_eq!(doubles.decompress_all().unwrap(), vec![1.5, -2.0, 3.25]);
+        assert_eq!(doubles.decompress_range(1..3).unwrap(), vec![-2.0, 3.25]);
+    }
+
+    #[test]
+    fn test_uncompressed_floats() {
+        let data = build_uncompressed(&[&[0.5f32, 1.0], &[2.0]], 2, |buf, v| {
+            buf.write_f32::<BigEndian>(v).unwrap()
+        });
+        let floats = CompressedColumnarFloats::from_bytes(&data).unwrap();
+        assert_eq!(floats.decompress_all().unwrap(), vec![0.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_uncompressed_block_size_mismatch() {
+        // The last block should hold one value but holds two
+        let data = build_uncompressed(&[&[1.0, 2.0], &[3.0, 4.0]], 2, write_f64);
+        let mut doubles = CompressedColumnarDoubles::from_bytes(&data).unwrap();
+        doubles.total_size = 3;
+        assert!(doubles.decompress_all().is_err());
+        assert_eq!(doubles.decompress_prefix(2).unwrap(), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_writer_round_trips_doubles_bit_exact() {
+        let specials = [
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            -0.0,
+            f64::MIN_POSITIVE,
+        ];
+        let values: Vec<f64> = (0..2500)
+            .map(|i| match specials.get(i % 50) {
+                Some(&special) => special,
+                None => (i as f64).sqrt() * if i % 2 == 0 { 1.0 } else { -1e300 },
+            })
+            .collect();
+        for byte_order in [ByteOrder::BigEndian, ByteOrder::LittleEndian] {
+            let data = CompressedColumnarDoublesWriter::new()
+                .with_size_per(1000)
+                .with_byte_order(byte_order)
+                .write(&values)
+                .unwrap();
+            let doubles =
+                CompressedColumnarDoubles::from_bytes_with_order(&data, byte_order).unwrap();
+            assert_eq!(doubles.block_count(), 3);
+            assert_eq!(doubles.block_value_count(2), Some(500));
+            let read = doubles.decompress_all().unwrap();
+            let bits = |v: &[f64]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+            assert_eq!(bits(&read), bits(&values));
+        }
+    }
+
+    #[test]
+    fn test_blocks_decode_to_all() {
+        let doubles: Vec<f64> = (0..250).map(|i| i as f64 / 7.0).collect();
+        let data = CompressedColumnarDoublesWriter::new()
+            .with_size_per(100)
+            .write(&doubles)
+            .unwrap();
+        let reader = CompressedColumnarDoubles::from_bytes(&data).unwrap();
+        let blocks: Vec<Vec<f64>> = (0..reader.block_count())
+            .map(|i| reader.decompress_block(i).unwrap())
+            .collect();
+        assert_eq!(blocks.concat(), reader.decompress_all().unwrap());
+        assert_eq!(reader.block_value_range(2), Some((200, 50)));
+        assert!(reader.decompress_block(3).is_err());
+
+        let floats: Vec<f32> = (0..250).map(|i| i as f32 * 0.25).collect();
+        let data = CompressedColumnarFloatsWriter::new()
+            .with_size_per(100)
+            .write(&floats)
+            .unwrap();
+        let reader = CompressedColumnarFloats::from_bytes(&data).unwrap();
+        let blocks: Vec<Vec<f32>> = (0..reader.block_count())
+            .map(|i| reader.decompress_block(i).unwrap())
+            .collect();
+        assert_eq!(blocks.concat(), reader.decompress_all().unwrap());
+        assert_eq!(reader.block_value_range(1), Some((100, 100)));
+        assert!(reader.decompress_block(3).is_err());
+    }
+
+    #[test]
+    fn test_writer_round_trips_floats_bit_exact() {
+        let nan_payload = f32::from_bits(0x7FC0_1234);
+        let values: Vec<f32> = (0..20_000)
+            .map(|i| match i % 7 {
+                0 => nan_payload,
+                1 => f32::INFINITY,
+                2 => f32::NEG_INFINITY,
+                _ => i as f32 / 3.0,
+            })
+            .collect();
+        let data = CompressedColumnarFloatsWriter::new()
+            .write(&values)
+            .unwrap();
+        let floats = CompressedColumnarFloats::from_bytes(&data).unwrap();
+        // Druid's default 64 KiB blocks hold 16384 floats
+        assert_eq!(floats.block_count(), 2);
+        assert_eq!(floats.layout().unwrap().size_per, 0x10000 / 4);
+        let read = floats.decompress_all().unwrap();
+        let bits = |v: &[f32]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&read), bits(&values));
+
+        let uncompressed = CompressedColumnarFloatsWriter::new()
+            .with_size_per(2)
+            .with_compression(CompressionStrategy::Uncompressed)
+            .write(&[0.5, 1.0, 2.0])
+            .unwrap();
+        let expected = build_uncompressed(&[&[0.5f32, 1.0], &[2.0]], 2, |buf, v| {
+            buf.write_f32::<BigEndian>(v).unwrap()
+        });
+        assert_eq!(uncompressed, expected);
+    }
+
+    /// Druid's entire layout for the `none` strategy: a size_per of 0 and
+    /// the raw values after the header.
+    fn build_entire<T: Copy>(values: &[T], write: fn(&mut Vec<u8>, T)) -> Vec<u8> {
+        let mut buf = vec![0x02];
+        buf.write_i32::<BigEndian>(values.len() as i32).unwrap();
+        buf.write_i32::<BigEndian>(0).unwrap();
+        buf.push(0xFE);
+        for &v in values {
+            write(&mut buf, v);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_entire_layout() {
+        let mut data = build_entire(&[1.5, -2.0, 3.25], write_f64);
+        // Bytes past the values, such as a null bitmap, are not read
+        data.extend_from_slice(&[0xAB; 5]);
+        let doubles = CompressedColumnarDoubles::from_bytes(&data).unwrap();
+        assert_eq!(doubles.block_count(), 1);
+        assert_eq!(doubles.layout().unwrap().block_sizes, vec![24]);
+        assert_eq!(doubles.decompress_all().unwrap(), vec![1.5, -2.0, 3.25]);
+        assert_eq!(doubles.decompress_range(1..3).unwrap(), vec![-2.0, 3.25]);
+
+        let data = build_entire(&[0.5f32, 2.0], |buf, v| {
+            buf.write_f32::<BigEndian>(v).unwrap()
+        });
+        let floats = CompressedColumnarFloats::from_bytes(&data).unwrap();
+        assert_eq!(floats.decompress_all().unwrap(), vec![0.5, 2.0]);
+
+        let empty = build_entire::<f64>(&[], write_f64);
+        let doubles = CompressedColumnarDoubles::from_bytes(&empty).unwrap();
+        assert!(doubles.decompress_all().unwrap().is_empty());
+
+        // Fewer bytes than the header's count of values
+        let mut short = build_entire(&[1.0, 2.0], write_f64);
+        short.truncate(short.len() - 1);
+        let doubles = CompressedColumnarDoubles::from_bytes(&short).unwrap();
+        assert!(doubles.decompress_all().is_err());
+    }
+}
@@ -1,10 +1,15 @@
 use std::io::Cursor;
+use std::ops::Range;
 
-use byteorder::{BigEndian, ReadBytesExt};
-
+use super::block_layout::{
+    BlockLayout, block_compressed_size, block_out_of_range, block_value_count, block_value_range,
+};
 use super::generic_indexed::GenericIndexedV1;
+use super::read_len;
 use crate::compression::{CompressionStrategy, decompress_block};
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+use crate::segment::read_options::{CancellationToken, check_cancelled};
 
 /// Reader for Druid's CompressedColumnarInts (CompressedVSizeColumnarIntsSupplier).
 ///
@@ -13,42 +18,94 @@ use crate::error::{DruidSegmentError, Result};
 /// Header layout (version 0x02):
 /// ```text
 /// [version: u8 = 0x02]
+/// [num_bytes: u8]       -- bytes per integer (1-4)
 /// [total_size: i32]     -- total number of int values
 /// [size_per: i32]       -- ints per compressed block
-/// [num_bytes: u8]       -- bytes per integer (1-4)
 /// [compression: u8]     -- CompressionStrategy ID
 /// [GenericIndexed<ByteBuffer>]  -- compressed blocks
 /// ```
+///
+/// Legacy segments use version 0x01, which stores 4-byte ints with LZF
+/// compression implied:
+/// ```text
+/// [version: u8 = 0x01]
+/// [total_size: i32]
+/// [size_per: i32]
+/// [GenericIndexed<ByteBuffer>]  -- LZF-compressed blocks
+/// ```
+///
+/// Values are packed `num_bytes` wide in the column's byte order. Blocks of
+/// 3-byte values carry one byte of padding so Druid can read them as ints.
 pub struct CompressedColumnarInts<'a> {
+    version: u8,
     total_size: usize,
     size_per: usize,
     num_bytes: usize,
     compression: CompressionStrategy,
+    byte_order: ByteOrder,
     blocks: GenericIndexedV1<'a>,
+    header_size: usize,
+    cancellation: Option<CancellationToken>,
 }
 
+const VERSION_LZF: u8 = 0x01;
+const VERSION: u8 = 0x02;
+const HEADER_SIZE: usize = 11; // version(1) + num_bytes(1) + total_size(4) + size_per(4) + compression(1)
+const HEADER_SIZE_LZF: usize = 9; // version(1) + total_size(4) + size_per(4)
+const HEADER_SIZE_FIXED_WIDTH: usize = 10; // version(1) + total_size(4) + size_per(4) + compression(1)
+
 impl<'a> CompressedColumnarInts<'a> {
-    /// Parse from raw bytes.
+    /// Parse from raw bytes, assuming big-endian values.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
-        if data.len() < 11 {
+        Self::from_bytes_with_order(data, ByteOrder::BigEndian)
+    }
+
+    /// Parse from raw bytes whose decompressed values use `byte_order`.
+    pub fn from_bytes_with_order(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
+        Self::parse(data, byte_order, false)
+    }
+
+    /// Parse Druid's fixed-width CompressedColumnarInts
+    /// (CompressedColumnarIntsSupplier), whose v2 header has no `num_bytes`
+    /// byte because every value is 4 bytes wide:
+    /// ```text
+    /// [version: u8 = 0x02]
+    /// [total_size: i32]
+    /// [size_per: i32]
+    /// [compression: u8]
+    /// [GenericIndexed<ByteBuffer>]
+    /// ```
+    /// Version 0x01 is the same legacy LZF layout [`from_bytes`](Self::from_bytes) accepts.
+    pub fn from_fixed_width_bytes(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
+        Self::parse(data, byte_order, true)
+    }
+
+    fn parse(data: &'a [u8], byte_order: ByteOrder, fixed_width: bool) -> Result<Self> {
+        let version = *data.first().ok_or_else(|| {
+            DruidSegmentError::InvalidData("CompressedColumnarInts: data too short".into())
+        })?;
+        let header_size = match version {
+            VERSION_LZF => HEADER_SIZE_LZF,
+            VERSION if fixed_width => HEADER_SIZE_FIXED_WIDTH,
+            VERSION => HEADER_SIZE,
+            other => {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "CompressedColumnarInts: unsupported version {:#x}",
+                    other
+                )));
+            }
+        };
+        if data.len() < header_size {
             return Err(DruidSegmentError::InvalidData(
                 "CompressedColumnarInts: data too short".into(),
             ));
         }
 
-        let version = data[0];
-        if version != 0x02 {
-            return Err(DruidSegmentError::InvalidData(format!(
-                "CompressedColumnarInts: unsupported version {:#x}",
-                version
-            )));
-        }
-
-        let mut cursor = Cursor::new(&data[1..]);
-        let total_size = cursor.read_i32::<BigEndian>()? as usize;
-        let size_per = cursor.read_i32::<BigEndian>()? as usize;
-
-        let num_bytes = data[9] as usize;
+        let (num_bytes, compression) = match version {
+            VERSION_LZF => (4, CompressionStrategy::Lzf),
+            _ if fixed_width => (4, CompressionStrategy::from_id(data[9])?),
+            _ => (data[1] as usize, CompressionStrategy::from_id(data[10])?),
+        };
         if num_bytes == 0 || num_bytes > 4 {
             return Err(DruidSegmentError::InvalidData(format!(
                 "CompressedColumnarInts: invalid num_bytes {}",
@@ -56,18 +113,39 @@ impl<'a> CompressedColumnarInts<'a> {
             )));
         }
 
-        let compression = CompressionStrategy::from_id(data[10])?;
-        let blocks = GenericIndexedV1::from_bytes(&data[11..])?;
+        // total_size and size_per follow the version byte, and num_bytes in
+        // variable-width v2
+        let counts_offset = if version == VERSION_LZF || fixed_width {
+            1
+        } else {
+            2
+        };
+        let mut cursor = Cursor::new(&data[counts_offset..]);
+        let total_size = read_len(&mut cursor, "CompressedColumnarInts: total size")?;
+        let size_per = read_len(&mut cursor, "CompressedColumnarInts: values per block")?;
+
+        let blocks = GenericIndexedV1::from_bytes(&data[header_size..])?;
 
         Ok(Self {
+            version,
             total_size,
             size_per,
             num_bytes,
             compression,
+            byte_order,
             blocks,
+            header_size,
+            cancellation: None,
         })
     }
 
+    /// Check `token` before decompressing each block, returning
+    /// `Err(Cancelled)` once it has been cancelled.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
     /// Total number of int values.
     pub fn len(&self) -> usize {
         self.total_size
@@ -78,12 +156,85 @@ impl<'a> CompressedColumnarInts<'a> {
         self.total_size == 0
     }
 
+    /// Number of compressed blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Compressed size in bytes of block `i`, from the GenericIndexed
+    /// offsets.
+    pub fn block_compressed_size(&self, i: usize) -> Result<usize> {
+        block_compressed_size(&self.blocks, i)
+    }
+
+    /// Number of values stored in block `i`.
+    pub fn block_value_count(&self, i: usize) -> Option<usize> {
+        block_value_count(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// Index of the first value of block `i` and the number of values it
+    /// holds, or `None` past the last block.
+    pub fn block_value_range(&self, i: usize) -> Option<(usize, usize)> {
+        block_value_range(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// The header fields and block sizes, read without decompressing any
+    /// block.
+    pub fn layout(&self) -> Result<BlockLayout> {
+        BlockLayout::read(
+            self.version,
+            self.total_size,
+            self.size_per,
+            self.compression,
+            &self.blocks,
+        )
+    }
+
+    /// Total bytes consumed by this structure, header included.
+    pub fn total_bytes(&self) -> Result<usize> {
+        Ok(self.header_size + self.blocks.total_size()?)
+    }
+
     /// Decompress all values into a Vec<u32>.
     pub fn decompress_all(&self) -> Result<Vec<u32>> {
-        let mut result = Vec::with_capacity(self.total_size);
-        let num_blocks = self.blocks.len();
+        self.decompress_prefix(self.total_size)
+    }
+
+    /// Decompress only the first `max_values` values, stopping after the
+    /// block that contains the last one.
+    pub fn decompress_prefix(&self, max_values: usize) -> Result<Vec<u32>> {
+        self.decompress_range(0..max_values)
+    }
+
+    /// Decompress block `i` alone, e.g. to decode blocks in parallel.
+    pub fn decompress_block(&self, i: usize) -> Result<Vec<u32>> {
+        let (start, len) = self
+            .block_value_range(i)
+            .ok_or_else(|| block_out_of_range("CompressedColumnarInts", i, self.block_count()))?;
+        self.decompress_range(start..start + len)
+    }
 
-        for block_idx in 0..num_blocks {
+    /// Decompress the values in `range`, skipping the blocks before it and
+    /// stopping after the block that contains its last value.
+    pub fn decompress_range(&self, range: Range<usize>) -> Result<Vec<u32>> {
+        let end = range.end.min(self.total_size);
+        let start = range.start.min(end);
+        let mut result = Vec::with_capacity(end - start);
+        if start == end {
+            return Ok(result);
+        }
+        if self.size_per == 0 {
+            return Err(DruidSegmentError::InvalidData(
+                "CompressedColumnarInts: zero values per block".into(),
+            ));
+        }
+        let padding = match self.num_bytes {
+            1 | 2 => 0,
+            n => 4 - n,
+        };
+
+        for block_idx in start / self.size_per..end.div_ceil(self.size_per) {
+            check_cancelled(self.cancellation.as_ref())?;
             let block_data = self.blocks.get(block_idx)?.ok_or_else(|| {
                 DruidSegmentError::InvalidData(format!(
                     "CompressedColumnarInts: null block at index {}",
@@ -91,20 +242,32 @@ impl<'a> CompressedColumnarInts<'a> {
                 ))
             })?;
 
-            let remaining = self.total_size - result.len();
-            let values_in_block = remaining.min(self.size_per);
-            let decompressed_size = values_in_block * self.num_bytes;
+            let block_start = block_idx * self.size_per;
+            let values_in_block = (self.total_size - block_start).min(self.size_per);
+            // Values of this block that fall inside the range
+            let skip = start.saturating_sub(block_start);
+            let take = (end - block_start).min(values_in_block);
+            let decompressed_size = values_in_block * self.num_bytes + padding;
 
             let decompressed = decompress_block(self.compression, block_data, decompressed_size)?;
+            if decompressed.len() < values_in_block * self.num_bytes {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "CompressedColumnarInts: block {} holds {} bytes, expected {}",
+                    block_idx,
+                    decompressed.len(),
+                    values_in_block * self.num_bytes
+                )));
+            }
 
-            // Read big-endian unsigned integers of variable width
-            for i in 0..values_in_block {
-                let offset = i * self.num_bytes;
-                let bytes = &decompressed[offset..offset + self.num_bytes];
-                let mut value: u32 = 0;
-                for &b in bytes {
-                    value = (value << 8) | (b as u32);
-                }
+            // Read unsigned integers of variable width
+            let bytes = &decompressed[skip * self.num_bytes..take * self.num_bytes];
+            for chunk in bytes.chunks(self.num_bytes) {
+                let value = match self.byte_order {
+                    ByteOrder::BigEndian => chunk.iter().fold(0u32, |v, &b| (v << 8) | b as u32),
+                    ByteOrder::LittleEndian => {
+                        chunk.iter().rev().fold(0u32, |v, &b| (v << 8) | b as u32)
+                    }
+                };
                 result.push(value);
             }
         }
@@ -112,3 +275,173 @@ impl<'a> CompressedColumnarInts<'a> {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    /// Build LZ4-compressed CompressedColumnarInts with the given width.
+    fn build_compressed_ints(values: &[u32], num_bytes: u8, size_per: usize) -> Vec<u8> {
+        let padding = if num_bytes == 3 { 1 } else { 0 };
+        let mut blocks = Vec::new();
+        for chunk in values.chunks(size_per) {
+            let mut raw = Vec::new();
+            for &v in chunk {
+                raw.extend_from_slice(&v.to_le_bytes()[..num_bytes as usize]);
+            }
+            raw.extend(std::iter::repeat_n(0u8, padding));
+            blocks.push(lz4_flex::block::compress(&raw));
+        }
+        assemble(values.len(), num_bytes, size_per, &blocks)
+    }
+
+    /// Assemble a v2 header and already-compressed LZ4 blocks.
+    fn assemble(total: usize, num_bytes: u8, size_per: usize, blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut buf = vec![0x02, num_bytes];
+        buf.write_i32::<BigEndian>(total as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push(0x01); // LZ4
+
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for block in blocks {
+            body.write_i32::<BigEndian>(0).unwrap();
+            body.extend_from_slice(block);
+            offsets.push(body.len() as i32);
+        }
+        buf.push(0x01);
+        buf.push(0x00);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(blocks.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    #[test]
+    fn test_decompress_prefix_skips_later_blocks() {
+        // The second block is not valid LZ4, so only a prefix that stays
+        // within the first block can succeed.
+        let first = lz4_flex::block::compress(&[5, 6]);
+        let data = assemble(4, 1, 2, &[first, vec![0xF0]]);
+        let ints = CompressedColumnarInts::from_bytes(&data).unwrap();
+        assert_eq!(ints.decompress_prefix(1).unwrap(), vec![5]);
+        assert_eq!(ints.decompress_prefix(2).unwrap(), vec![5, 6]);
+        assert!(ints.decompress_prefix(3).is_err());
+        assert!(ints.decompress_all().is_err());
+    }
+
+    #[test]
+    fn test_decompress_range_skips_earlier_blocks() {
+        // The first block is not valid LZ4, so only a range that starts in
+        // the second block can succeed.
+        let second = lz4_flex::block::compress(&[7, 8]);
+        let data = assemble(4, 1, 2, &[vec![0xF0], second]);
+        let ints = CompressedColumnarInts::from_bytes(&data).unwrap();
+        assert_eq!(ints.decompress_range(2..4).unwrap(), vec![7, 8]);
+        assert_eq!(ints.decompress_range(3..9).unwrap(), vec![8]);
+        assert!(ints.decompress_range(9..12).unwrap().is_empty());
+        assert!(ints.decompress_range(1..3).is_err());
+    }
+
+    /// Wrap raw bytes in a single stored LZF chunk.
+    fn lzf_stored_chunk(raw: &[u8]) -> Vec<u8> {
+        let mut chunk = b"ZV\x00".to_vec();
+        chunk.extend_from_slice(&(raw.len() as u16).to_be_bytes());
+        chunk.extend_from_slice(raw);
+        chunk
+    }
+
+    /// Build a legacy v1 CompressedColumnarInts: 4-byte ints, LZF implied.
+    fn build_lzf_ints(values: &[u32], size_per: usize) -> Vec<u8> {
+        let mut buf = vec![VERSION_LZF];
+        buf.write_i32::<BigEndian>(values.len() as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for chunk in values.chunks(size_per) {
+            let raw: Vec<u8> = chunk.iter().flat_map(|v| v.to_le_bytes()).collect();
+            body.write_i32::<BigEndian>(0).unwrap();
+            body.extend_from_slice(&lzf_stored_chunk(&raw));
+            offsets.push(body.len() as i32);
+        }
+        buf.extend_from_slice(&[0x01, 0x00]);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(offsets.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    #[test]
+    fn test_blocks_decode_to_all() {
+        let values: Vec<u32> = (0..10).map(|i| i * 1000).collect();
+        let data = build_compressed_ints(&values, 2, 4);
+        let ints =
+            CompressedColumnarInts::from_bytes_with_order(&data, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(ints.block_count(), 3);
+
+        let blocks: Vec<Vec<u32>> = (0..ints.block_count())
+            .map(|i| ints.decompress_block(i).unwrap())
+            .collect();
+        assert_eq!(blocks.concat(), ints.decompress_all().unwrap());
+        assert_eq!(blocks[2], vec![8000, 9000]);
+        assert_eq!(ints.block_value_range(2), Some((8, 2)));
+        assert!(ints.decompress_block(3).is_err());
+    }
+
+    #[test]
+    fn test_legacy_lzf_version_matches_v2() {
+        let values = vec![0, 7, 3, 70_000, 12, 1, 0];
+        let legacy = build_lzf_ints(&values, 3);
+        let current = build_compressed_ints(&values, 4, 3);
+
+        let legacy_ints =
+            CompressedColumnarInts::from_bytes_with_order(&legacy, ByteOrder::LittleEndian)
+                .unwrap();
+        let current_ints =
+            CompressedColumnarInts::from_bytes_with_order(&current, ByteOrder::LittleEndian)
+                .unwrap();
+        assert_eq!(legacy_ints.len(), values.len());
+        assert_eq!(legacy_ints.total_bytes().unwrap(), legacy.len());
+        assert_eq!(legacy_ints.decompress_all().unwrap(), values);
+        assert_eq!(
+            legacy_ints.decompress_all().unwrap(),
+            current_ints.decompress_all().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut data = build_compressed_ints(&[1], 1, 1);
+        data[0] = 0x03;
+        assert!(CompressedColumnarInts::from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn test_widths() {
+        for (num_bytes, max) in [(1u8, 0xFFu32), (2, 0xFFFF), (3, 0xFF_FFFF), (4, u32::MAX)] {
+            let values = vec![0, 1, max, max / 2, 7];
+            let data = build_compressed_ints(&values, num_bytes, 2);
+            let ints =
+                CompressedColumnarInts::from_bytes_with_order(&data, ByteOrder::LittleEndian)
+                    .unwrap();
+            assert_eq!(ints.len(), 5);
+            assert_eq!(
+                ints.decompress_all().unwrap(),
+                values,
+                "num_bytes {}",
+                num_bytes
+            );
+            assert_eq!(ints.total_bytes().unwrap(), data.len());
+        }
+    }
+}
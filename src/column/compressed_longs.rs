@@ -1,10 +1,17 @@
 use std::io::Cursor;
+use std::ops::Range;
 
-use byteorder::{BigEndian, ReadBytesExt};
-
-use super::generic_indexed::GenericIndexedV1;
+use super::block_layout::{
+    BlockLayout, ValueBlocks, block_out_of_range, block_value_count, block_value_range,
+    check_uncompressed_block,
+};
+use super::block_writer::CompressedBlockWriter;
+use super::long_encoding::LongEncoding;
+use super::read_len;
 use crate::compression::{CompressionStrategy, decompress_block};
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+use crate::segment::read_options::{CancellationToken, check_cancelled};
 
 /// Reader for Druid's CompressedColumnarLongs format.
 ///
@@ -13,22 +20,38 @@ use crate::error::{DruidSegmentError, Result};
 /// [version: u8 = 0x02]
 /// [total_size: i32]     -- total number of long values
 /// [size_per: i32]       -- longs per compressed block
-/// [compression: u8]     -- CompressionStrategy ID
+/// [compression: u8]     -- CompressionStrategy ID, possibly flagged
+/// [encoding header]     -- only if the compression ID is flagged
 /// [GenericIndexed<ByteBuffer>]  -- compressed blocks
 /// ```
 ///
-/// Each block in the GenericIndexed decompresses to an array of `size_per`
-/// i64 values (big-endian), except possibly the last block which may be shorter.
+/// With the `none` strategy the values follow the header as they are,
+/// with no blocks: Druid's "entire layout", read as a single block.
+/// Otherwise each block in the GenericIndexed decompresses to `size_per`
+/// values, except possibly the last block which may be shorter. Without an
+/// encoding header the values are i64s in the column's byte order;
+/// segments written with `longEncoding: auto` flag the compression ID and
+/// store delta- or table-encoded packed values instead (see
+/// [`LongEncoding`]).
 pub struct CompressedColumnarLongs<'a> {
+    version: u8,
     total_size: usize,
     size_per: usize,
     compression: CompressionStrategy,
-    blocks: GenericIndexedV1<'a>,
+    encoding: LongEncoding,
+    byte_order: ByteOrder,
+    blocks: ValueBlocks<'a>,
+    cancellation: Option<CancellationToken>,
 }
 
 impl<'a> CompressedColumnarLongs<'a> {
-    /// Parse from raw bytes.
+    /// Parse from raw bytes, assuming big-endian values.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
+        Self::from_bytes_with_order(data, ByteOrder::BigEndian)
+    }
+
+    /// Parse from raw bytes whose decompressed values use `byte_order`.
+    pub fn from_bytes_with_order(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
         if data.len() < 10 {
             return Err(DruidSegmentError::InvalidData(
                 "CompressedColumnarLongs: data too short".into(),
@@ -37,13 +60,13 @@ impl<'a> CompressedColumnarLongs<'a> {
 
         let version = data[0];
         let mut cursor = Cursor::new(&data[1..]);
-        let total_size = cursor.read_i32::<BigEndian>()? as usize;
-        let size_per = cursor.read_i32::<BigEndian>()? as usize;
+        let total_size = read_len(&mut cursor, "CompressedColumnarLongs: total size")?;
+        let size_per = read_len(&mut cursor, "CompressedColumnarLongs: values per block")?;
 
-        let (compression, blocks_offset) = match version {
+        let (compression, encoding, blocks_offset) = match version {
             0x01 => {
                 // V1: LZF compression implied
-                (CompressionStrategy::Lzf, 9)
+                (CompressionStrategy::Lzf, LongEncoding::Longs, 9)
             }
             0x02 => {
                 // V2: explicit compression byte
@@ -52,8 +75,14 @@ impl<'a> CompressedColumnarLongs<'a> {
                         "CompressedColumnarLongs v2: data too short for compression byte".into(),
                     ));
                 }
-                let compression = CompressionStrategy::from_id(data[9])?;
-                (compression, 10)
+                let (compression_id, has_encoding) = LongEncoding::split_compression_id(data[9]);
+                let compression = CompressionStrategy::from_id(compression_id)?;
+                if has_encoding {
+                    let (encoding, len) = LongEncoding::parse(&data[10..])?;
+                    (compression, encoding, 10 + len)
+                } else {
+                    (compression, LongEncoding::Longs, 10)
+                }
             }
             other => {
                 return Err(DruidSegmentError::InvalidData(format!(
@@ -63,16 +92,36 @@ impl<'a> CompressedColumnarLongs<'a> {
             }
         };
 
-        let blocks = GenericIndexedV1::from_bytes(&data[blocks_offset..])?;
+        let values = &data[blocks_offset..];
+        // Packed values take at least a bit each, which bounds a corrupt count
+        let entire_size = encoding.block_size_bound(total_size.min(values.len() * 8));
+        let blocks = ValueBlocks::parse(compression, values, entire_size)?;
+        let size_per = blocks.size_per(size_per, total_size);
 
         Ok(Self {
+            version,
             total_size,
             size_per,
             compression,
+            encoding,
+            byte_order,
             blocks,
+            cancellation: None,
         })
     }
 
+    /// Check `token` before decompressing each block, returning
+    /// `Err(Cancelled)` once it has been cancelled.
+    pub fn with_cancellation(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// How values are encoded within each block.
+    pub fn encoding(&self) -> &LongEncoding {
+        &self.encoding
+    }
+
     /// Total number of long values.
     pub fn len(&self) -> usize {
         self.total_size
@@ -83,12 +132,75 @@ impl<'a> CompressedColumnarLongs<'a> {
         self.total_size == 0
     }
 
+    /// Number of compressed blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Compressed size in bytes of block `i`, from the GenericIndexed
+    /// offsets.
+    pub fn block_compressed_size(&self, i: usize) -> Result<usize> {
+        self.blocks.compressed_size(i)
+    }
+
+    /// Number of values stored in block `i`.
+    pub fn block_value_count(&self, i: usize) -> Option<usize> {
+        block_value_count(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// Index of the first value of block `i` and the number of values it
+    /// holds, or `None` past the last block.
+    pub fn block_value_range(&self, i: usize) -> Option<(usize, usize)> {
+        block_value_range(self.total_size, self.size_per, self.blocks.len(), i)
+    }
+
+    /// The header fields and block sizes, read without decompressing any
+    /// block.
+    pub fn layout(&self) -> Result<BlockLayout> {
+        self.blocks.layout(
+            self.version,
+            self.total_size,
+            self.size_per,
+            self.compression,
+        )
+    }
+
     /// Decompress all values into a Vec<i64>.
     pub fn decompress_all(&self) -> Result<Vec<i64>> {
-        let mut result = Vec::with_capacity(self.total_size);
-        let num_blocks = self.blocks.len();
+        self.decompress_prefix(self.total_size)
+    }
+
+    /// Decompress only the first `max_values` values, stopping after the
+    /// block that contains the last one.
+    pub fn decompress_prefix(&self, max_values: usize) -> Result<Vec<i64>> {
+        self.decompress_range(0..max_values)
+    }
+
+    /// Decompress block `i` alone, e.g. to decode blocks in parallel.
+    pub fn decompress_block(&self, i: usize) -> Result<Vec<i64>> {
+        let (start, len) = self
+            .block_value_range(i)
+            .ok_or_else(|| block_out_of_range("CompressedColumnarLongs", i, self.block_count()))?;
+        self.decompress_range(start..start + len)
+    }
+
+    /// Decompress the values in `range`, skipping the blocks before it and
+    /// stopping after the block that contains its last value.
+    pub fn decompress_range(&self, range: Range<usize>) -> Result<Vec<i64>> {
+        let end = range.end.min(self.total_size);
+        let start = range.start.min(end);
+        let mut result = Vec::with_capacity(end - start);
+        if start == end {
+            return Ok(result);
+        }
+        if self.size_per == 0 {
+            return Err(DruidSegmentError::InvalidData(
+                "CompressedColumnarLongs: zero values per block".into(),
+            ));
+        }
 
-        for block_idx in 0..num_blocks {
+        for block_idx in start / self.size_per..end.div_ceil(self.size_per) {
+            check_cancelled(self.cancellation.as_ref())?;
             let block_data = self.blocks.get(block_idx)?.ok_or_else(|| {
                 DruidSegmentError::InvalidData(format!(
                     "CompressedColumnarLongs: null block at index {}",
@@ -96,21 +208,375 @@ impl<'a> CompressedColumnarLongs<'a> {
                 ))
             })?;
 
-            // Determine expected decompressed size for this block
-            let remaining = self.total_size - result.len();
-            let values_in_block = remaining.min(self.size_per);
-            let decompressed_size = values_in_block * 8; // 8 bytes per i64
+            let block_start = block_idx * self.size_per;
+            let values_in_block = (self.total_size - block_start).min(self.size_per);
+            // Values of this block that fall inside the range
+            let skip = start.saturating_sub(block_start);
+            let take = (end - block_start).min(values_in_block);
+            let decompressed_size = self.encoding.block_size_bound(values_in_block);
+            // Packed encodings pad their blocks, so only plain longs have
+            // an exact size to check
+            if self.encoding == LongEncoding::Longs {
+                check_uncompressed_block(
+                    "CompressedColumnarLongs",
+                    self.compression,
+                    block_idx,
+                    block_data,
+                    decompressed_size,
+                )?;
+            }
 
             let decompressed = decompress_block(self.compression, block_data, decompressed_size)?;
+            let mut block = Vec::with_capacity(take);
+            self.encoding
+                .decode_block(&decompressed, take, self.byte_order, &mut block)?;
+            result.extend_from_slice(&block[skip..]);
+        }
+
+        Ok(result)
+    }
+}
+
+/// Longs per block in the columns Druid writes: one 64 KiB buffer.
+pub const DEFAULT_LONGS_PER_BLOCK: usize = 0x10000 / 8;
+
+/// Writer for version 0x02 CompressedColumnarLongs with plain (unencoded)
+/// values, the layout [`CompressedColumnarLongs`] reads.
+pub type CompressedColumnarLongsWriter = CompressedBlockWriter<i64>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::generic_indexed::GenericIndexedWriter;
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    /// Build a v2 CompressedColumnarLongs with a flagged LZ4 compression
+    /// byte, the given encoding header, and LZ4 blocks of raw bytes.
+    fn build_encoded_longs(
+        total: usize,
+        size_per: usize,
+        encoding_header: &[u8],
+        blocks: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut buf = vec![0x02];
+        buf.write_i32::<BigEndian>(total as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push((0x01i8 - 126) as u8); // LZ4 with the encoding flag
+        buf.extend_from_slice(encoding_header);
+
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for block in blocks {
+            body.write_i32::<BigEndian>(0).unwrap();
+            body.extend_from_slice(&lz4_flex::block::compress(block));
+            offsets.push(body.len() as i32);
+        }
+        buf.extend_from_slice(&[0x01, 0x00]);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(blocks.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    fn delta_header(base: i64, bits: i32) -> Vec<u8> {
+        let mut header = vec![0x00, 0x01];
+        header.write_i64::<BigEndian>(base).unwrap();
+        header.write_i32::<BigEndian>(bits).unwrap();
+        header
+    }
 
-            // Read big-endian i64 values from decompressed bytes
-            let mut cursor = Cursor::new(&decompressed);
-            for _ in 0..values_in_block {
-                let value = cursor.read_i64::<BigEndian>()?;
-                result.push(value);
+    #[test]
+    fn test_delta_16_bits() {
+        let base = 1_442_018_818_771i64;
+        let offsets = [0u16, 5, 65_535, 300, 1];
+        let blocks: Vec<Vec<u8>> = offsets
+            .chunks(2)
+            .map(|chunk| chunk.iter().flat_map(|o| o.to_be_bytes()).collect())
+            .collect();
+        let data = build_encoded_longs(5, 2, &delta_header(base, 16), &blocks);
+
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(longs.encoding(), &LongEncoding::Delta { base, bits: 16 });
+        let expected: Vec<i64> = offsets.iter().map(|&o| base + o as i64).collect();
+        assert_eq!(longs.decompress_all().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_delta_12_bits() {
+        // Values 0xABC, 0x123, 0xFFF packed as 0xAB 0xC1 0x23 0xFF 0xF0
+        let block = vec![0xAB, 0xC1, 0x23, 0xFF, 0xF0];
+        let data = build_encoded_longs(3, 3, &delta_header(-10, 12), &[block]);
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(
+            longs.decompress_all().unwrap(),
+            vec![0xABC - 10, 0x123 - 10, 0xFFF - 10]
+        );
+    }
+
+    #[test]
+    fn test_table_encoding() {
+        let table = [100i64, -7, i64::MAX];
+        let mut header = vec![0x01, 0x01];
+        header.write_i32::<BigEndian>(table.len() as i32).unwrap();
+        for v in table {
+            header.write_i64::<BigEndian>(v).unwrap();
+        }
+        // 2 bits per index: [2, 0, 1, 1] = 0b10_00_01_01, then [0] = 0b00_000000
+        let blocks = vec![vec![0b1000_0101], vec![0b0000_0000]];
+        let data = build_encoded_longs(5, 4, &header, &blocks);
+
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(
+            longs.decompress_all().unwrap(),
+            vec![i64::MAX, 100, -7, -7, 100]
+        );
+    }
+
+    #[test]
+    fn test_flagged_longs_encoding() {
+        let values = [3i64, -4, 5];
+        let block: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let data = build_encoded_longs(3, 8, &[0xFF], &[block]);
+        let longs =
+            CompressedColumnarLongs::from_bytes_with_order(&data, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(longs.encoding(), &LongEncoding::Longs);
+        assert_eq!(longs.decompress_all().unwrap(), values);
+    }
+
+    /// Build a v2 CompressedColumnarLongs whose blocks are stored as-is
+    /// under compression id `compression`.
+    fn build_uncompressed_longs(compression: u8, blocks: &[&[i64]], size_per: usize) -> Vec<u8> {
+        let total: usize = blocks.iter().map(|b| b.len()).sum();
+        let mut buf = vec![0x02];
+        buf.write_i32::<BigEndian>(total as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push(compression);
+
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for block in blocks {
+            body.write_i32::<BigEndian>(0).unwrap();
+            for &v in *block {
+                body.write_i64::<byteorder::LittleEndian>(v).unwrap();
             }
+            offsets.push(body.len() as i32);
+        }
+        buf.extend_from_slice(&[0x01, 0x00]);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(blocks.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
         }
+        buf.extend_from_slice(&body);
+        buf
+    }
 
-        Ok(result)
+    #[test]
+    fn test_uncompressed_blocks() {
+        let data = build_uncompressed_longs(0xFF, &[&[1, -2, 3], &[4, 5]], 3);
+        let longs =
+            CompressedColumnarLongs::from_bytes_with_order(&data, ByteOrder::LittleEndian).unwrap();
+        assert!(longs.layout().unwrap().compression.is_uncompressed());
+        assert_eq!(longs.decompress_all().unwrap(), vec![1, -2, 3, 4, 5]);
+        assert_eq!(longs.decompress_range(2..4).unwrap(), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_entire_layout() {
+        // The `none` strategy: a size_per of 0 and raw values, no blocks
+        let values = [7i64, -1, 1_442_016_000_000];
+        let mut data = vec![0x02];
+        data.write_i32::<BigEndian>(values.len() as i32).unwrap();
+        data.write_i32::<BigEndian>(0).unwrap();
+        data.push(0xFE);
+        for v in values {
+            data.write_i64::<BigEndian>(v).unwrap();
+        }
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(longs.block_count(), 1);
+        assert_eq!(
+            longs.layout().unwrap().compression,
+            CompressionStrategy::None
+        );
+        assert_eq!(longs.decompress_all().unwrap(), values);
+        assert_eq!(longs.decompress_range(1..2).unwrap(), vec![-1]);
+
+        // With a flagged compression byte, the values are packed
+        let mut data = vec![0x02];
+        data.write_i32::<BigEndian>(3).unwrap();
+        data.write_i32::<BigEndian>(0).unwrap();
+        data.push((0xFEu8 as i8 - 126) as u8);
+        data.extend(delta_header(100, 8));
+        data.extend([0, 5, 255]);
+        data.extend([0; 8]);
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(longs.decompress_all().unwrap(), vec![100, 105, 355]);
+    }
+
+    #[test]
+    fn test_uncompressed_block_size_mismatch() {
+        // The first block should hold 3 values but holds 2
+        let data = build_uncompressed_longs(0xFF, &[&[1, 2], &[3]], 3);
+        let longs =
+            CompressedColumnarLongs::from_bytes_with_order(&data, ByteOrder::LittleEndian).unwrap();
+        let err = longs.decompress_all().unwrap_err();
+        assert!(err.to_string().contains("uncompressed block 0"), "{}", err);
+    }
+
+    /// Frame `data` as one LZF chunk stored without compression.
+    fn lzf_stored(data: &[u8]) -> Vec<u8> {
+        let mut chunk = b"ZV\x00".to_vec();
+        chunk.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    #[test]
+    fn test_v1_lzf_blocks() {
+        let first: Vec<u8> = [1i64, -2, 1_442_016_000_000]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        // [7, 7]: an 8-byte literal, then a back-reference of 8 at distance 8
+        let mut lzf = vec![0x07];
+        lzf.extend_from_slice(&7i64.to_be_bytes());
+        lzf.extend_from_slice(&[0xC0, 0x07]);
+        let mut second = b"ZV\x01".to_vec();
+        second.extend_from_slice(&(lzf.len() as u16).to_be_bytes());
+        second.extend_from_slice(&16u16.to_be_bytes());
+        second.extend_from_slice(&lzf);
+
+        // V1 has no compression byte: the blocks follow size_per
+        let mut data = vec![0x01];
+        data.write_i32::<BigEndian>(5).unwrap();
+        data.write_i32::<BigEndian>(3).unwrap();
+        data.extend(
+            GenericIndexedWriter::write([Some(&lzf_stored(&first)[..]), Some(&second[..])], false)
+                .unwrap(),
+        );
+
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        let layout = longs.layout().unwrap();
+        assert_eq!(layout.version, 1);
+        assert_eq!(layout.compression, CompressionStrategy::Lzf);
+        assert_eq!(longs.block_count(), 2);
+        assert_eq!(
+            longs.decompress_all().unwrap(),
+            vec![1, -2, 1_442_016_000_000, 7, 7]
+        );
+        assert_eq!(
+            longs.decompress_range(2..4).unwrap(),
+            vec![1_442_016_000_000, 7]
+        );
+    }
+
+    #[test]
+    fn test_writer_round_trips_blocks() {
+        // Three full blocks and a partial one
+        let values: Vec<i64> = (0..3 * 1000 + 123)
+            .map(|i: i64| i.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64) >> (i % 60))
+            .collect();
+        let data = CompressedColumnarLongsWriter::new()
+            .with_size_per(1000)
+            .write(&values)
+            .unwrap();
+
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(longs.len(), values.len());
+        assert_eq!(longs.block_count(), 4);
+        assert_eq!(longs.block_value_count(3), Some(123));
+        assert_eq!(
+            longs.layout().unwrap().compression,
+            CompressionStrategy::Lz4
+        );
+        assert_eq!(longs.decompress_all().unwrap(), values);
+        assert_eq!(
+            longs.decompress_range(999..2001).unwrap(),
+            values[999..2001]
+        );
+    }
+
+    #[test]
+    fn test_blocks_decode_to_all() {
+        let values: Vec<i64> = (0..2500).map(|i| i * i - 1000).collect();
+        let data = CompressedColumnarLongsWriter::new()
+            .with_size_per(1000)
+            .write(&values)
+            .unwrap();
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+
+        let mut joined = Vec::new();
+        for i in 0..longs.block_count() {
+            let (start, len) = longs.block_value_range(i).unwrap();
+            assert_eq!(start, joined.len());
+            let block = longs.decompress_block(i).unwrap();
+            assert_eq!(block.len(), len);
+            joined.extend(block);
+        }
+        assert_eq!(joined, longs.decompress_all().unwrap());
+        assert_eq!(longs.block_value_range(2), Some((2000, 500)));
+        assert_eq!(longs.block_value_range(3), None);
+        assert!(longs.decompress_block(3).is_err());
+    }
+
+    #[test]
+    fn test_negative_header_sizes() {
+        let data = CompressedColumnarLongsWriter::new()
+            .write(&[1, 2, 3])
+            .unwrap();
+        for (at, field) in [(1, "total size"), (5, "values per block")] {
+            let mut bad = data.clone();
+            bad[at..at + 4].copy_from_slice(&i32::MIN.to_be_bytes());
+            let err = CompressedColumnarLongs::from_bytes(&bad).err().unwrap();
+            assert!(
+                err.to_string()
+                    .contains(&format!("{} is negative: {}", field, i32::MIN)),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_writer_options() {
+        let values: Vec<i64> = (0..DEFAULT_LONGS_PER_BLOCK as i64 + 1).collect();
+        let data = CompressedColumnarLongsWriter::new().write(&values).unwrap();
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(longs.block_count(), 2);
+        assert_eq!(longs.decompress_all().unwrap(), values);
+
+        let data = CompressedColumnarLongsWriter::new()
+            .with_size_per(2)
+            .with_compression(CompressionStrategy::Uncompressed)
+            .with_byte_order(ByteOrder::LittleEndian)
+            .write(&[1, -2, 3])
+            .unwrap();
+        let longs =
+            CompressedColumnarLongs::from_bytes_with_order(&data, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(longs.block_compressed_size(0).unwrap(), 16);
+        assert_eq!(longs.decompress_all().unwrap(), vec![1, -2, 3]);
+
+        let empty = CompressedColumnarLongsWriter::new().write(&[]).unwrap();
+        let longs = CompressedColumnarLongs::from_bytes(&empty).unwrap();
+        assert!(longs.is_empty());
+        assert!(longs.decompress_all().unwrap().is_empty());
+
+        let data = CompressedColumnarLongsWriter::new()
+            .with_compression(CompressionStrategy::None)
+            .write(&[1, -2, 3])
+            .unwrap();
+        assert_eq!(data.len(), 10 + 24);
+        let longs = CompressedColumnarLongs::from_bytes(&data).unwrap();
+        assert_eq!(longs.layout().unwrap().size_per, 3);
+        assert_eq!(longs.decompress_all().unwrap(), vec![1, -2, 3]);
+
+        let zero = CompressedColumnarLongsWriter::new().with_size_per(0);
+        assert!(zero.write(&[1]).is_err());
+        let lzf = CompressedColumnarLongsWriter::new().with_compression(CompressionStrategy::Lzf);
+        assert!(lzf.write(&[1]).is_err());
     }
 }
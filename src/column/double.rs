@@ -1,14 +1,20 @@
 use arrow::array::Float64Array;
 
+use super::NumericPart;
+use super::bitmap::{null_range, to_null_buffer};
 use super::compressed_doubles::CompressedColumnarDoubles;
 use crate::error::Result;
+use crate::segment::read_options::ReadOptions;
 
-/// Read a double (Float64) column from its binary data (after the JSON header).
+/// Read a double (Float64) column from its numeric part.
 ///
 /// Double columns are stored as CompressedColumnarDoubles, optionally with
 /// a null bitmap.
-pub fn read_double_column(data: &[u8]) -> Result<Float64Array> {
-    let doubles = CompressedColumnarDoubles::from_bytes(data)?;
-    let values = doubles.decompress_all()?;
-    Ok(Float64Array::from(values))
+pub fn read_double_column(part: &NumericPart<'_>, options: &ReadOptions) -> Result<Float64Array> {
+    let doubles = CompressedColumnarDoubles::from_bytes_with_order(part.values, part.byte_order)?
+        .with_cancellation(options.cancellation.clone());
+    let range = options.row_range(doubles.len());
+    let values = doubles.decompress_range(range.clone())?;
+    let nulls = null_range(to_null_buffer(&part.nulls, doubles.len())?, range);
+    Ok(Float64Array::new(values.into(), nulls))
 }
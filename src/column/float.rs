@@ -1,14 +1,20 @@
 use arrow::array::Float32Array;
 
+use super::NumericPart;
+use super::bitmap::{null_range, to_null_buffer};
 use super::compressed_doubles::CompressedColumnarFloats;
 use crate::error::Result;
+use crate::segment::read_options::ReadOptions;
 
-/// Read a float (Float32) column from its binary data (after the JSON header).
+/// Read a float (Float32) column from its numeric part.
 ///
 /// Float columns are stored as CompressedColumnarFloats, optionally with
 /// a null bitmap.
-pub fn read_float_column(data: &[u8]) -> Result<Float32Array> {
-    let floats = CompressedColumnarFloats::from_bytes(data)?;
-    let values = floats.decompress_all()?;
-    Ok(Float32Array::from(values))
+pub fn read_float_column(part: &NumericPart<'_>, options: &ReadOptions) -> Result<Float32Array> {
+    let floats = CompressedColumnarFloats::from_bytes_with_order(part.values, part.byte_order)?
+        .with_cancellation(options.cancellation.clone());
+    let range = options.row_range(floats.len());
+    let values = floats.decompress_range(range.clone())?;
+    let nulls = null_range(to_null_buffer(&part.nulls, floats.len())?, range);
+    Ok(Float32Array::new(values.into(), nulls))
 }
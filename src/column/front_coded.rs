@@ -0,0 +1,341 @@
+use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+
+/// Reader for Druid's FrontCodedIndexed: a sorted string dictionary split
+/// into buckets of prefix-compressed values.
+///
+/// Layout:
+/// ```text
+/// [version: u8]             -- 0x00 = prefixes relative to the bucket's first value,
+///                              0x01 = prefixes relative to the previous value
+/// [bucket_size: u8]         -- values per bucket, a power of two
+/// [has_null: u8]            -- 0x01 if the dictionary contains null (id 0)
+/// [num_values: VByte]       -- number of non-null values
+/// [size: VByte]             -- bytes of offsets + buckets
+/// [offsets: i32 * (num_buckets - 1)]  -- start of buckets 1.. relative to bucket 0,
+///                                        in the column's byte order
+/// [buckets: ...]
+/// ```
+///
+/// Each bucket stores its first value whole as `[len: VByte][bytes]`, then
+/// every following value as `[prefix_len: VByte][suffix_len: VByte][suffix]`.
+/// VByte integers are little-endian groups of 7 bits whose final byte has
+/// the high bit set.
+#[derive(Debug)]
+pub struct FrontCodedIndexed<'a> {
+    version: u8,
+    bucket_size: usize,
+    has_null: bool,
+    num_values: usize,
+    byte_order: ByteOrder,
+    offsets: &'a [u8],
+    buckets: &'a [u8],
+    total_size: usize,
+}
+
+const VERSION_V0: u8 = 0x00;
+const VERSION_V1: u8 = 0x01;
+
+impl<'a> FrontCodedIndexed<'a> {
+    /// Parse a FrontCodedIndexed whose bucket offsets use `byte_order`.
+    pub fn from_bytes(data: &'a [u8], byte_order: ByteOrder) -> Result<Self> {
+        if data.len() < 3 {
+            return Err(DruidSegmentError::InvalidData(
+                "FrontCodedIndexed: data too short for header".into(),
+            ));
+        }
+
+        let version = data[0];
+        if version != VERSION_V0 && version != VERSION_V1 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "FrontCodedIndexed: unsupported version {:#x}",
+                version
+            )));
+        }
+
+        let bucket_size = data[1] as usize;
+        if !bucket_size.is_power_of_two() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "FrontCodedIndexed: bucket size {} is not a power of two",
+                bucket_size
+            )));
+        }
+        let has_null = data[2] == 0x01;
+
+        let mut pos = 3;
+        let num_values = read_vbyte(data, &mut pos)? as usize;
+        let size = read_vbyte(data, &mut pos)? as usize;
+        let end = pos + size;
+        if end > data.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "FrontCodedIndexed: {} bytes of buckets but only {} available",
+                size,
+                data.len() - pos
+            )));
+        }
+
+        let num_buckets = num_values.div_ceil(bucket_size);
+        let offsets_len = num_buckets.saturating_sub(1) * 4;
+        if offsets_len > size {
+            return Err(DruidSegmentError::InvalidData(
+                "FrontCodedIndexed: offsets overrun the buckets section".into(),
+            ));
+        }
+
+        Ok(Self {
+            version,
+            bucket_size,
+            has_null,
+            num_values,
+            byte_order,
+            offsets: &data[pos..pos + offsets_len],
+            buckets: &data[pos + offsets_len..end],
+            total_size: end,
+        })
+    }
+
+    /// Number of dictionary entries, including the null entry if present.
+    pub fn len(&self) -> usize {
+        self.num_values + self.has_null as usize
+    }
+
+    /// Whether the dictionary has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total number of bytes consumed by this structure.
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    /// Get the i-th entry as a UTF-8 string. Returns `None` for the null entry.
+    pub fn get_str(&self, index: usize) -> Result<Option<String>> {
+        if index >= self.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "FrontCodedIndexed: index {} out of bounds (len={})",
+                index,
+                self.len()
+            )));
+        }
+        if self.has_null {
+            if index == 0 {
+                return Ok(None);
+            }
+            return self.get_value(index - 1).map(Some);
+        }
+        self.get_value(index).map(Some)
+    }
+
+    fn get_value(&self, index: usize) -> Result<String> {
+        let bucket = index / self.bucket_size;
+        let position = index % self.bucket_size;
+        let mut pos = self.bucket_start(bucket)?;
+        let data = self.buckets;
+
+        let first_len = read_vbyte(data, &mut pos)? as usize;
+        let mut value = take(data, &mut pos, first_len)?.to_vec();
+        let first = value.clone();
+
+        for _ in 0..position {
+            let prefix_len = read_vbyte(data, &mut pos)? as usize;
+            let suffix_len = read_vbyte(data, &mut pos)? as usize;
+            let suffix = take(data, &mut pos, suffix_len)?;
+            let base = if self.version == VERSION_V0 {
+                &first
+            } else {
+                &value
+            };
+            if prefix_len > base.len() {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "FrontCodedIndexed: prefix length {} exceeds value length {}",
+                    prefix_len,
+                    base.len()
+                )));
+            }
+            let mut next = Vec::with_capacity(prefix_len + suffix_len);
+            next.extend_from_slice(&base[..prefix_len]);
+            next.extend_from_slice(suffix);
+            value = next;
+        }
+
+        String::from_utf8(value).map_err(|e| {
+            DruidSegmentError::InvalidData(format!(
+                "FrontCodedIndexed: value {} is not valid UTF-8: {}",
+                index, e
+            ))
+        })
+    }
+
+    fn bucket_start(&self, bucket: usize) -> Result<usize> {
+        if bucket == 0 {
+            return Ok(0);
+        }
+        let bytes: [u8; 4] = self.offsets[(bucket - 1) * 4..bucket * 4]
+            .try_into()
+            .expect("offset slice is 4 bytes");
+        let offset = match self.byte_order {
+            ByteOrder::BigEndian => i32::from_be_bytes(bytes),
+            ByteOrder::LittleEndian => i32::from_le_bytes(bytes),
+        };
+        if offset < 0 || offset as usize > self.buckets.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "FrontCodedIndexed: bucket {} offset {} out of range",
+                bucket, offset
+            )));
+        }
+        Ok(offset as usize)
+    }
+}
+
+/// Read a Druid VByte-encoded integer, advancing `pos`.
+fn read_vbyte(data: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = *data.get(*pos).ok_or_else(|| {
+            DruidSegmentError::InvalidData("FrontCodedIndexed: truncated VByte integer".into())
+        })?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+    }
+    Err(DruidSegmentError::InvalidData(
+        "FrontCodedIndexed: VByte integer longer than 5 bytes".into(),
+    ))
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let bytes = data.get(*pos..*pos + len).ok_or_else(|| {
+        DruidSegmentError::InvalidData("FrontCodedIndexed: value extends past bucket data".into())
+    })?;
+    *pos += len;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_vbyte(buf: &mut Vec<u8>, mut value: u32) {
+        while value >= 0x80 {
+            buf.push((value & 0x7F) as u8);
+            value >>= 7;
+        }
+        buf.push(value as u8 | 0x80);
+    }
+
+    fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+        a.iter().zip(b).take_while(|(x, y)| x == y).count()
+    }
+
+    /// Build a FrontCodedIndexed with little-endian offsets from sorted values.
+    fn build_front_coded(values: &[&str], bucket_size: u8, version: u8, has_null: bool) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut buckets = Vec::new();
+        for (i, bucket) in values.chunks(bucket_size as usize).enumerate() {
+            if i > 0 {
+                offsets.extend_from_slice(&(buckets.len() as i32).to_le_bytes());
+            }
+            let first = bucket[0].as_bytes();
+            write_vbyte(&mut buckets, first.len() as u32);
+            buckets.extend_from_slice(first);
+            let mut prev = first;
+            for value in &bucket[1..] {
+                let value = value.as_bytes();
+                let base = if version == VERSION_V0 { first } else { prev };
+                let prefix = common_prefix(base, value);
+                write_vbyte(&mut buckets, prefix as u32);
+                write_vbyte(&mut buckets, (value.len() - prefix) as u32);
+                buckets.extend_from_slice(&value[prefix..]);
+                prev = value;
+            }
+        }
+
+        let mut buf = vec![version, bucket_size, has_null as u8];
+        write_vbyte(&mut buf, values.len() as u32);
+        write_vbyte(&mut buf, (offsets.len() + buckets.len()) as u32);
+        buf.extend_from_slice(&offsets);
+        buf.extend_from_slice(&buckets);
+        buf
+    }
+
+    fn sample_values(n: usize) -> Vec<String> {
+        let mut values: Vec<String> = (0..n).map(|i| format!("page/{:03}", i * 7)).collect();
+        values.push("pagination".into());
+        values.push("zebra".into());
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn test_bucket_sizes() {
+        let values = sample_values(40);
+        let refs: Vec<&str> = values.iter().map(String::as_str).collect();
+        for bucket_size in [4u8, 16] {
+            for version in [VERSION_V0, VERSION_V1] {
+                let mut data = build_front_coded(&refs, bucket_size, version, false);
+                data.extend_from_slice(b"trailing");
+                let dict = FrontCodedIndexed::from_bytes(&data, ByteOrder::LittleEndian).unwrap();
+                assert_eq!(dict.len(), values.len());
+                assert_eq!(dict.total_size(), data.len() - b"trailing".len());
+                for (i, expected) in values.iter().enumerate() {
+                    assert_eq!(
+                        dict.get_str(i).unwrap().as_deref(),
+                        Some(expected.as_str()),
+                        "bucket size {}, version {}, index {}",
+                        bucket_size,
+                        version,
+                        i
+                    );
+                }
+                assert!(dict.get_str(values.len()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_prefixes() {
+        // "applet" shares "apple" with the bucket's first value, and
+        // "application" only "appl"
+        let values = ["apple", "applet", "application", "apply"];
+        for version in [VERSION_V0, VERSION_V1] {
+            let data = build_front_coded(&values, 4, version, false);
+            let dict = FrontCodedIndexed::from_bytes(&data, ByteOrder::LittleEndian).unwrap();
+            let decoded: Vec<_> = (0..dict.len())
+                .map(|i| dict.get_str(i).unwrap().unwrap())
+                .collect();
+            assert_eq!(decoded, values, "version {}", version);
+        }
+    }
+
+    #[test]
+    fn test_null_entry() {
+        let data = build_front_coded(&["a", "ab", "b"], 4, VERSION_V1, true);
+        let dict = FrontCodedIndexed::from_bytes(&data, ByteOrder::LittleEndian).unwrap();
+        assert_eq!(dict.len(), 4);
+        assert_eq!(dict.get_str(0).unwrap(), None);
+        assert_eq!(dict.get_str(2).unwrap().as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn test_empty_dictionary() {
+        let data = build_front_coded(&[], 4, VERSION_V0, false);
+        let dict = FrontCodedIndexed::from_bytes(&data, ByteOrder::LittleEndian).unwrap();
+        assert!(dict.is_empty());
+        assert_eq!(dict.total_size(), data.len());
+        assert!(dict.get_str(0).is_err());
+    }
+
+    #[test]
+    fn test_vbyte() {
+        for value in [0u32, 1, 127, 128, 16_383, 16_384, u32::MAX] {
+            let mut buf = Vec::new();
+            write_vbyte(&mut buf, value);
+            let mut pos = 0;
+            assert_eq!(read_vbyte(&buf, &mut pos).unwrap(), value);
+            assert_eq!(pos, buf.len());
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use std::cmp::Ordering;
 use std::io::Cursor;
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 
+use super::read_len;
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::smoosh::SmooshReader;
 
 /// Reader for Druid's GenericIndexed<T> binary format (V1).
 ///
@@ -13,18 +16,19 @@ use crate::error::{DruidSegmentError, Result};
 /// ```text
 /// [version: u8 = 0x01]
 /// [flags: u8]           -- 0x01 = sorted/reverse-lookup, 0x00 = unsorted
-/// [total_bytes: i32]    -- total size of offsets + values (excluding version/flags/total_bytes/num_elements)
+/// [total_bytes: i32]    -- size of num_elements + offsets + values
 /// [num_elements: i32]
 /// [offsets: i32 * N]    -- cumulative end-offset of each element's data (relative to values_start)
 /// [values: ...]         -- concatenated elements
 /// ```
 ///
-/// Element format varies by strategy:
-/// - For length-prefixed format: `[length: i32][bytes]` where length=-1 means null
-/// - For null-prefixed strings (ObjectStrategy): `[4 zero bytes][string bytes]`
+/// Each element is written as `[null_marker: i32][bytes]`: the marker is -1
+/// for a null element and 0 otherwise, and the element's size is given by
+/// the offset table rather than the marker.
 #[derive(Debug)]
 pub struct GenericIndexedV1<'a> {
     data: &'a [u8],
+    sorted: bool,
     num_elements: usize,
     header_size: usize,
     values_start: usize,
@@ -34,6 +38,12 @@ const VERSION_V1: u8 = 0x01;
 
 impl<'a> GenericIndexedV1<'a> {
     /// Parse a GenericIndexed V1 from raw bytes.
+    ///
+    /// `total_bytes` is checked to cover the offset table and to fit in
+    /// `data`, so a container cut short fails here rather than on reading
+    /// an element. The offsets themselves are only checked as elements are
+    /// read; [`from_bytes_strict`](Self::from_bytes_strict) checks them up
+    /// front.
     pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
         if data.is_empty() {
             return Err(DruidSegmentError::InvalidData(
@@ -52,12 +62,18 @@ impl<'a> GenericIndexedV1<'a> {
             ));
         }
 
-        // flags at offset 1 (we don't need it for reading)
-        let _flags = data[1];
+        let sorted = data[1] & FLAG_SORTED != 0;
 
         let mut cursor = Cursor::new(&data[2..]);
-        let _total_bytes = cursor.read_i32::<BigEndian>()? as usize;
-        let num_elements = cursor.read_i32::<BigEndian>()? as usize;
+        let total_bytes = cursor.read_i32::<BigEndian>()?;
+        let num_elements = cursor.read_i32::<BigEndian>()?;
+        if total_bytes < 0 || num_elements < 0 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V1: negative total_bytes {} or num_elements {}",
+                total_bytes, num_elements
+            )));
+        }
+        let (total_bytes, num_elements) = (total_bytes as usize, num_elements as usize);
 
         // Header: version(1) + flags(1) + total_bytes(4) + num_elements(4) = 10 bytes
         let header_size = 10;
@@ -65,14 +81,60 @@ impl<'a> GenericIndexedV1<'a> {
         let offsets_size = num_elements * 4;
         let values_start = header_size + offsets_size;
 
+        // total_bytes counts num_elements, the offsets and the values
+        if total_bytes < 4 + offsets_size {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V1: total_bytes {} is too small for {} offsets",
+                total_bytes, num_elements
+            )));
+        }
+        if 6 + total_bytes > data.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V1: total_bytes {} overflows buffer of {} bytes",
+                total_bytes,
+                data.len()
+            )));
+        }
+
         Ok(Self {
             data,
+            sorted,
             num_elements,
             header_size,
             values_start,
         })
     }
 
+    /// Parse a GenericIndexed V1, also checking every offset.
+    ///
+    /// Offsets must not decrease, and the last one must end exactly where
+    /// `total_bytes` says the values do. This walks the whole offset table,
+    /// so it suits validating untrusted segments rather than hot paths.
+    pub fn from_bytes_strict(data: &'a [u8]) -> Result<Self> {
+        let gi = Self::from_bytes(data)?;
+        let total_bytes = Cursor::new(&data[2..]).read_i32::<BigEndian>()? as usize;
+        let values_len = total_bytes - 4 - gi.num_elements * 4;
+
+        let mut prev = 0;
+        for i in 0..gi.num_elements {
+            let offset = gi.offset_at(i)?;
+            if offset < prev {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "GenericIndexed V1: offset {} of element {} is before the previous one ({})",
+                    offset, i, prev
+                )));
+            }
+            prev = offset;
+        }
+        if prev != values_len {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V1: total_bytes {} implies {} bytes of values, but the offsets end at {}",
+                total_bytes, values_len, prev
+            )));
+        }
+        Ok(gi)
+    }
+
     /// Number of elements.
     pub fn len(&self) -> usize {
         self.num_elements
@@ -83,6 +145,40 @@ impl<'a> GenericIndexedV1<'a> {
         self.num_elements == 0
     }
 
+    /// Whether the flags mark the elements as sorted, as in dictionaries.
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    /// Find the index of the element equal to `needle`.
+    ///
+    /// Sorted containers are binary-searched, with null first and other
+    /// elements compared as unsigned bytes; unsorted ones are scanned.
+    /// String dictionaries sorted like Java strings only order differently
+    /// for characters outside the BMP.
+    pub fn index_of(&self, needle: &[u8]) -> Result<Option<usize>> {
+        if !self.sorted {
+            for i in 0..self.num_elements {
+                if self.get(i)? == Some(needle) {
+                    return Ok(Some(i));
+                }
+            }
+            return Ok(None);
+        }
+
+        let (mut lo, mut hi) = (0, self.num_elements);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            // Option orders None first, as Druid sorts null
+            match self.get(mid)?.cmp(&Some(needle)) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Ok(Some(mid)),
+            }
+        }
+        Ok(None)
+    }
+
     /// Get the cumulative end-offset for element `i` (relative to values_start).
     fn offset_at(&self, i: usize) -> Result<usize> {
         let pos = self.header_size + i * 4;
@@ -94,8 +190,13 @@ impl<'a> GenericIndexedV1<'a> {
             )));
         }
         let mut cursor = Cursor::new(&self.data[pos..]);
-        let offset = cursor.read_i32::<BigEndian>()? as usize;
-        Ok(offset)
+        let offset = cursor.read_i32::<BigEndian>()?;
+        usize::try_from(offset).map_err(|_| {
+            DruidSegmentError::InvalidData(format!(
+                "GenericIndexed: offset of element {} is negative: {}",
+                i, offset
+            ))
+        })
     }
 
     /// Get the byte range for element `i` within the values section.
@@ -113,51 +214,11 @@ impl<'a> GenericIndexedV1<'a> {
 
     /// Get the i-th element as `Option<&[u8]>`.
     ///
-    /// This handles the length-prefixed format where:
-    /// - length == -1 means null
-    /// - length >= 0 means that many bytes of data follow
+    /// The 4-byte null marker preceding each element is -1 for null;
+    /// otherwise the element's bytes run from after the marker to the
+    /// element's end offset.
     pub fn get(&self, index: usize) -> Result<Option<&'a [u8]>> {
-        let (start, end) = self.element_range(index)?;
-        let abs_start = self.values_start + start;
-        let abs_end = self.values_start + end;
-
-        if abs_end > self.data.len() {
-            return Err(DruidSegmentError::InvalidData(format!(
-                "GenericIndexed: element {} data range [{}, {}) exceeds buffer size {}",
-                index,
-                abs_start,
-                abs_end,
-                self.data.len()
-            )));
-        }
-
-        let element_data = &self.data[abs_start..abs_end];
-        if element_data.len() < 4 {
-            return Err(DruidSegmentError::InvalidData(format!(
-                "GenericIndexed: element {} too short for length prefix ({} bytes)",
-                index,
-                element_data.len()
-            )));
-        }
-
-        let mut cursor = Cursor::new(element_data);
-        let length = cursor.read_i32::<BigEndian>()?;
-
-        if length < 0 {
-            // Null value
-            Ok(None)
-        } else {
-            let length = length as usize;
-            let value_start = abs_start + 4;
-            let value_end = value_start + length;
-            if value_end > self.data.len() {
-                return Err(DruidSegmentError::InvalidData(format!(
-                    "GenericIndexed: element {} value overflows buffer",
-                    index
-                )));
-            }
-            Ok(Some(&self.data[value_start..value_end]))
-        }
+        split_null_marker(index, self.get_raw(index)?)
     }
 
     /// Get the i-th element as raw bytes, using the offset table to determine boundaries.
@@ -225,18 +286,7 @@ impl<'a> GenericIndexedV1<'a> {
 
     /// Get the i-th element as a UTF-8 string.
     pub fn get_str(&self, index: usize) -> Result<Option<&'a str>> {
-        match self.get(index)? {
-            Some(bytes) => {
-                let s = std::str::from_utf8(bytes).map_err(|e| {
-                    DruidSegmentError::InvalidData(format!(
-                        "GenericIndexed: element {} is not valid UTF-8: {}",
-                        index, e
-                    ))
-                })?;
-                Ok(Some(s))
-            }
-            None => Ok(None),
-        }
+        self.get(index)?.map(|bytes| utf8(index, bytes)).transpose()
     }
 
     /// Total number of bytes consumed by this GenericIndexed structure.
@@ -250,6 +300,350 @@ impl<'a> GenericIndexedV1<'a> {
     }
 }
 
+/// Flag byte of a GenericIndexed V1 whose elements are sorted, which lets
+/// readers binary-search it.
+const FLAG_SORTED: u8 = 0x01;
+
+/// Writer for the GenericIndexed V1 layout read by [`GenericIndexedV1`].
+///
+/// Elements are buffered as they are pushed, each as a null marker and its
+/// bytes, and [`finish`](Self::finish) prepends the header and offsets.
+/// Strings use the layout of Druid's string `ObjectStrategy`, as in
+/// dictionaries and the column name lists of `index.drd`.
+#[derive(Debug, Default)]
+pub struct GenericIndexedWriter {
+    sorted: bool,
+    offsets: Vec<i32>,
+    values: Vec<u8>,
+}
+
+impl GenericIndexedWriter {
+    /// Create a writer. `sorted` sets the flag telling readers that the
+    /// elements are in ascending order; the writer does not check it.
+    pub fn new(sorted: bool) -> Self {
+        Self {
+            sorted,
+            ..Self::default()
+        }
+    }
+
+    /// Write `values` as a GenericIndexed V1; `None` elements are null.
+    pub fn write<'b>(
+        values: impl IntoIterator<Item = Option<&'b [u8]>>,
+        sorted: bool,
+    ) -> Result<Vec<u8>> {
+        let mut writer = Self::new(sorted);
+        for value in values {
+            writer.push(value)?;
+        }
+        writer.finish()
+    }
+
+    /// Write `values` as a GenericIndexed V1 of UTF-8 strings.
+    pub fn write_strings<'b>(
+        values: impl IntoIterator<Item = Option<&'b str>>,
+        sorted: bool,
+    ) -> Result<Vec<u8>> {
+        Self::write(values.into_iter().map(|v| v.map(str::as_bytes)), sorted)
+    }
+
+    /// Append an element; `None` is written as a null.
+    pub fn push(&mut self, value: Option<&[u8]>) -> Result<()> {
+        match value {
+            Some(bytes) => {
+                self.values.extend_from_slice(&0i32.to_be_bytes());
+                self.values.extend_from_slice(bytes);
+            }
+            None => self.values.extend_from_slice(&(-1i32).to_be_bytes()),
+        }
+        let offset = i32::try_from(self.values.len()).map_err(|_| {
+            DruidSegmentError::InvalidData(
+                "GenericIndexed V1: values exceed 2 GiB, which needs V2".into(),
+            )
+        })?;
+        self.offsets.push(offset);
+        Ok(())
+    }
+
+    /// Append a string element; `None` is written as a null.
+    pub fn push_str(&mut self, value: Option<&str>) -> Result<()> {
+        self.push(value.map(str::as_bytes))
+    }
+
+    /// Number of elements pushed so far.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether no element has been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The serialized container.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let too_large = || {
+            DruidSegmentError::InvalidData(
+                "GenericIndexed V1: container exceeds 2 GiB, which needs V2".into(),
+            )
+        };
+        // Druid counts num_elements in total_bytes
+        let total_bytes = self
+            .offsets
+            .len()
+            .checked_add(1)
+            .and_then(|ints| ints.checked_mul(4))
+            .and_then(|size| size.checked_add(self.values.len()))
+            .and_then(|size| i32::try_from(size).ok())
+            .ok_or_else(too_large)?;
+        let num_elements = i32::try_from(self.offsets.len()).map_err(|_| too_large())?;
+
+        let mut buf = Vec::with_capacity(6 + total_bytes as usize);
+        buf.push(VERSION_V1);
+        buf.push(if self.sorted { FLAG_SORTED } else { 0x00 });
+        buf.extend_from_slice(&total_bytes.to_be_bytes());
+        buf.extend_from_slice(&num_elements.to_be_bytes());
+        for offset in &self.offsets {
+            buf.extend_from_slice(&offset.to_be_bytes());
+        }
+        buf.extend_from_slice(&self.values);
+        Ok(buf)
+    }
+}
+
+/// Reader for Druid's GenericIndexed V2 format, written when a column's
+/// elements would not fit in a single V1 buffer.
+///
+/// Only a small header is stored in place; the offsets and values live in
+/// separate logical files of the same smoosh archive, named after the
+/// header's file name base:
+/// ```text
+/// [version: u8 = 0x02]
+/// [flags: u8]
+/// [log2_elements_per_file: i32]
+/// [num_elements: i32]
+/// [name_len: i32][name: utf8]  -- file name base
+///
+/// <name>_header   -- [i32 * N] end offset of each element within its value file
+/// <name>_value_K  -- elements K << log2 .. (K + 1) << log2, each [null_marker: i32][bytes]
+/// ```
+#[derive(Debug)]
+pub struct GenericIndexedV2<'a> {
+    num_elements: usize,
+    log_elements_per_file: u32,
+    header: &'a [u8],
+    value_files: Vec<&'a [u8]>,
+    meta_size: usize,
+}
+
+const VERSION_V2: u8 = 0x02;
+
+impl<'a> GenericIndexedV2<'a> {
+    /// Parse a GenericIndexed V2 header from `data`, mapping its offsets
+    /// and values from `smoosh`.
+    pub fn from_bytes(data: &'a [u8], smoosh: &'a SmooshReader) -> Result<Self> {
+        if data.first() != Some(&VERSION_V2) {
+            return Err(DruidSegmentError::InvalidGenericIndexedVersion(
+                data.first().copied().unwrap_or(0),
+            ));
+        }
+        if data.len() < 14 {
+            return Err(DruidSegmentError::InvalidData(
+                "GenericIndexed V2: data too short for header".into(),
+            ));
+        }
+
+        let mut cursor = Cursor::new(&data[2..]);
+        let log_elements_per_file = cursor.read_i32::<BigEndian>()?;
+        let num_elements = read_len(&mut cursor, "GenericIndexed V2: num_elements")?;
+        let name_len = read_len(&mut cursor, "GenericIndexed V2: file name length")?;
+        if !(0..31).contains(&log_elements_per_file) {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V2: invalid log2 elements per file {}",
+                log_elements_per_file
+            )));
+        }
+        let meta_size = 14 + name_len;
+        let name = data.get(14..meta_size).ok_or_else(|| {
+            DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V2: file name of {} bytes exceeds remaining {} bytes",
+                name_len,
+                data.len() - 14
+            ))
+        })?;
+        let name = utf8(0, name)?;
+
+        let log_elements_per_file = log_elements_per_file as u32;
+        let num_files = num_elements.div_ceil(1 << log_elements_per_file);
+        let header = smoosh.map_file(&format!("{}_header", name))?;
+        if header.len() < num_elements * 4 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V2: header of {} bytes is too short for {} elements",
+                header.len(),
+                num_elements
+            )));
+        }
+        let value_files = (0..num_files)
+            .map(|i| smoosh.map_file(&format!("{}_value_{}", name, i)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            num_elements,
+            log_elements_per_file,
+            header,
+            value_files,
+            meta_size,
+        })
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.num_elements
+    }
+
+    /// Whether empty.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements == 0
+    }
+
+    /// Get the i-th element's bytes, null marker included.
+    pub fn get_raw(&self, index: usize) -> Result<&'a [u8]> {
+        if index >= self.num_elements {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "GenericIndexed: index {} out of range (len {})",
+                index, self.num_elements
+            )));
+        }
+        let offset_at = |i: usize| BigEndian::read_i32(&self.header[i * 4..i * 4 + 4]) as usize;
+        let first_in_file = index & ((1 << self.log_elements_per_file) - 1) == 0;
+        let start = if first_in_file {
+            0
+        } else {
+            offset_at(index - 1)
+        };
+        let end = offset_at(index);
+
+        let file = self.value_files[index >> self.log_elements_per_file];
+        file.get(start..end).ok_or_else(|| {
+            DruidSegmentError::InvalidData(format!(
+                "GenericIndexed V2: element {} data range [{}, {}) exceeds value file size {}",
+                index,
+                start,
+                end,
+                file.len()
+            ))
+        })
+    }
+
+    /// Get the i-th element as `Option<&[u8]>`, `None` for a null element.
+    pub fn get(&self, index: usize) -> Result<Option<&'a [u8]>> {
+        split_null_marker(index, self.get_raw(index)?)
+    }
+
+    /// Get the i-th element as a UTF-8 string.
+    pub fn get_str(&self, index: usize) -> Result<Option<&'a str>> {
+        self.get(index)?.map(|bytes| utf8(index, bytes)).transpose()
+    }
+
+    /// Number of bytes of the header stored in place, which is all a
+    /// compound format needs to skip.
+    pub fn total_size(&self) -> Result<usize> {
+        Ok(self.meta_size)
+    }
+}
+
+/// A GenericIndexed of either version.
+///
+/// V2 containers keep their elements in other logical files, so reading
+/// one needs the smoosh archive the data came from.
+#[derive(Debug)]
+pub enum GenericIndexed<'a> {
+    V1(GenericIndexedV1<'a>),
+    V2(GenericIndexedV2<'a>),
+}
+
+impl<'a> GenericIndexed<'a> {
+    /// Parse a GenericIndexed of either version from `data`. `smoosh` is
+    /// only used, and required, for V2.
+    pub fn from_bytes(data: &'a [u8], smoosh: Option<&'a SmooshReader>) -> Result<Self> {
+        match (data.first(), smoosh) {
+            (Some(&VERSION_V2), Some(smoosh)) => {
+                Ok(Self::V2(GenericIndexedV2::from_bytes(data, smoosh)?))
+            }
+            (Some(&VERSION_V2), None) => Err(DruidSegmentError::InvalidData(
+                "GenericIndexed V2: elements are stored in other smoosh files, which are not available"
+                    .into(),
+            )),
+            _ => Ok(Self::V1(GenericIndexedV1::from_bytes(data)?)),
+        }
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::V1(indexed) => indexed.len(),
+            Self::V2(indexed) => indexed.len(),
+        }
+    }
+
+    /// Whether empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the i-th element as `Option<&[u8]>`, `None` for a null element.
+    pub fn get(&self, index: usize) -> Result<Option<&'a [u8]>> {
+        match self {
+            Self::V1(indexed) => indexed.get(index),
+            Self::V2(indexed) => indexed.get(index),
+        }
+    }
+
+    /// Get the i-th element as a UTF-8 string.
+    pub fn get_str(&self, index: usize) -> Result<Option<&'a str>> {
+        match self {
+            Self::V1(indexed) => indexed.get_str(index),
+            Self::V2(indexed) => indexed.get_str(index),
+        }
+    }
+
+    /// Number of bytes the container takes in the data it was parsed from.
+    pub fn total_size(&self) -> Result<usize> {
+        match self {
+            Self::V1(indexed) => indexed.total_size(),
+            Self::V2(indexed) => indexed.total_size(),
+        }
+    }
+}
+
+/// Split an element's 4-byte null marker from its bytes. The marker is
+/// negative for a null element, which has no bytes after it.
+fn split_null_marker(index: usize, raw: &[u8]) -> Result<Option<&[u8]>> {
+    if raw.len() < 4 {
+        return Err(DruidSegmentError::InvalidData(format!(
+            "GenericIndexed: element {} too short for null marker ({} bytes)",
+            index,
+            raw.len()
+        )));
+    }
+
+    let marker = BigEndian::read_i32(raw);
+    if marker < 0 && raw.len() == 4 {
+        Ok(None)
+    } else {
+        Ok(Some(&raw[4..]))
+    }
+}
+
+fn utf8(index: usize, bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        DruidSegmentError::InvalidData(format!(
+            "GenericIndexed: element {} is not valid UTF-8: {}",
+            index, e
+        ))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,7 +663,7 @@ mod tests {
         for elem in elements {
             match elem {
                 Some(data) => {
-                    values.write_i32::<BigEndian>(data.len() as i32).unwrap();
+                    values.write_i32::<BigEndian>(0).unwrap();
                     values.extend_from_slice(data);
                 }
                 None => {
@@ -279,9 +673,9 @@ mod tests {
             offsets.push(values.len() as i32);
         }
 
-        // total_bytes = offsets_size + values_size
+        // total_bytes = num_elements + offsets + values
         let offsets_size = elements.len() * 4;
-        let total_bytes = (offsets_size + values.len()) as i32;
+        let total_bytes = (4 + offsets_size + values.len()) as i32;
         buf.write_i32::<BigEndian>(total_bytes).unwrap();
 
         // num_elements
@@ -298,6 +692,49 @@ mod tests {
         buf
     }
 
+    #[test]
+    fn test_total_bytes_checked() {
+        let data = build_generic_indexed(&[Some(b"alpha"), None, Some(b"gamma")]);
+        assert!(GenericIndexedV1::from_bytes_strict(&data).is_ok());
+        let total = i32::from_be_bytes(data[2..6].try_into().unwrap());
+
+        let with_total = |total: i32| {
+            let mut data = data.clone();
+            data[2..6].copy_from_slice(&total.to_be_bytes());
+            data
+        };
+
+        let long = with_total(total + 1);
+        let err = GenericIndexedV1::from_bytes(&long).unwrap_err();
+        assert!(err.to_string().contains("overflows buffer"), "{err}");
+        assert!(GenericIndexedV1::from_bytes_strict(&long).is_err());
+
+        assert!(GenericIndexedV1::from_bytes(&with_total(-1)).is_err());
+        // Too small to hold num_elements and three offsets
+        assert!(GenericIndexedV1::from_bytes(&with_total(4)).is_err());
+
+        // Short of the values: only the strict check notices
+        let short = with_total(total - 2);
+        assert!(GenericIndexedV1::from_bytes(&short).is_ok());
+        let err = GenericIndexedV1::from_bytes_strict(&short).unwrap_err();
+        assert!(err.to_string().contains("offsets end at"), "{err}");
+
+        // The first offset past the second
+        let mut shuffled = data.clone();
+        shuffled[10..14].copy_from_slice(&100i32.to_be_bytes());
+        let err = GenericIndexedV1::from_bytes_strict(&shuffled).unwrap_err();
+        assert!(err.to_string().contains("before the previous one"), "{err}");
+    }
+
+    #[test]
+    fn test_negative_offset() {
+        let mut data = build_generic_indexed(&[Some(b"alpha"), Some(b"beta")]);
+        data[10..14].copy_from_slice(&(-5i32).to_be_bytes());
+        let gi = GenericIndexedV1::from_bytes(&data).unwrap();
+        let err = gi.get(0).unwrap_err();
+        assert!(err.to_string().contains("is negative: -5"), "{err}");
+    }
+
     #[test]
     fn test_read_strings() {
         let data = build_generic_indexed(&[Some(b"alpha"), Some(b"beta"), Some(b"gamma")]);
@@ -334,6 +771,81 @@ mod tests {
         assert!(gi.is_empty());
     }
 
+    /// A GenericIndexed V2 with `2^log` elements per value file, returned
+    /// as its in-place header and a smoosh archive of its external files.
+    fn build_generic_indexed_v2(elements: &[Option<&[u8]>], log: u32) -> (Vec<u8>, SmooshReader) {
+        let name = "dim";
+        let mut meta = vec![VERSION_V2, 0x01];
+        meta.write_i32::<BigEndian>(log as i32).unwrap();
+        meta.write_i32::<BigEndian>(elements.len() as i32).unwrap();
+        meta.write_i32::<BigEndian>(name.len() as i32).unwrap();
+        meta.extend_from_slice(name.as_bytes());
+
+        let mut header = Vec::new();
+        let mut files: Vec<Vec<u8>> = Vec::new();
+        for (i, elem) in elements.iter().enumerate() {
+            if i % (1 << log) == 0 {
+                files.push(Vec::new());
+            }
+            let file = files.last_mut().unwrap();
+            match elem {
+                Some(data) => {
+                    file.write_i32::<BigEndian>(0).unwrap();
+                    file.extend_from_slice(data);
+                }
+                None => file.write_i32::<BigEndian>(-1).unwrap(),
+            }
+            header.write_i32::<BigEndian>(file.len() as i32).unwrap();
+        }
+
+        // Lay every logical file out back to back in one chunk
+        let mut chunk = Vec::new();
+        let mut smoosh_meta = String::from("v1,2147483647,1\n");
+        let named = std::iter::once((format!("{}_header", name), header)).chain(
+            files
+                .into_iter()
+                .enumerate()
+                .map(|(i, file)| (format!("{}_value_{}", name, i), file)),
+        );
+        for (file_name, bytes) in named {
+            let start = chunk.len();
+            chunk.extend_from_slice(&bytes);
+            smoosh_meta.push_str(&format!("{},0,{},{}\n", file_name, start, chunk.len()));
+        }
+        let smoosh = SmooshReader::from_parts(&smoosh_meta, vec![chunk]).unwrap();
+        (meta, smoosh)
+    }
+
+    #[test]
+    fn test_read_v2_across_value_files() {
+        let elements: [Option<&[u8]>; 3] = [Some(b"alpha"), None, Some(b"gamma")];
+        let (mut data, smoosh) = build_generic_indexed_v2(&elements, 1);
+        assert_eq!(smoosh.len(), 3, "a header and two value files");
+        let meta_size = data.len();
+        data.extend_from_slice(b"trailing");
+
+        let gi = GenericIndexed::from_bytes(&data, Some(&smoosh)).unwrap();
+        assert!(matches!(gi, GenericIndexed::V2(_)));
+        assert_eq!(gi.len(), 3);
+        assert_eq!(gi.get_str(0).unwrap(), Some("alpha"));
+        assert_eq!(gi.get(1).unwrap(), None);
+        assert_eq!(gi.get_str(2).unwrap(), Some("gamma"));
+        assert!(gi.get(3).is_err());
+        assert_eq!(gi.total_size().unwrap(), meta_size);
+    }
+
+    #[test]
+    fn test_v2_needs_smoosh() {
+        let (data, _) = build_generic_indexed_v2(&[Some(b"x")], 4);
+        assert!(GenericIndexed::from_bytes(&data, None).is_err());
+
+        let v1 = build_generic_indexed(&[Some(b"x")]);
+        let gi = GenericIndexed::from_bytes(&v1, None).unwrap();
+        assert!(matches!(gi, GenericIndexed::V1(_)));
+        assert_eq!(gi.get_str(0).unwrap(), Some("x"));
+        assert_eq!(gi.total_size().unwrap(), v1.len());
+    }
+
     #[test]
     fn test_invalid_version() {
         let data = [0x02, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
@@ -343,4 +855,96 @@ mod tests {
             DruidSegmentError::InvalidGenericIndexedVersion(0x02)
         ));
     }
+
+    #[test]
+    fn test_index_of_sorted() {
+        let elements: Vec<Option<&[u8]>> = vec![
+            None,
+            Some(b""),
+            Some(b"a"),
+            Some(b"abc"),
+            Some(b"b"),
+            Some(b"\xff"),
+        ];
+        let data = build_generic_indexed(&elements);
+        let indexed = GenericIndexedV1::from_bytes(&data).unwrap();
+        assert!(indexed.is_sorted());
+        for (i, element) in elements.iter().enumerate().skip(1) {
+            assert_eq!(indexed.index_of(element.unwrap()).unwrap(), Some(i));
+        }
+        for missing in [&b"0"[..], b"ab", b"abcd", b"c", b"\xff\x00"] {
+            assert_eq!(indexed.index_of(missing).unwrap(), None);
+        }
+
+        let empty = build_generic_indexed(&[]);
+        let empty = GenericIndexedV1::from_bytes(&empty).unwrap();
+        assert_eq!(empty.index_of(b"a").unwrap(), None);
+    }
+
+    #[test]
+    fn test_index_of_unsorted_scans() {
+        let elements: Vec<Option<&[u8]>> =
+            vec![Some(b"zebra"), None, Some(b"apple"), Some(b"mango")];
+        let data = GenericIndexedWriter::write(elements.iter().copied(), false).unwrap();
+        let indexed = GenericIndexedV1::from_bytes(&data).unwrap();
+        assert!(!indexed.is_sorted());
+        // A binary search would miss the first and last elements
+        assert_eq!(indexed.index_of(b"zebra").unwrap(), Some(0));
+        assert_eq!(indexed.index_of(b"apple").unwrap(), Some(2));
+        assert_eq!(indexed.index_of(b"mango").unwrap(), Some(3));
+        assert_eq!(indexed.index_of(b"kiwi").unwrap(), None);
+    }
+
+    #[test]
+    fn test_writer_matches_layout() {
+        let elements: &[Option<&[u8]>] = &[Some(b"hello"), None, Some(b""), Some(b"world")];
+        let written = GenericIndexedWriter::write(elements.iter().copied(), true).unwrap();
+        assert_eq!(written, build_generic_indexed(elements));
+
+        let unsorted = GenericIndexedWriter::write(elements.iter().copied(), false).unwrap();
+        assert_eq!(unsorted[1], 0x00);
+    }
+
+    #[test]
+    fn test_writer_round_trips_random_elements() {
+        // A small LCG keeps the generated cases reproducible
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |bound: u64| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) % bound
+        };
+        for _ in 0..200 {
+            let elements: Vec<Option<Vec<u8>>> = (0..next(20))
+                .map(|_| (next(5) != 0).then(|| (0..next(40)).map(|_| next(256) as u8).collect()))
+                .collect();
+            let data =
+                GenericIndexedWriter::write(elements.iter().map(|e| e.as_deref()), false).unwrap();
+
+            let indexed = GenericIndexedV1::from_bytes(&data).unwrap();
+            assert_eq!(indexed.len(), elements.len());
+            assert_eq!(indexed.total_size().unwrap(), data.len());
+            for (i, element) in elements.iter().enumerate() {
+                assert_eq!(indexed.get(i).unwrap(), element.as_deref());
+            }
+        }
+    }
+
+    #[test]
+    fn test_writer_strings() {
+        let mut writer = GenericIndexedWriter::new(true);
+        assert!(writer.is_empty());
+        for name in ["__time", "added", "channel"] {
+            writer.push_str(Some(name)).unwrap();
+        }
+        assert_eq!(writer.len(), 3);
+        let data = writer.finish().unwrap();
+
+        let indexed = GenericIndexed::from_bytes(&data, None).unwrap();
+        assert_eq!(indexed.get_str(2).unwrap(), Some("channel"));
+
+        let empty = GenericIndexedWriter::write_strings([], false).unwrap();
+        assert!(GenericIndexedV1::from_bytes(&empty).unwrap().is_empty());
+    }
 }
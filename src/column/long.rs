@@ -1,15 +1,20 @@
 use arrow::array::Int64Array;
 
+use super::NumericPart;
+use super::bitmap::{null_range, to_null_buffer};
 use super::compressed_longs::CompressedColumnarLongs;
 use crate::error::Result;
+use crate::segment::read_options::ReadOptions;
 
-/// Read a long (Int64) column from its binary data (after the JSON header).
+/// Read a long (Int64) column from its numeric part.
 ///
-/// Long columns are stored as CompressedColumnarLongs, optionally with
-/// a null bitmap. For now we skip null bitmap handling and treat all
-/// values as non-null.
-pub fn read_long_column(data: &[u8]) -> Result<Int64Array> {
-    let longs = CompressedColumnarLongs::from_bytes(data)?;
-    let values = longs.decompress_all()?;
-    Ok(Int64Array::from(values))
+/// Long columns are stored as CompressedColumnarLongs, optionally followed
+/// by a null bitmap. Rows in the null bitmap become nulls in the array.
+pub fn read_long_column(part: &NumericPart<'_>, options: &ReadOptions) -> Result<Int64Array> {
+    let longs = CompressedColumnarLongs::from_bytes_with_order(part.values, part.byte_order)?
+        .with_cancellation(options.cancellation.clone());
+    let range = options.row_range(longs.len());
+    let values = longs.decompress_range(range.clone())?;
+    let nulls = null_range(to_null_buffer(&part.nulls, longs.len())?, range);
+    Ok(Int64Array::new(values.into(), nulls))
 }
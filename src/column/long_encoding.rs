@@ -0,0 +1,390 @@
+use std::io::Cursor;
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+
+use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+
+/// Bit widths Druid's VSizeLongSerde packs values with. Zero is accepted
+/// for columns where every value equals the delta base or table entry 0.
+pub const SUPPORTED_BITS: [u8; 14] = [0, 1, 2, 4, 8, 12, 16, 20, 24, 32, 40, 48, 56, 64];
+
+/// Encoding format ids written after a flagged compression byte.
+const ENCODING_DELTA: u8 = 0x00;
+const ENCODING_TABLE: u8 = 0x01;
+const ENCODING_LONGS: u8 = 0xFF;
+
+/// Version byte that starts the delta and table encoding headers.
+const ENCODING_HEADER_VERSION: u8 = 0x01;
+
+/// Compression ids below this (as a signed byte) carry the encoding flag:
+/// the real id is `id + ENCODING_FLAG_VALUE` and an encoding byte follows.
+const ENCODING_FLAG_BOUND: i8 = -2;
+const ENCODING_FLAG_VALUE: i8 = 126;
+
+/// How the values inside CompressedColumnarLongs blocks are encoded
+/// (Druid's `LongEncodingFormat`, chosen by `longEncoding: auto`).
+///
+/// Delta and table values are bit-packed big-endian, most significant bit
+/// first, `bits` per value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LongEncoding {
+    /// Plain 8-byte longs in the column's byte order (the legacy format).
+    Longs,
+    /// Each value is `base` plus a packed unsigned offset.
+    Delta { base: i64, bits: u8 },
+    /// Each value is an index into `table`.
+    Table { table: Vec<i64>, bits: u8 },
+}
+
+impl LongEncoding {
+    /// Split a flagged compression byte into the real compression id and
+    /// whether an encoding byte follows it.
+    pub fn split_compression_id(id: u8) -> (u8, bool) {
+        let signed = id as i8;
+        if signed < ENCODING_FLAG_BOUND {
+            ((signed + ENCODING_FLAG_VALUE) as u8, true)
+        } else {
+            (id, false)
+        }
+    }
+
+    /// Parse the encoding id and its header from the start of `data`,
+    /// returning the encoding and the number of bytes consumed.
+    ///
+    /// Header layouts (big-endian):
+    /// ```text
+    /// delta: [id = 0x00][version: u8 = 0x01][base: i64][bits: i32]
+    /// table: [id = 0x01][version: u8 = 0x01][size: i32][values: i64 * size]
+    /// longs: [id = 0xFF]
+    /// ```
+    pub fn parse(data: &[u8]) -> Result<(Self, usize)> {
+        let id = *data.first().ok_or_else(|| {
+            DruidSegmentError::InvalidData("Long encoding: missing encoding byte".into())
+        })?;
+        if id == ENCODING_LONGS {
+            return Ok((LongEncoding::Longs, 1));
+        }
+
+        let mut cursor = Cursor::new(&data[1..]);
+        let version = cursor.read_u8()?;
+        if version != ENCODING_HEADER_VERSION {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "Long encoding {:#x}: unsupported header version {:#x}",
+                id, version
+            )));
+        }
+
+        let encoding = match id {
+            ENCODING_DELTA => {
+                let base = cursor.read_i64::<BigEndian>()?;
+                let bits = cursor.read_i32::<BigEndian>()?;
+                LongEncoding::Delta {
+                    base,
+                    bits: validate_bits(bits)?,
+                }
+            }
+            ENCODING_TABLE => {
+                let size = cursor.read_i32::<BigEndian>()?;
+                if size < 0 {
+                    return Err(DruidSegmentError::InvalidData(format!(
+                        "Table long encoding: negative table size {}",
+                        size
+                    )));
+                }
+                let table = (0..size)
+                    .map(|_| cursor.read_i64::<BigEndian>())
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                let bits = bits_for_max(size as u64);
+                LongEncoding::Table { table, bits }
+            }
+            other => {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "Unknown long encoding {:#x}",
+                    other
+                )));
+            }
+        };
+
+        Ok((encoding, 1 + cursor.position() as usize))
+    }
+
+    /// Upper bound on the decompressed size of a block holding `count` values.
+    pub fn block_size_bound(&self, count: usize) -> usize {
+        match self {
+            LongEncoding::Longs => count * 8,
+            LongEncoding::Delta { bits, .. } | LongEncoding::Table { bits, .. } => {
+                // Druid pads packed blocks so values can be read with wide loads
+                (count * *bits as usize).div_ceil(8) + 8
+            }
+        }
+    }
+
+    /// Decode `count` values from a decompressed block, appending to `out`.
+    pub fn decode_block(
+        &self,
+        block: &[u8],
+        count: usize,
+        byte_order: ByteOrder,
+        out: &mut Vec<i64>,
+    ) -> Result<()> {
+        match self {
+            LongEncoding::Longs => match block.get(..count * 8) {
+                Some(values) => decode_longs(values, byte_order, out),
+                // A short block fails on the value running past its end
+                None => decode_longs_scalar(block, count, byte_order, out)?,
+            },
+            LongEncoding::Delta { base, bits } => {
+                for i in 0..count {
+                    let delta = read_packed(block, i, *bits)?;
+                    out.push(base.wrapping_add(delta as i64));
+                }
+            }
+            LongEncoding::Table { table, bits } => {
+                for i in 0..count {
+                    let idx = read_packed(block, i, *bits)? as usize;
+                    let value = table.get(idx).ok_or_else(|| {
+                        DruidSegmentError::InvalidData(format!(
+                            "Table long encoding: index {} out of range (table size {})",
+                            idx,
+                            table.len()
+                        ))
+                    })?;
+                    out.push(*value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode plain longs from `values`, whose length must be a multiple of 8,
+/// appending to `out`. Whole blocks are converted in 8-byte strides, which
+/// the compiler turns into bulk byte swaps.
+pub fn decode_longs(values: &[u8], byte_order: ByteOrder, out: &mut Vec<i64>) {
+    let chunks = values.chunks_exact(8);
+    out.reserve(chunks.len());
+    match byte_order {
+        ByteOrder::BigEndian => out.extend(
+            chunks.map(|c| i64::from_be_bytes(c.try_into().expect("chunks_exact yields 8 bytes"))),
+        ),
+        ByteOrder::LittleEndian => out.extend(
+            chunks.map(|c| i64::from_le_bytes(c.try_into().expect("chunks_exact yields 8 bytes"))),
+        ),
+    }
+}
+
+/// Decode `count` plain longs from `block` one value at a time, appending
+/// to `out`. Slower than [`decode_longs`], but reports a block too short
+/// for `count` values as a read past its end.
+pub fn decode_longs_scalar(
+    block: &[u8],
+    count: usize,
+    byte_order: ByteOrder,
+    out: &mut Vec<i64>,
+) -> Result<()> {
+    let mut cursor = Cursor::new(block);
+    for _ in 0..count {
+        let value = match byte_order {
+            ByteOrder::BigEndian => cursor.read_i64::<BigEndian>()?,
+            ByteOrder::LittleEndian => cursor.read_i64::<LittleEndian>()?,
+        };
+        out.push(value);
+    }
+    Ok(())
+}
+
+/// Smallest supported bit width `bits` with `2^bits >= value`, as Druid's
+/// `VSizeLongSerde.getBitsForMax` picks it. Table encodings size their
+/// indexes with `bits_for_max(table_size)`.
+pub fn bits_for_max(value: u64) -> u8 {
+    let needed = match value {
+        0 | 1 => 0,
+        v => (64 - (v - 1).leading_zeros()) as u8,
+    };
+    SUPPORTED_BITS
+        .iter()
+        .copied()
+        .find(|&bits| bits >= needed.max(1))
+        .unwrap_or(64)
+}
+
+fn validate_bits(bits: i32) -> Result<u8> {
+    u8::try_from(bits)
+        .ok()
+        .filter(|b| SUPPORTED_BITS.contains(b))
+        .ok_or_else(|| {
+            DruidSegmentError::InvalidData(format!(
+                "Long encoding: unsupported bits per value {}",
+                bits
+            ))
+        })
+}
+
+/// Read the `index`-th `bits`-wide value from a big-endian bit-packed buffer.
+fn read_packed(data: &[u8], index: usize, bits: u8) -> Result<u64> {
+    if bits == 0 {
+        return Ok(0);
+    }
+    let bits = bits as usize;
+    let bit_start = index * bits;
+    let first = bit_start / 8;
+    let last = (bit_start + bits - 1) / 8;
+    let bytes = data.get(first..=last).ok_or_else(|| {
+        DruidSegmentError::InvalidData(format!(
+            "Packed longs: value {} ({} bits) past end of {}-byte block",
+            index,
+            bits,
+            data.len()
+        ))
+    })?;
+
+    let acc = bytes.iter().fold(0u128, |acc, &b| (acc << 8) | b as u128);
+    let shift = bytes.len() * 8 - bit_start % 8 - bits;
+    let mask = if bits == 64 {
+        u64::MAX as u128
+    } else {
+        (1u128 << bits) - 1
+    };
+    Ok(((acc >> shift) & mask) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack values big-endian, most significant bit first, `bits` per value.
+    fn pack(values: &[u64], bits: u8) -> Vec<u8> {
+        let total_bits = values.len() * bits as usize;
+        let mut out = vec![0u8; total_bits.div_ceil(8)];
+        for (i, &v) in values.iter().enumerate() {
+            for b in 0..bits as usize {
+                if (v >> (bits as usize - 1 - b)) & 1 == 1 {
+                    let bit = i * bits as usize + b;
+                    out[bit / 8] |= 0x80 >> (bit % 8);
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_read_packed_all_widths() {
+        for bits in SUPPORTED_BITS {
+            let max = if bits == 64 {
+                u64::MAX
+            } else {
+                (1u64 << bits) - 1
+            };
+            let values = vec![0, max, max / 3, 1.min(max), max / 2, max];
+            let data = pack(&values, bits);
+            for (i, &expected) in values.iter().enumerate() {
+                assert_eq!(
+                    read_packed(&data, i, bits).unwrap(),
+                    expected,
+                    "bits {}, index {}",
+                    bits,
+                    i
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_packed_past_end() {
+        assert!(read_packed(&[0xFF], 1, 8).is_err());
+    }
+
+    #[test]
+    fn test_decode_longs_matches_scalar() {
+        let values: Vec<i64> = (0..1000)
+            .map(|i: i64| i.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64))
+            .chain([i64::MIN, -1, 0, i64::MAX])
+            .collect();
+        for byte_order in [ByteOrder::BigEndian, ByteOrder::LittleEndian] {
+            let block: Vec<u8> = values
+                .iter()
+                .flat_map(|v| match byte_order {
+                    ByteOrder::BigEndian => v.to_be_bytes(),
+                    ByteOrder::LittleEndian => v.to_le_bytes(),
+                })
+                .collect();
+            let mut fast = Vec::new();
+            decode_longs(&block, byte_order, &mut fast);
+            let mut scalar = Vec::new();
+            decode_longs_scalar(&block, values.len(), byte_order, &mut scalar).unwrap();
+            assert_eq!(fast, values);
+            assert_eq!(scalar, values);
+
+            // Fewer values than the block holds, and a block too short
+            let mut prefix = Vec::new();
+            LongEncoding::Longs
+                .decode_block(&block, 10, byte_order, &mut prefix)
+                .unwrap();
+            assert_eq!(prefix, values[..10]);
+            assert!(
+                LongEncoding::Longs
+                    .decode_block(&block[..79], 10, byte_order, &mut Vec::new())
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_compression_id() {
+        // LZ4 (0x01) flagged: 1 - 126 = -125
+        assert_eq!(LongEncoding::split_compression_id(0x83), (0x01, true));
+        assert_eq!(LongEncoding::split_compression_id(0x01), (0x01, false));
+        // Uncompressed (0xFF = -1) and NONE (0xFE = -2) are not flagged
+        assert_eq!(LongEncoding::split_compression_id(0xFF), (0xFF, false));
+        assert_eq!(LongEncoding::split_compression_id(0xFE), (0xFE, false));
+    }
+
+    #[test]
+    fn test_bits_for_max() {
+        assert_eq!(bits_for_max(0), 1);
+        assert_eq!(bits_for_max(2), 1);
+        assert_eq!(bits_for_max(3), 2);
+        assert_eq!(bits_for_max(4), 2);
+        assert_eq!(bits_for_max(5), 4);
+        assert_eq!(bits_for_max(256), 8);
+        assert_eq!(bits_for_max(257), 12);
+        assert_eq!(bits_for_max(u64::MAX), 64);
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let mut delta = vec![ENCODING_DELTA, ENCODING_HEADER_VERSION];
+        delta.extend_from_slice(&(-5i64).to_be_bytes());
+        delta.extend_from_slice(&12i32.to_be_bytes());
+        assert_eq!(
+            LongEncoding::parse(&delta).unwrap(),
+            (LongEncoding::Delta { base: -5, bits: 12 }, 14)
+        );
+
+        let mut table = vec![ENCODING_TABLE, ENCODING_HEADER_VERSION];
+        table.extend_from_slice(&3i32.to_be_bytes());
+        for v in [7i64, -1, 100] {
+            table.extend_from_slice(&v.to_be_bytes());
+        }
+        assert_eq!(
+            LongEncoding::parse(&table).unwrap(),
+            (
+                LongEncoding::Table {
+                    table: vec![7, -1, 100],
+                    bits: 2
+                },
+                30
+            )
+        );
+
+        assert_eq!(
+            LongEncoding::parse(&[ENCODING_LONGS]).unwrap(),
+            (LongEncoding::Longs, 1)
+        );
+
+        let mut bad_bits = delta.clone();
+        bad_bits[10..14].copy_from_slice(&3i32.to_be_bytes());
+        assert!(LongEncoding::parse(&bad_bits).is_err());
+    }
+}
@@ -1,11 +1,18 @@
 pub mod bitmap;
+pub mod block_layout;
+pub mod block_writer;
+pub mod complex;
 pub mod compressed_doubles;
 pub mod compressed_ints;
 pub mod compressed_longs;
 pub mod double;
 pub mod float;
+pub mod front_coded;
 pub mod generic_indexed;
 pub mod long;
+pub mod long_encoding;
+pub mod multi_ints;
+pub mod spatial;
 pub mod string;
 pub mod time;
 pub mod vsize_ints;
@@ -13,11 +20,28 @@ pub mod vsize_ints;
 use std::io::Cursor;
 use std::sync::Arc;
 
-use arrow::array::ArrayRef;
+use arrow::array::{ArrayRef, new_null_array};
+use arrow::datatypes::DataType;
 use byteorder::{BigEndian, ReadBytesExt};
+use roaring::RoaringBitmap;
 
 use crate::error::{DruidSegmentError, Result};
-use crate::segment::column_descriptor::{ColumnDescriptor, ValueType};
+use crate::segment::column_descriptor::{ByteOrder, ColumnDescriptor, ColumnPartSerde, ValueType};
+use crate::segment::read_options::ReadOptions;
+use crate::segment::smoosh::SmooshReader;
+use crate::segment::{TIME_COLUMN, druid_type_to_arrow};
+
+/// Read a big-endian i32 size, count or offset as a `usize`.
+///
+/// Druid writes these as Java ints, so a negative one means corrupt data
+/// or a structure past 2GB; either fails with
+/// [`DruidSegmentError::InvalidData`] naming `what` and the value, instead
+/// of wrapping around to a huge `usize`.
+pub(crate) fn read_len(reader: &mut impl std::io::Read, what: &str) -> Result<usize> {
+    let value = reader.read_i32::<BigEndian>()?;
+    usize::try_from(value)
+        .map_err(|_| DruidSegmentError::InvalidData(format!("{} is negative: {}", what, value)))
+}
 
 /// Parse the column header: a length-prefixed JSON ColumnDescriptor string
 /// followed by binary column data.
@@ -30,7 +54,7 @@ pub fn parse_column_header(data: &[u8]) -> Result<(ColumnDescriptor, &[u8])> {
         ));
     }
     let mut cursor = Cursor::new(data);
-    let json_len = cursor.read_i32::<BigEndian>()? as usize;
+    let json_len = read_len(&mut cursor, "Column header: JSON length")?;
 
     if data.len() < 4 + json_len {
         return Err(DruidSegmentError::InvalidData(format!(
@@ -46,20 +70,922 @@ pub fn parse_column_header(data: &[u8]) -> Result<(ColumnDescriptor, &[u8])> {
     Ok((descriptor, remaining))
 }
 
+/// Write a column's logical file: its descriptor as length-prefixed JSON,
+/// then `binary`, the inverse of [`parse_column_header`].
+pub fn write_column_file(descriptor: &ColumnDescriptor, binary: &[u8]) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(descriptor)?;
+    let mut file = Vec::with_capacity(4 + json.len() + binary.len());
+    file.extend_from_slice(&(json.len() as i32).to_be_bytes());
+    file.extend(json);
+    file.extend_from_slice(binary);
+    Ok(file)
+}
+
 /// Read a column's data and return the descriptor and an Arrow array.
 pub fn read_column(name: &str, data: &[u8]) -> Result<(ColumnDescriptor, ArrayRef)> {
+    read_column_with_options(name, data, &ReadOptions::default())
+}
+
+/// Read a column's data with the given options.
+pub fn read_column_with_options(
+    name: &str,
+    data: &[u8],
+    options: &ReadOptions,
+) -> Result<(ColumnDescriptor, ArrayRef)> {
+    read_column_with_smoosh(name, data, None, options)
+}
+
+/// Read a column's data, mapping the external files of any GenericIndexed
+/// V2 it contains from `smoosh`.
+pub fn read_column_with_smoosh(
+    name: &str,
+    data: &[u8],
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+) -> Result<(ColumnDescriptor, ArrayRef)> {
+    read_column_cached(name, data, smoosh, options, None)
+}
+
+/// Like [`read_column_with_smoosh`], but string columns read as
+/// dictionaries take their dictionary from `dictionaries`, or add it there.
+pub(crate) fn read_column_cached(
+    name: &str,
+    data: &[u8],
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+    dictionaries: Option<&self::string::DictionaryCache>,
+) -> Result<(ColumnDescriptor, ArrayRef)> {
+    options.check_cancelled()?;
+    if data.is_empty() {
+        return Err(DruidSegmentError::EmptyLogicalFile(name.to_string()));
+    }
+    let (descriptor, binary_data) = parse_column_header(data).map_err(|e| e.in_column(name, 0))?;
+    if self::complex::is_nested(&descriptor) && !self::complex::is_nested_json(&descriptor) {
+        return Err(DruidSegmentError::UnsupportedColumnType(format!(
+            "nested column ({})",
+            nested_logical_type(&descriptor)
+        ))
+        .in_column(name, 0));
+    }
+    let header_size = data.len() - binary_data.len();
+
+    // Each part reads its section of the binary data in turn, as Druid's
+    // ColumnDescriptor does, and exactly one of them holds the values.
+    let mut array = None;
+    let mut offset = 0;
+    for part in &descriptor.parts {
+        let in_part = |e: DruidSegmentError| e.in_column(name, header_size + offset);
+        let data = binary_data.get(offset..).ok_or_else(|| {
+            in_part(DruidSegmentError::InvalidData(format!(
+                "parts overrun the column's {} bytes",
+                binary_data.len()
+            )))
+        })?;
+        let (part_array, size) =
+            read_part(name, &descriptor, part, data, smoosh, options, dictionaries)
+                .map_err(in_part)?;
+        if let Some(part_array) = part_array
+            && array.replace(part_array).is_some()
+        {
+            return Err(in_part(DruidSegmentError::InvalidData(
+                "more than one value part".into(),
+            )));
+        }
+        offset += size;
+    }
+    let array = array.ok_or_else(|| {
+        DruidSegmentError::ColumnDescriptorError("no value part".into()).in_column(name, 0)
+    })?;
+
+    Ok((descriptor, array))
+}
+
+/// Read one part of a column from the start of `data`, dispatching on its
+/// serde type. Returns the part's values, if it holds them, and the number
+/// of bytes it takes.
+fn read_part(
+    name: &str,
+    descriptor: &ColumnDescriptor,
+    part: &ColumnPartSerde,
+    data: &[u8],
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+    dictionaries: Option<&self::string::DictionaryCache>,
+) -> Result<(Option<ArrayRef>, usize)> {
+    let expect_type = |value_type: ValueType| {
+        if descriptor.value_type == value_type {
+            Ok(())
+        } else {
+            Err(DruidSegmentError::ColumnDescriptorError(format!(
+                "part type '{}' in {:?} column",
+                part.serde_type, descriptor.value_type
+            )))
+        }
+    };
+
+    if name == TIME_COLUMN {
+        check_time_descriptor(descriptor)?;
+    }
+    let (array, size): (ArrayRef, usize) = match part.serde_type.as_str() {
+        "long" | "longV2" => {
+            expect_type(ValueType::Long)?;
+            let numeric = NumericPart::parse_part(part, data)?;
+            numeric.check_version(part)?;
+            let array: ArrayRef = if name == TIME_COLUMN {
+                Arc::new(self::time::read_time_column(&numeric, options)?)
+            } else {
+                Arc::new(self::long::read_long_column(&numeric, options)?)
+            };
+            (array, numeric.size)
+        }
+        "float" | "floatV2" => {
+            expect_type(ValueType::Float)?;
+            let numeric = NumericPart::parse_part(part, data)?;
+            numeric.check_version(part)?;
+            let array = self::float::read_float_column(&numeric, options)?;
+            (Arc::new(array), numeric.size)
+        }
+        "double" | "doubleV2" => {
+            expect_type(ValueType::Double)?;
+            let numeric = NumericPart::parse_part(part, data)?;
+            numeric.check_version(part)?;
+            let array = self::double::read_double_column(&numeric, options)?;
+            (Arc::new(array), numeric.size)
+        }
+        "stringDictionary" => {
+            expect_type(ValueType::String)?;
+            let byte_order = part.byte_order()?.unwrap_or_default();
+            if descriptor.has_multiple_values {
+                let array = self::string::read_multi_value_string_column(
+                    data, byte_order, smoosh, options,
+                )?;
+                (
+                    Arc::new(array),
+                    self::string::part_size(data, byte_order, smoosh)?,
+                )
+            } else if options.strings_as_dictionary {
+                let array = self::string::read_dictionary_array(
+                    data,
+                    byte_order,
+                    smoosh,
+                    options,
+                    dictionaries.map(|cache| (name, cache)),
+                )?;
+                (
+                    Arc::new(array),
+                    self::string::part_size(data, byte_order, smoosh)?,
+                )
+            } else {
+                let column =
+                    self::string::StringColumn::from_bytes(data, byte_order, smoosh, options)?;
+                let size = column.total_consumed();
+                (Arc::new(column.into_values()), size)
+            }
+        }
+        "complex" => {
+            expect_type(ValueType::Complex)?;
+            let array = self::complex::read_complex_column(descriptor, data, options)?;
+            (Arc::new(array), self::complex::part_size(data)?)
+        }
+        self::complex::NESTED_COMMON_FORMAT => {
+            expect_type(ValueType::Complex)?;
+            let (array, size) =
+                self::complex::nested::read_json_column(part, data, smoosh, options)?;
+            let array: ArrayRef = if options.strings_as_dictionary {
+                arrow::compute::cast(&array, &options.read_type(DataType::Utf8))?
+            } else {
+                Arc::new(array)
+            };
+            (array, size)
+        }
+        "nullColumn" => {
+            // A column with no non-null row stores only its row count
+            let num_rows = part
+                .extra
+                .get("numRows")
+                .and_then(|n| n.as_u64())
+                .ok_or_else(|| {
+                    DruidSegmentError::ColumnDescriptorError(
+                        "nullColumn part has no numRows".into(),
+                    )
+                })?;
+            let data_type = druid_type_to_arrow(descriptor, name, options);
+            let num_rows = options.rows_to_decode(num_rows as usize);
+            (new_null_array(&data_type, num_rows), 0)
+        }
+        other => {
+            return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                "unsupported part type '{}'",
+                other
+            )));
+        }
+    };
+    Ok((Some(array), size))
+}
+
+/// The logical type a nested column declares, for errors about it.
+fn nested_logical_type(descriptor: &ColumnDescriptor) -> String {
+    descriptor
+        .parts
+        .iter()
+        .find_map(|p| p.extra.get("logicalType")?.as_str())
+        .map_or_else(|| "COMPLEX<json>".to_string(), str::to_string)
+}
+
+/// Read the number of rows from a `__time` column's compressed values
+/// header, without decompressing any block.
+pub fn read_time_row_count(data: &[u8]) -> Result<usize> {
+    if data.is_empty() {
+        return Err(DruidSegmentError::EmptyLogicalFile("__time".to_string()));
+    }
     let (descriptor, binary_data) = parse_column_header(data)?;
+    check_time_descriptor(&descriptor)?;
+    let part = NumericPart::parse(&descriptor, binary_data)?;
+    let longs = self::compressed_longs::CompressedColumnarLongs::from_bytes_with_order(
+        part.values,
+        part.byte_order,
+    )?;
+    Ok(longs.len())
+}
+
+/// Read a column's number of rows from the headers of its values, without
+/// decompressing any block. Returns `None` for multi-value string and
+/// complex columns, whose headers do not hold it.
+pub fn read_row_count(data: &[u8], smoosh: Option<&SmooshReader>) -> Result<Option<usize>> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    if let Some(part) = descriptor.parts.first()
+        && part.serde_type == "nullColumn"
+    {
+        return Ok(part
+            .extra
+            .get("numRows")
+            .and_then(|n| n.as_u64())
+            .map(|n| n as usize));
+    }
+    let rows = match descriptor.value_type {
+        ValueType::Long => {
+            let part = NumericPart::parse(&descriptor, binary_data)?;
+            self::compressed_longs::CompressedColumnarLongs::from_bytes_with_order(
+                part.values,
+                part.byte_order,
+            )?
+            .len()
+        }
+        ValueType::Float => {
+            let part = NumericPart::parse(&descriptor, binary_data)?;
+            self::compressed_doubles::CompressedColumnarFloats::from_bytes_with_order(
+                part.values,
+                part.byte_order,
+            )?
+            .len()
+        }
+        ValueType::Double => {
+            let part = NumericPart::parse(&descriptor, binary_data)?;
+            self::compressed_doubles::CompressedColumnarDoubles::from_bytes_with_order(
+                part.values,
+                part.byte_order,
+            )?
+            .len()
+        }
+        ValueType::String => {
+            return self::string::row_count(binary_data, part_byte_order(&descriptor)?, smoosh);
+        }
+        ValueType::Complex => return Ok(None),
+    };
+    Ok(Some(rows))
+}
 
-    let array: ArrayRef = match (&descriptor.value_type, name) {
-        (_, "__time") => Arc::new(self::time::read_time_column(binary_data)?),
-        (ValueType::String, _) => Arc::new(self::string::read_string_column(binary_data)?),
-        (ValueType::Long, _) => Arc::new(self::long::read_long_column(binary_data)?),
-        (ValueType::Float, _) => Arc::new(self::float::read_float_column(binary_data)?),
-        (ValueType::Double, _) => Arc::new(self::double::read_double_column(binary_data)?),
-        (ValueType::Complex, _) => {
-            return Err(DruidSegmentError::UnsupportedColumnType("Complex".into()));
+/// Check that a `__time` column is stored the only way Druid stores it:
+/// as a long column, whose values are epoch milliseconds already truncated
+/// to the segment's query granularity.
+fn check_time_descriptor(descriptor: &ColumnDescriptor) -> Result<()> {
+    let serde_type = descriptor.parts.first().map(|p| p.serde_type.as_str());
+    if descriptor.value_type == ValueType::Long && matches!(serde_type, Some("long" | "longV2")) {
+        return Ok(());
+    }
+    Err(DruidSegmentError::UnsupportedColumnType(format!(
+        "__time stored as a {:?} column with part type '{}'",
+        descriptor.value_type,
+        serde_type.unwrap_or("none")
+    )))
+}
+
+/// Read the block layout of a numeric column's compressed values from its
+/// header, without decompressing any block. Returns `None` for other
+/// column types.
+pub fn read_block_layout(data: &[u8]) -> Result<Option<self::block_layout::BlockLayout>> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    let part = match descriptor.value_type {
+        ValueType::Long | ValueType::Float | ValueType::Double => {
+            NumericPart::parse(&descriptor, binary_data)?
+        }
+        _ => return Ok(None),
+    };
+    let layout = match descriptor.value_type {
+        ValueType::Long => self::compressed_longs::CompressedColumnarLongs::from_bytes_with_order(
+            part.values,
+            part.byte_order,
+        )?
+        .layout()?,
+        ValueType::Float => {
+            self::compressed_doubles::CompressedColumnarFloats::from_bytes_with_order(
+                part.values,
+                part.byte_order,
+            )?
+            .layout()?
         }
+        _ => self::compressed_doubles::CompressedColumnarDoubles::from_bytes_with_order(
+            part.values,
+            part.byte_order,
+        )?
+        .layout()?,
     };
+    Ok(Some(layout))
+}
 
-    Ok((descriptor, array))
+/// Read the number of null rows of a column without decoding its values:
+/// from the null bitmap of a numeric column, or from the null dictionary
+/// entry of a single-value string column. Returns `None` for other column
+/// types, whose null count is only known after decoding.
+pub fn read_null_count(data: &[u8], smoosh: Option<&SmooshReader>) -> Result<Option<usize>> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    if let Some(part) = descriptor.parts.first()
+        && part.serde_type == "nullColumn"
+    {
+        return Ok(part
+            .extra
+            .get("numRows")
+            .and_then(|n| n.as_u64())
+            .map(|n| n as usize));
+    }
+    match descriptor.value_type {
+        ValueType::Long | ValueType::Float | ValueType::Double => {
+            let part = NumericPart::parse(&descriptor, binary_data)?;
+            Ok(Some(part.nulls.len() as usize))
+        }
+        ValueType::String => {
+            self::string::null_count(binary_data, part_byte_order(&descriptor)?, smoosh)
+        }
+        ValueType::Complex => Ok(None),
+    }
+}
+
+/// Read the bitmap of rows whose value is `value` from a string dimension
+/// column's data (header included). Returns `None` if no row has `value`.
+pub fn read_dimension_bitmap(
+    data: &[u8],
+    smoosh: Option<&SmooshReader>,
+    value: &str,
+) -> Result<Option<RoaringBitmap>> {
+    read_string_index(data, smoosh)?.bitmap_for_value(value)
+}
+
+/// Open the inverted indexes of a string dimension column's data (header
+/// included). No bitmap is deserialized until it is looked up.
+pub fn read_string_index<'a>(
+    data: &'a [u8],
+    smoosh: Option<&'a SmooshReader>,
+) -> Result<self::string::StringColumnIndex<'a>> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    if descriptor.value_type != ValueType::String {
+        return Err(DruidSegmentError::UnsupportedColumnType(format!(
+            "bitmap index on {:?} column",
+            descriptor.value_type
+        )));
+    }
+    self::string::StringColumnIndex::from_bytes(binary_data, part_byte_order(&descriptor)?, smoosh)
+}
+
+/// Locate the dictionary and encoded values of a string column's data
+/// (header included), for lookups that decode nothing else.
+pub fn read_string_values<'a>(
+    data: &'a [u8],
+    smoosh: Option<&'a SmooshReader>,
+) -> Result<self::string::StringColumnValues<'a>> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    if descriptor.value_type != ValueType::String {
+        return Err(DruidSegmentError::UnsupportedColumnType(format!(
+            "dictionary of {:?} column",
+            descriptor.value_type
+        )));
+    }
+    self::string::StringColumnValues::from_bytes(binary_data, part_byte_order(&descriptor)?, smoosh)
+}
+
+/// Read whether a column stores bitmap indexes. Only string dimensions
+/// can have them.
+pub fn read_has_bitmap_index(data: &[u8], smoosh: Option<&SmooshReader>) -> Result<bool> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    if descriptor.value_type != ValueType::String {
+        return Ok(false);
+    }
+    self::string::has_bitmap_index(binary_data, part_byte_order(&descriptor)?, smoosh)
+}
+
+/// Read the dictionary cardinality of a string column, or `None` for
+/// other column types, which have no dictionary.
+pub fn read_dictionary_cardinality(
+    data: &[u8],
+    smoosh: Option<&SmooshReader>,
+) -> Result<Option<usize>> {
+    let (descriptor, binary_data) = parse_column_header(data)?;
+    if descriptor.value_type != ValueType::String {
+        return Ok(None);
+    }
+    self::string::dictionary_cardinality(binary_data, part_byte_order(&descriptor)?, smoosh)
+        .map(Some)
+}
+
+/// The byte order declared by the descriptor's first part, defaulting to
+/// big-endian when absent.
+fn part_byte_order(descriptor: &ColumnDescriptor) -> Result<ByteOrder> {
+    match descriptor.parts.first() {
+        Some(part) => Ok(part.byte_order()?.unwrap_or_default()),
+        None => Ok(ByteOrder::default()),
+    }
+}
+
+/// The sections of a numeric (long, float, double) column's binary data.
+///
+/// The `longV2`/`floatV2`/`doubleV2` part serdes lay the data out as:
+/// ```text
+/// [values_size: i32]
+/// [values: CompressedColumnar{Longs,Floats,Doubles}, values_size bytes]
+/// [null_bitmap_size: i32][null_bitmap]  -- absent when no row is null
+/// ```
+/// The legacy `long`/`float`/`double` serdes store only the values.
+pub struct NumericPart<'a> {
+    /// Bytes the part takes in the column's binary data.
+    pub size: usize,
+    /// Serialized compressed values.
+    pub values: &'a [u8],
+    /// Byte order of the values inside decompressed blocks.
+    pub byte_order: ByteOrder,
+    /// Rows whose value is null.
+    pub nulls: RoaringBitmap,
+}
+
+impl<'a> NumericPart<'a> {
+    /// Split a numeric column's binary data according to its descriptor.
+    pub fn parse(descriptor: &ColumnDescriptor, data: &'a [u8]) -> Result<Self> {
+        match descriptor.parts.first() {
+            Some(part) => Self::parse_part(part, data),
+            None => Err(DruidSegmentError::ColumnDescriptorError(
+                "numeric column has no parts".into(),
+            )),
+        }
+    }
+
+    /// Split the binary data of a numeric part serde.
+    pub fn parse_part(part: &ColumnPartSerde, data: &'a [u8]) -> Result<Self> {
+        let byte_order = part.byte_order()?.unwrap_or_default();
+        if !part.serde_type.ends_with("V2") {
+            return Ok(Self {
+                size: data.len(),
+                values: data,
+                byte_order,
+                nulls: RoaringBitmap::new(),
+            });
+        }
+
+        if data.len() < 4 {
+            return Err(DruidSegmentError::InvalidData(
+                "Numeric column: data too short for values size".into(),
+            ));
+        }
+        let mut cursor = Cursor::new(data);
+        let values_size = read_len(&mut cursor, "Numeric column: values size")?;
+        let values_end = 4 + values_size;
+        if data.len() < values_end {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "Numeric column: values size {} exceeds remaining {} bytes",
+                values_size,
+                data.len() - 4
+            )));
+        }
+
+        let bitmap_section = &data[values_end..];
+        let (nulls, size) = if bitmap_section.is_empty() {
+            (RoaringBitmap::new(), values_end)
+        } else {
+            if bitmap_section.len() < 4 {
+                return Err(DruidSegmentError::InvalidData(
+                    "Numeric column: data too short for null bitmap size".into(),
+                ));
+            }
+            let mut cursor = Cursor::new(bitmap_section);
+            let bitmap_size = read_len(&mut cursor, "Numeric column: null bitmap size")?;
+            if bitmap_section.len() < 4 + bitmap_size {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "Numeric column: null bitmap size {} exceeds remaining {} bytes",
+                    bitmap_size,
+                    bitmap_section.len() - 4
+                )));
+            }
+            let bitmap = &bitmap_section[4..4 + bitmap_size];
+            // The part names its factory, so the bitmap needs no type byte
+            let nulls = match part.bitmap_serde_factory()? {
+                Some(factory) => self::bitmap::read_bitmap_with_factory(bitmap, factory)?,
+                None => self::bitmap::read_null_bitmap(bitmap)?,
+            };
+            (nulls, values_end + 4 + bitmap_size)
+        };
+
+        Ok(Self {
+            size,
+            values: &data[4..values_end],
+            byte_order,
+            nulls,
+        })
+    }
+
+    /// Check the version byte of the compressed values against the part
+    /// serde that declared them. Druid writes the `V2` serdes only over
+    /// version 0x02 values; the legacy ones may also hold LZF version 0x01
+    /// values, and are left to the values' reader.
+    pub fn check_version(&self, part: &ColumnPartSerde) -> Result<()> {
+        match self.values.first() {
+            Some(&version) if part.serde_type.ends_with("V2") && version != 0x02 => {
+                Err(DruidSegmentError::SerdeVersionMismatch {
+                    serde_type: part.serde_type.clone(),
+                    expected: 0x02,
+                    actual: version,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Lay out the binary data of a `V2` numeric part serde from its
+    /// serialized compressed `values` and its null rows, writing the null
+    /// bitmap only when some row is null.
+    pub fn write(values: &[u8], nulls: &RoaringBitmap) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(4 + values.len());
+        buf.extend_from_slice(&(values.len() as i32).to_be_bytes());
+        buf.extend_from_slice(values);
+        if !nulls.is_empty() {
+            let bitmap = bitmap::write_bitmap(nulls);
+            buf.extend_from_slice(&(bitmap.len() as i32).to_be_bytes());
+            buf.extend(bitmap);
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::metadata::BitmapSerdeFactory;
+    use crate::segment::read_options::CancellationToken;
+    use arrow::array::{Array, Float32Array, Float64Array, Int64Array};
+    use byteorder::WriteBytesExt;
+
+    /// Build a column file: length-prefixed JSON descriptor + binary data.
+    fn build_column(descriptor: &str, binary: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_i32::<BigEndian>(descriptor.len() as i32).unwrap();
+        buf.extend_from_slice(descriptor.as_bytes());
+        buf.extend_from_slice(binary);
+        buf
+    }
+
+    /// Build LZ4-compressed CompressedColumnar{Longs,Floats,Doubles}
+    /// (version 0x02) from each value's little-endian bytes.
+    fn build_compressed(values: &[Vec<u8>], size_per: usize) -> Vec<u8> {
+        let blocks: Vec<Vec<u8>> = values
+            .chunks(size_per)
+            .map(|chunk| lz4_flex::block::compress(&chunk.concat()))
+            .collect();
+
+        let mut buf = vec![0x02];
+        buf.write_i32::<BigEndian>(values.len() as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push(0x01); // LZ4
+
+        // GenericIndexed V1 of blocks, each prefixed with a 0 null marker
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for block in &blocks {
+            body.write_i32::<BigEndian>(0).unwrap();
+            body.extend_from_slice(block);
+            offsets.push(body.len() as i32);
+        }
+        buf.push(0x01);
+        buf.push(0x00);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(blocks.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    /// Build LZ4-compressed little-endian CompressedColumnarLongs (version 0x02).
+    fn build_compressed_longs(values: &[i64], size_per: usize) -> Vec<u8> {
+        let values: Vec<Vec<u8>> = values.iter().map(|v| v.to_le_bytes().to_vec()).collect();
+        build_compressed(&values, size_per)
+    }
+
+    /// Build a numeric V2 payload from compressed values and an optional
+    /// null bitmap.
+    fn build_numeric_v2(compressed: &[u8], nulls: Option<&RoaringBitmap>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_i32::<BigEndian>(compressed.len() as i32).unwrap();
+        buf.extend_from_slice(compressed);
+        if let Some(nulls) = nulls {
+            let mut bitmap = Vec::new();
+            nulls.serialize_into(&mut bitmap).unwrap();
+            buf.write_i32::<BigEndian>(bitmap.len() as i32).unwrap();
+            buf.extend_from_slice(&bitmap);
+        }
+        buf
+    }
+
+    /// Build a `longV2` payload with an optional null bitmap.
+    fn build_long_v2(values: &[i64], nulls: Option<&RoaringBitmap>) -> Vec<u8> {
+        build_numeric_v2(&build_compressed_longs(values, 2), nulls)
+    }
+
+    const LONG_V2_DESCRIPTOR: &str = r#"{"valueType":"LONG","hasMultipleValues":false,"parts":[{"type":"longV2","byteOrder":"LITTLE_ENDIAN","bitmapSerdeFactory":{"type":"roaring"}}]}"#;
+
+    #[test]
+    fn test_long_v2_with_null_bitmap() {
+        let nulls: RoaringBitmap = [1, 3].into_iter().collect();
+        let data = build_column(
+            LONG_V2_DESCRIPTOR,
+            &build_long_v2(&[10, 0, 30, 0, 50], Some(&nulls)),
+        );
+
+        let (_, array) = read_column("metric", &data).unwrap();
+        let array = array.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(array.len(), 5);
+        assert_eq!(array.null_count(), 2);
+        for i in 0..5 {
+            assert_eq!(array.is_null(i), i == 1 || i == 3, "row {}", i);
+        }
+        assert_eq!(array.value(0), 10);
+        assert_eq!(array.value(2), 30);
+        assert_eq!(array.value(4), 50);
+    }
+
+    #[test]
+    fn test_null_bitmap_prefixed_and_unprefixed() {
+        let nulls: RoaringBitmap = [0, 2, 4].into_iter().collect();
+        let values = build_compressed_longs(&[0, 7, 0, 9, 0], 2);
+        let mut bitmap = Vec::new();
+        nulls.serialize_into(&mut bitmap).unwrap();
+
+        // Sniffed: a Roaring type byte, and no factory on the part
+        let mut prefixed = Vec::new();
+        prefixed
+            .write_i32::<BigEndian>(values.len() as i32)
+            .unwrap();
+        prefixed.extend_from_slice(&values);
+        prefixed
+            .write_i32::<BigEndian>(bitmap.len() as i32 + 1)
+            .unwrap();
+        prefixed.push(0x01);
+        prefixed.extend_from_slice(&bitmap);
+        let descriptor = r#"{"valueType":"LONG","hasMultipleValues":false,"parts":[{"type":"longV2","byteOrder":"LITTLE_ENDIAN"}]}"#;
+        let (_, sniffed) = read_column("metric", &build_column(descriptor, &prefixed)).unwrap();
+
+        // Known factory: the bare portable Roaring bitmap
+        let unprefixed = build_numeric_v2(&values, Some(&nulls));
+        let (_, known) =
+            read_column("metric", &build_column(LONG_V2_DESCRIPTOR, &unprefixed)).unwrap();
+
+        assert_eq!(sniffed.as_ref(), known.as_ref());
+        assert_eq!(known.null_count(), 3);
+        assert!((0..5).all(|i| known.is_null(i) == nulls.contains(i as u32)));
+
+        assert_eq!(
+            bitmap::read_bitmap_with_factory(&bitmap, BitmapSerdeFactory::Roaring).unwrap(),
+            bitmap::read_bitmap(&prefixed[prefixed.len() - bitmap.len() - 1..]).unwrap()
+        );
+        assert!(bitmap::read_bitmap_with_factory(&bitmap, BitmapSerdeFactory::Concise).is_err());
+    }
+
+    #[test]
+    fn test_long_v2_entire_layout() {
+        // Written with the `none` strategy: raw big-endian longs, no blocks
+        let mut values = vec![0x02];
+        values.write_i32::<BigEndian>(4).unwrap();
+        values.write_i32::<BigEndian>(0).unwrap();
+        values.push(0xFE);
+        for v in [10i64, 0, -30, i64::MAX] {
+            values.write_i64::<BigEndian>(v).unwrap();
+        }
+        let nulls: RoaringBitmap = [1].into_iter().collect();
+        let data = build_column(
+            r#"{"valueType":"LONG","hasMultipleValues":false,"parts":[{"type":"longV2","byteOrder":"BIG_ENDIAN","bitmapSerdeFactory":{"type":"roaring"}}]}"#,
+            &build_numeric_v2(&values, Some(&nulls)),
+        );
+
+        let (_, array) = read_column("metric", &data).unwrap();
+        let array = array.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(
+            array,
+            &Int64Array::from(vec![Some(10), None, Some(-30), Some(i64::MAX)])
+        );
+    }
+
+    #[test]
+    fn test_double_v2_with_null_bitmap() {
+        let nulls: RoaringBitmap = [1, 3].into_iter().collect();
+        let values: Vec<Vec<u8>> = [1.5f64, 0.0, -2.5, 0.0, 4.0]
+            .iter()
+            .map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        let descriptor = LONG_V2_DESCRIPTOR
+            .replace("LONG", "DOUBLE")
+            .replace("longV2", "doubleV2");
+        let data = build_column(
+            &descriptor,
+            &build_numeric_v2(&build_compressed(&values, 2), Some(&nulls)),
+        );
+
+        let (_, array) = read_column("metric", &data).unwrap();
+        let array = array.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(array.null_count(), 2);
+        for i in 0..5 {
+            assert_eq!(array.is_null(i), i == 1 || i == 3, "row {}", i);
+        }
+        assert_eq!(array.value(2), -2.5);
+        assert_eq!(array.value(4), 4.0);
+    }
+
+    #[test]
+    fn test_float_v2_with_null_bitmap() {
+        let nulls: RoaringBitmap = [1, 3].into_iter().collect();
+        let values: Vec<Vec<u8>> = [0.25f32, 0.0, 8.0, 0.0]
+            .iter()
+            .map(|v| v.to_le_bytes().to_vec())
+            .collect();
+        let descriptor = LONG_V2_DESCRIPTOR
+            .replace("LONG", "FLOAT")
+            .replace("longV2", "floatV2");
+        let data = build_column(
+            &descriptor,
+            &build_numeric_v2(&build_compressed(&values, 3), Some(&nulls)),
+        );
+
+        let (_, array) = read_column("metric", &data).unwrap();
+        let array = array.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(array.len(), 4);
+        assert_eq!(array.null_count(), 2);
+        for i in 0..4 {
+            assert_eq!(array.is_null(i), i == 1 || i == 3, "row {}", i);
+        }
+        assert_eq!(array.value(0), 0.25);
+        assert_eq!(array.value(2), 8.0);
+    }
+
+    #[test]
+    fn test_long_v2_without_null_bitmap() {
+        let data = build_column(LONG_V2_DESCRIPTOR, &build_long_v2(&[1, 2, 3], None));
+
+        let (_, array) = read_column("metric", &data).unwrap();
+        let array = array.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(array.null_count(), 0);
+        assert!(array.nulls().is_none());
+        assert_eq!(array.values().as_ref(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_cancelled_read() {
+        let data = build_column(LONG_V2_DESCRIPTOR, &build_long_v2(&[1, 2, 3, 4, 5], None));
+        let token = CancellationToken::new();
+        let options = ReadOptions::default().with_cancellation(token.clone());
+        assert!(read_column_with_options("metric", &data, &options).is_ok());
+
+        token.cancel();
+        let err = read_column_with_options("metric", &data, &options).unwrap_err();
+        assert!(matches!(err, DruidSegmentError::Cancelled));
+    }
+
+    #[test]
+    fn test_cancelled_between_blocks() {
+        let longs = build_compressed_longs(&[1, 2, 3, 4, 5], 2);
+        let token = CancellationToken::new();
+        let reader = compressed_longs::CompressedColumnarLongs::from_bytes_with_order(
+            &longs,
+            ByteOrder::LittleEndian,
+        )
+        .unwrap()
+        .with_cancellation(Some(token.clone()));
+
+        token.cancel();
+        let err = reader.decompress_all().unwrap_err();
+        assert!(matches!(err, DruidSegmentError::Cancelled));
+    }
+
+    #[test]
+    fn test_null_bitmap_out_of_range() {
+        let nulls: RoaringBitmap = [7].into_iter().collect();
+        let data = build_column(LONG_V2_DESCRIPTOR, &build_long_v2(&[1, 2, 3], Some(&nulls)));
+        assert!(read_column("metric", &data).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_part_type() {
+        let descriptor = r#"{"valueType":"STRING","parts":[{"type":"frontCoded"}]}"#;
+        let err = read_column("page", &build_column(descriptor, &[])).unwrap_err();
+        // The part starts right after the length-prefixed descriptor
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to read column 'page' at byte {}: Unsupported column type: \
+                 unsupported part type 'frontCoded'",
+                4 + descriptor.len()
+            )
+        );
+    }
+
+    #[test]
+    fn test_part_type_must_match_value_type() {
+        let descriptor = r#"{"valueType":"DOUBLE","parts":[{"type":"longV2"}]}"#;
+        let data = build_column(descriptor, &build_long_v2(&[1, 2], None));
+        let err = read_column("metric", &data).unwrap_err();
+        assert!(matches!(
+            err,
+            DruidSegmentError::ColumnReadError { ref column, ref source, .. }
+                if column == "metric" && matches!(**source, DruidSegmentError::ColumnDescriptorError(_))
+        ));
+    }
+
+    #[test]
+    fn test_serde_version_must_match_data() {
+        // The legacy serde stores the compressed values without a size
+        // prefix, and takes version 0x02 values as well as 0x01 ones
+        let legacy =
+            r#"{"valueType":"LONG","parts":[{"type":"long","byteOrder":"LITTLE_ENDIAN"}]}"#;
+        let data = build_column(legacy, &build_compressed_longs(&[4, 5], 2));
+        let (_, array) = read_column("metric", &data).unwrap();
+        assert_eq!(
+            array.as_any().downcast_ref::<Int64Array>().unwrap(),
+            &Int64Array::from(vec![4, 5])
+        );
+
+        // A V2 serde over version 0x01 values
+        let mut values = build_compressed_longs(&[4, 5], 2);
+        values[0] = 0x01;
+        let data = build_column(LONG_V2_DESCRIPTOR, &build_numeric_v2(&values, None));
+        let err = read_column("metric", &data).unwrap_err();
+        assert!(matches!(
+            err,
+            DruidSegmentError::ColumnReadError { ref source, .. }
+                if matches!(
+                    **source,
+                    DruidSegmentError::SerdeVersionMismatch { ref serde_type, expected: 0x02, actual: 0x01 }
+                        if serde_type == "longV2"
+                )
+        ));
+        assert!(
+            err.to_string()
+                .contains("Part type 'longV2' holds version 0x2 data, found version 0x1"),
+            "{}",
+            err
+        );
+
+        let descriptor = r#"{"valueType":"DOUBLE","parts":[{"type":"doubleV2"}]}"#;
+        let mut values = build_compressed(&[1.0f64.to_be_bytes().to_vec()], 1);
+        values[0] = 0x03;
+        let data = build_column(descriptor, &build_numeric_v2(&values, None));
+        assert!(
+            read_column("metric", &data)
+                .unwrap_err()
+                .to_string()
+                .contains("found version 0x3")
+        );
+    }
+
+    #[test]
+    fn test_null_column_part() {
+        let descriptor = r#"{"valueType":"LONG","parts":[{"type":"nullColumn","numRows":3}]}"#;
+        let data = build_column(descriptor, &[]);
+        let (_, array) = read_column("metric", &data).unwrap();
+        assert_eq!(array.data_type(), &arrow::datatypes::DataType::Int64);
+        assert_eq!((array.len(), array.null_count()), (3, 3));
+
+        let options = ReadOptions::default().with_limit(2);
+        let (_, array) = read_column_with_options("metric", &data, &options).unwrap();
+        assert_eq!(array.len(), 2);
+    }
+
+    #[test]
+    fn test_parts_read_in_sequence() {
+        // The second part starts after the first one's null bitmap
+        let descriptor = r#"{"valueType":"LONG","parts":[{"type":"longV2","byteOrder":"LITTLE_ENDIAN"},{"type":"longV2","byteOrder":"LITTLE_ENDIAN"}]}"#;
+        let nulls: RoaringBitmap = [1].into_iter().collect();
+        let mut binary = build_long_v2(&[1, 0, 3], Some(&nulls));
+        binary.extend(build_long_v2(&[4, 5, 6], None));
+        let err = read_column("metric", &build_column(descriptor, &binary)).unwrap_err();
+        assert!(
+            err.to_string().contains("more than one value part"),
+            "{}",
+            err
+        );
+    }
 }
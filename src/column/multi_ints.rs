@@ -0,0 +1,277 @@
+use super::compressed_ints::CompressedColumnarInts;
+use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+use crate::segment::read_options::ReadOptions;
+
+/// Reader for Druid's compressed multi-value int rows
+/// (V3CompressedVSizeColumnarMultiIntsSupplier), the row → dictionary ids
+/// mapping of compressed multi-value string dimensions.
+///
+/// Layout:
+/// ```text
+/// [version: u8 = 0x03]
+/// [offsets: fixed-width CompressedColumnarInts]  -- num_rows + 1 entries
+/// [values: CompressedColumnarInts]               -- every row's ids, concatenated
+/// ```
+///
+/// The offsets start at 0 and row `i` holds `values[offsets[i]..offsets[i + 1]]`.
+/// Both sections are decompressed when the reader is built.
+#[derive(Debug)]
+pub struct CompressedVSizeColumnarMultiInts {
+    offsets: Vec<u32>,
+    values: Vec<u32>,
+}
+
+const VERSION: u8 = 0x03;
+
+impl CompressedVSizeColumnarMultiInts {
+    /// Parse and decompress from raw bytes, assuming big-endian values.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_options(data, ByteOrder::BigEndian, &ReadOptions::default())
+    }
+
+    /// Parse and decompress rows whose values use `byte_order`.
+    ///
+    /// Only the rows allowed by the options' offset and limit are
+    /// decompressed, and the cancellation token is checked before each
+    /// compressed block.
+    pub fn from_bytes_with_options(
+        data: &[u8],
+        byte_order: ByteOrder,
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        let (offset_ints, value_ints) = Self::sections(data, byte_order)?;
+        let offset_ints = offset_ints.with_cancellation(options.cancellation.clone());
+        let value_ints = value_ints.with_cancellation(options.cancellation.clone());
+
+        let num_rows = offset_ints.len().saturating_sub(1);
+        if let Some(&first) = offset_ints.decompress_range(0..1)?.first()
+            && first != 0
+        {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "CompressedVSizeColumnarMultiInts: first offset is {}, expected 0",
+                first
+            )));
+        }
+        let rows = options.row_range(num_rows);
+        let mut offsets = offset_ints.decompress_range(rows.start..rows.end + 1)?;
+        if offsets.windows(2).any(|w| w[0] > w[1]) {
+            return Err(DruidSegmentError::InvalidData(
+                "CompressedVSizeColumnarMultiInts: offsets are not non-decreasing".into(),
+            ));
+        }
+
+        let num_values = offsets.last().copied().unwrap_or(0) as usize;
+        if num_values > value_ints.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "CompressedVSizeColumnarMultiInts: offsets reference {} values but only {} are stored",
+                num_values,
+                value_ints.len()
+            )));
+        }
+        let values_start = offsets.first().copied().unwrap_or(0);
+        let values = value_ints.decompress_range(values_start as usize..num_values)?;
+        // Rebase the offsets onto the decoded values
+        for offset in &mut offsets {
+            *offset -= values_start;
+        }
+
+        Ok(Self { offsets, values })
+    }
+
+    /// Total bytes consumed by the structure at the start of `data`,
+    /// found from the section headers without decompressing anything.
+    pub fn total_bytes(data: &[u8], byte_order: ByteOrder) -> Result<usize> {
+        let (offset_ints, value_ints) = Self::sections(data, byte_order)?;
+        Ok(1 + offset_ints.total_bytes()? + value_ints.total_bytes()?)
+    }
+
+    /// Parse the version byte and the headers of the offsets and values.
+    fn sections(
+        data: &[u8],
+        byte_order: ByteOrder,
+    ) -> Result<(CompressedColumnarInts<'_>, CompressedColumnarInts<'_>)> {
+        let version = *data.first().ok_or_else(|| {
+            DruidSegmentError::InvalidData("CompressedVSizeColumnarMultiInts: empty data".into())
+        })?;
+        if version != VERSION {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "CompressedVSizeColumnarMultiInts: unsupported version {:#x}, expected {:#x}",
+                version, VERSION
+            )));
+        }
+
+        let offset_ints = CompressedColumnarInts::from_fixed_width_bytes(&data[1..], byte_order)?;
+        let values_start = 1 + offset_ints.total_bytes()?;
+        let value_ints = CompressedColumnarInts::from_bytes_with_order(
+            data.get(values_start..).unwrap_or_default(),
+            byte_order,
+        )?;
+        Ok((offset_ints, value_ints))
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// Whether there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the values of row `row`.
+    pub fn get(&self, row: usize) -> Result<&[u32]> {
+        if row >= self.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "CompressedVSizeColumnarMultiInts: row {} out of bounds (len={})",
+                row,
+                self.len()
+            )));
+        }
+        let start = self.offsets[row] as usize;
+        let end = self.offsets[row + 1] as usize;
+        Ok(&self.values[start..end])
+    }
+
+    /// Read all rows into a Vec of rows.
+    pub fn to_vecs(&self) -> Vec<Vec<u32>> {
+        self.offsets
+            .windows(2)
+            .map(|w| self.values[w[0] as usize..w[1] as usize].to_vec())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    /// Wrap LZ4-compressed blocks of raw bytes in a GenericIndexed V1.
+    fn write_blocks(buf: &mut Vec<u8>, raw_blocks: &[Vec<u8>]) {
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for raw in raw_blocks {
+            body.write_i32::<BigEndian>(0).unwrap();
+            body.extend_from_slice(&lz4_flex::block::compress(raw));
+            offsets.push(body.len() as i32);
+        }
+        buf.extend_from_slice(&[0x01, 0x00]);
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(raw_blocks.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+    }
+
+    /// Build a V3 multi-int column from rows: little-endian 4-byte offsets
+    /// and 1-byte values, each LZ4-compressed in blocks of `size_per`.
+    fn build_multi_ints(rows: &[&[u32]], size_per: usize) -> Vec<u8> {
+        let mut offsets = vec![0u32];
+        for row in rows {
+            offsets.push(offsets.last().unwrap() + row.len() as u32);
+        }
+        let values: Vec<u32> = rows.iter().flat_map(|r| r.iter().copied()).collect();
+
+        let mut buf = vec![VERSION];
+        buf.push(0x02);
+        buf.write_i32::<BigEndian>(offsets.len() as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push(0x01); // LZ4
+        let offset_blocks: Vec<Vec<u8>> = offsets
+            .chunks(size_per)
+            .map(|c| c.iter().flat_map(|v| v.to_le_bytes()).collect())
+            .collect();
+        write_blocks(&mut buf, &offset_blocks);
+
+        buf.extend_from_slice(&[0x02, 1]);
+        buf.write_i32::<BigEndian>(values.len() as i32).unwrap();
+        buf.write_i32::<BigEndian>(size_per as i32).unwrap();
+        buf.push(0x01); // LZ4
+        let value_blocks: Vec<Vec<u8>> = values
+            .chunks(size_per)
+            .map(|c| c.iter().map(|&v| v as u8).collect())
+            .collect();
+        write_blocks(&mut buf, &value_blocks);
+        buf
+    }
+
+    fn read(data: &[u8], options: &ReadOptions) -> CompressedVSizeColumnarMultiInts {
+        CompressedVSizeColumnarMultiInts::from_bytes_with_options(
+            data,
+            ByteOrder::LittleEndian,
+            options,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_empty_rows() {
+        let rows: &[&[u32]] = &[&[], &[4], &[], &[], &[1, 2], &[]];
+        let ints = read(&build_multi_ints(rows, 4), &ReadOptions::default());
+        assert_eq!(ints.len(), rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(ints.get(i).unwrap(), *row, "row {}", i);
+        }
+        assert!(ints.get(rows.len()).is_err());
+        assert_eq!(ints.to_vecs(), rows);
+    }
+
+    #[test]
+    fn test_rows_span_block_boundaries() {
+        // With 3 values per block, rows 1 and 3 straddle value blocks and the
+        // offsets themselves span three blocks.
+        let rows: &[&[u32]] = &[&[0, 1], &[2, 3, 4, 5, 6], &[7], &[8, 9, 10, 11], &[12]];
+        let ints = read(&build_multi_ints(rows, 3), &ReadOptions::default());
+        assert_eq!(ints.len(), rows.len());
+        assert_eq!(ints.get(1).unwrap(), &[2, 3, 4, 5, 6]);
+        assert_eq!(ints.get(3).unwrap(), &[8, 9, 10, 11]);
+        assert_eq!(ints.to_vecs(), rows);
+    }
+
+    #[test]
+    fn test_total_bytes() {
+        let mut data = build_multi_ints(&[&[1, 2], &[], &[3]], 2);
+        let len = data.len();
+        data.extend_from_slice(b"bitmaps");
+        assert_eq!(
+            CompressedVSizeColumnarMultiInts::total_bytes(&data, ByteOrder::LittleEndian).unwrap(),
+            len
+        );
+    }
+
+    #[test]
+    fn test_no_rows() {
+        let ints = read(&build_multi_ints(&[], 4), &ReadOptions::default());
+        assert!(ints.is_empty());
+        assert!(ints.to_vecs().is_empty());
+    }
+
+    #[test]
+    fn test_limit_decodes_prefix() {
+        let rows: &[&[u32]] = &[&[1], &[2, 3], &[], &[4, 5, 6]];
+        let data = build_multi_ints(rows, 2);
+        let ints = read(&data, &ReadOptions::default().with_limit(2));
+        assert_eq!(ints.to_vecs(), &rows[..2]);
+    }
+
+    #[test]
+    fn test_offset_skips_rows() {
+        let rows: &[&[u32]] = &[&[1], &[2, 3], &[], &[4, 5, 6], &[7]];
+        let data = build_multi_ints(rows, 2);
+        let ints = read(&data, &ReadOptions::default().with_offset(1).with_limit(3));
+        assert_eq!(ints.to_vecs(), &rows[1..4]);
+        let ints = read(&data, &ReadOptions::default().with_offset(9));
+        assert!(ints.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut data = build_multi_ints(&[&[1]], 4);
+        data[0] = 0x02;
+        assert!(CompressedVSizeColumnarMultiInts::from_bytes(&data).is_err());
+    }
+}
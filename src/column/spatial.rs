@@ -0,0 +1,137 @@
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use crate::error::{DruidSegmentError, Result};
+
+/// Version byte of Druid's `ImmutableRTree` serialization.
+const RTREE_VERSION: u8 = 0x00;
+
+/// Bytes before the root node: the version and the number of dimensions.
+const RTREE_HEADER_SIZE: usize = 5;
+
+/// The spatial index of a dimension declared in `spatialDimensions`: an
+/// immutable R-tree over the dimension's coordinates, stored after the
+/// column's bitmap indexes.
+///
+/// Only the bounding box of the root node is read, which covers every
+/// point in the column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpatialIndex {
+    /// Number of coordinate dimensions.
+    pub num_dims: usize,
+    /// Lowest coordinate in each dimension, empty if the tree is empty.
+    pub min: Vec<f32>,
+    /// Highest coordinate in each dimension, empty if the tree is empty.
+    pub max: Vec<f32>,
+}
+
+impl SpatialIndex {
+    /// Parse a size-prefixed R-tree, as Druid's `ImmutableRTreeObjectStrategy`
+    /// writes it:
+    /// ```text
+    /// [size: i32]
+    /// [version: u8][num_dims: i32]
+    /// [root node: header: u16][min: f32 * num_dims][max: f32 * num_dims]...
+    /// ```
+    /// Returns the index and the number of bytes it takes, size included.
+    pub fn from_bytes(data: &[u8]) -> Result<(Self, usize)> {
+        let mut cursor = Cursor::new(data);
+        let size = cursor.read_i32::<BigEndian>()?;
+        let end = usize::try_from(size)
+            .ok()
+            .and_then(|size| size.checked_add(4))
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!(
+                    "Spatial index: size {} exceeds the {} bytes left",
+                    size,
+                    data.len().saturating_sub(4)
+                ))
+            })?;
+        let tree = &data[4..end];
+        if tree.is_empty() {
+            let empty = Self {
+                num_dims: 0,
+                min: Vec::new(),
+                max: Vec::new(),
+            };
+            return Ok((empty, end));
+        }
+
+        let mut cursor = Cursor::new(tree);
+        let version = cursor.read_u8()?;
+        if version != RTREE_VERSION {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "Spatial index: unsupported R-tree version {:#x}",
+                version
+            )));
+        }
+        let num_dims = cursor.read_i32::<BigEndian>()?;
+        let num_dims = usize::try_from(num_dims).map_err(|_| {
+            DruidSegmentError::InvalidData(format!(
+                "Spatial index: negative dimension count {}",
+                num_dims
+            ))
+        })?;
+        if tree.len() == RTREE_HEADER_SIZE {
+            let empty = Self {
+                num_dims,
+                min: Vec::new(),
+                max: Vec::new(),
+            };
+            return Ok((empty, end));
+        }
+
+        // The root node's header holds its leaf flag and child count
+        cursor.read_u16::<BigEndian>()?;
+        let mut read_coordinates = || {
+            (0..num_dims)
+                .map(|_| cursor.read_f32::<BigEndian>())
+                .collect::<std::io::Result<Vec<_>>>()
+        };
+        let min = read_coordinates()?;
+        let max = read_coordinates()?;
+        Ok((Self { num_dims, min, max }, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    /// Build a size-prefixed R-tree whose root is a leaf with no children.
+    fn build_rtree(min: &[f32], max: &[f32]) -> Vec<u8> {
+        let mut tree = vec![RTREE_VERSION];
+        tree.write_i32::<BigEndian>(min.len() as i32).unwrap();
+        tree.write_u16::<BigEndian>(0x8000).unwrap();
+        for &c in min.iter().chain(max) {
+            tree.write_f32::<BigEndian>(c).unwrap();
+        }
+        // The root's (empty) bitmap
+        tree.write_i32::<BigEndian>(0).unwrap();
+
+        let mut buf = Vec::new();
+        buf.write_i32::<BigEndian>(tree.len() as i32).unwrap();
+        buf.extend(tree);
+        buf
+    }
+
+    #[test]
+    fn test_root_bounds() {
+        let data = build_rtree(&[-1.5, 2.0], &[3.0, 4.25]);
+        let (index, size) = SpatialIndex::from_bytes(&data).unwrap();
+        assert_eq!(size, data.len());
+        assert_eq!(index.num_dims, 2);
+        assert_eq!(index.min, vec![-1.5, 2.0]);
+        assert_eq!(index.max, vec![3.0, 4.25]);
+    }
+
+    #[test]
+    fn test_size_overrun() {
+        let mut data = build_rtree(&[0.0], &[1.0]);
+        data.truncate(data.len() - 1);
+        assert!(SpatialIndex::from_bytes(&data).is_err());
+    }
+}
@@ -1,12 +1,44 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::sync::{Arc, Mutex};
 
-use arrow::array::StringArray;
+use arrow::array::{
+    Array, ArrayRef, AsArray, DictionaryArray, Int32Array, ListArray, ListBuilder, StringArray,
+    StringBuilder,
+};
+use arrow::datatypes::Int32Type;
 use byteorder::{BigEndian, ReadBytesExt};
+use roaring::RoaringBitmap;
 
+use super::bitmap::{read_bitmap, write_bitmap};
 use super::compressed_ints::CompressedColumnarInts;
-use super::generic_indexed::GenericIndexedV1;
-use super::vsize_ints::VSizeColumnarInts;
+use super::front_coded::FrontCodedIndexed;
+use super::generic_indexed::{GenericIndexed, GenericIndexedWriter};
+use super::multi_ints::CompressedVSizeColumnarMultiInts;
+use super::spatial::SpatialIndex;
+use super::vsize_ints::{VSizeColumnarInts, VSizeColumnarMultiInts};
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::column_descriptor::ByteOrder;
+use crate::segment::read_options::{NullHandling, ReadOptions};
+use crate::segment::smoosh::SmooshReader;
+
+/// Column serialization versions of dictionary-encoded string columns.
+const VERSION_UNCOMPRESSED_SINGLE_VALUE: u8 = 0x00;
+const VERSION_UNCOMPRESSED_MULTI_VALUE: u8 = 0x01;
+const VERSION_COMPRESSED: u8 = 0x02;
+const VERSION_UNCOMPRESSED_WITH_FLAGS: u8 = 0x03;
+
+/// Feature flags stored after the version byte of versions 0x02 and 0x03.
+const FLAG_MULTI_VALUE: i32 = 1 << 0;
+const FLAG_MULTI_VALUE_V3: i32 = 1 << 1;
+const FLAG_NO_BITMAP_INDEX: i32 = 1 << 2;
+
+/// Marker byte that precedes a dictionary written by Druid's
+/// `EncodedStringDictionaryWriter`, followed by the encoding id.
+const ENCODED_DICTIONARY_MARKER: u8 = 0x7F;
+const ENCODING_FRONT_CODED: u8 = 0x01;
 
 /// Read a dictionary-encoded string column from its binary data
 /// (after the JSON header).
@@ -14,112 +46,1120 @@ use crate::error::{DruidSegmentError, Result};
 /// Binary layout:
 /// ```text
 /// [version: u8]         -- column serialization version
-/// [flags/feature_mask depending on version]
-/// [dictionary: GenericIndexed<String>]
-/// [encoded_values: CompressedColumnarInts or VSizeColumnarInts]
-/// [bitmap serializer: optional, skipped]
+/// [flags: i32]          -- feature flags, versions 0x02 and 0x03 only
+/// [dictionary: GenericIndexed<String> or FrontCodedIndexed]
+/// [encoded_values: VSizeColumnarInts or CompressedColumnarInts]
+/// [bitmaps: GenericIndexed<Bitmap>, absent with FLAG_NO_BITMAP_INDEX]
+/// [spatial index: optional, see SpatialIndex]
 /// ```
 ///
 /// The version byte determines the exact layout:
-/// - 0x00: Legacy uncompressed (VSizeColumnarInts for values)
+/// - 0x00: Legacy uncompressed single-value (VSizeColumnarInts for values)
+/// - 0x01: Legacy uncompressed multi-value (VSizeColumnarMultiInts)
 /// - 0x02: Compressed with CompressedColumnarInts for values
-/// - 0x03: Compressed with additional feature flags
+/// - 0x03: Uncompressed with feature flags
+///
+/// Columns written with `stringEncodingStrategy: frontCoded` mark their
+/// dictionary with a leading 0x7F byte and the encoding id, then store a
+/// [`FrontCodedIndexed`] instead of a GenericIndexed.
 pub fn read_string_column(data: &[u8]) -> Result<StringArray> {
-    if data.is_empty() {
+    read_string_column_with_options(data, ByteOrder::default(), None, &ReadOptions::default())
+}
+
+/// Read a single-value string column whose compressed values use `byte_order`.
+pub fn read_string_column_with_options(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+) -> Result<StringArray> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    read_single_values(&layout, byte_order, options)
+}
+
+/// Dictionaries of a segment's string columns built as Arrow arrays, by
+/// column name, so that reads returning dictionary arrays share them.
+pub(crate) type DictionaryCache = Mutex<HashMap<String, ArrayRef>>;
+
+/// Read a single-value string column as a dictionary array, keeping its
+/// encoding: the values are the column's dictionary, built once, and the
+/// keys are the decoded ids of the rows `options` selects. Rows whose id is
+/// the null dictionary entry get a null key.
+pub fn read_string_column_as_dictionary(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+) -> Result<DictionaryArray<Int32Type>> {
+    read_dictionary_array(data, byte_order, smoosh, options, None)
+}
+
+/// Like [`read_string_column_as_dictionary`], but taking the dictionary
+/// from `cached`, a cache and the column's name in it, if it is there, and
+/// adding it otherwise.
+pub(crate) fn read_dictionary_array(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+    cached: Option<(&str, &DictionaryCache)>,
+) -> Result<DictionaryArray<Int32Type>> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    if layout.is_multi_value() {
         return Err(DruidSegmentError::InvalidData(
-            "String column: empty data".into(),
+            "String column: multi-value data read as a single-value column".into(),
         ));
     }
 
-    let version = data[0];
+    let ids = read_ids(&layout, byte_order, options)?;
+    let values = match cached {
+        Some((name, cache)) => {
+            let mut cache = cache.lock().expect("dictionary cache lock poisoned");
+            match cache.get(name) {
+                Some(values) => values.clone(),
+                None => {
+                    let values = dictionary_values(&layout.dictionary)?;
+                    cache.insert(name.to_string(), values.clone());
+                    values
+                }
+            }
+        }
+        None => dictionary_values(&layout.dictionary)?,
+    };
+    // Ids past the dictionary are left for try_new to reject. The cached
+    // values are shared by every null handling, so it applies to the keys.
+    let strings = values.as_string::<i32>();
+    let keys: Int32Array = ids
+        .iter()
+        .map(|&id| {
+            let null = (id as usize) < strings.len()
+                && options
+                    .null_handling
+                    .apply(
+                        strings
+                            .is_valid(id as usize)
+                            .then(|| strings.value(id as usize)),
+                    )
+                    .is_none();
+            (!null).then_some(id as i32)
+        })
+        .collect();
+    Ok(DictionaryArray::try_new(keys, values)?)
+}
 
-    match version {
-        0x00 => read_string_v0(data),
-        0x02 => read_string_v2(data),
-        0x03 => read_string_v3(data),
-        other => Err(DruidSegmentError::InvalidData(format!(
-            "String column: unsupported version {:#x}",
-            other
-        ))),
-    }
+/// Every entry of a dictionary, in id order.
+fn dictionary_values(dictionary: &Dictionary<'_>) -> Result<ArrayRef> {
+    let values = (0..dictionary.len() as u32)
+        .map(|id| dictionary.get_str(id))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Arc::new(StringArray::from_iter(values)))
 }
 
-/// V0: Legacy uncompressed format.
-/// Layout: [version=0x00][dictionary: GenericIndexed][values: VSizeColumnarInts]
-fn read_string_v0(data: &[u8]) -> Result<StringArray> {
-    let offset = 1; // skip version byte
+/// A single-value string column read in full: its values, its indexes,
+/// and where its data ends.
+pub struct StringColumn<'a> {
+    values: StringArray,
+    index: Option<StringColumnIndex<'a>>,
+    consumed: usize,
+}
+
+impl<'a> StringColumn<'a> {
+    /// Read the values of a single-value string column and locate the
+    /// bitmap and spatial indexes that follow them.
+    pub fn from_bytes(
+        data: &'a [u8],
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+        let values = read_single_values(&layout, byte_order, options)?;
+        let (indexes, consumed) = layout.indexes(byte_order, smoosh)?;
+        let index = match indexes {
+            Some((bitmaps, spatial)) => Some(StringColumnIndex::from_sections(
+                layout.dictionary,
+                bitmaps,
+                spatial,
+            )?),
+            None => None,
+        };
+        Ok(Self {
+            values,
+            index,
+            consumed,
+        })
+    }
+
+    /// The column's values.
+    pub fn values(&self) -> &StringArray {
+        &self.values
+    }
+
+    /// Take the column's values.
+    pub fn into_values(self) -> StringArray {
+        self.values
+    }
+
+    /// The column's inverted indexes, or `None` if it was written without
+    /// them.
+    pub fn index(&self) -> Option<&StringColumnIndex<'a>> {
+        self.index.as_ref()
+    }
 
-    // Read dictionary
-    let dictionary = GenericIndexedV1::from_bytes(&data[offset..])?;
-    let dict_size = dictionary.total_size()?;
-    let values_offset = offset + dict_size;
+    /// Bytes the column takes, from its version byte to the end of its
+    /// last index.
+    pub fn total_consumed(&self) -> usize {
+        self.consumed
+    }
+}
 
-    // Read encoded values
-    let encoded = VSizeColumnarInts::from_bytes(&data[values_offset..])?;
+/// Decode the rows of a single-value string column that `options` selects.
+fn read_single_values(
+    layout: &StringColumnLayout<'_>,
+    byte_order: ByteOrder,
+    options: &ReadOptions,
+) -> Result<StringArray> {
+    if layout.is_multi_value() {
+        return Err(DruidSegmentError::InvalidData(
+            "String column: multi-value data read as a single-value column".into(),
+        ));
+    }
 
-    // Resolve dictionary IDs to strings
-    resolve_dictionary(&dictionary, &encoded.to_vec()?)
+    let ids = read_ids(layout, byte_order, options)?;
+    resolve_dictionary(&layout.dictionary, &ids, options.null_handling)
 }
 
-/// V2: Compressed format.
-/// Layout: [version=0x02][flags: i32][dictionary: GenericIndexed][values: CompressedColumnarInts]
-fn read_string_v2(data: &[u8]) -> Result<StringArray> {
-    if data.len() < 5 {
+/// Decode the dictionary ids of the single-value rows `options` selects.
+fn read_ids(
+    layout: &StringColumnLayout<'_>,
+    byte_order: ByteOrder,
+    options: &ReadOptions,
+) -> Result<Vec<u32>> {
+    match layout.version {
+        VERSION_COMPRESSED => {
+            let ints = CompressedColumnarInts::from_bytes_with_order(layout.values, byte_order)?
+                .with_cancellation(options.cancellation.clone());
+            ints.decompress_range(options.row_range(ints.len()))
+        }
+        _ => {
+            let mut ids = VSizeColumnarInts::from_bytes(layout.values)?.to_vec()?;
+            let range = options.row_range(ids.len());
+            ids.truncate(range.end);
+            ids.drain(..range.start);
+            Ok(ids)
+        }
+    }
+}
+
+/// Read a multi-value string column into a list of strings per row.
+///
+/// Rows with no values become empty lists; dictionary entries that are
+/// null become null list elements.
+pub fn read_multi_value_string_column(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+    options: &ReadOptions,
+) -> Result<ListArray> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    if !layout.is_multi_value() {
         return Err(DruidSegmentError::InvalidData(
-            "String column v2: data too short for flags".into(),
+            "String column: single-value data read as a multi-value column".into(),
         ));
     }
 
-    let mut cursor = Cursor::new(&data[1..]);
-    let _flags = cursor.read_i32::<BigEndian>()?;
-    let offset = 5; // version(1) + flags(4)
+    options.check_cancelled()?;
+    let rows = match layout.version {
+        VERSION_COMPRESSED => CompressedVSizeColumnarMultiInts::from_bytes_with_options(
+            layout.values,
+            byte_order,
+            options,
+        )?
+        .to_vecs(),
+        _ => {
+            let mut rows = VSizeColumnarMultiInts::from_bytes(layout.values)?.to_vecs()?;
+            let range = options.row_range(rows.len());
+            rows.truncate(range.end);
+            rows.drain(..range.start);
+            rows
+        }
+    };
 
-    // Read dictionary
-    let dictionary = GenericIndexedV1::from_bytes(&data[offset..])?;
-    let dict_size = dictionary.total_size()?;
-    let values_offset = offset + dict_size;
+    resolve_dictionary_rows(&layout.dictionary, &rows, options.null_handling)
+}
 
-    // Read compressed encoded values
-    let encoded = CompressedColumnarInts::from_bytes(&data[values_offset..])?;
-    let ids = encoded.decompress_all()?;
+/// Read the inverted-index bitmap of the rows containing `value`.
+/// Returns `None` if the dictionary does not contain `value`.
+pub fn read_value_bitmap(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+    value: &str,
+) -> Result<Option<RoaringBitmap>> {
+    StringColumnIndex::from_bytes(data, byte_order, smoosh)?.bitmap_for_value(value)
+}
 
-    resolve_dictionary(&dictionary, &ids)
+/// The inverted indexes of a string column: its dictionary and, for each
+/// dictionary entry, a bitmap of the rows holding that value.
+///
+/// The bitmaps are stored after the encoded values as a GenericIndexed of
+/// serialized bitmaps in dictionary order. Opening the index only locates
+/// that section; each bitmap is deserialized when it is asked for.
+///
+/// Dimensions declared in `spatialDimensions` also store an R-tree after
+/// the bitmaps, see [`spatial_index`](Self::spatial_index).
+pub struct StringColumnIndex<'a> {
+    dictionary: Dictionary<'a>,
+    bitmaps: GenericIndexed<'a>,
+    spatial: Option<&'a [u8]>,
 }
 
-/// V3: Compressed format with feature flags.
-/// Layout: [version=0x03][feature_mask: i32][dictionary: GenericIndexed][values: CompressedColumnarInts]
-fn read_string_v3(data: &[u8]) -> Result<StringArray> {
-    if data.len() < 5 {
-        return Err(DruidSegmentError::InvalidData(
-            "String column v3: data too short for feature mask".into(),
-        ));
+impl<'a> StringColumnIndex<'a> {
+    /// Locate the dictionary and bitmaps of a string column's binary data.
+    /// Fails if the column was written without bitmap indexes.
+    pub fn from_bytes(
+        data: &'a [u8],
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+    ) -> Result<Self> {
+        let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+        if layout.flags & FLAG_NO_BITMAP_INDEX != 0 {
+            return Err(DruidSegmentError::UnsupportedColumnType(
+                "string column written without bitmap indexes".into(),
+            ));
+        }
+        let (bitmaps, spatial) = layout.bitmap_sections(byte_order, smoosh)?;
+        Self::from_sections(layout.dictionary, bitmaps, spatial)
+    }
+
+    fn from_sections(
+        dictionary: Dictionary<'a>,
+        bitmaps: GenericIndexed<'a>,
+        spatial: Option<&'a [u8]>,
+    ) -> Result<Self> {
+        if bitmaps.len() != dictionary.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "String column: {} bitmaps for {} dictionary entries",
+                bitmaps.len(),
+                dictionary.len()
+            )));
+        }
+        Ok(Self {
+            dictionary,
+            bitmaps,
+            spatial,
+        })
+    }
+
+    /// The column's spatial index, or `None` if it is not a spatial
+    /// dimension.
+    pub fn spatial_index(&self) -> Result<Option<SpatialIndex>> {
+        self.spatial
+            .map(|data| SpatialIndex::from_bytes(data).map(|(index, _)| index))
+            .transpose()
+    }
+
+    /// Number of distinct values, null included if any row is null.
+    pub fn cardinality(&self) -> usize {
+        self.dictionary.len()
+    }
+
+    /// Number of bitmaps stored, one per dictionary entry.
+    pub fn bitmap_count(&self) -> usize {
+        self.bitmaps.len()
+    }
+
+    /// The dictionary value with id `id`; `None` is the null value.
+    pub fn value(&self, id: usize) -> Result<Option<Cow<'a, str>>> {
+        self.dictionary.get_str(id as u32)
+    }
+
+    /// The rows holding the value with dictionary id `id`.
+    pub fn bitmap(&self, id: usize) -> Result<RoaringBitmap> {
+        match self.bitmaps.get(id)? {
+            Some(bytes) => read_bitmap(bytes),
+            None => Ok(RoaringBitmap::new()),
+        }
+    }
+
+    /// The rows holding `value`, found by binary search of the dictionary.
+    /// Returns `None` if the dictionary does not contain `value`.
+    pub fn bitmap_for_value(&self, value: &str) -> Result<Option<RoaringBitmap>> {
+        match self.dictionary.find(value)? {
+            Some(id) => self.bitmap(id).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A string column's dictionary and encoded values, located once so that
+/// dictionary entries and single rows can be looked up without decoding
+/// the column.
+pub struct StringColumnValues<'a> {
+    layout: StringColumnLayout<'a>,
+    byte_order: ByteOrder,
+}
+
+impl<'a> StringColumnValues<'a> {
+    /// Locate the dictionary and encoded values of a string column's binary
+    /// data.
+    pub fn from_bytes(
+        data: &'a [u8],
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+    ) -> Result<Self> {
+        let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+        Ok(Self { layout, byte_order })
+    }
+
+    /// Whether rows hold lists of values.
+    pub fn is_multi_value(&self) -> bool {
+        self.layout.is_multi_value()
+    }
+
+    /// Number of dictionary entries, null included.
+    pub fn cardinality(&self) -> usize {
+        self.layout.dictionary.len()
+    }
+
+    /// The dictionary entry with id `id`; `None` is the null value.
+    pub fn dictionary_value(&self, id: usize) -> Result<Option<Cow<'a, str>>> {
+        if id >= self.cardinality() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "String column: dictionary id {} out of range for {} entries",
+                id,
+                self.cardinality()
+            )));
+        }
+        self.layout.dictionary.get_str(id as u32)
+    }
+
+    /// The dictionary entries in id order, which is sorted order with null
+    /// first.
+    pub fn dictionary(&self) -> impl Iterator<Item = Result<Option<Cow<'a, str>>>> + '_ {
+        (0..self.cardinality()).map(|id| self.layout.dictionary.get_str(id as u32))
+    }
+
+    /// The dictionary id of row `row` of a single-value column. For
+    /// compressed columns this decompresses the block holding the row.
+    pub fn id_at(&self, row: usize) -> Result<u32> {
+        if self.is_multi_value() {
+            return Err(DruidSegmentError::InvalidData(
+                "String column: multi-value data read as a single-value column".into(),
+            ));
+        }
+        match self.layout.version {
+            VERSION_COMPRESSED => {
+                let ints = CompressedColumnarInts::from_bytes_with_order(
+                    self.layout.values,
+                    self.byte_order,
+                )?;
+                if row >= ints.len() {
+                    return Err(DruidSegmentError::RowOutOfRange {
+                        offset: row,
+                        num_rows: ints.len(),
+                    });
+                }
+                Ok(ints.decompress_range(row..row + 1)?[0])
+            }
+            _ => {
+                let ints = VSizeColumnarInts::from_bytes(self.layout.values)?;
+                if row >= ints.len() {
+                    return Err(DruidSegmentError::RowOutOfRange {
+                        offset: row,
+                        num_rows: ints.len(),
+                    });
+                }
+                ints.get(row)
+            }
+        }
+    }
+
+    /// The value of row `row` of a single-value column.
+    pub fn value_at(&self, row: usize) -> Result<Option<Cow<'a, str>>> {
+        self.dictionary_value(self.id_at(row)? as usize)
+    }
+}
+
+/// Bytes taken by a string column's sections: the dictionary, the encoded
+/// values, and the bitmap and spatial indexes if present.
+pub(crate) fn part_size(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+) -> Result<usize> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    Ok(layout.indexes(byte_order, smoosh)?.1)
+}
+
+/// Whether a string column stores bitmap indexes, read from its flags.
+pub fn has_bitmap_index(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+) -> Result<bool> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    Ok(layout.flags & FLAG_NO_BITMAP_INDEX == 0)
+}
+
+/// Number of entries in a string column's dictionary, null included.
+pub fn dictionary_cardinality(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+) -> Result<usize> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    Ok(layout.dictionary.len())
+}
+
+/// Number of rows of a single-value string column, read from the header
+/// of its encoded values, or `None` for a multi-value one.
+pub fn row_count(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+) -> Result<Option<usize>> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    if layout.is_multi_value() {
+        return Ok(None);
+    }
+    let rows = match layout.version {
+        VERSION_COMPRESSED => {
+            CompressedColumnarInts::from_bytes_with_order(layout.values, byte_order)?.len()
+        }
+        _ => VSizeColumnarInts::from_bytes(layout.values)?.len(),
+    };
+    Ok(Some(rows))
+}
+
+/// Number of null rows in a single-value string column, or `None` for a
+/// multi-value one.
+///
+/// Null rows point at a null first dictionary entry, so a column without
+/// one has no nulls. Otherwise the count is read from that entry's bitmap
+/// index, or, for columns without indexes, by counting its id among the
+/// encoded values without looking any string up.
+pub fn null_count(
+    data: &[u8],
+    byte_order: ByteOrder,
+    smoosh: Option<&SmooshReader>,
+) -> Result<Option<usize>> {
+    let layout = StringColumnLayout::parse(data, byte_order, smoosh)?;
+    if layout.is_multi_value() {
+        return Ok(None);
+    }
+    if layout.dictionary.len() == 0 || layout.dictionary.get_str(0)?.is_some() {
+        return Ok(Some(0));
+    }
+    if let (Some((bitmaps, _)), _) = layout.indexes(byte_order, smoosh)? {
+        let nulls = match bitmaps.get(0)? {
+            Some(bytes) => read_bitmap(bytes)?.len() as usize,
+            None => 0,
+        };
+        return Ok(Some(nulls));
+    }
+    let ids = read_ids(&layout, byte_order, &ReadOptions::default())?;
+    Ok(Some(ids.iter().filter(|&&id| id == 0).count()))
+}
+
+/// Serialize a single-value string column as Druid's uncompressed version
+/// 0x00 layout, with a bitmap index per dictionary value:
+///
+/// ```text
+/// [version: u8 = 0x00]
+/// [dictionary: GenericIndexed<String>]  -- sorted, null first if present
+/// [ids: VSizeColumnarInts]
+/// [bitmaps: GenericIndexed<Bitmap>]     -- one per dictionary entry
+/// ```
+///
+/// The dictionary is sorted like Java strings, by UTF-16 code unit, so
+/// that it can be binary-searched.
+pub fn write_string_column(values: &StringArray) -> Result<Vec<u8>> {
+    let mut distinct: Vec<&str> = values.iter().flatten().collect();
+    distinct.sort_unstable_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+    distinct.dedup();
+
+    let has_null = values.null_count() > 0;
+    let first_id = u32::from(has_null);
+    let ids_by_value: HashMap<&str, u32> = distinct
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| (value, first_id + i as u32))
+        .collect();
+
+    let mut bitmaps = vec![RoaringBitmap::new(); distinct.len() + has_null as usize];
+    let ids: Vec<u32> = values
+        .iter()
+        .enumerate()
+        .map(|(row, value)| {
+            let id = value.map_or(0, |v| ids_by_value[v]);
+            bitmaps[id as usize].insert(row as u32);
+            id
+        })
+        .collect();
+
+    let dictionary = has_null
+        .then_some(None)
+        .into_iter()
+        .chain(distinct.iter().map(|&value| Some(value)));
+    let mut buf = vec![VERSION_UNCOMPRESSED_SINGLE_VALUE];
+    buf.extend(GenericIndexedWriter::write_strings(dictionary, true)?);
+    VSizeColumnarInts::write_into(&ids, &mut buf);
+    let bitmaps: Vec<Vec<u8>> = bitmaps.iter().map(write_bitmap).collect();
+    buf.extend(GenericIndexedWriter::write(
+        bitmaps.iter().map(|b| Some(b.as_slice())),
+        false,
+    )?);
+    Ok(buf)
+}
+
+/// A string column's bitmap indexes and the bytes of its spatial index.
+type BitmapSections<'a> = (GenericIndexed<'a>, Option<&'a [u8]>);
+
+/// The sections of a string column shared by every version: the version,
+/// feature flags, the dictionary, and the bytes of the encoded values
+/// (plus whatever follows them).
+struct StringColumnLayout<'a> {
+    version: u8,
+    flags: i32,
+    dictionary: Dictionary<'a>,
+    /// Offset of the encoded values in the column's data.
+    values_offset: usize,
+    values: &'a [u8],
+}
+
+/// A string column's value dictionary, in either of Druid's encodings.
+enum Dictionary<'a> {
+    Generic(GenericIndexed<'a>),
+    FrontCoded(FrontCodedIndexed<'a>),
+}
+
+impl<'a> Dictionary<'a> {
+    fn parse(
+        data: &'a [u8],
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+    ) -> Result<Self> {
+        if data.first() != Some(&ENCODED_DICTIONARY_MARKER) {
+            return Ok(Dictionary::Generic(GenericIndexed::from_bytes(
+                data, smoosh,
+            )?));
+        }
+        match data.get(1) {
+            Some(&ENCODING_FRONT_CODED) => Ok(Dictionary::FrontCoded(
+                FrontCodedIndexed::from_bytes(&data[2..], byte_order)?,
+            )),
+            other => Err(DruidSegmentError::InvalidData(format!(
+                "String column: unknown dictionary encoding {:?}",
+                other
+            ))),
+        }
+    }
+
+    /// Total number of bytes consumed by the dictionary, markers included.
+    fn total_size(&self) -> Result<usize> {
+        match self {
+            Dictionary::Generic(indexed) => indexed.total_size(),
+            Dictionary::FrontCoded(indexed) => Ok(2 + indexed.total_size()),
+        }
+    }
+
+    fn get_str(&self, id: u32) -> Result<Option<Cow<'a, str>>> {
+        match self {
+            Dictionary::Generic(indexed) => Ok(indexed.get_str(id as usize)?.map(Cow::Borrowed)),
+            Dictionary::FrontCoded(indexed) => Ok(indexed.get_str(id as usize)?.map(Cow::Owned)),
+        }
     }
 
-    let mut cursor = Cursor::new(&data[1..]);
-    let _feature_mask = cursor.read_i32::<BigEndian>()?;
-    let offset = 5; // version(1) + feature_mask(4)
+    fn len(&self) -> usize {
+        match self {
+            Dictionary::Generic(indexed) => indexed.len(),
+            Dictionary::FrontCoded(indexed) => indexed.len(),
+        }
+    }
+
+    /// Binary-search the sorted dictionary for `value`, returning its id.
+    ///
+    /// Null sorts first. GenericIndexed dictionaries are sorted like Java
+    /// strings (by UTF-16 code unit) and front-coded ones by UTF-8 bytes;
+    /// the two orders differ only for characters outside the BMP.
+    fn find(&self, value: &str) -> Result<Option<usize>> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let ordering = match self.get_str(mid as u32)? {
+                None => Ordering::Less,
+                Some(entry) => match self {
+                    Dictionary::Generic(_) => entry.encode_utf16().cmp(value.encode_utf16()),
+                    Dictionary::FrontCoded(_) => entry.as_bytes().cmp(value.as_bytes()),
+                },
+            };
+            match ordering {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Ok(Some(mid)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> StringColumnLayout<'a> {
+    fn parse(
+        data: &'a [u8],
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+    ) -> Result<Self> {
+        if data.is_empty() {
+            return Err(DruidSegmentError::InvalidData(
+                "String column: empty data".into(),
+            ));
+        }
+
+        let version = data[0];
+        let (flags, offset) = match version {
+            VERSION_UNCOMPRESSED_SINGLE_VALUE => (0, 1),
+            VERSION_UNCOMPRESSED_MULTI_VALUE => (FLAG_MULTI_VALUE, 1),
+            VERSION_COMPRESSED | VERSION_UNCOMPRESSED_WITH_FLAGS => {
+                if data.len() < 5 {
+                    return Err(DruidSegmentError::InvalidData(format!(
+                        "String column v{}: data too short for flags",
+                        version
+                    )));
+                }
+                let mut cursor = Cursor::new(&data[1..]);
+                (cursor.read_i32::<BigEndian>()?, 5) // version(1) + flags(4)
+            }
+            other => {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "String column: unsupported version {:#x}",
+                    other
+                )));
+            }
+        };
 
-    // Read dictionary
-    let dictionary = GenericIndexedV1::from_bytes(&data[offset..])?;
-    let dict_size = dictionary.total_size()?;
-    let values_offset = offset + dict_size;
+        // Read dictionary
+        let dictionary = Dictionary::parse(&data[offset..], byte_order, smoosh)?;
+        let values_offset = offset + dictionary.total_size()?;
 
-    // Read compressed encoded values
-    let encoded = CompressedColumnarInts::from_bytes(&data[values_offset..])?;
-    let ids = encoded.decompress_all()?;
+        Ok(Self {
+            version,
+            flags,
+            dictionary,
+            values_offset,
+            values: &data[values_offset..],
+        })
+    }
+
+    fn is_multi_value(&self) -> bool {
+        self.flags & (FLAG_MULTI_VALUE | FLAG_MULTI_VALUE_V3) != 0
+    }
+
+    /// Bytes taken by the encoded values, which the bitmap section follows.
+    fn values_size(&self, byte_order: ByteOrder) -> Result<usize> {
+        match (self.version, self.is_multi_value()) {
+            (VERSION_COMPRESSED, false) => {
+                CompressedColumnarInts::from_bytes_with_order(self.values, byte_order)?
+                    .total_bytes()
+            }
+            (VERSION_COMPRESSED, true) => {
+                CompressedVSizeColumnarMultiInts::total_bytes(self.values, byte_order)
+            }
+            (_, false) => Ok(VSizeColumnarInts::from_bytes(self.values)?.total_size()),
+            (_, true) => Ok(VSizeColumnarMultiInts::from_bytes(self.values)?.total_size()),
+        }
+    }
 
-    resolve_dictionary(&dictionary, &ids)
+    /// The bitmap and spatial indexes after the encoded values, or `None`
+    /// if the column has none, and the number of bytes the column takes.
+    fn indexes(
+        &self,
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+    ) -> Result<(Option<BitmapSections<'a>>, usize)> {
+        let values_size = self.values_size(byte_order)?;
+        let values_end = self.values_offset + values_size;
+        // Columns written without indexes may also omit the bitmap section
+        if self.flags & FLAG_NO_BITMAP_INDEX != 0 || values_size == self.values.len() {
+            return Ok((None, values_end));
+        }
+        let (bitmaps, spatial) = self.bitmap_sections(byte_order, smoosh)?;
+        let size = values_end + bitmaps.total_size()? + spatial.map_or(0, <[u8]>::len);
+        Ok((Some((bitmaps, spatial)), size))
+    }
+
+    /// The bitmap indexes after the encoded values, and the spatial index
+    /// after them if the column has one.
+    ///
+    /// Druid writes the spatial index only for spatial dimensions and
+    /// records it nowhere but in the bytes that follow the bitmaps, so any
+    /// bytes left there are taken to be a size-prefixed R-tree.
+    fn bitmap_sections(
+        &self,
+        byte_order: ByteOrder,
+        smoosh: Option<&'a SmooshReader>,
+    ) -> Result<BitmapSections<'a>> {
+        let values_size = self.values_size(byte_order)?;
+        let data = self.values.get(values_size..).ok_or_else(|| {
+            DruidSegmentError::InvalidData(format!(
+                "String column: values size {} exceeds remaining {} bytes",
+                values_size,
+                self.values.len()
+            ))
+        })?;
+        let bitmaps = GenericIndexed::from_bytes(data, smoosh)?;
+        let rest = data.get(bitmaps.total_size()?..).ok_or_else(|| {
+            DruidSegmentError::InvalidData("String column: bitmaps overrun the column".into())
+        })?;
+        if rest.is_empty() {
+            return Ok((bitmaps, None));
+        }
+        let (_, size) = SpatialIndex::from_bytes(rest)?;
+        Ok((bitmaps, Some(&rest[..size])))
+    }
 }
 
 /// Given a dictionary and a list of integer IDs, resolve each ID to its
-/// string value and build an Arrow StringArray.
-fn resolve_dictionary(dictionary: &GenericIndexedV1<'_>, ids: &[u32]) -> Result<StringArray> {
-    let mut builder = Vec::with_capacity(ids.len());
+/// string value under `null_handling` and build an Arrow StringArray.
+fn resolve_dictionary(
+    dictionary: &Dictionary<'_>,
+    ids: &[u32],
+    null_handling: NullHandling,
+) -> Result<StringArray> {
+    let mut builder = StringBuilder::with_capacity(ids.len(), ids.len() * 8);
 
     for &id in ids {
-        let value = dictionary.get_str(id as usize)?;
-        builder.push(value);
+        let value = dictionary.get_str(id)?;
+        builder.append_option(null_handling.apply(value));
+    }
+
+    Ok(builder.finish())
+}
+
+/// Resolve each row's dictionary IDs under `null_handling` and build an
+/// Arrow list of strings.
+fn resolve_dictionary_rows(
+    dictionary: &Dictionary<'_>,
+    rows: &[Vec<u32>],
+    null_handling: NullHandling,
+) -> Result<ListArray> {
+    let mut builder = ListBuilder::with_capacity(StringBuilder::new(), rows.len());
+
+    for row in rows {
+        for &id in row {
+            builder
+                .values()
+                .append_option(null_handling.apply(dictionary.get_str(id)?));
+        }
+        builder.append(true);
     }
 
-    Ok(StringArray::from(builder))
+    Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use byteorder::WriteBytesExt;
+
+    /// Build a GenericIndexed V1 of strings; `None` entries are null.
+    fn build_dictionary(values: &[Option<&str>]) -> Vec<u8> {
+        let values: Vec<Option<&[u8]>> = values.iter().map(|v| v.map(str::as_bytes)).collect();
+        build_indexed(&values)
+    }
+
+    /// Build a GenericIndexed V1; `None` entries are null.
+    fn build_indexed(values: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut body = Vec::new();
+        for value in values {
+            match value {
+                Some(bytes) => {
+                    body.write_i32::<BigEndian>(0).unwrap();
+                    body.extend_from_slice(bytes);
+                }
+                None => body.write_i32::<BigEndian>(-1).unwrap(),
+            }
+            offsets.push(body.len() as i32);
+        }
+        let mut buf = vec![0x01, 0x01];
+        buf.write_i32::<BigEndian>((4 + offsets.len() * 4 + body.len()) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(values.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    /// Build a single-byte VSizeColumnarMultiInts.
+    fn build_multi_ints(rows: &[&[u32]]) -> Vec<u8> {
+        let values: Vec<u8> = rows
+            .iter()
+            .flat_map(|r| r.iter().map(|&v| v as u8))
+            .collect();
+        let mut buf = vec![0x01, 0x01];
+        buf.write_i32::<BigEndian>((4 + rows.len() * 4 + values.len() + 3) as i32)
+            .unwrap();
+        buf.write_i32::<BigEndian>(rows.len() as i32).unwrap();
+        let mut end = 0;
+        for row in rows {
+            end += row.len() as i32;
+            buf.write_i32::<BigEndian>(end).unwrap();
+        }
+        buf.extend_from_slice(&values);
+        buf.extend_from_slice(&[0, 0, 0]);
+        buf
+    }
+
+    #[test]
+    fn test_multi_value_rows() {
+        let mut data = vec![VERSION_UNCOMPRESSED_MULTI_VALUE];
+        data.extend(build_dictionary(&[None, Some("a"), Some("b"), Some("c")]));
+        data.extend(build_multi_ints(&[&[], &[1], &[1, 2, 3], &[0, 2]]));
+
+        let list = read_multi_value_string_column(
+            &data,
+            ByteOrder::BigEndian,
+            None,
+            &ReadOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.null_count(), 0);
+
+        let row = |i: usize| {
+            let values = list.value(i);
+            let values = values.as_string::<i32>();
+            (0..values.len())
+                .map(|j| values.is_valid(j).then(|| values.value(j).to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert!(row(0).is_empty());
+        assert_eq!(row(1), vec![Some("a".to_string())]);
+        assert_eq!(
+            row(2),
+            vec![
+                Some("a".to_string()),
+                Some("b".to_string()),
+                Some("c".to_string())
+            ]
+        );
+        assert_eq!(row(3), vec![None, Some("b".to_string())]);
+    }
+
+    #[test]
+    fn test_front_coded_dictionary() {
+        // version 0x03 with no flags, then a front-coded dictionary of
+        // [null, "apple", "apricot", "banana"] in one bucket of 4
+        let mut data = vec![VERSION_UNCOMPRESSED_WITH_FLAGS, 0, 0, 0, 0];
+        data.extend_from_slice(&[ENCODED_DICTIONARY_MARKER, ENCODING_FRONT_CODED]);
+        let buckets: &[u8] = &[
+            0x85, b'a', b'p', b'p', b'l', b'e', // "apple"
+            0x82, 0x85, b'r', b'i', b'c', b'o', b't', // "ap" + "ricot"
+            0x80, 0x86, b'b', b'a', b'n', b'a', b'n', b'a', // "" + "banana"
+        ];
+        data.extend_from_slice(&[0x00, 4, 0x01, 0x83, 0x80 | buckets.len() as u8]);
+        data.extend_from_slice(buckets);
+
+        // VSizeColumnarInts with 4-byte ids (no padding)
+        let ids = [1u32, 0, 3, 2, 1];
+        data.extend_from_slice(&[0x00, 4]);
+        data.write_i32::<BigEndian>((ids.len() * 4) as i32).unwrap();
+        for id in ids {
+            data.write_u32::<BigEndian>(id).unwrap();
+        }
+
+        let array = read_string_column(&data).unwrap();
+        let values: Vec<Option<&str>> = array.iter().collect();
+        assert_eq!(
+            values,
+            vec![
+                Some("apple"),
+                None,
+                Some("banana"),
+                Some("apricot"),
+                Some("apple")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dictionary_find_uses_java_order() {
+        // Java sorts by UTF-16 code unit, so U+10000 (a surrogate pair
+        // starting 0xD800) comes before U+FFFD despite its larger UTF-8 bytes.
+        let data = build_dictionary(&[None, Some("a"), Some("\u{10000}"), Some("\u{FFFD}")]);
+        let dictionary = Dictionary::parse(&data, ByteOrder::BigEndian, None).unwrap();
+        assert_eq!(dictionary.find("a").unwrap(), Some(1));
+        assert_eq!(dictionary.find("\u{10000}").unwrap(), Some(2));
+        assert_eq!(dictionary.find("\u{FFFD}").unwrap(), Some(3));
+        assert_eq!(dictionary.find("b").unwrap(), None);
+        assert_eq!(dictionary.find("").unwrap(), None);
+    }
+
+    #[test]
+    fn test_spatial_index_after_bitmaps() {
+        let mut data = vec![VERSION_UNCOMPRESSED_WITH_FLAGS, 0, 0, 0, 0];
+        data.extend(build_dictionary(&[Some("1.0,2.0"), Some("3.0,-4.0")]));
+
+        // VSizeColumnarInts with 4-byte ids (no padding)
+        let ids = [0u32, 1, 1];
+        data.extend_from_slice(&[0x00, 4]);
+        data.write_i32::<BigEndian>((ids.len() * 4) as i32).unwrap();
+        for id in ids {
+            data.write_u32::<BigEndian>(id).unwrap();
+        }
+
+        let bitmaps: Vec<Vec<u8>> = [vec![0], vec![1, 2]]
+            .iter()
+            .map(|rows| {
+                let mut bytes = Vec::new();
+                RoaringBitmap::from_iter(rows.iter().copied())
+                    .serialize_into(&mut bytes)
+                    .unwrap();
+                bytes
+            })
+            .collect();
+        let bitmaps: Vec<Option<&[u8]>> = bitmaps.iter().map(|b| Some(b.as_slice())).collect();
+        data.extend(build_indexed(&bitmaps));
+
+        // A 2-d R-tree whose root is a leaf bounding both points
+        let mut tree = vec![0x00];
+        tree.write_i32::<BigEndian>(2).unwrap();
+        tree.write_u16::<BigEndian>(0x8000).unwrap();
+        for c in [1.0f32, -4.0, 3.0, 2.0] {
+            tree.write_f32::<BigEndian>(c).unwrap();
+        }
+        tree.write_i32::<BigEndian>(0).unwrap();
+        data.write_i32::<BigEndian>(tree.len() as i32).unwrap();
+        data.extend(tree);
+
+        let array = read_string_column(&data).unwrap();
+        let values: Vec<Option<&str>> = array.iter().collect();
+        assert_eq!(
+            values,
+            vec![Some("1.0,2.0"), Some("3.0,-4.0"), Some("3.0,-4.0")]
+        );
+
+        let index = StringColumnIndex::from_bytes(&data, ByteOrder::BigEndian, None).unwrap();
+        let rows = index.bitmap_for_value("3.0,-4.0").unwrap().unwrap();
+        assert_eq!(rows.iter().collect::<Vec<_>>(), vec![1, 2]);
+        let spatial = index.spatial_index().unwrap().unwrap();
+        assert_eq!(spatial.num_dims, 2);
+        assert_eq!(spatial.min, vec![1.0, -4.0]);
+        assert_eq!(spatial.max, vec![3.0, 2.0]);
+
+        // Bytes after the bitmaps that cannot be an R-tree are an error
+        data.truncate(data.len() - 1);
+        assert!(StringColumnIndex::from_bytes(&data, ByteOrder::BigEndian, None).is_err());
+    }
+
+    #[test]
+    fn test_truncated_values_buffer() {
+        let mut data = vec![VERSION_UNCOMPRESSED_WITH_FLAGS, 0, 0, 0, 0];
+        data.extend(build_dictionary(&[Some("a"), Some("b")]));
+        // Four bytes of ids, but a buffer size claiming a thousand
+        data.extend_from_slice(&[0x00, 4]);
+        data.write_i32::<BigEndian>(1000).unwrap();
+        data.extend_from_slice(&[0, 0, 0, 1]);
+
+        assert!(read_string_column(&data).is_err());
+        let err = read_value_bitmap(&data, ByteOrder::BigEndian, None, "b").unwrap_err();
+        assert!(err.to_string().contains("exceeds remaining"), "{err}");
+    }
+
+    #[test]
+    fn test_multi_value_flag_mismatch() {
+        let mut data = vec![VERSION_UNCOMPRESSED_MULTI_VALUE];
+        data.extend(build_dictionary(&[Some("a")]));
+        data.extend(build_multi_ints(&[&[0]]));
+        assert!(read_string_column(&data).is_err());
+    }
+
+    #[test]
+    fn test_null_count() {
+        let values = StringArray::from(vec![Some("b"), None, Some("a"), None, None]);
+        let indexed = write_string_column(&values).unwrap();
+        assert_eq!(
+            null_count(&indexed, ByteOrder::BigEndian, None).unwrap(),
+            Some(3)
+        );
+
+        // Without bitmap indexes the null entry's id is counted instead
+        let mut unindexed = vec![VERSION_UNCOMPRESSED_SINGLE_VALUE];
+        unindexed.extend(build_dictionary(&[None, Some("a"), Some("b")]));
+        VSizeColumnarInts::write_into(&[2, 0, 1, 0, 0], &mut unindexed);
+        assert_eq!(read_string_column(&unindexed).unwrap(), values);
+        assert_eq!(
+            null_count(&unindexed, ByteOrder::BigEndian, None).unwrap(),
+            Some(3)
+        );
+
+        let no_nulls = write_string_column(&StringArray::from(vec!["a", ""])).unwrap();
+        assert_eq!(
+            null_count(&no_nulls, ByteOrder::BigEndian, None).unwrap(),
+            Some(0)
+        );
+
+        let mut multi = vec![VERSION_UNCOMPRESSED_MULTI_VALUE];
+        multi.extend(build_dictionary(&[None, Some("a")]));
+        multi.extend(build_multi_ints(&[&[0], &[1]]));
+        assert_eq!(
+            null_count(&multi, ByteOrder::BigEndian, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_as_dictionary() {
+        let values = StringArray::from(vec![Some("b"), None, Some("a"), Some("b"), None]);
+        let data = write_string_column(&values).unwrap();
+        let options = ReadOptions::default();
+        let array =
+            read_string_column_as_dictionary(&data, ByteOrder::BigEndian, None, &options).unwrap();
+        // The dictionary is the column's, null entry first; rows keep its ids
+        assert_eq!(array.values().len(), 3);
+        assert_eq!(
+            array.keys().iter().collect::<Vec<_>>(),
+            [Some(2), None, Some(1), Some(2), None]
+        );
+        let decoded = arrow::compute::cast(&array, &arrow::datatypes::DataType::Utf8).unwrap();
+        assert_eq!(decoded.as_string::<i32>(), &values);
+
+        let options = ReadOptions::default().with_offset(2).with_limit(2);
+        let array =
+            read_string_column_as_dictionary(&data, ByteOrder::BigEndian, None, &options).unwrap();
+        assert_eq!(array.keys().iter().collect::<Vec<_>>(), [Some(1), Some(2)]);
+
+        let mut multi = vec![VERSION_UNCOMPRESSED_MULTI_VALUE];
+        multi.extend(build_dictionary(&[None, Some("a")]));
+        multi.extend(build_multi_ints(&[&[0], &[1]]));
+        assert!(
+            read_string_column_as_dictionary(&multi, ByteOrder::BigEndian, None, &options).is_err()
+        );
+    }
+
+    #[test]
+    fn test_column_values_lookups() {
+        let values = StringArray::from(vec![Some("b"), None, Some("a"), Some("b")]);
+        let data = write_string_column(&values).unwrap();
+        let column = StringColumnValues::from_bytes(&data, ByteOrder::BigEndian, None).unwrap();
+        assert!(!column.is_multi_value());
+        assert_eq!(column.cardinality(), 3);
+        let dictionary = column.dictionary().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(dictionary, [None, Some("a".into()), Some("b".into())]);
+        for (row, value) in values.iter().enumerate() {
+            assert_eq!(column.value_at(row).unwrap().as_deref(), value);
+        }
+        assert!(matches!(
+            column.value_at(4),
+            Err(DruidSegmentError::RowOutOfRange {
+                offset: 4,
+                num_rows: 4
+            })
+        ));
+        assert!(column.dictionary_value(3).is_err());
+    }
 }
@@ -1,14 +1,23 @@
 use arrow::array::TimestampMillisecondArray;
 
+use super::NumericPart;
 use super::compressed_longs::CompressedColumnarLongs;
 use crate::error::Result;
+use crate::segment::read_options::ReadOptions;
 
-/// Read the `__time` column from its binary data (after the JSON header).
+/// Read the `__time` column from its numeric part.
 ///
-/// The __time column stores epoch milliseconds as compressed longs.
-/// We produce an Arrow TimestampMillisecondArray.
-pub fn read_time_column(data: &[u8]) -> Result<TimestampMillisecondArray> {
-    let longs = CompressedColumnarLongs::from_bytes(data)?;
-    let values = longs.decompress_all()?;
+/// The __time column stores epoch milliseconds as compressed longs and is
+/// never null. We produce an Arrow TimestampMillisecondArray.
+///
+/// Druid truncates timestamps to the segment's query granularity before
+/// writing them, so a rolled-up segment's buckets are read as stored.
+pub fn read_time_column(
+    part: &NumericPart<'_>,
+    options: &ReadOptions,
+) -> Result<TimestampMillisecondArray> {
+    let longs = CompressedColumnarLongs::from_bytes_with_order(part.values, part.byte_order)?
+        .with_cancellation(options.cancellation.clone());
+    let values = longs.decompress_range(options.row_range(longs.len()))?;
     Ok(TimestampMillisecondArray::from(values))
 }
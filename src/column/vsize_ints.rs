@@ -1,7 +1,6 @@
 use std::io::Cursor;
 
-use byteorder::{BigEndian, ReadBytesExt};
-
+use super::read_len;
 use crate::error::{DruidSegmentError, Result};
 
 /// Reader for Druid's VSizeColumnarInts.
@@ -15,12 +14,17 @@ use crate::error::{DruidSegmentError, Result};
 /// [num_bytes: u8]     -- bytes per value (1-4)
 /// [size: i32]         -- total size of the values buffer in bytes
 /// [values: ...]       -- packed integers, each `num_bytes` wide, big-endian
+/// [padding: 4 - num_bytes bytes]
 /// ```
+///
+/// The padding lets Druid read every value with a 4-byte load; it is
+/// counted in `size` but holds no value.
 pub struct VSizeColumnarInts<'a> {
     data: &'a [u8],
     num_bytes: usize,
     num_values: usize,
     values_offset: usize,
+    buffer_size: usize,
 }
 
 const VERSION: u8 = 0x00;
@@ -52,9 +56,16 @@ impl<'a> VSizeColumnarInts<'a> {
         }
 
         let mut cursor = Cursor::new(&data[2..]);
-        let buffer_size = cursor.read_i32::<BigEndian>()? as usize;
+        let buffer_size = read_len(&mut cursor, "VSizeColumnarInts: buffer size")?;
+        if HEADER_SIZE + buffer_size > data.len() {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "VSizeColumnarInts: buffer size {} exceeds remaining {} bytes",
+                buffer_size,
+                data.len() - HEADER_SIZE
+            )));
+        }
 
-        let num_values = buffer_size / num_bytes;
+        let num_values = buffer_size.saturating_sub(4 - num_bytes) / num_bytes;
         let values_offset = HEADER_SIZE;
 
         Ok(Self {
@@ -62,6 +73,7 @@ impl<'a> VSizeColumnarInts<'a> {
             num_bytes,
             num_values,
             values_offset,
+            buffer_size,
         })
     }
 
@@ -106,20 +118,184 @@ impl<'a> VSizeColumnarInts<'a> {
 
     /// Total bytes consumed by this structure.
     pub fn total_size(&self) -> usize {
-        HEADER_SIZE + self.num_values * self.num_bytes
+        HEADER_SIZE + self.buffer_size
+    }
+
+    /// Serialize `values` with the fewest bytes per value that hold the
+    /// largest of them, as Druid's `VSizeColumnarInts.fromArray` does.
+    pub fn write(values: &[u32]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        Self::write_into(values, &mut buf);
+        buf
+    }
+
+    /// Append the serialization of `values` to `buf`, e.g. after a string
+    /// column's dictionary.
+    pub fn write_into(values: &[u32], buf: &mut Vec<u8>) {
+        let max = values.iter().copied().max().unwrap_or(0);
+        let num_bytes = num_bytes_for_max(max);
+        let padding = 4 - num_bytes;
+        let buffer_size = values.len() * num_bytes + padding;
+
+        buf.reserve(HEADER_SIZE + buffer_size);
+        buf.push(VERSION);
+        buf.push(num_bytes as u8);
+        buf.extend_from_slice(&(buffer_size as i32).to_be_bytes());
+        for value in values {
+            buf.extend_from_slice(&value.to_be_bytes()[padding..]);
+        }
+        buf.extend(std::iter::repeat_n(0u8, padding));
+    }
+}
+
+/// Bytes per value needed to store values up to `max`.
+pub fn num_bytes_for_max(max: u32) -> usize {
+    match max {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    }
+}
+
+/// Reader for Druid's VSizeColumnarMultiInts.
+///
+/// Stores one variable-length row of unsigned integers per entry, used for
+/// uncompressed multi-value dictionary-encoded columns.
+///
+/// Layout:
+/// ```text
+/// [version: u8 = 0x01]
+/// [num_bytes: u8]     -- bytes per value (1-4)
+/// [size: i32]         -- bytes that follow: count, offsets, values and padding
+/// [count: i32]        -- number of rows
+/// [offsets: i32 * count] -- cumulative end byte offset of each row within values
+/// [values: ...]       -- packed integers, each `num_bytes` wide, big-endian
+/// [padding: 4 - num_bytes bytes]
+/// ```
+pub struct VSizeColumnarMultiInts<'a> {
+    data: &'a [u8],
+    num_bytes: usize,
+    count: usize,
+    size: usize,
+}
+
+const MULTI_VERSION: u8 = 0x01;
+
+impl<'a> VSizeColumnarMultiInts<'a> {
+    /// Parse a VSizeColumnarMultiInts from raw bytes.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE + 4 {
+            return Err(DruidSegmentError::InvalidData(
+                "VSizeColumnarMultiInts: data too short for header".into(),
+            ));
+        }
+
+        let version = data[0];
+        if version != MULTI_VERSION {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "VSizeColumnarMultiInts: unexpected version {:#x}, expected {:#x}",
+                version, MULTI_VERSION
+            )));
+        }
+
+        let num_bytes = data[1] as usize;
+        if num_bytes == 0 || num_bytes > 4 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "VSizeColumnarMultiInts: invalid num_bytes {}, expected 1-4",
+                num_bytes
+            )));
+        }
+
+        let mut cursor = Cursor::new(&data[2..]);
+        let size = read_len(&mut cursor, "VSizeColumnarMultiInts: size")?;
+        let count = read_len(&mut cursor, "VSizeColumnarMultiInts: row count")?;
+
+        if data.len() < HEADER_SIZE + size || size < 4 + count * 4 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "VSizeColumnarMultiInts: size {} inconsistent with {} rows and {} available bytes",
+                size,
+                count,
+                data.len() - HEADER_SIZE
+            )));
+        }
+
+        Ok(Self {
+            data,
+            num_bytes,
+            count,
+            size,
+        })
+    }
+
+    /// Number of rows.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn offset_at(&self, index: usize) -> usize {
+        let pos = HEADER_SIZE + 4 + index * 4;
+        let bytes = &self.data[pos..pos + 4];
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+    }
+
+    /// Get the values of row `index`.
+    pub fn get(&self, index: usize) -> Result<Vec<u32>> {
+        if index >= self.count {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "VSizeColumnarMultiInts: row {} out of range (len {})",
+                index, self.count
+            )));
+        }
+
+        let start = if index == 0 {
+            0
+        } else {
+            self.offset_at(index - 1)
+        };
+        let end = self.offset_at(index);
+        let values_start = HEADER_SIZE + 4 + self.count * 4;
+        if end < start || values_start + end > HEADER_SIZE + self.size {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "VSizeColumnarMultiInts: row {} byte range [{}, {}) out of bounds",
+                index, start, end
+            )));
+        }
+
+        let bytes = &self.data[values_start + start..values_start + end];
+        Ok(bytes
+            .chunks(self.num_bytes)
+            .map(|chunk| chunk.iter().fold(0u32, |v, &b| (v << 8) | b as u32))
+            .collect())
+    }
+
+    /// Read all rows into a Vec of rows.
+    pub fn to_vecs(&self) -> Result<Vec<Vec<u32>>> {
+        (0..self.count).map(|i| self.get(i)).collect()
+    }
+
+    /// Total bytes consumed by this structure.
+    pub fn total_size(&self) -> usize {
+        HEADER_SIZE + self.size
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use byteorder::WriteBytesExt;
+    use byteorder::{BigEndian, WriteBytesExt};
 
     fn build_vsize_ints(num_bytes: u8, values: &[u32]) -> Vec<u8> {
         let mut buf = Vec::new();
         buf.push(VERSION);
         buf.push(num_bytes);
-        let buffer_size = values.len() * num_bytes as usize;
+        let padding = 4 - num_bytes as usize;
+        let buffer_size = values.len() * num_bytes as usize + padding;
         buf.write_i32::<BigEndian>(buffer_size as i32).unwrap();
         for &v in values {
             // Write big-endian with the specified width
@@ -128,6 +304,7 @@ mod tests {
                 buf.push(((v >> (i * 8)) & 0xFF) as u8);
             }
         }
+        buf.extend(std::iter::repeat_n(0u8, padding));
         buf
     }
 
@@ -153,6 +330,55 @@ mod tests {
         assert_eq!(col.get(3).unwrap(), 65535);
     }
 
+    fn build_vsize_multi_ints(num_bytes: u8, rows: &[&[u32]]) -> Vec<u8> {
+        let mut values = Vec::new();
+        let mut offsets = Vec::new();
+        for row in rows {
+            for &v in *row {
+                for i in (0..num_bytes as usize).rev() {
+                    values.push(((v >> (i * 8)) & 0xFF) as u8);
+                }
+            }
+            offsets.push(values.len() as i32);
+        }
+        let padding = 4 - num_bytes as usize;
+
+        let mut buf = vec![MULTI_VERSION, num_bytes];
+        let size = 4 + offsets.len() * 4 + values.len() + padding;
+        buf.write_i32::<BigEndian>(size as i32).unwrap();
+        buf.write_i32::<BigEndian>(rows.len() as i32).unwrap();
+        for off in offsets {
+            buf.write_i32::<BigEndian>(off).unwrap();
+        }
+        buf.extend_from_slice(&values);
+        buf.extend(std::iter::repeat_n(0u8, padding));
+        buf
+    }
+
+    #[test]
+    fn test_multi_ints() {
+        let rows: &[&[u32]] = &[&[], &[3], &[1, 2, 300], &[]];
+        let data = build_vsize_multi_ints(2, rows);
+        let col = VSizeColumnarMultiInts::from_bytes(&data).unwrap();
+        assert_eq!(col.len(), 4);
+        assert_eq!(col.get(0).unwrap(), Vec::<u32>::new());
+        assert_eq!(col.get(1).unwrap(), vec![3]);
+        assert_eq!(col.get(2).unwrap(), vec![1, 2, 300]);
+        assert_eq!(col.to_vecs().unwrap().len(), 4);
+        assert_eq!(col.total_size(), data.len());
+        assert!(col.get(4).is_err());
+    }
+
+    #[test]
+    fn test_truncated_buffer() {
+        let data = build_vsize_ints(2, &[1, 2, 3]);
+        assert!(VSizeColumnarInts::from_bytes(&data).is_ok());
+        let err = VSizeColumnarInts::from_bytes(&data[..data.len() - 1])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("exceeds remaining"), "{err}");
+    }
+
     #[test]
     fn test_to_vec() {
         let values = &[10, 20, 30];
@@ -160,4 +386,45 @@ mod tests {
         let col = VSizeColumnarInts::from_bytes(&data).unwrap();
         assert_eq!(col.to_vec().unwrap(), vec![10, 20, 30]);
     }
+
+    #[test]
+    fn test_write_picks_minimal_width() {
+        for (max, num_bytes) in [
+            (0, 1),
+            (255, 1),
+            (256, 2),
+            (65535, 2),
+            (65536, 3),
+            (0xFF_FFFF, 3),
+            (0x100_0000, 4),
+            (u32::MAX, 4),
+        ] {
+            let values = [0, max / 2, max];
+            let data = VSizeColumnarInts::write(&values);
+            assert_eq!(data[1], num_bytes, "max {}", max);
+
+            let col = VSizeColumnarInts::from_bytes(&data).unwrap();
+            assert_eq!(col.to_vec().unwrap(), values);
+            assert_eq!(col.total_size(), data.len());
+        }
+    }
+
+    #[test]
+    fn test_write_matches_druid_layout() {
+        assert_eq!(
+            VSizeColumnarInts::write(&[0, 1, 2, 255]),
+            build_vsize_ints(1, &[0, 1, 2, 255])
+        );
+        let empty = VSizeColumnarInts::write(&[]);
+        assert!(VSizeColumnarInts::from_bytes(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_after_dictionary() {
+        let mut buf = b"dictionary".to_vec();
+        VSizeColumnarInts::write_into(&[7, 300], &mut buf);
+        let col = VSizeColumnarInts::from_bytes(&buf[10..]).unwrap();
+        assert_eq!(col.to_vec().unwrap(), vec![7, 300]);
+        assert_eq!(10 + col.total_size(), buf.len());
+    }
 }
@@ -1,7 +1,17 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
 use crate::error::{DruidSegmentError, Result};
 
 /// Compression strategies used by Druid for columnar data blocks.
-#[derive(Debug, Clone, Copy, PartialEq)]
+///
+/// In JSON (e.g. a part serde's `compression`) strategies are named in
+/// lowercase, as Druid's `CompressionStrategy` serializes them.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CompressionStrategy {
     /// LZF compression (legacy).
     Lzf,
@@ -27,20 +37,368 @@ impl CompressionStrategy {
             other => Err(DruidSegmentError::UnsupportedCompression(other)),
         }
     }
+
+    /// The single-byte identifier written in column headers.
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::Lzf => 0x00,
+            Self::Lz4 => 0x01,
+            Self::Zstd => 0x02,
+            Self::Uncompressed => 0xFF,
+            Self::None => 0xFE,
+        }
+    }
+
+    /// Whether blocks are stored as-is, without compression.
+    pub fn is_uncompressed(&self) -> bool {
+        matches!(self, Self::Uncompressed | Self::None)
+    }
+
+    /// Whether [`decompress_block`] can read blocks compressed this way.
+    pub fn is_supported(&self) -> bool {
+        !matches!(self, Self::Zstd)
+    }
+}
+
+impl std::fmt::Display for CompressionStrategy {
+    /// The lowercase name Druid uses in JSON, e.g. `lz4`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Lzf => "lzf",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+            Self::Uncompressed => "uncompressed",
+            Self::None => "none",
+        })
+    }
 }
 
 /// Decompress a block of data using the given strategy.
 /// `decompressed_size` is the expected output size in bytes.
+///
+/// Uncompressed blocks are borrowed as they are, without copying.
 pub fn decompress_block(
     strategy: CompressionStrategy,
     compressed: &[u8],
     decompressed_size: usize,
-) -> Result<Vec<u8>> {
+) -> Result<Cow<'_, [u8]>> {
+    let started = DECOMPRESSION_TIME.get().is_some().then(Instant::now);
+    let block = decompress(strategy, compressed, decompressed_size);
+    if let Some(started) = started {
+        DECOMPRESSION_TIME.set(
+            DECOMPRESSION_TIME
+                .get()
+                .map(|time| time + started.elapsed()),
+        );
+    }
+    block
+}
+
+fn decompress(
+    strategy: CompressionStrategy,
+    compressed: &[u8],
+    decompressed_size: usize,
+) -> Result<Cow<'_, [u8]>> {
     match strategy {
         CompressionStrategy::Lz4 => lz4_flex::block::decompress(compressed, decompressed_size)
+            .map(Cow::Owned)
             .map_err(|e| DruidSegmentError::DecompressionError(e.to_string())),
-        CompressionStrategy::Uncompressed | CompressionStrategy::None => Ok(compressed.to_vec()),
-        CompressionStrategy::Lzf => Err(DruidSegmentError::UnsupportedCompression(0x00)),
+        CompressionStrategy::Uncompressed | CompressionStrategy::None => {
+            Ok(Cow::Borrowed(compressed))
+        }
+        CompressionStrategy::Lzf => lzf_decompress(compressed, decompressed_size).map(Cow::Owned),
         CompressionStrategy::Zstd => Err(DruidSegmentError::UnsupportedCompression(0x02)),
     }
 }
+
+thread_local! {
+    /// Time [`decompress_block`] has spent on this thread since the
+    /// innermost running [`DecompressionTimer`] started, or `None` when no
+    /// timer is running and blocks are not timed.
+    static DECOMPRESSION_TIME: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Measures the time [`decompress_block`] spends on the current thread
+/// while it runs. Timers nest: an outer timer's time includes the inner's.
+pub(crate) struct DecompressionTimer {
+    /// Time of the enclosing timer when this one started.
+    outer: Option<Duration>,
+}
+
+impl DecompressionTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            outer: DECOMPRESSION_TIME.replace(Some(Duration::ZERO)),
+        }
+    }
+
+    /// Time spent decompressing since the timer started.
+    pub(crate) fn stop(self) -> Duration {
+        DECOMPRESSION_TIME.get().unwrap_or_default()
+    }
+}
+
+impl Drop for DecompressionTimer {
+    /// Hand the time measured back to the enclosing timer, if any.
+    fn drop(&mut self) {
+        let time = DECOMPRESSION_TIME.get().unwrap_or_default();
+        DECOMPRESSION_TIME.set(self.outer.map(|outer| outer + time));
+    }
+}
+
+/// Compress a block of data using the given strategy, the inverse of
+/// [`decompress_block`]. Only LZ4 and uncompressed blocks can be written.
+pub fn compress_block(strategy: CompressionStrategy, data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    match strategy {
+        CompressionStrategy::Lz4 => Ok(Cow::Owned(lz4_flex::block::compress(data))),
+        CompressionStrategy::Uncompressed | CompressionStrategy::None => Ok(Cow::Borrowed(data)),
+        other => Err(DruidSegmentError::UnsupportedCompression(other.id())),
+    }
+}
+
+/// Decompress an LZF block as written by Druid's `LZFCompressor`: a
+/// sequence of chunks, each starting with the `ZV` signature.
+///
+/// Chunk layout:
+/// ```text
+/// ['Z' 'V'][type: u8 = 0x00][len: u16 BE][bytes]                     -- stored
+/// ['Z' 'V'][type: u8 = 0x01][len: u16 BE][decoded_len: u16 BE][lzf]  -- compressed
+/// ```
+fn lzf_decompress(input: &[u8], decompressed_size: usize) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(decompressed_size);
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let header = input
+            .get(pos..pos + 5)
+            .ok_or_else(|| lzf_error("truncated chunk header"))?;
+        if &header[..2] != b"ZV" {
+            return Err(lzf_error("missing chunk signature"));
+        }
+        let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+        match header[2] {
+            0x00 => {
+                let bytes = input
+                    .get(pos + 5..pos + 5 + len)
+                    .ok_or_else(|| lzf_error("truncated stored chunk"))?;
+                out.extend_from_slice(bytes);
+                pos += 5 + len;
+            }
+            0x01 => {
+                let decoded_len = input
+                    .get(pos + 5..pos + 7)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                    .ok_or_else(|| lzf_error("truncated chunk header"))?;
+                let bytes = input
+                    .get(pos + 7..pos + 7 + len)
+                    .ok_or_else(|| lzf_error("truncated compressed chunk"))?;
+                let start = out.len();
+                lzf_decode_chunk(bytes, &mut out)?;
+                if out.len() - start != decoded_len {
+                    return Err(lzf_error(&format!(
+                        "chunk decoded to {} bytes, expected {}",
+                        out.len() - start,
+                        decoded_len
+                    )));
+                }
+                pos += 7 + len;
+            }
+            other => {
+                return Err(lzf_error(&format!("unknown chunk type {:#x}", other)));
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Decode one chunk of raw LZF data, appending to `out`.
+///
+/// Each control byte is either a literal run (`ctrl < 32`: copy `ctrl + 1`
+/// bytes) or a back-reference of length `(ctrl >> 5) + 2` (with an extra
+/// length byte when `ctrl >> 5 == 7`) at distance `((ctrl & 0x1f) << 8) +
+/// next_byte + 1`.
+fn lzf_decode_chunk(input: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let chunk_start = out.len();
+    let mut pos = 0;
+    let next = |pos: &mut usize| -> Result<u8> {
+        let b = *input
+            .get(*pos)
+            .ok_or_else(|| lzf_error("truncated chunk data"))?;
+        *pos += 1;
+        Ok(b)
+    };
+
+    while pos < input.len() {
+        let ctrl = next(&mut pos)? as usize;
+        if ctrl < 32 {
+            let literal = input
+                .get(pos..pos + ctrl + 1)
+                .ok_or_else(|| lzf_error("truncated literal run"))?;
+            out.extend_from_slice(literal);
+            pos += ctrl + 1;
+            continue;
+        }
+
+        let mut len = ctrl >> 5;
+        if len == 7 {
+            len += next(&mut pos)? as usize;
+        }
+        len += 2;
+        let distance = ((ctrl & 0x1f) << 8) + next(&mut pos)? as usize + 1;
+        if distance > out.len() - chunk_start {
+            return Err(lzf_error("back-reference before start of chunk"));
+        }
+        let from = out.len() - distance;
+        // Copy byte by byte: the source may overlap the bytes being written
+        for i in 0..len {
+            let b = out[from + i];
+            out.push(b);
+        }
+    }
+
+    Ok(())
+}
+
+fn lzf_error(msg: &str) -> DruidSegmentError {
+    DruidSegmentError::DecompressionError(format!("LZF: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompression_timer() {
+        let data = vec![7u8; 1 << 20];
+        let block = compress_block(CompressionStrategy::Lz4, &data).unwrap();
+
+        let outer = DecompressionTimer::start();
+        decompress_block(CompressionStrategy::Lz4, &block, data.len()).unwrap();
+        let inner = DecompressionTimer::start();
+        assert_eq!(inner.stop(), Duration::ZERO);
+        let inner = DecompressionTimer::start();
+        decompress_block(CompressionStrategy::Lz4, &block, data.len()).unwrap();
+        let inner = inner.stop();
+        assert!(inner > Duration::ZERO);
+        // The outer timer counts the inner timer's blocks too
+        assert!(outer.stop() > inner);
+        assert_eq!(DECOMPRESSION_TIME.get(), None);
+    }
+
+    #[test]
+    fn test_lzf_stored_and_compressed_chunks() {
+        let mut block = b"ZV\x00\x00\x03abc".to_vec();
+        // "xyxyxyxyz": literal "xy", back-reference of 6 at distance 2, literal "z"
+        let lzf = [0x01, b'x', b'y', 0x80, 0x01, 0x00, b'z'];
+        block.extend_from_slice(b"ZV\x01");
+        block.extend_from_slice(&(lzf.len() as u16).to_be_bytes());
+        block.extend_from_slice(&9u16.to_be_bytes());
+        block.extend_from_slice(&lzf);
+
+        let out = decompress_block(CompressionStrategy::Lzf, &block, 12).unwrap();
+        assert_eq!(&out[..], b"abcxyxyxyxyz");
+    }
+
+    #[test]
+    fn test_lzf_long_back_reference() {
+        // literal "a", then a run of 7 + 3 + 2 = 12 copies at distance 1
+        let lzf = [0x00, b'a', 0xE0, 0x03, 0x00];
+        let mut block = b"ZV\x01".to_vec();
+        block.extend_from_slice(&(lzf.len() as u16).to_be_bytes());
+        block.extend_from_slice(&13u16.to_be_bytes());
+        block.extend_from_slice(&lzf);
+        let out = decompress_block(CompressionStrategy::Lzf, &block, 13).unwrap();
+        assert_eq!(out, vec![b'a'; 13]);
+    }
+
+    #[test]
+    fn test_lzf_rejects_bad_input() {
+        assert!(decompress_block(CompressionStrategy::Lzf, b"XX\x00\x00\x00", 0).is_err());
+        // back-reference with nothing decoded yet
+        let lzf = [0x20, 0x00];
+        let mut block = b"ZV\x01".to_vec();
+        block.extend_from_slice(&(lzf.len() as u16).to_be_bytes());
+        block.extend_from_slice(&3u16.to_be_bytes());
+        block.extend_from_slice(&lzf);
+        assert!(decompress_block(CompressionStrategy::Lzf, &block, 3).is_err());
+    }
+
+    const ALL: [CompressionStrategy; 5] = [
+        CompressionStrategy::Lzf,
+        CompressionStrategy::Lz4,
+        CompressionStrategy::Zstd,
+        CompressionStrategy::Uncompressed,
+        CompressionStrategy::None,
+    ];
+
+    #[test]
+    fn test_id_round_trips() {
+        for strategy in ALL {
+            assert_eq!(
+                CompressionStrategy::from_id(strategy.id()).unwrap(),
+                strategy
+            );
+            // Display gives the name the JSON form is parsed from
+            let json = format!("\"{}\"", strategy);
+            assert_eq!(
+                serde_json::from_str::<CompressionStrategy>(&json).unwrap(),
+                strategy
+            );
+        }
+        assert_eq!(CompressionStrategy::Lz4.to_string(), "lz4");
+    }
+
+    #[test]
+    fn test_is_supported() {
+        for strategy in ALL {
+            let block = compress_block(strategy, b"").unwrap_or_default();
+            let result = decompress_block(strategy, &block, 0);
+            assert_eq!(result.is_ok(), strategy.is_supported(), "{}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_compress_block_round_trips() {
+        let data: Vec<u8> = (0..10_000u32)
+            .flat_map(|v| (v % 97).to_be_bytes())
+            .collect();
+        for strategy in [CompressionStrategy::Lz4, CompressionStrategy::Uncompressed] {
+            assert_eq!(
+                CompressionStrategy::from_id(strategy.id()).unwrap(),
+                strategy
+            );
+            let compressed = compress_block(strategy, &data).unwrap();
+            let out = decompress_block(strategy, &compressed, data.len()).unwrap();
+            assert_eq!(&out[..], &data[..]);
+        }
+        assert!(compress_block(CompressionStrategy::Lzf, &data).is_err());
+    }
+
+    #[test]
+    fn test_compress_block_round_trips_random_buffers() {
+        // A small LCG keeps the generated cases reproducible
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = |bound: u64| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) % bound
+        };
+        let writable = ALL.into_iter().filter(|s| compress_block(*s, b"").is_ok());
+        for strategy in writable {
+            for _ in 0..100 {
+                // Small alphabets give LZ4 repeats to find
+                let alphabet = next(256) + 1;
+                let data: Vec<u8> = (0..next(5000)).map(|_| next(alphabet) as u8).collect();
+                let compressed = compress_block(strategy, &data).unwrap();
+                if strategy.is_uncompressed() {
+                    // Passed through without copying
+                    assert!(matches!(compressed, Cow::Borrowed(b) if b.as_ptr() == data.as_ptr()));
+                }
+                let out = decompress_block(strategy, &compressed, data.len()).unwrap();
+                assert_eq!(&out[..], &data[..], "{}", strategy);
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! DataFusion APIs whose paths or signatures change between major
+//! versions, wrapped so an upgrade only has to touch this module.
+//!
+//! The `df-44` and `df-43` features pick the major to build against; code
+//! that differs between them is selected here with `cfg`.
+
+use std::error::Error;
+
+use arrow::datatypes::SchemaRef;
+use datafusion::common::ColumnStatistics;
+use datafusion::common::stats::Precision;
+use datafusion::error::DataFusionError;
+use datafusion::physical_expr::EquivalenceProperties;
+#[cfg(feature = "df-43")]
+use datafusion::physical_plan::ExecutionMode;
+#[cfg(feature = "df-44")]
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::{Partitioning, PlanProperties};
+
+pub(crate) use datafusion::catalog::Session;
+pub(crate) use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+
+/// Properties of a bounded scan with `partitions` partitions that emits
+/// batches as it reads them.
+#[cfg(feature = "df-44")]
+pub(crate) fn scan_properties(schema: SchemaRef, partitions: usize) -> PlanProperties {
+    PlanProperties::new(
+        EquivalenceProperties::new(schema),
+        Partitioning::UnknownPartitioning(partitions),
+        EmissionType::Incremental,
+        Boundedness::Bounded,
+    )
+}
+
+/// Properties of a bounded scan with `partitions` partitions; DataFusion 43
+/// has a single execution mode where 44 splits emission and boundedness.
+#[cfg(feature = "df-43")]
+pub(crate) fn scan_properties(schema: SchemaRef, partitions: usize) -> PlanProperties {
+    PlanProperties::new(
+        EquivalenceProperties::new(schema),
+        Partitioning::UnknownPartitioning(partitions),
+        ExecutionMode::Bounded,
+    )
+}
+
+/// Statistics of a column of which only the null count is known.
+pub(crate) fn null_count_statistics(null_count: Precision<usize>) -> ColumnStatistics {
+    ColumnStatistics {
+        null_count,
+        ..ColumnStatistics::new_unknown()
+    }
+}
+
+/// Wrap an error from outside DataFusion, such as a segment read.
+pub(crate) fn external_error(e: impl Error + Send + Sync + 'static) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
@@ -3,59 +3,298 @@ use std::fmt;
 use std::sync::Arc;
 
 use arrow::datatypes::{Field, Schema, SchemaRef};
-use datafusion::error::Result as DFResult;
+use arrow::record_batch::RecordBatch;
+use datafusion::common::Statistics;
+use datafusion::common::stats::Precision;
+use datafusion::error::{DataFusionError, Result as DFResult};
 use datafusion::execution::context::TaskContext;
-use datafusion::physical_expr::EquivalenceProperties;
-use datafusion::physical_plan::memory::MemoryStream;
 use datafusion::physical_plan::{
-    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
-    SendableRecordBatchStream,
+    DisplayAs, DisplayFormatType, ExecutionPlan, PlanProperties, SendableRecordBatchStream,
 };
+use futures::{StreamExt, stream};
 
+use super::compat::{
+    RecordBatchStreamAdapter, external_error, null_count_statistics, scan_properties,
+};
+
+use crate::error::{DruidSegmentError, Result};
 use crate::segment::DruidSegment;
+use crate::segment::read_options::{CancellationToken, ReadOptions};
 
-/// An ExecutionPlan that reads data from a Druid segment.
+/// An ExecutionPlan that reads data from one or more Druid segments.
 ///
 /// Supports projection pushdown: only the columns requested by DataFusion
-/// are read from the segment, avoiding IO for unused columns.
+/// are read from the segment, avoiding IO for unused columns. A time range
+/// in the read options prunes the segment or its rows by `__time`.
+///
+/// A single segment is read in a single partition and emits rows in
+/// storage order (see [`DruidSegment::read_all`]). Several segments are
+/// read one partition each, unless [`ReadOptions::preserve_order`] is set,
+/// in which case one partition reads them in turn, in the order given. A
+/// limit in the options caps the rows produced by each partition and lets
+/// column readers skip the remaining compressed blocks.
+///
+/// Rows are emitted in batches of [`ReadOptions::batch_size`] rows, or of
+/// the session's configured batch size if the options do not set one.
+///
+/// Decoding happens on a blocking task started when the stream is first
+/// polled, one batch ahead of the consumer, so memory use does not grow
+/// with the segment. A read that yields no rows emits a single empty
+/// batch. Dropping the stream cancels the read, so abandoned queries stop
+/// decoding at the next column or block boundary.
 #[derive(Debug)]
 pub struct DruidSegmentExec {
-    segment: Arc<DruidSegment>,
+    segments: Vec<Arc<DruidSegment>>,
     projection: Option<Vec<usize>>,
     projected_schema: SchemaRef,
     properties: PlanProperties,
+    options: ReadOptions,
 }
 
 impl DruidSegmentExec {
-    pub fn new(segment: Arc<DruidSegment>, projection: Option<Vec<usize>>) -> Self {
+    pub fn new(segment: Arc<DruidSegment>, projection: Option<Vec<usize>>) -> Result<Self> {
+        Self::with_options(segment, projection, ReadOptions::default())
+    }
+
+    /// Create an exec whose reads use `options`. A cancellation token in
+    /// `options` cancels every stream created by this plan.
+    pub fn with_options(
+        segment: Arc<DruidSegment>,
+        projection: Option<Vec<usize>>,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        Self::with_segments(vec![segment], projection, options)
+    }
+
+    /// Create an exec that scans several segments. `projection` indexes the
+    /// first segment's schema, which is built if it has not been, and
+    /// every segment must store the projected columns with the same types.
+    pub fn with_segments(
+        segments: Vec<Arc<DruidSegment>>,
+        projection: Option<Vec<usize>>,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        let first = segments
+            .first()
+            .ok_or_else(|| DruidSegmentError::NoSegments("the given list".to_string()))?;
+        let schema = options.read_schema(first.try_schema()?);
         let projected_schema = match &projection {
             Some(indices) => {
-                let schema = segment.schema();
                 let fields: Vec<Field> = indices.iter().map(|&i| schema.field(i).clone()).collect();
-                Arc::new(Schema::new(fields))
+                Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
             }
-            None => segment.schema(),
+            None => schema,
         };
 
-        let properties = PlanProperties::new(
-            EquivalenceProperties::new(projected_schema.clone()),
-            Partitioning::UnknownPartitioning(1),
-            datafusion::physical_plan::execution_plan::EmissionType::Incremental,
-            datafusion::physical_plan::execution_plan::Boundedness::Bounded,
-        );
+        let partitions = if options.preserve_order {
+            1
+        } else {
+            segments.len()
+        };
+        let properties = scan_properties(projected_schema.clone(), partitions);
 
-        Self {
-            segment,
+        Ok(Self {
+            segments,
             projection,
             projected_schema,
             properties,
+            options,
+        })
+    }
+
+    /// The segments read by `partition`.
+    fn partition_segments(&self, partition: usize) -> DFResult<Vec<Arc<DruidSegment>>> {
+        if self.options.preserve_order {
+            return Ok(self.segments.clone());
         }
+        self.segments
+            .get(partition)
+            .map(|segment| vec![segment.clone()])
+            .ok_or_else(|| {
+                DataFusionError::Internal(format!(
+                    "DruidSegmentExec has no partition {} ({} segments)",
+                    partition,
+                    self.segments.len()
+                ))
+            })
+    }
+
+    /// Build the output stream for `segments`, reading them in turn under
+    /// `token` and emitting batches of at most `batch_size` rows. The token
+    /// is cancelled when the stream is dropped.
+    fn read_stream(
+        &self,
+        segments: Vec<Arc<DruidSegment>>,
+        token: CancellationToken,
+        batch_size: usize,
+    ) -> SendableRecordBatchStream {
+        let col_names: Vec<String> = self
+            .projected_schema
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        let options = self
+            .options
+            .clone()
+            .with_cancellation(token.clone())
+            .with_batch_size(batch_size);
+        let guard = CancelOnDrop(token);
+        let schema = self.projected_schema.clone();
+
+        // The blocking task decodes one batch ahead of the consumer; a
+        // dropped stream closes the channel and stops it at the next batch
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let batches = stream::once(async move {
+            tokio::task::spawn_blocking(move || {
+                let names: Vec<&str> = col_names.iter().map(|s| s.as_str()).collect();
+                let mut sent = false;
+                let result = read_segments(&segments, &names, &options, |batch| {
+                    sent = true;
+                    tx.blocking_send(Ok(batch)).is_ok()
+                });
+                let last = match result {
+                    Ok(()) if sent => return,
+                    Ok(()) => Ok(RecordBatch::new_empty(schema)),
+                    Err(e) => Err(external_error(e)),
+                };
+                let _ = tx.blocking_send(last);
+            });
+            stream::unfold((rx, guard), |(mut rx, guard)| async move {
+                let batch = rx.recv().await?;
+                Some((batch, (rx, guard)))
+            })
+        })
+        .flatten();
+        Box::pin(RecordBatchStreamAdapter::new(
+            self.projected_schema.clone(),
+            batches,
+        ))
+    }
+
+    /// Statistics read from segment headers, without decoding any values.
+    ///
+    /// The row count comes from the `__time` headers, the byte size from
+    /// the smoosh entries of the projected columns, and null counts from
+    /// the null bitmaps of numeric columns. Counts are exact unless a time
+    /// range may drop rows; the byte size is always an estimate.
+    fn segment_statistics(&self) -> crate::error::Result<Statistics> {
+        let exact = self.options.time_range.is_none();
+        let mut num_rows = 0;
+        let mut all_rows = exact;
+        for segment in &self.segments {
+            let total = segment.num_rows()?;
+            let read = self.options.row_range(total).len();
+            num_rows += read;
+            all_rows &= read == total;
+        }
+        if self.options.preserve_order
+            && let Some(limit) = self.options.limit
+        {
+            all_rows &= num_rows <= limit;
+            num_rows = num_rows.min(limit);
+        }
+
+        let mut total_byte_size = 0;
+        let mut column_statistics = Vec::with_capacity(self.projected_schema.fields().len());
+        for field in self.projected_schema.fields() {
+            let mut null_count = Some(0);
+            for segment in &self.segments {
+                let Some(entry) = segment.smoosh().entry(field.name()) else {
+                    null_count = None;
+                    continue;
+                };
+                total_byte_size += entry.size();
+                null_count = match (null_count, segment.null_count(field.name())?) {
+                    (Some(sum), Some(count)) => Some(sum + count),
+                    _ => None,
+                };
+            }
+            let null_count = match null_count {
+                Some(0) if exact => Precision::Exact(0),
+                Some(count) if all_rows => Precision::Exact(count),
+                Some(count) => Precision::Inexact(count.min(num_rows)),
+                None => Precision::Absent,
+            };
+            column_statistics.push(null_count_statistics(null_count));
+        }
+
+        Ok(Statistics {
+            num_rows: if exact {
+                Precision::Exact(num_rows)
+            } else {
+                Precision::Inexact(num_rows)
+            },
+            total_byte_size: Precision::Inexact(total_byte_size),
+            column_statistics,
+        })
+    }
+}
+
+/// Read `columns` from each segment in turn, counting the options' limit
+/// across all of them, and pass each non-empty batch to `emit` as it is
+/// decoded. Stops early once `emit` returns false.
+fn read_segments(
+    segments: &[Arc<DruidSegment>],
+    columns: &[&str],
+    options: &ReadOptions,
+    mut emit: impl FnMut(RecordBatch) -> bool,
+) -> crate::error::Result<()> {
+    let mut remaining = options.limit;
+    for segment in segments {
+        if remaining == Some(0) {
+            break;
+        }
+        let options = ReadOptions {
+            limit: remaining,
+            ..options.clone()
+        };
+        for batch in segment.batches_with_options(columns, &options)? {
+            let batch = batch?;
+            if let Some(remaining) = &mut remaining {
+                *remaining = remaining.saturating_sub(batch.num_rows());
+            }
+            if !emit(batch) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Cancels its token when dropped, tying a read's lifetime to its stream.
+struct CancelOnDrop(CancellationToken);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
     }
 }
 
 impl DisplayAs for DruidSegmentExec {
     fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "DruidSegmentExec: projection={:?}", self.projection)
+        write!(f, "DruidSegmentExec: projection={:?}", self.projection)?;
+        if self.segments.len() > 1 {
+            write!(f, ", segments={}", self.segments.len())?;
+        }
+        let segment = &self.segments[0];
+        write!(
+            f,
+            ", bitmap={}, pushdown={}",
+            segment.metadata().bitmap_serde_factory,
+            if segment.index_pushdown_enabled() {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        )?;
+        if let Some(range) = &self.options.time_range {
+            write!(f, ", time_range=[{:?}, {:?})", range.start_ms, range.end_ms)?;
+        }
+        if let Some(limit) = self.options.limit {
+            write!(f, ", limit={}", limit)?;
+        }
+        Ok(())
     }
 }
 
@@ -80,6 +319,19 @@ impl ExecutionPlan for DruidSegmentExec {
         vec![]
     }
 
+    fn fetch(&self) -> Option<usize> {
+        // Each partition applies the limit on its own
+        if self.properties.output_partitioning().partition_count() == 1 {
+            self.options.limit
+        } else {
+            None
+        }
+    }
+
+    fn statistics(&self) -> DFResult<Statistics> {
+        self.segment_statistics().map_err(external_error)
+    }
+
     fn with_new_children(
         self: Arc<Self>,
         _children: Vec<Arc<dyn ExecutionPlan>>,
@@ -89,30 +341,238 @@ impl ExecutionPlan for DruidSegmentExec {
 
     fn execute(
         &self,
-        _partition: usize,
-        _context: Arc<TaskContext>,
+        partition: usize,
+        context: Arc<TaskContext>,
     ) -> DFResult<SendableRecordBatchStream> {
-        let batch = match &self.projection {
-            Some(indices) => {
-                let schema = self.segment.schema();
-                let col_names: Vec<&str> = indices
-                    .iter()
-                    .map(|&i| schema.field(i).name().as_str())
-                    .collect();
-                self.segment
-                    .read_columns(&col_names)
-                    .map_err(|e| datafusion::error::DataFusionError::External(Box::new(e)))?
-            }
-            None => self
-                .segment
-                .read_all()
-                .map_err(|e| datafusion::error::DataFusionError::External(Box::new(e)))?,
+        let segments = self.partition_segments(partition)?;
+        let token = match &self.options.cancellation {
+            Some(parent) => parent.child_token(),
+            None => CancellationToken::new(),
         };
+        let batch_size = self
+            .options
+            .batch_size
+            .unwrap_or_else(|| context.session_config().batch_size());
+        Ok(self.read_stream(segments, token, batch_size))
+    }
+}
 
-        Ok(Box::pin(MemoryStream::try_new(
-            vec![batch],
-            self.projected_schema.clone(),
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use arrow::compute::concat_batches;
+    use arrow::record_batch::RecordBatch;
+
+    use super::*;
+    use crate::segment::read_options::TimeRange;
+
+    const FIXTURE_PATH: &str = "tests/fixtures/wikipedia-segment";
+
+    fn open_fixture() -> Arc<DruidSegment> {
+        Arc::new(DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap())
+    }
+
+    /// Execute the plan and collect every batch it emits.
+    async fn execute_all(exec: &DruidSegmentExec) -> Vec<RecordBatch> {
+        let stream = exec.execute(0, Arc::new(TaskContext::default())).unwrap();
+        stream.map(|batch| batch.unwrap()).collect().await
+    }
+
+    /// Execute the plan and concatenate its batches.
+    async fn execute_concat(exec: &DruidSegmentExec) -> RecordBatch {
+        let batches = execute_all(exec).await;
+        concat_batches(&exec.schema(), &batches).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dropping_stream_cancels_read() {
+        let exec = DruidSegmentExec::new(open_fixture(), Some(vec![0])).unwrap();
+        let token = CancellationToken::new();
+        let stream = exec.read_stream(exec.segments.clone(), token.clone(), 8192);
+        assert!(!token.is_cancelled());
+        drop(stream);
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_out_of_range_time_range_skips_decoding() {
+        // A cancelled token fails any column decode, so an Ok result shows
+        // the segment was pruned before touching column data.
+        let token = CancellationToken::new();
+        token.cancel();
+        let segment = open_fixture();
+        let interval_end = segment.metadata().interval_end_ms;
+        let exec = DruidSegmentExec::with_options(
+            segment,
+            None,
+            ReadOptions::default()
+                .with_cancellation(token)
+                .with_time_range(TimeRange::new(Some(interval_end), None)),
+        )
+        .unwrap();
+        let mut stream = exec.execute(0, Arc::new(TaskContext::default())).unwrap();
+        let batch = stream.next().await.unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 0);
+        assert_eq!(batch.schema(), exec.schema());
+    }
+
+    #[tokio::test]
+    async fn test_time_range_filters_rows() {
+        let segment = open_fixture();
+        let start = segment.metadata().interval_start_ms + 3_600_000;
+        let range = TimeRange::new(Some(start), Some(start + 3_600_000));
+        // Project a column other than __time so the filter reads it separately
+        let exec = DruidSegmentExec::with_options(
+            segment.clone(),
+            Some(vec![1]),
+            ReadOptions::default().with_time_range(range),
+        )
+        .unwrap();
+        let batch = execute_concat(&exec).await;
+
+        let time = segment.read_columns(&["__time"]).unwrap();
+        let time = time
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::TimestampMillisecondArray>()
+            .unwrap();
+        let expected = time.values().iter().filter(|&&t| range.contains(t)).count();
+        assert!(expected > 0);
+        assert_eq!(batch.num_rows(), expected);
+        assert_eq!(batch.schema(), exec.schema());
+    }
+
+    #[tokio::test]
+    async fn test_limit_caps_rows() {
+        let segment = open_fixture();
+        for (options, expected) in [
+            (ReadOptions::default().with_limit(7), 7),
+            (ReadOptions::default().with_limit(0), 0),
+            (ReadOptions::default().with_limit(1_000_000), 39244),
+        ] {
+            let exec = DruidSegmentExec::with_options(segment.clone(), None, options).unwrap();
+            let batch = execute_concat(&exec).await;
+            assert_eq!(batch.num_rows(), expected);
+            assert_eq!(batch.schema(), exec.schema());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limit_applies_after_time_range() {
+        let segment = open_fixture();
+        let start = segment.metadata().interval_start_ms + 3_600_000;
+        let range = TimeRange::new(Some(start), None);
+        let exec = DruidSegmentExec::with_options(
+            segment,
+            Some(vec![0]),
+            ReadOptions::default().with_time_range(range).with_limit(5),
+        )
+        .unwrap();
+        let mut stream = exec.execute(0, Arc::new(TaskContext::default())).unwrap();
+        let batch = stream.next().await.unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 5);
+        let time = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::TimestampMillisecondArray>()
+            .unwrap();
+        assert!(time.values().iter().all(|&t| t >= start));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_options_fail_stream() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let exec = DruidSegmentExec::with_options(
+            open_fixture(),
+            Some(vec![0]),
+            ReadOptions::default().with_cancellation(token),
+        )
+        .unwrap();
+        let mut stream = exec.execute(0, Arc::new(TaskContext::default())).unwrap();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("cancelled"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_batches_reconstruct_segment() {
+        let segment = open_fixture();
+        let expected = segment.read_all().unwrap();
+        let num_rows = segment.num_rows().unwrap();
+
+        for batch_size in [1_000, 8_192, num_rows, num_rows + 1] {
+            let exec = DruidSegmentExec::with_options(
+                segment.clone(),
+                None,
+                ReadOptions::default().with_batch_size(batch_size),
+            )
+            .unwrap();
+            let batches = execute_all(&exec).await;
+            assert_eq!(batches.len(), num_rows.div_ceil(batch_size));
+            let (last, full) = batches.split_last().unwrap();
+            assert!(full.iter().all(|b| b.num_rows() == batch_size));
+            assert!(last.num_rows() <= batch_size);
+
+            let total: usize = batches.iter().map(|b| b.num_rows()).sum();
+            assert_eq!(total, num_rows);
+            assert_eq!(
+                concat_batches(&exec.schema(), &batches).unwrap(),
+                expected,
+                "batch size {}",
+                batch_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let segment = open_fixture();
+        let schema = segment.schema();
+        let added = schema.index_of("added").unwrap();
+        let channel = schema.index_of("channel").unwrap();
+        let exec = DruidSegmentExec::new(segment.clone(), Some(vec![0, added, channel])).unwrap();
+        let stats = exec.statistics().unwrap();
+        assert_eq!(stats.num_rows, Precision::Exact(39244));
+
+        let sizes: usize = ["__time", "added", "channel"]
+            .iter()
+            .map(|c| segment.smoosh().entry(c).unwrap().size())
+            .sum();
+        assert_eq!(stats.total_byte_size, Precision::Inexact(sizes));
+        assert_eq!(stats.column_statistics.len(), 3);
+        assert_eq!(stats.column_statistics[0].null_count, Precision::Exact(0));
+        assert_eq!(stats.column_statistics[1].null_count, Precision::Exact(0));
+        assert_eq!(stats.column_statistics[2].null_count, Precision::Exact(0));
+
+        let limited = DruidSegmentExec::with_options(
+            segment.clone(),
             None,
-        )?))
+            ReadOptions::default().with_limit(7),
+        )
+        .unwrap();
+        assert_eq!(limited.statistics().unwrap().num_rows, Precision::Exact(7));
+
+        let start = segment.metadata().interval_start_ms;
+        let filtered = DruidSegmentExec::with_options(
+            segment,
+            None,
+            ReadOptions::default().with_time_range(TimeRange::new(Some(start + 1), None)),
+        )
+        .unwrap();
+        assert_eq!(
+            filtered.statistics().unwrap().num_rows,
+            Precision::Inexact(39244)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_batch_size_is_default() {
+        let exec = DruidSegmentExec::new(open_fixture(), Some(vec![0])).unwrap();
+        let config = datafusion::prelude::SessionConfig::new().with_batch_size(10_000);
+        let context = TaskContext::default().with_session_config(config);
+        let stream = exec.execute(0, Arc::new(context)).unwrap();
+        let sizes: Vec<usize> = stream.map(|b| b.unwrap().num_rows()).collect().await;
+        assert_eq!(sizes, vec![10_000, 10_000, 10_000, 9_244]);
     }
 }
@@ -1,2 +1,4 @@
+mod compat;
 pub mod execution_plan;
 pub mod table_provider;
+pub mod time_filter;
@@ -4,15 +4,17 @@ use std::sync::Arc;
 
 use arrow::datatypes::SchemaRef;
 use async_trait::async_trait;
-use datafusion::catalog::Session;
 use datafusion::datasource::{TableProvider, TableType};
 use datafusion::error::Result as DFResult;
-use datafusion::logical_expr::Expr;
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown};
 use datafusion::physical_plan::ExecutionPlan;
 
+use super::compat::Session;
 use super::execution_plan::DruidSegmentExec;
-use crate::error::Result;
+use super::time_filter::{is_time_filter, time_range_from_filters};
+use crate::error::{DruidSegmentError, Result};
 use crate::segment::DruidSegment;
+use crate::segment::read_options::ReadOptions;
 
 /// A DataFusion TableProvider backed by a Druid segment directory.
 ///
@@ -23,23 +25,77 @@ use crate::segment::DruidSegment;
 /// ctx.register_table("my_datasource", Arc::new(table))?;
 /// let df = ctx.sql("SELECT * FROM my_datasource LIMIT 10").await?;
 /// ```
+///
+/// Range predicates on `__time` are pushed down: segments outside the range
+/// are skipped entirely and rows outside it are dropped after decoding.
+/// A `LIMIT` stops decoding once enough rows have been read.
 #[derive(Debug)]
 pub struct DruidSegmentTable {
     segment: Arc<DruidSegment>,
+    /// The segment's schema, built when the table is created.
+    schema: SchemaRef,
+    options: ReadOptions,
 }
 
 impl DruidSegmentTable {
-    /// Create from an already-opened segment.
-    pub fn new(segment: DruidSegment) -> Self {
-        Self {
-            segment: Arc::new(segment),
-        }
+    /// Create from an already-opened segment, building its schema from
+    /// every column header unless it was supplied or built already.
+    pub fn new(segment: DruidSegment) -> Result<Self> {
+        Self::from_arc(Arc::new(segment))
+    }
+
+    /// Create from a shared segment, so that one opened segment can back
+    /// tables in several sessions. What the segment caches on first use,
+    /// such as its schema and row count, is then shared by all of them.
+    pub fn from_arc(segment: Arc<DruidSegment>) -> Result<Self> {
+        Ok(Self {
+            schema: segment.try_schema()?,
+            segment,
+            options: ReadOptions::default(),
+        })
+    }
+
+    /// Use `options` as the base for every scan. Pushed-down `__time`
+    /// filters narrow any time range they already carry.
+    pub fn with_options(mut self, options: ReadOptions) -> Self {
+        self.options = options;
+        self
     }
 
-    /// Open a segment directory and create a table provider.
+    /// Open a segment directory and create a table provider, building its
+    /// schema from every column header.
     pub fn open(path: &Path) -> Result<Self> {
-        let segment = DruidSegment::open(path)?;
-        Ok(Self::new(segment))
+        Self::new(DruidSegment::open(path)?)
+    }
+
+    /// Open a segment directory with a caller-supplied schema, such as the
+    /// one given to `CREATE EXTERNAL TABLE`.
+    ///
+    /// No column header is parsed at registration; queries parse only the
+    /// headers of the columns they read, and fail at execution if a column's
+    /// stored type does not match `schema` (see
+    /// [`DruidSegment::open_with_schema`]).
+    pub fn new_with_schema(path: &Path, schema: SchemaRef) -> Result<Self> {
+        Self::new(DruidSegment::open_with_schema(path, schema)?)
+    }
+
+    /// Open the segment stored under `prefix` in an object store and create
+    /// a table provider, fetching only its metadata, `__time` and column
+    /// headers (see [`DruidSegment::open_remote`]).
+    ///
+    /// Each scan then fetches the columns it projects that no earlier scan
+    /// has, so a query transfers only the columns it reads.
+    #[cfg(feature = "object_store")]
+    pub async fn open_remote(
+        store: Arc<dyn object_store::ObjectStore>,
+        prefix: &object_store::path::Path,
+    ) -> Result<Self> {
+        Self::new(DruidSegment::open_remote(store, prefix).await?)
+    }
+
+    /// The segment the table reads.
+    pub fn segment(&self) -> &Arc<DruidSegment> {
+        &self.segment
     }
 }
 
@@ -50,23 +106,439 @@ impl TableProvider for DruidSegmentTable {
     }
 
     fn schema(&self) -> SchemaRef {
-        self.segment.schema()
+        self.options.read_schema(self.schema.clone())
     }
 
     fn table_type(&self) -> TableType {
         TableType::Base
     }
 
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DFResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters_pushdown(filters))
+    }
+
     async fn scan(
         &self,
         _state: &dyn Session,
         projection: Option<&Vec<usize>>,
-        _filters: &[Expr],
-        _limit: Option<usize>,
+        filters: &[Expr],
+        limit: Option<usize>,
     ) -> DFResult<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(DruidSegmentExec::new(
+        #[cfg(feature = "object_store")]
+        {
+            let schema = &self.schema;
+            let columns: Vec<&str> = match projection {
+                Some(indices) => indices
+                    .iter()
+                    .map(|&i| schema.field(i).name().as_str())
+                    .collect(),
+                None => schema.fields().iter().map(|f| f.name().as_str()).collect(),
+            };
+            self.segment
+                .fetch_columns(&columns)
+                .await
+                .map_err(super::compat::external_error)?;
+        }
+        let exec = DruidSegmentExec::with_options(
             self.segment.clone(),
             projection.cloned(),
-        )))
+            scan_options(&self.options, filters, limit),
+        )
+        .map_err(super::compat::external_error)?;
+        Ok(Arc::new(exec))
+    }
+}
+
+/// A DataFusion TableProvider backed by several segments of one
+/// datasource, scanned one partition per segment.
+///
+/// ```ignore
+/// let table = DruidSegmentsTable::open_dir(Path::new("/path/to/datasource"))?;
+/// ctx.register_table("my_datasource", Arc::new(table))?;
+/// ```
+///
+/// The table has the schema of its earliest segment. Every other segment
+/// must store each of its columns with the same type; extra columns are
+/// ignored. Pushdown works as for [`DruidSegmentTable`], applied to each
+/// segment. With [`ReadOptions::preserve_order`] set, the segments are
+/// read in a single partition, ordered by interval start.
+#[derive(Debug)]
+pub struct DruidSegmentsTable {
+    segments: Vec<Arc<DruidSegment>>,
+    /// The earliest segment's schema.
+    schema: SchemaRef,
+    options: ReadOptions,
+}
+
+impl DruidSegmentsTable {
+    /// Create from already-opened segments, checking that their schemas
+    /// are compatible. Segments are ordered by interval start.
+    pub fn new(segments: Vec<DruidSegment>) -> Result<Self> {
+        let mut segments: Vec<Arc<DruidSegment>> = segments.into_iter().map(Arc::new).collect();
+        segments.sort_by_key(|s| s.metadata().interval_start_ms);
+        let first = segments
+            .first()
+            .ok_or_else(|| DruidSegmentError::NoSegments("the given list".to_string()))?;
+
+        let schema = first.try_schema()?;
+        for segment in &segments[1..] {
+            let other = segment.try_schema()?;
+            for field in schema.fields() {
+                let actual = match other.field_with_name(field.name()) {
+                    Ok(f) if f.data_type() == field.data_type() => continue,
+                    Ok(f) => f.data_type().to_string(),
+                    Err(_) => "no such column".to_string(),
+                };
+                return Err(DruidSegmentError::SchemaMismatch {
+                    column: field.name().clone(),
+                    expected: field.data_type().to_string(),
+                    actual,
+                });
+            }
+        }
+
+        Ok(Self {
+            segments,
+            schema,
+            options: ReadOptions::default(),
+        })
+    }
+
+    /// Use `options` as the base for every scan. Pushed-down `__time`
+    /// filters narrow any time range they already carry.
+    pub fn with_options(mut self, options: ReadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Open every subdirectory of `path` that holds a segment (a
+    /// `version.bin` file) and create a table provider over them.
+    pub fn open_dir(path: &Path) -> Result<Self> {
+        let mut dirs = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let dir = entry?.path();
+            if dir.join("version.bin").is_file() {
+                dirs.push(dir);
+            }
+        }
+        if dirs.is_empty() {
+            return Err(DruidSegmentError::NoSegments(path.display().to_string()));
+        }
+        dirs.sort();
+        let segments = dirs
+            .iter()
+            .map(|dir| DruidSegment::open(dir))
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(segments)
+    }
+
+    /// The table's segments, ordered by interval start.
+    pub fn segments(&self) -> &[Arc<DruidSegment>] {
+        &self.segments
+    }
+}
+
+#[async_trait]
+impl TableProvider for DruidSegmentsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.options.read_schema(self.schema.clone())
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DFResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters_pushdown(filters))
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DFResult<Arc<dyn ExecutionPlan>> {
+        let exec = DruidSegmentExec::with_segments(
+            self.segments.clone(),
+            projection.cloned(),
+            scan_options(&self.options, filters, limit),
+        )
+        .map_err(super::compat::external_error)?;
+        Ok(Arc::new(exec))
+    }
+}
+
+/// `__time` range filters are pushed down inexactly: rows outside the
+/// range are dropped, but DataFusion still applies the filter.
+fn filters_pushdown(filters: &[&Expr]) -> Vec<TableProviderFilterPushDown> {
+    filters
+        .iter()
+        .map(|expr| {
+            if is_time_filter(expr) {
+                TableProviderFilterPushDown::Inexact
+            } else {
+                TableProviderFilterPushDown::Unsupported
+            }
+        })
+        .collect()
+}
+
+/// Narrow the base options by a scan's limit and `__time` filters.
+fn scan_options(base: &ReadOptions, filters: &[Expr], limit: Option<usize>) -> ReadOptions {
+    let mut options = base.clone();
+    if let Some(limit) = limit {
+        let limit = options.limit.map_or(limit, |base| base.min(limit));
+        options = options.with_limit(limit);
+    }
+    if let Some(range) = time_range_from_filters(filters) {
+        let range = match options.time_range {
+            Some(base) => base.intersect(range),
+            None => range,
+        };
+        options = options.with_time_range(range);
+    }
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::{Array, Int64Array, TimestampMillisecondArray};
+    use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+    use datafusion::prelude::SessionContext;
+
+    use super::*;
+
+    const FIXTURE_PATH: &str = "tests/fixtures/wikipedia-segment";
+
+    #[tokio::test]
+    async fn test_shared_segment_in_two_sessions() {
+        let segment = Arc::new(DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap());
+        segment.try_schema().unwrap();
+        let mut counts = Vec::new();
+        for sql in [
+            "SELECT count(*) FROM segment",
+            "SELECT count(*) FROM segment WHERE channel = '#en.wikipedia'",
+        ] {
+            let ctx = SessionContext::new();
+            let table = DruidSegmentTable::from_arc(segment.clone()).unwrap();
+            ctx.register_table("segment", Arc::new(table)).unwrap();
+            let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+            let column = batches[0].column(0);
+            counts.push(
+                column
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap()
+                    .value(0),
+            );
+        }
+        assert_eq!(counts[0], 39244);
+        assert!(counts[1] > 0 && counts[1] < counts[0]);
+        // Both tables read through the one segment
+        assert_eq!(Arc::strong_count(&segment), 1);
+        assert!(segment.parsed_columns().contains(&"channel".to_string()));
+    }
+
+    async fn count(sql: &str) -> i64 {
+        let ctx = SessionContext::new();
+        let table = DruidSegmentTable::open(Path::new(FIXTURE_PATH)).unwrap();
+        ctx.register_table("segment", Arc::new(table)).unwrap();
+        let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        let counts = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(counts.len(), 1);
+        counts.value(0)
+    }
+
+    #[tokio::test]
+    async fn test_out_of_range_time_filter_is_empty() {
+        let n = count(
+            "SELECT count(*) FROM segment \
+             WHERE __time >= TIMESTAMP '2016-01-01' AND __time < TIMESTAMP '2016-01-02'",
+        )
+        .await;
+        assert_eq!(n, 0);
+    }
+
+    #[tokio::test]
+    async fn test_empty_projection_decodes_no_values() {
+        let table = DruidSegmentTable::new_with_schema(
+            Path::new(FIXTURE_PATH),
+            partial_schema(DataType::Int64),
+        )
+        .unwrap();
+        let segment = table.segment.clone();
+        let ctx = SessionContext::new();
+        ctx.register_table("segment", Arc::new(table)).unwrap();
+
+        let batches = ctx
+            .sql("SELECT 1 FROM segment LIMIT 3")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 3);
+
+        let count = |sql: &'static str| {
+            let ctx = &ctx;
+            async move {
+                let batches = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+                batches[0]
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap()
+                    .value(0)
+            }
+        };
+        assert_eq!(count("SELECT count(*) FROM segment").await, 39244);
+        let n = count(
+            "SELECT count(*) FROM segment \
+             WHERE __time >= TIMESTAMP '2015-09-12 01:00:00'",
+        )
+        .await;
+        assert!(n > 0 && n < 39244);
+        // Only the row count header and the filtered time column are read
+        assert_eq!(segment.parsed_columns(), vec!["__time".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_limit_is_pushed_down() {
+        let ctx = SessionContext::new();
+        let table = DruidSegmentTable::open(Path::new(FIXTURE_PATH)).unwrap();
+        ctx.register_table("segment", Arc::new(table)).unwrap();
+        let df = ctx.sql("SELECT * FROM segment LIMIT 10").await.unwrap();
+
+        let plan = df.clone().create_physical_plan().await.unwrap();
+        let plan = datafusion::physical_plan::displayable(plan.as_ref())
+            .indent(true)
+            .to_string();
+        assert!(plan.contains("limit=10"), "{}", plan);
+
+        let batches = df.collect().await.unwrap();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 10);
+    }
+
+    #[tokio::test]
+    async fn test_time_filter_keeps_rows_in_range() {
+        let pushed = count(
+            "SELECT count(*) FROM segment \
+             WHERE __time >= TIMESTAMP '2015-09-12 01:00:00' \
+             AND __time < TIMESTAMP '2015-09-12 02:00:00'",
+        )
+        .await;
+
+        let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+        let batch = segment.read_columns(&["__time"]).unwrap();
+        let time = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        let (start, end) = (1_442_019_600_000, 1_442_023_200_000);
+        let expected = time
+            .values()
+            .iter()
+            .filter(|&&t| t >= start && t < end)
+            .count();
+        assert!(expected > 0);
+        assert_eq!(pushed, expected as i64);
+    }
+
+    fn partial_schema(added_type: DataType) -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new(
+                "__time",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+            Field::new("channel", DataType::Utf8, true),
+            Field::new("added", added_type, true),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn test_user_schema_parses_only_queried_columns() {
+        let table = DruidSegmentTable::new_with_schema(
+            Path::new(FIXTURE_PATH),
+            partial_schema(DataType::Int64),
+        )
+        .unwrap();
+        let segment = table.segment.clone();
+        assert!(segment.parsed_columns().is_empty());
+
+        let ctx = SessionContext::new();
+        ctx.register_table("segment", Arc::new(table)).unwrap();
+        let batches = ctx
+            .sql("SELECT sum(added) FROM segment")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let sum = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert!(sum.value(0) > 0);
+        // Planning reads the row count from the __time header for statistics
+        assert_eq!(
+            segment.parsed_columns(),
+            vec!["__time".to_string(), "added".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_schema_mismatch_fails_at_execution() {
+        let table = DruidSegmentTable::new_with_schema(
+            Path::new(FIXTURE_PATH),
+            partial_schema(DataType::Utf8),
+        )
+        .unwrap();
+        let ctx = SessionContext::new();
+        ctx.register_table("segment", Arc::new(table)).unwrap();
+
+        // Columns with correct types still read fine
+        ctx.sql("SELECT channel FROM segment LIMIT 1")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+
+        let err = ctx
+            .sql("SELECT added FROM segment")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("added"), "{}", err);
+        assert!(err.contains("Utf8") && err.contains("Int64"), "{}", err);
+    }
+
+    #[test]
+    fn test_user_schema_with_unknown_column() {
+        let schema = Arc::new(Schema::new(vec![Field::new("nope", DataType::Utf8, true)]));
+        assert!(DruidSegmentTable::new_with_schema(Path::new(FIXTURE_PATH), schema).is_err());
     }
 }
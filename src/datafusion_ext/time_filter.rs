@@ -0,0 +1,184 @@
+use arrow::datatypes::DataType;
+use datafusion::logical_expr::{Between, BinaryExpr, Cast, Expr, Operator, TryCast};
+use datafusion::scalar::ScalarValue;
+
+use crate::segment::TIME_COLUMN;
+use crate::segment::read_options::TimeRange;
+
+/// Extract the `__time` range implied by a conjunction of filters.
+///
+/// Filters that do not constrain `__time` are ignored, so the result may be
+/// wider than the filters themselves. Returns `None` if no filter applies.
+pub fn time_range_from_filters(filters: &[Expr]) -> Option<TimeRange> {
+    filters
+        .iter()
+        .filter_map(expr_time_range)
+        .reduce(TimeRange::intersect)
+}
+
+/// Whether `expr` constrains `__time` in a way [`time_range_from_filters`]
+/// understands.
+pub fn is_time_filter(expr: &Expr) -> bool {
+    expr_time_range(expr).is_some()
+}
+
+fn expr_time_range(expr: &Expr) -> Option<TimeRange> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        }) => match (expr_time_range(left), expr_time_range(right)) {
+            (Some(l), Some(r)) => Some(l.intersect(r)),
+            (l, r) => l.or(r),
+        },
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            if is_time_column(left) {
+                comparison_range(*op, right)
+            } else if is_time_column(right) {
+                comparison_range(op.swap()?, left)
+            } else {
+                None
+            }
+        }
+        Expr::Between(Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        }) if is_time_column(expr) => {
+            let low = comparison_range(Operator::GtEq, low)?;
+            let high = comparison_range(Operator::LtEq, high)?;
+            Some(low.intersect(high))
+        }
+        _ => None,
+    }
+}
+
+/// The range of `__time` values satisfying `__time <op> literal`.
+fn comparison_range(op: Operator, literal: &Expr) -> Option<TimeRange> {
+    let (floor, ceil) = literal_millis(literal)?;
+    let range = match op {
+        Operator::Gt => TimeRange::new(Some(floor.checked_add(1)?), None),
+        Operator::GtEq => TimeRange::new(Some(ceil), None),
+        Operator::Lt => TimeRange::new(None, Some(ceil)),
+        Operator::LtEq => TimeRange::new(None, Some(floor.checked_add(1)?)),
+        Operator::Eq => TimeRange::new(Some(ceil), Some(floor.checked_add(1)?)),
+        _ => return None,
+    };
+    Some(range)
+}
+
+/// A timestamp literal as epoch milliseconds, rounded down and up.
+fn literal_millis(expr: &Expr) -> Option<(i64, i64)> {
+    let Expr::Literal(value) = expr else {
+        return None;
+    };
+    match value {
+        ScalarValue::TimestampSecond(Some(v), _) => {
+            let ms = v.checked_mul(1_000)?;
+            Some((ms, ms))
+        }
+        ScalarValue::TimestampMillisecond(Some(v), _) => Some((*v, *v)),
+        ScalarValue::TimestampMicrosecond(Some(v), _) => Some(floor_ceil(*v, 1_000)),
+        ScalarValue::TimestampNanosecond(Some(v), _) => Some(floor_ceil(*v, 1_000_000)),
+        _ => None,
+    }
+}
+
+fn floor_ceil(value: i64, divisor: i64) -> (i64, i64) {
+    let floor = value.div_euclid(divisor);
+    let ceil = if value.rem_euclid(divisor) == 0 {
+        floor
+    } else {
+        floor + 1
+    };
+    (floor, ceil)
+}
+
+/// Whether `expr` is the `__time` column, possibly cast to another
+/// timestamp unit (which preserves ordering).
+fn is_time_column(expr: &Expr) -> bool {
+    match expr {
+        Expr::Column(column) => column.name == TIME_COLUMN,
+        Expr::Cast(Cast { expr, data_type }) | Expr::TryCast(TryCast { expr, data_type }) => {
+            matches!(data_type, DataType::Timestamp(_, _)) && is_time_column(expr)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::logical_expr::{col, lit};
+
+    use super::*;
+
+    fn ms(v: i64) -> Expr {
+        lit(ScalarValue::TimestampMillisecond(Some(v), None))
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let cases = [
+            (col("__time").gt(ms(10)), TimeRange::new(Some(11), None)),
+            (col("__time").gt_eq(ms(10)), TimeRange::new(Some(10), None)),
+            (col("__time").lt(ms(10)), TimeRange::new(None, Some(10))),
+            (col("__time").lt_eq(ms(10)), TimeRange::new(None, Some(11))),
+            (col("__time").eq(ms(10)), TimeRange::new(Some(10), Some(11))),
+            (ms(10).lt(col("__time")), TimeRange::new(Some(11), None)),
+            (
+                col("__time").between(ms(10), ms(20)),
+                TimeRange::new(Some(10), Some(21)),
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                time_range_from_filters(std::slice::from_ref(&expr)),
+                Some(expected),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_conjunction_and_other_filters() {
+        let filters = [
+            col("__time")
+                .gt_eq(ms(10))
+                .and(col("channel").eq(lit("#en"))),
+            col("__time").lt(ms(20)),
+            col("added").gt(lit(5i64)),
+        ];
+        assert!(is_time_filter(&filters[0]));
+        assert!(!is_time_filter(&filters[2]));
+        assert_eq!(
+            time_range_from_filters(&filters),
+            Some(TimeRange::new(Some(10), Some(20)))
+        );
+        assert_eq!(time_range_from_filters(&filters[2..]), None);
+    }
+
+    #[test]
+    fn test_sub_millisecond_literals() {
+        let ns = |v: i64| lit(ScalarValue::TimestampNanosecond(Some(v), None));
+        let time = || col("__time");
+        assert_eq!(
+            time_range_from_filters(&[time().gt(ns(10_500_000))]),
+            Some(TimeRange::new(Some(11), None))
+        );
+        assert_eq!(
+            time_range_from_filters(&[time().gt_eq(ns(10_500_000))]),
+            Some(TimeRange::new(Some(11), None))
+        );
+        assert_eq!(
+            time_range_from_filters(&[time().lt(ns(10_500_000))]),
+            Some(TimeRange::new(None, Some(11)))
+        );
+        assert_eq!(
+            time_range_from_filters(&[time().lt_eq(ns(10_500_000))]),
+            Some(TimeRange::new(None, Some(11)))
+        );
+    }
+}
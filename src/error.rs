@@ -5,6 +5,13 @@ pub enum DruidSegmentError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("Zip archive error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[cfg(feature = "object_store")]
+    #[error("Object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+
     #[error("Invalid segment version: expected 9, got {0}")]
     InvalidVersion(i32),
 
@@ -14,6 +21,22 @@ pub enum DruidSegmentError {
     #[error("Logical file not found in smoosh: {0}")]
     LogicalFileNotFound(String),
 
+    #[error("Logical file '{0}' has not been fetched from the object store")]
+    LogicalFileNotFetched(String),
+
+    #[error("Logical file '{0}' is empty")]
+    EmptyLogicalFile(String),
+
+    #[error("Logical file '{0}' was already added to the smoosh archive")]
+    DuplicateLogicalFile(String),
+
+    #[error("Logical file '{name}' is {size} bytes, larger than the {max} byte chunk limit")]
+    LogicalFileTooLarge {
+        name: String,
+        size: usize,
+        max: usize,
+    },
+
     #[error("Unsupported compression strategy: {0:#x}")]
     UnsupportedCompression(u8),
 
@@ -40,6 +63,152 @@ pub enum DruidSegmentError {
 
     #[error("DataFusion error: {0}")]
     DataFusionError(#[from] datafusion::error::DataFusionError),
+
+    #[error(
+        "Schema mismatch for column {column}: schema declares {expected}, segment stores {actual}"
+    )]
+    SchemaMismatch {
+        column: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Failed to read column '{column}' at byte {offset}: {source}")]
+    ColumnReadError {
+        column: String,
+        /// Offset in the column's logical file of the section that failed.
+        offset: usize,
+        source: Box<DruidSegmentError>,
+    },
+
+    #[error("Part type '{serde_type}' holds version {expected:#x} data, found version {actual:#x}")]
+    SerdeVersionMismatch {
+        serde_type: String,
+        expected: u8,
+        actual: u8,
+    },
+
+    #[error("Unknown column '{requested}'{}", column_hint(requested, available))]
+    UnknownColumn {
+        requested: String,
+        /// The segment's columns, `__time` first.
+        available: Vec<String>,
+    },
+
+    #[error("Row offset {offset} is out of range for a segment of {num_rows} rows")]
+    RowOutOfRange { offset: usize, num_rows: usize },
+
+    #[error("No segment directories found in {0}")]
+    NoSegments(String),
+
+    #[error("Read cancelled")]
+    Cancelled,
+}
+
+impl DruidSegmentError {
+    /// Attach the column being read, and the offset in its logical file of
+    /// the section that failed, to a low-level parse error. Cancellation
+    /// and errors that already carry a column are returned unchanged.
+    pub fn in_column(self, column: &str, offset: usize) -> Self {
+        match self {
+            Self::Cancelled | Self::ColumnReadError { .. } => self,
+            source => Self::ColumnReadError {
+                column: column.to_string(),
+                offset,
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// For an [`UnknownColumn`](Self::UnknownColumn) error, the available
+    /// column closest to the requested one, if any is close enough.
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            Self::UnknownColumn {
+                requested,
+                available,
+            } => closest_column(requested, available.iter().map(String::as_str)),
+            _ => None,
+        }
+    }
+}
+
+/// The name in `available` closest to `requested` by edit distance,
+/// ignoring case, if it is within a third of the requested name's length
+/// (at least one edit).
+pub fn closest_column<'a>(
+    requested: &str,
+    available: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let requested = requested.to_lowercase();
+    let max_distance = (requested.chars().count() / 3).max(1);
+    available
+        .into_iter()
+        .map(|name| (edit_distance(&requested, &name.to_lowercase()), name))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+/// Levenshtein distance between `a` and `b`, in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a != b);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The end of an unknown column's message: the closest column, or every
+/// column if none is close.
+fn column_hint(requested: &str, available: &[String]) -> String {
+    match closest_column(requested, available.iter().map(String::as_str)) {
+        Some(name) => format!("; did you mean '{}'?", name),
+        None => format!("; the segment has columns {}", available.join(", ")),
+    }
 }
 
 pub type Result<T> = std::result::Result<T, DruidSegmentError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("usr", "user"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("été", "ete"), 2);
+    }
+
+    #[test]
+    fn test_unknown_column_suggestion() {
+        let available = vec!["__time".to_string(), "cityName".into(), "user".into()];
+        let error = |requested: &str| DruidSegmentError::UnknownColumn {
+            requested: requested.into(),
+            available: available.clone(),
+        };
+        assert_eq!(error("usr").suggestion(), Some("user"));
+        assert_eq!(
+            error("usr").to_string(),
+            "Unknown column 'usr'; did you mean 'user'?"
+        );
+        // Case differences cost nothing
+        assert_eq!(error("cityname").suggestion(), Some("cityName"));
+        assert_eq!(error("xyz").suggestion(), None);
+        assert_eq!(
+            error("xyz").to_string(),
+            "Unknown column 'xyz'; the segment has columns __time, cityName, user"
+        );
+        assert_eq!(DruidSegmentError::Cancelled.suggestion(), None);
+    }
+}
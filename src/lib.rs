@@ -1,5 +1,19 @@
+#[cfg(all(feature = "df-43", feature = "df-44"))]
+compile_error!(
+    "features `df-43` and `df-44` are mutually exclusive; build DataFusion 43 with \
+     `--no-default-features --features df-43`"
+);
+#[cfg(not(any(feature = "df-43", feature = "df-44")))]
+compile_error!("enable one of the `df-43` or `df-44` features to pick a DataFusion version");
+
+// Every module refers to the selected major as `datafusion`
+#[cfg(feature = "df-43")]
+extern crate datafusion_43 as datafusion;
+
 pub mod column;
 pub mod compression;
 pub mod datafusion_ext;
 pub mod error;
 pub mod segment;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -1,13 +1,41 @@
+#[cfg(feature = "df-43")]
+extern crate datafusion_43 as datafusion;
+
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::Result;
+use arrow::array::{Array, ArrayRef, BinaryArray, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use chrono::DateTime;
+use chrono_tz::Tz;
 use clap::{Parser, Subcommand, ValueEnum};
-use datafusion::prelude::SessionContext;
+use datafusion::common::SchemaError;
+use datafusion::error::DataFusionError;
+use datafusion::prelude::{SessionConfig, SessionContext};
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
 
+use druid_datafusion_bridge::column::block_layout::BlockLayout;
+use druid_datafusion_bridge::column::complex::{self, quantiles};
+use druid_datafusion_bridge::compression::CompressionStrategy;
 use druid_datafusion_bridge::datafusion_ext::table_provider::DruidSegmentTable;
-use druid_datafusion_bridge::segment::DruidSegment;
+use druid_datafusion_bridge::error::closest_column;
+use druid_datafusion_bridge::segment::create::{CreateOptions, CreateSummary, create_segment};
+use druid_datafusion_bridge::segment::merge::{MergeOptions, MergeSummary, merge_segments};
+use druid_datafusion_bridge::segment::progress::{ProgressReport, ProgressSink};
+use druid_datafusion_bridge::segment::read_options::ReadOptions;
+use druid_datafusion_bridge::segment::smoosh::SmooshReader;
+use druid_datafusion_bridge::segment::stats::ColumnStats;
+use druid_datafusion_bridge::segment::writer::SegmentWriter;
+use druid_datafusion_bridge::segment::{
+    COLUMN_ROLE_KEY, DruidSegment, HAS_MULTIPLE_VALUES_KEY, IS_DIMENSION_KEY, VALUE_TYPE_KEY,
+};
 
 #[derive(Parser)]
 #[command(
@@ -17,27 +45,43 @@ use druid_datafusion_bridge::segment::DruidSegment;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Do not show progress bars
+    #[arg(short, long, global = true)]
+    quiet: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Show segment metadata: columns, types, interval, row count
     Info {
-        /// Path to the segment directory
+        /// Path to the segment directory or its zip archive
         #[arg(value_name = "SEGMENT_DIR")]
         path: PathBuf,
+
+        /// IANA time zone to show the interval in, e.g. America/New_York
+        #[arg(long, default_value = "UTC")]
+        timezone: Tz,
+
+        /// Also print the compressed block layout of each numeric column
+        #[arg(long)]
+        layout: bool,
+
+        /// Print the schema and Druid column details as JSON instead
+        #[arg(long, conflicts_with_all = ["timezone", "layout"])]
+        json: bool,
     },
 
     /// List all logical files in the smoosh archive
     Files {
-        /// Path to the segment directory
+        /// Path to the segment directory or its zip archive
         #[arg(value_name = "SEGMENT_DIR")]
         path: PathBuf,
     },
 
     /// Print rows from the segment
     Dump {
-        /// Path to the segment directory
+        /// Path to the segment directory or its zip archive
         #[arg(value_name = "SEGMENT_DIR")]
         path: PathBuf,
 
@@ -54,15 +98,168 @@ enum Commands {
         format: OutputFormat,
     },
 
+    /// Profile each column: min, max, null count, distinct count
+    Stats {
+        /// Path to the segment directory or its zip archive
+        #[arg(value_name = "SEGMENT_DIR")]
+        path: PathBuf,
+
+        /// Also decode every column in full and print the time each took,
+        /// slowest first
+        #[arg(long)]
+        timing: bool,
+
+        /// Also print the compressed block layout of each numeric column
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Convert a segment to a Parquet file
+    #[cfg(feature = "parquet")]
+    Convert {
+        /// Path to the segment directory or its zip archive
+        #[arg(value_name = "SEGMENT_DIR")]
+        path: PathBuf,
+
+        /// Parquet file to write
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Rewrite a segment with a different block compression
+    Recompress {
+        /// Path to the segment directory or its zip archive
+        #[arg(value_name = "SEGMENT_DIR")]
+        path: PathBuf,
+
+        /// Directory to write the rewritten segment to
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+
+        /// Block compression of the rewritten numeric columns
+        #[arg(short, long, default_value = "lz4")]
+        compression: BlockCompression,
+
+        /// Write into the output directory even if it is not empty
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Merge segments into one, sorted by time and dimensions
+    Merge {
+        /// Paths to the segment directories or their zip archives
+        #[arg(value_name = "SEGMENT_DIR", required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Directory to write the merged segment to
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+
+        /// Roll up rows with the same time and dimensions using the
+        /// aggregators in metadata.drd
+        #[arg(long)]
+        rollup: bool,
+
+        /// Block compression of the merged numeric columns
+        #[arg(short, long, default_value = "lz4")]
+        compression: BlockCompression,
+
+        /// Write into the output directory even if it is not empty
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Build a segment from a Parquet or CSV file, sorted by time
+    Create {
+        /// Parquet or CSV file to read, told apart by its extension
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Column holding each row's timestamp
+        #[arg(long)]
+        time_column: String,
+
+        /// Comma-separated columns to store as dimensions (default: every
+        /// column that is not the time column or a metric)
+        #[arg(long, value_delimiter = ',')]
+        dimensions: Option<Vec<String>>,
+
+        /// Comma-separated metrics, e.g. count:count,added:longSum; each
+        /// aggregates the input column of its name
+        #[arg(long, value_delimiter = ',', value_name = "NAME:AGGREGATOR")]
+        metrics: Vec<String>,
+
+        /// Roll up rows with the same time and dimensions using the
+        /// metrics' aggregators
+        #[arg(long)]
+        rollup: bool,
+
+        /// Directory to write the segment to
+        #[arg(short, long, value_name = "DIR")]
+        output: PathBuf,
+
+        /// Block compression of the numeric columns
+        #[arg(short, long, default_value = "lz4")]
+        compression: BlockCompression,
+
+        /// Write into the output directory even if it is not empty
+        #[arg(long)]
+        force: bool,
+    },
+
     /// Run a SQL query against a segment using DataFusion
     Query {
-        /// Path to the segment directory
+        /// Path to the segment directory or its zip archive
         #[arg(value_name = "SEGMENT_DIR")]
         path: PathBuf,
 
         /// SQL query to execute (table name is 'segment')
         #[arg(short, long)]
         sql: String,
+
+        /// Rows per record batch (default: DataFusion's 8192)
+        #[arg(long)]
+        batch_size: Option<usize>,
+
+        /// Scan string columns as dictionary arrays
+        #[arg(long)]
+        strings_as_dictionary: bool,
+    },
+
+    /// Time a SQL query against a segment
+    Bench {
+        /// Path to the segment directory or its zip archive
+        #[arg(value_name = "SEGMENT_DIR")]
+        path: PathBuf,
+
+        /// SQL query to time (table name is 'segment')
+        #[arg(short, long, default_value = "SELECT * FROM segment")]
+        sql: String,
+
+        /// Runs per configuration; the fastest is reported
+        #[arg(short, long, default_value = "3")]
+        iterations: usize,
+
+        /// Rows per record batch when not tuning
+        #[arg(long, default_value = "8192")]
+        batch_size: usize,
+
+        /// Scan string columns as dictionary arrays when not tuning
+        #[arg(long)]
+        strings_as_dictionary: bool,
+
+        /// Search the batch sizes in --grid, each with string columns read
+        /// as plain and as dictionary arrays, and print the fastest as JSON
+        #[arg(long)]
+        tune: bool,
+
+        /// Batch sizes to try with --tune
+        #[arg(
+            long,
+            value_delimiter = ',',
+            default_value = "1024,4096,8192,32768,65536"
+        )]
+        grid: Vec<usize>,
     },
 }
 
@@ -73,6 +270,22 @@ enum OutputFormat {
     Csv,
 }
 
+/// Block compressions a segment can be rewritten with.
+#[derive(Clone, Copy, ValueEnum)]
+enum BlockCompression {
+    Lz4,
+    None,
+}
+
+impl From<BlockCompression> for CompressionStrategy {
+    fn from(compression: BlockCompression) -> Self {
+        match compression {
+            BlockCompression::Lz4 => CompressionStrategy::Lz4,
+            BlockCompression::None => CompressionStrategy::Uncompressed,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
@@ -80,34 +293,170 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Info { path } => cmd_info(&path)?,
+        Commands::Info {
+            path, json: true, ..
+        } => {
+            let segment = DruidSegment::open(&path)?;
+            println!("{}", serde_json::to_string_pretty(&schema_json(&segment)?)?);
+        }
+        Commands::Info {
+            path,
+            timezone,
+            layout,
+            ..
+        } => cmd_info(&path, &timezone, layout)?,
         Commands::Files { path } => cmd_files(&path)?,
         Commands::Dump {
             path,
             columns,
             limit,
             format,
-        } => cmd_dump(&path, columns.as_deref(), limit, &format)?,
-        Commands::Query { path, sql } => cmd_query(&path, &sql).await?,
+        } => cmd_dump(
+            &path,
+            columns.as_deref(),
+            limit,
+            &format,
+            progress_sink(cli.quiet),
+            &mut std::io::BufWriter::new(std::io::stdout().lock()),
+        )?,
+        Commands::Stats {
+            path,
+            timing,
+            verbose,
+        } => cmd_stats(&path, timing, verbose, progress_sink(cli.quiet))?,
+        #[cfg(feature = "parquet")]
+        Commands::Convert { path, output } => {
+            let rows = cmd_convert(&path, &output, progress_sink(cli.quiet))?;
+            println!("Wrote {} rows to {}", rows, output.display());
+        }
+        Commands::Recompress {
+            path,
+            out,
+            compression,
+            force,
+        } => cmd_recompress(
+            &path,
+            &out,
+            compression.into(),
+            force,
+            progress_sink(cli.quiet),
+        )?,
+        Commands::Merge {
+            paths,
+            out,
+            rollup,
+            compression,
+            force,
+        } => {
+            let mut options = MergeOptions::default()
+                .with_rollup(rollup)
+                .with_compression(compression.into());
+            if let Some(sink) = progress_sink(cli.quiet) {
+                options = options.with_progress(sink);
+            }
+            let summary = merge(&paths, &out, &options, force)?;
+            println!(
+                "Merged {} segments ({} rows) into {}: {} rows",
+                paths.len(),
+                summary.input_rows,
+                out.display(),
+                summary.output_rows
+            );
+        }
+        Commands::Create {
+            input,
+            time_column,
+            dimensions,
+            metrics,
+            rollup,
+            output,
+            compression,
+            force,
+        } => {
+            let mut options = CreateOptions::new(time_column)
+                .with_rollup(rollup)
+                .with_compression(compression.into());
+            if let Some(dimensions) = dimensions {
+                options = options.with_dimensions(dimensions);
+            }
+            for metric in &metrics {
+                let (name, aggregator) = parse_metric(metric)?;
+                options = options.with_metric(name, aggregator);
+            }
+            let summary = cmd_create(&input, &output, &options, force)?;
+            println!(
+                "Created {} from {} ({} rows): {} rows",
+                output.display(),
+                input.display(),
+                summary.input_rows,
+                summary.output_rows
+            );
+        }
+        Commands::Query {
+            path,
+            sql,
+            batch_size,
+            strings_as_dictionary,
+        } => cmd_query(&path, &sql, batch_size, strings_as_dictionary).await?,
+        Commands::Bench {
+            path,
+            sql,
+            iterations,
+            batch_size,
+            strings_as_dictionary,
+            tune,
+            grid,
+        } => {
+            if tune {
+                let report = tune_read_options(&path, &sql, iterations, &grid).await?;
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                let run =
+                    bench_query(&path, &sql, batch_size, strings_as_dictionary, iterations).await?;
+                println!(
+                    "{} rows in {:.3}s ({:.0} rows/s, batch size {}{}, best of {})",
+                    run.rows,
+                    run.seconds,
+                    run.rows_per_second(),
+                    batch_size,
+                    if strings_as_dictionary {
+                        ", strings as dictionaries"
+                    } else {
+                        ""
+                    },
+                    iterations
+                );
+            }
+        }
     }
 
     Ok(())
 }
 
-fn cmd_info(path: &Path) -> Result<()> {
+fn cmd_info(path: &Path, tz: &Tz, layout: bool) -> Result<()> {
     let segment = DruidSegment::open(path)?;
     let metadata = segment.metadata();
-    let schema = segment.schema();
+    let schema = segment.try_schema()?;
 
     println!("Segment: {}", path.display());
     println!(
         "Interval: {} .. {}",
-        format_millis(metadata.interval_start_ms),
-        format_millis(metadata.interval_end_ms)
+        format_millis(metadata.interval_start_ms, tz),
+        format_millis(metadata.interval_end_ms, tz)
     );
-    println!("Columns ({}):", metadata.columns.len());
+    println!("Columns ({}):", schema.fields().len());
     for field in schema.fields() {
-        println!("  {}: {}", field.name(), field.data_type());
+        match quantiles_description(&segment, field) {
+            Some(description) => {
+                println!(
+                    "  {}: {} ({})",
+                    field.name(),
+                    field.data_type(),
+                    description
+                )
+            }
+            None => println!("  {}: {}", field.name(), field.data_type()),
+        }
     }
     println!("Dimensions: {}", metadata.dimensions.join(", "));
 
@@ -116,9 +465,121 @@ fn cmd_info(path: &Path) -> Result<()> {
         Err(e) => println!("Rows: (error reading: {})", e),
     }
 
+    if layout {
+        print_layouts(&segment, &schema);
+    }
+
     Ok(())
 }
 
+/// Describe a segment's schema as JSON: its interval, row count, and for
+/// each column its Arrow type, Druid value type, role, and whether it is a
+/// (multi-value) dimension, as recorded in the field metadata. Columns
+/// keep the segment's order.
+fn schema_json(segment: &DruidSegment) -> Result<serde_json::Value> {
+    let metadata = segment.metadata();
+    let mut columns = Vec::new();
+    for field in segment.try_schema()?.fields() {
+        let field_metadata = field.metadata();
+        let flag = |key: &str| field_metadata.get(key).is_some_and(|v| v == "true");
+        columns.push(serde_json::json!({
+            "name": field.name(),
+            "arrow_type": field.data_type().to_string(),
+            "value_type": field_metadata.get(VALUE_TYPE_KEY),
+            "role": field_metadata.get(COLUMN_ROLE_KEY),
+            "dimension": flag(IS_DIMENSION_KEY),
+            "multi_value": flag(HAS_MULTIPLE_VALUES_KEY),
+            "serde_type": field_metadata.get(complex::SERDE_TYPE_KEY),
+            "complex_type": field_metadata.get(complex::COMPLEX_TYPE_KEY),
+        }));
+    }
+    Ok(serde_json::json!({
+        "interval": {
+            "start_ms": metadata.interval_start_ms,
+            "end_ms": metadata.interval_end_ms,
+            "start": format_rfc3339(metadata.interval_start_ms),
+            "end": format_rfc3339(metadata.interval_end_ms),
+        },
+        "num_rows": segment.num_rows()?,
+        "bitmap_serde_factory": metadata.bitmap_serde_factory.to_string(),
+        "dimensions": metadata.dimensions,
+        "columns": columns,
+    }))
+}
+
+/// Format epoch milliseconds as an RFC 3339 UTC timestamp, or `None` if
+/// out of chrono's range.
+fn format_rfc3339(millis: i64) -> Option<String> {
+    DateTime::from_timestamp_millis(millis)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+/// Print the block layout of every column of `schema` that has one.
+fn print_layouts(segment: &DruidSegment, schema: &Schema) {
+    println!("Block layout:");
+    for field in schema.fields() {
+        match segment.column_layout(field.name()) {
+            Ok(Some(layout)) => print_layout(field.name(), &layout),
+            Ok(None) => {}
+            Err(e) => println!("  {}: (error reading: {})", field.name(), e),
+        }
+    }
+}
+
+/// Print a column's block layout, one line per block.
+fn print_layout(column: &str, layout: &BlockLayout) {
+    println!(
+        "  {}: version {}, {}, {} values, {} per block, {} blocks, {} bytes",
+        column,
+        layout.version,
+        layout.compression,
+        layout.total_size,
+        layout.size_per,
+        layout.block_count(),
+        layout.compressed_size()
+    );
+    for (i, size) in layout.block_sizes.iter().enumerate() {
+        println!(
+            "    block {}: {} values, {} bytes",
+            i,
+            layout.block_value_count(i).unwrap_or(0),
+            size
+        );
+    }
+}
+
+/// Describe the merged sketches of a quantiles sketch column, or `None`
+/// for other columns.
+fn quantiles_description(segment: &DruidSegment, field: &Field) -> Option<String> {
+    let extension = field.metadata().get(complex::EXTENSION_NAME_KEY);
+    if extension.map(String::as_str) != Some(complex::QUANTILES_EXTENSION_NAME) {
+        return None;
+    }
+    Some(match quantiles_summary(segment, field.name()) {
+        Ok(s) => format!(
+            "quantiles sketch: n={}, min={}, max={}",
+            s.n,
+            s.min.map_or("-".to_string(), |v| v.to_string()),
+            s.max.map_or("-".to_string(), |v| v.to_string())
+        ),
+        Err(e) => format!("error reading sketches: {}", e),
+    })
+}
+
+/// Merge the summaries of every sketch in a quantiles sketch column.
+fn quantiles_summary(
+    segment: &DruidSegment,
+    column: &str,
+) -> Result<quantiles::DoublesSketchSummary> {
+    let batch = segment.read_columns(&[column])?;
+    let sketches = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .ok_or_else(|| anyhow::anyhow!("column '{}' is not binary", column))?;
+    Ok(quantiles::summarize_array(sketches)?)
+}
+
 fn cmd_files(path: &Path) -> Result<()> {
     let segment = DruidSegment::open(path)?;
     let smoosh = segment.smoosh();
@@ -139,92 +600,1101 @@ fn cmd_files(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Write the first `limit` rows of a segment to `out`.
+///
+/// Rows are decoded a batch at a time and CSV and JSON rows written as
+/// each batch is decoded, so memory stays bounded by the batch size; the
+/// table format lays out all rows at once. Decoding stops at the limit.
 fn cmd_dump(
     path: &Path,
     columns: Option<&[String]>,
     limit: usize,
     format: &OutputFormat,
+    progress: Option<Arc<dyn ProgressSink>>,
+    out: &mut impl Write,
 ) -> Result<()> {
     let segment = DruidSegment::open(path)?;
+    let mut options = ReadOptions::default().with_limit(limit);
+    if let Some(sink) = progress {
+        options = options.with_progress(sink);
+    }
 
-    let batch = match columns {
-        Some(cols) => {
-            let col_refs: Vec<&str> = cols.iter().map(|s| s.as_str()).collect();
-            segment.read_columns(&col_refs)?
-        }
-        None => segment.read_all()?,
+    let schema = segment.try_schema()?;
+    let col_refs: Vec<&str> = match columns {
+        Some(cols) => cols.iter().map(|s| s.as_str()).collect(),
+        None => schema.fields().iter().map(|f| f.name().as_str()).collect(),
     };
-
-    // Apply row limit
-    let batch = if batch.num_rows() > limit {
-        batch.slice(0, limit)
-    } else {
-        batch
+    // Only the batches up to the row limit are decoded
+    let mut batches = segment
+        .batches_with_options(&col_refs, &options)?
+        .peekable();
+    // A dump with no rows still shows the column names
+    let empty = match batches.peek() {
+        Some(_) => None,
+        None => {
+            let indices = col_refs
+                .iter()
+                .map(|c| schema.index_of(c))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Some(Ok(RecordBatch::new_empty(Arc::new(
+                schema.project(&indices)?,
+            ))))
+        }
     };
+    let batches = batches.chain(empty);
 
     match format {
         OutputFormat::Table => {
-            let formatted = arrow::util::pretty::pretty_format_batches(&[batch])?;
-            println!("{}", formatted);
+            let batches = batches
+                .map(|batch| render_lists(&batch?))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            let formatted = arrow::util::pretty::pretty_format_batches(&batches)?;
+            writeln!(out, "{}", formatted)?;
         }
         OutputFormat::Json => {
-            let mut writer = arrow::json::LineDelimitedWriter::new(std::io::stdout());
-            writer.write(&batch)?;
+            let mut writer = arrow::json::LineDelimitedWriter::new(&mut *out);
+            for batch in batches {
+                writer.write(&batch?)?;
+            }
             writer.finish()?;
         }
         OutputFormat::Csv => {
             let mut writer = arrow::csv::WriterBuilder::new()
                 .with_header(true)
-                .build(std::io::stdout());
-            writer.write(&batch)?;
+                .build(&mut *out);
+            for batch in batches {
+                writer.write(&render_lists(&batch?)?)?;
+            }
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// A progress bar on stderr for reads, unless `quiet` is set or stderr is
+/// not a terminal.
+#[cfg(feature = "progress")]
+fn progress_sink(quiet: bool) -> Option<Arc<dyn ProgressSink>> {
+    use std::io::IsTerminal;
+
+    if quiet || !std::io::stderr().is_terminal() {
+        return None;
+    }
+    Some(Arc::new(ProgressBarSink(indicatif::ProgressBar::new(0))))
+}
+
+#[cfg(not(feature = "progress"))]
+fn progress_sink(_quiet: bool) -> Option<Arc<dyn ProgressSink>> {
+    None
+}
+
+/// Shows the bytes of stored column data read so far, with the last
+/// column read and an ETA.
+#[cfg(feature = "progress")]
+#[derive(Debug)]
+struct ProgressBarSink(indicatif::ProgressBar);
+
+#[cfg(feature = "progress")]
+impl ProgressSink for ProgressBarSink {
+    fn start(&self, _units: usize, total_bytes: u64) {
+        let style = indicatif::ProgressStyle::with_template(
+            "{msg:30} [{bar:40}] {bytes}/{total_bytes} ({eta})",
+        )
+        .expect("progress template is valid")
+        .progress_chars("=> ");
+        self.0.set_style(style);
+        self.0.set_length(total_bytes);
+    }
+
+    fn advance(&self, unit: &str, rows: usize, bytes: u64) {
+        self.0.set_message(format!("{}: {} rows", unit, rows));
+        self.0.inc(bytes);
+    }
+
+    fn finish(&self) {
+        self.0.finish_and_clear();
+    }
+}
+
+fn cmd_stats(
+    path: &Path,
+    timing: bool,
+    verbose: bool,
+    progress: Option<Arc<dyn ProgressSink>>,
+) -> Result<()> {
+    let segment = DruidSegment::open(path)?;
+    let stats = column_stats(&segment, progress.clone())?;
+    let schema = segment.try_schema()?;
+
+    println!("Segment: {}", path.display());
+    println!(
+        "  {:20} {:28} {:>10} {:>10} {:>24} {:>24}",
+        "column", "type", "nulls", "distinct", "min", "max"
+    );
+    let dash = || "-".to_string();
+    for s in &stats {
+        let data_type = schema.field_with_name(&s.name)?.data_type();
+        println!(
+            "  {:20} {:28} {:>10} {:>10} {:>24} {:>24}",
+            s.name,
+            data_type.to_string(),
+            s.null_count,
+            s.distinct_count.map_or_else(dash, |d| d.to_string()),
+            s.min.as_ref().map_or_else(dash, ToString::to_string),
+            s.max.as_ref().map_or_else(dash, ToString::to_string)
+        );
+    }
+
+    let sketches: Vec<_> = schema
+        .fields()
+        .iter()
+        .filter_map(|field| Some((field.name(), quantiles_description(&segment, field)?)))
+        .collect();
+    if !sketches.is_empty() {
+        println!();
+        println!("Sketches:");
+        for (name, description) in sketches {
+            println!("  {}: {}", name, description);
+        }
+    }
+
+    if verbose {
+        println!();
+        print_layouts(&segment, &schema);
+    }
+
+    if timing {
+        let mut options = ReadOptions::default();
+        if let Some(sink) = progress {
+            options = options.with_progress(sink);
+        }
+        let report = segment.validate_with_options(&options)?;
+        let millis = |d: std::time::Duration| format!("{:.3}", d.as_secs_f64() * 1000.0);
+        println!();
+        println!(
+            "  {:20} {:>12} {:>14} {:>12} {:>12}",
+            "column", "map ms", "decompress ms", "build ms", "total ms"
+        );
+        for t in report.slowest_columns() {
+            println!(
+                "  {:20} {:>12} {:>14} {:>12} {:>12}",
+                t.name,
+                millis(t.map),
+                millis(t.decompress),
+                millis(t.build),
+                millis(t.total)
+            );
         }
     }
 
     Ok(())
 }
 
-async fn cmd_query(path: &Path, sql: &str) -> Result<()> {
-    let table = DruidSegmentTable::open(path)?;
-    let ctx = SessionContext::new();
+/// The statistics of every column, reporting each column to `progress` as
+/// it is done.
+fn column_stats(
+    segment: &DruidSegment,
+    progress: Option<Arc<dyn ProgressSink>>,
+) -> Result<Vec<ColumnStats>> {
+    let schema = segment.try_schema()?;
+    let size = |name: &str| segment.smoosh().entry(name).map_or(0, |e| e.size() as u64);
+    let report = progress.as_ref().map(|sink| {
+        let total = schema.fields().iter().map(|f| size(f.name())).sum();
+        ProgressReport::start(sink, schema.fields().len(), total)
+    });
+    let mut stats = Vec::with_capacity(schema.fields().len());
+    for field in schema.fields() {
+        stats.push(segment.column_stats(field.name())?);
+        if let Some(report) = &report {
+            report.advance(field.name(), segment.num_rows()?, size(field.name()));
+        }
+    }
+    Ok(stats)
+}
+
+/// Write every column of a segment to a Parquet file, keeping the Arrow
+/// schema (`__time` stays a millisecond timestamp). Returns the number of
+/// rows written.
+#[cfg(feature = "parquet")]
+fn cmd_convert(
+    path: &Path,
+    output: &Path,
+    progress: Option<Arc<dyn ProgressSink>>,
+) -> Result<usize> {
+    let segment = DruidSegment::open(path)?;
+    let mut options = ReadOptions::default();
+    if let Some(sink) = progress {
+        options = options.with_progress(sink);
+    }
+    let batch = segment.read_all_with_options(&options)?;
+
+    let file = std::fs::File::create(output)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(batch.num_rows())
+}
+
+/// Sizes of one logical file before and after a segment is rewritten.
+#[derive(Debug)]
+struct FileSizes {
+    name: String,
+    before: usize,
+    after: usize,
+}
+
+fn cmd_recompress(
+    path: &Path,
+    out: &Path,
+    compression: CompressionStrategy,
+    force: bool,
+    progress: Option<Arc<dyn ProgressSink>>,
+) -> Result<()> {
+    let sizes = recompress(path, out, compression, force, progress)?;
+    println!(
+        "{:<30} {:>12} {:>12} {:>8}",
+        "file", "before", "after", "change"
+    );
+    for s in &sizes {
+        let change = if s.before == 0 {
+            "-".to_string()
+        } else {
+            format!("{:+.1}%", (s.after as f64 / s.before as f64 - 1.0) * 100.0)
+        };
+        println!(
+            "{:<30} {:>12} {:>12} {:>8}",
+            s.name, s.before, s.after, change
+        );
+    }
+    let before: usize = sizes.iter().map(|s| s.before).sum();
+    let after: usize = sizes.iter().map(|s| s.after).sum();
+    println!("{:<30} {:>12} {:>12}", "total", before, after);
+    Ok(())
+}
+
+/// Read the segment at `path` and write it to `out` with numeric blocks
+/// compressed with `compression`, keeping its interval, dimensions and
+/// `metadata.drd`. Returns the size of each logical file in both segments.
+///
+/// String columns are always written as uncompressed dictionary columns,
+/// and multi-value or complex columns cannot be written.
+fn recompress(
+    path: &Path,
+    out: &Path,
+    compression: CompressionStrategy,
+    force: bool,
+    progress: Option<Arc<dyn ProgressSink>>,
+) -> Result<Vec<FileSizes>> {
+    check_output_dir(out, force)?;
+
+    let segment = DruidSegment::open(path)?;
+    let mut options = ReadOptions::default();
+    if let Some(sink) = progress {
+        options = options.with_progress(sink);
+    }
+    let batch = segment.read_all_with_options(&options)?;
+
+    let metadata = segment.metadata();
+    let mut writer = SegmentWriter::new()
+        .with_compression(compression)
+        .with_dimensions(metadata.dimensions.clone());
+    if segment.smoosh().has_file("metadata.drd") {
+        writer = writer.with_aggregate_metadata(segment.aggregate_metadata()?);
+    }
+    writer.write(
+        &batch,
+        metadata.interval_start_ms..metadata.interval_end_ms,
+        out,
+    )?;
+
+    let written = SmooshReader::open(out)?;
+    Ok(written
+        .entries()
+        .map(|entry| FileSizes {
+            name: entry.name.clone(),
+            before: segment.smoosh().entry(&entry.name).map_or(0, |e| e.size()),
+            after: entry.size(),
+        })
+        .collect())
+}
+
+/// Merge the segments at `paths` into `out`, refusing to write into a
+/// non-empty directory unless `force` is set.
+fn merge(
+    paths: &[PathBuf],
+    out: &Path,
+    options: &MergeOptions,
+    force: bool,
+) -> Result<MergeSummary> {
+    check_output_dir(out, force)?;
+    Ok(merge_segments(paths, out, options)?)
+}
+
+/// Split a `--metrics` entry such as `added:longSum` into its name and
+/// aggregator.
+fn parse_metric(metric: &str) -> Result<(&str, &str)> {
+    match metric.split_once(':') {
+        Some((name, aggregator)) if !name.is_empty() && !aggregator.is_empty() => {
+            Ok((name, aggregator))
+        }
+        _ => anyhow::bail!(
+            "metric '{}' must be NAME:AGGREGATOR, e.g. added:longSum",
+            metric
+        ),
+    }
+}
+
+/// Build a segment in `out` from the Parquet or CSV file at `input`,
+/// refusing to write into a non-empty directory unless `force` is set.
+fn cmd_create(
+    input: &Path,
+    out: &Path,
+    options: &CreateOptions,
+    force: bool,
+) -> Result<CreateSummary> {
+    check_output_dir(out, force)?;
+    let batch = read_input(input)?;
+    Ok(create_segment(&batch, out, options)?)
+}
+
+/// Read a whole Parquet or CSV file, by its extension, into one batch. CSV
+/// files must have a header row; column types are inferred from them.
+fn read_input(input: &Path) -> Result<RecordBatch> {
+    let extension = input
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let (schema, batches) = match extension.as_deref() {
+        #[cfg(feature = "parquet")]
+        Some("parquet") => {
+            let file = std::fs::File::open(input)?;
+            let builder =
+                parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(file)?;
+            let schema = builder.schema().clone();
+            (schema, builder.build()?.collect::<Result<Vec<_>, _>>()?)
+        }
+        #[cfg(not(feature = "parquet"))]
+        Some("parquet") => anyhow::bail!("reading Parquet needs the `parquet` feature"),
+        Some("csv") => {
+            let format = arrow::csv::reader::Format::default().with_header(true);
+            let (schema, _) = format.infer_schema(std::fs::File::open(input)?, None)?;
+            let file = std::fs::File::open(input)?;
+            let reader = arrow::csv::ReaderBuilder::new(Arc::new(schema))
+                .with_format(format)
+                .build(file)?;
+            let schema = reader.schema();
+            (schema, reader.collect::<Result<Vec<_>, _>>()?)
+        }
+        _ => anyhow::bail!(
+            "cannot tell the format of {}; expected a .parquet or .csv file",
+            input.display()
+        ),
+    };
+    Ok(arrow::compute::concat_batches(&schema, &batches)?)
+}
+
+/// Fail if `out` is a non-empty directory, unless `force` is set.
+fn check_output_dir(out: &Path, force: bool) -> Result<()> {
+    if !force && out.is_dir() && std::fs::read_dir(out)?.next().is_some() {
+        anyhow::bail!(
+            "{} is not empty; pass --force to write into it anyway",
+            out.display()
+        );
+    }
+    Ok(())
+}
+
+/// Replace list columns (multi-value dimensions) with their text form,
+/// e.g. `[a, null, b]` or `[]`, for formats without native list support.
+/// JSON output keeps the lists as arrays.
+fn render_lists(batch: &RecordBatch) -> Result<RecordBatch> {
+    let options = FormatOptions::default().with_null("null");
+    let mut fields = Vec::with_capacity(batch.num_columns());
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        if !matches!(field.data_type(), DataType::List(_)) {
+            fields.push(field.as_ref().clone());
+            columns.push(column.clone());
+            continue;
+        }
+        let formatter = ArrayFormatter::try_new(column.as_ref(), &options)?;
+        let rendered: StringArray = (0..column.len())
+            .map(|i| column.is_valid(i).then(|| formatter.value(i).to_string()))
+            .collect();
+        fields.push(Field::new(field.name(), DataType::Utf8, true));
+        columns.push(Arc::new(rendered) as ArrayRef);
+    }
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
+
+async fn cmd_query(
+    path: &Path,
+    sql: &str,
+    batch_size: Option<usize>,
+    strings_as_dictionary: bool,
+) -> Result<()> {
+    let table = DruidSegmentTable::open(path)?
+        .with_options(ReadOptions::default().with_strings_as_dictionary(strings_as_dictionary));
+    let mut config = SessionConfig::new();
+    if let Some(batch_size) = batch_size {
+        config = config.with_batch_size(batch_size);
+    }
+    let ctx = SessionContext::new_with_config(config);
     ctx.register_table("segment", Arc::new(table))?;
 
-    let df = ctx.sql(sql).await?;
+    let df = ctx.sql(sql).await.map_err(with_column_suggestion)?;
     df.show().await?;
 
     Ok(())
 }
 
-/// Format epoch millis as a human-readable datetime string.
-fn format_millis(millis: i64) -> String {
-    // Simple formatting without chrono dependency
-    let secs = millis / 1000;
-    let ms = millis % 1000;
-    let days_since_epoch = secs / 86400;
-    let time_of_day = secs % 86400;
-    let hours = time_of_day / 3600;
-    let minutes = (time_of_day % 3600) / 60;
-    let seconds = time_of_day % 60;
-
-    // Simple date calc from days since epoch (1970-01-01)
-    let (year, month, day) = days_to_ymd(days_since_epoch);
-    format!(
-        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} UTC",
-        year, month, day, hours, minutes, seconds, ms
-    )
-}
-
-/// Convert days since Unix epoch to (year, month, day).
-fn days_to_ymd(days: i64) -> (i64, u32, u32) {
-    // Civil calendar algorithm from http://howardhinnant.github.io/date_algorithms.html
-    let z = days + 719468;
-    let era = if z >= 0 { z } else { z - 146096 } / 146097;
-    let doe = (z - era * 146097) as u32;
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
-    let y = yoe as i64 + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
-    let mp = (5 * doy + 2) / 153;
-    let d = doy - (153 * mp + 2) / 5 + 1;
-    let m = if mp < 10 { mp + 3 } else { mp - 9 };
-    let year = if m <= 2 { y + 1 } else { y };
-    (year, m, d)
+/// Add the closest valid column to DataFusion's error for an unknown one,
+/// quoted since SQL folds unquoted names to lower case.
+fn with_column_suggestion(error: DataFusionError) -> anyhow::Error {
+    if let DataFusionError::SchemaError(
+        SchemaError::FieldNotFound {
+            field,
+            valid_fields,
+        },
+        _,
+    ) = &error
+        && let Some(name) = closest_column(&field.name, valid_fields.iter().map(|c| c.name()))
+    {
+        let hint = format!(
+            "Unknown column '{}'; did you mean \"{}\"?",
+            field.name, name
+        );
+        return anyhow::Error::new(error).context(hint);
+    }
+    error.into()
+}
+
+/// The fastest of several runs of a query.
+struct BenchRun {
+    rows: usize,
+    seconds: f64,
+}
+
+impl BenchRun {
+    fn rows_per_second(&self) -> f64 {
+        self.rows as f64 / self.seconds.max(f64::EPSILON)
+    }
+}
+
+/// Run `sql` `iterations` times with the given batch size and string
+/// encoding and keep the fastest run. The segment is opened once, outside
+/// the timed section.
+async fn bench_query(
+    path: &Path,
+    sql: &str,
+    batch_size: usize,
+    strings_as_dictionary: bool,
+    iterations: usize,
+) -> Result<BenchRun> {
+    let table = DruidSegmentTable::open(path)?
+        .with_options(ReadOptions::default().with_strings_as_dictionary(strings_as_dictionary));
+    let table = Arc::new(table);
+    let ctx = SessionContext::new_with_config(SessionConfig::new().with_batch_size(batch_size));
+    ctx.register_table("segment", table)?;
+
+    let mut best: Option<BenchRun> = None;
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        let batches = ctx.sql(sql).await?.collect().await?;
+        let run = BenchRun {
+            rows: batches.iter().map(|b| b.num_rows()).sum(),
+            seconds: start.elapsed().as_secs_f64(),
+        };
+        if best.as_ref().is_none_or(|b| run.seconds < b.seconds) {
+            best = Some(run);
+        }
+    }
+    Ok(best.expect("at least one iteration runs"))
+}
+
+/// Time `sql` at each batch size in `grid`, reading string columns both
+/// as plain and as dictionary arrays, and report the fastest, with the
+/// flags and `ReadOptions` call that select it.
+async fn tune_read_options(
+    path: &Path,
+    sql: &str,
+    iterations: usize,
+    grid: &[usize],
+) -> Result<serde_json::Value> {
+    let mut results = Vec::with_capacity(grid.len() * 2);
+    let mut best: Option<(usize, bool, f64)> = None;
+    for &batch_size in grid {
+        for strings_as_dictionary in [false, true] {
+            let run = bench_query(path, sql, batch_size, strings_as_dictionary, iterations).await?;
+            if best.is_none_or(|(_, _, seconds)| run.seconds < seconds) {
+                best = Some((batch_size, strings_as_dictionary, run.seconds));
+            }
+            results.push(serde_json::json!({
+                "batch_size": batch_size,
+                "strings_as_dictionary": strings_as_dictionary,
+                "rows": run.rows,
+                "seconds": run.seconds,
+                "rows_per_second": run.rows_per_second(),
+            }));
+        }
+    }
+    let (batch_size, strings_as_dictionary, seconds) =
+        best.ok_or_else(|| anyhow::anyhow!("--grid must list at least one batch size"))?;
+
+    let mut cli_flags = format!("--batch-size {}", batch_size);
+    let mut read_options = format!("ReadOptions::default().with_batch_size({})", batch_size);
+    if strings_as_dictionary {
+        cli_flags.push_str(" --strings-as-dictionary");
+        read_options.push_str(".with_strings_as_dictionary(true)");
+    }
+    Ok(serde_json::json!({
+        "sql": sql,
+        "iterations": iterations.max(1),
+        "best": {
+            "batch_size": batch_size,
+            "strings_as_dictionary": strings_as_dictionary,
+            "seconds": seconds,
+        },
+        "results": results,
+        "cli_flags": cli_flags,
+        "read_options": read_options,
+    }))
+}
+
+/// Format epoch milliseconds as a date and time in `tz`, e.g.
+/// `2015-09-12 00:00:00.000 UTC`. Values outside chrono's range are
+/// printed as raw milliseconds.
+fn format_millis(millis: i64, tz: &Tz) -> String {
+    match DateTime::from_timestamp_millis(millis) {
+        Some(utc) => utc
+            .with_timezone(tz)
+            .format("%Y-%m-%d %H:%M:%S%.3f %Z")
+            .to_string(),
+        None => format!("{} ms", millis),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use druid_datafusion_bridge::segment::stats::StatValue;
+
+    #[test]
+    fn test_format_millis() {
+        let utc = Tz::UTC;
+        assert_eq!(format_millis(0, &utc), "1970-01-01 00:00:00.000 UTC");
+        assert_eq!(
+            format_millis(1442016000000, &utc),
+            "2015-09-12 00:00:00.000 UTC"
+        );
+        assert_eq!(format_millis(-1, &utc), "1969-12-31 23:59:59.999 UTC");
+        assert_eq!(
+            format_millis(-86_400_000 * 365 - 1500, &utc),
+            "1968-12-31 23:59:58.500 UTC"
+        );
+        assert_eq!(format_millis(i64::MIN, &utc), format!("{} ms", i64::MIN));
+    }
+
+    #[test]
+    fn test_format_millis_timezone() {
+        let kolkata: Tz = "Asia/Kolkata".parse().unwrap();
+        assert_eq!(format_millis(0, &kolkata), "1970-01-01 05:30:00.000 IST");
+        let new_york: Tz = "America/New_York".parse().unwrap();
+        assert_eq!(
+            format_millis(1442016000000, &new_york),
+            "2015-09-11 20:00:00.000 EDT"
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_convert_to_parquet() {
+        use arrow::array::TimestampMillisecondArray;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = Path::new("tests/fixtures/wikipedia-segment");
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("wikipedia.parquet");
+        assert_eq!(cmd_convert(path, &output, None).unwrap(), 39244);
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&output).unwrap())
+                .unwrap()
+                .build()
+                .unwrap();
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>().unwrap();
+        let batch = arrow::compute::concat_batches(&batches[0].schema(), &batches).unwrap();
+        let expected = DruidSegment::open(path).unwrap().read_all().unwrap();
+        assert_eq!(batch.num_rows(), expected.num_rows());
+        assert_eq!(batch.schema().fields(), expected.schema().fields());
+
+        let time = |b: &RecordBatch| {
+            let idx = b.schema().index_of("__time").unwrap();
+            b.column(idx)
+                .as_any()
+                .downcast_ref::<TimestampMillisecondArray>()
+                .unwrap()
+                .value(12345)
+        };
+        assert_eq!(time(&batch), time(&expected));
+        let channel = batch.column_by_name("channel").unwrap();
+        assert_eq!(
+            channel
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(12345),
+            expected
+                .column_by_name("channel")
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(12345)
+        );
+    }
+
+    #[test]
+    fn test_recompress() {
+        let path = Path::new("tests/fixtures/wikipedia-segment");
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("recompressed");
+        let sizes = recompress(path, &out, CompressionStrategy::Uncompressed, false, None).unwrap();
+
+        let original = DruidSegment::open(path).unwrap();
+        let rewritten = DruidSegment::open(&out).unwrap();
+        assert_eq!(rewritten.read_all().unwrap(), original.read_all().unwrap());
+        assert_eq!(
+            rewritten.metadata().dimensions,
+            original.metadata().dimensions
+        );
+        assert_eq!(
+            rewritten.aggregate_metadata().unwrap(),
+            original.aggregate_metadata().unwrap()
+        );
+        let layout = rewritten.column_layout("added").unwrap().unwrap();
+        assert_eq!(layout.compression, CompressionStrategy::Uncompressed);
+
+        // One entry per logical file, sized as stored
+        let added = sizes.iter().find(|s| s.name == "added").unwrap();
+        assert_eq!(
+            added.before,
+            original.smoosh().entry("added").unwrap().size()
+        );
+        assert_eq!(
+            added.after,
+            rewritten.smoosh().entry("added").unwrap().size()
+        );
+        assert_eq!(sizes.len(), rewritten.smoosh().len());
+
+        // The output is no longer empty
+        assert!(recompress(path, &out, CompressionStrategy::Lz4, false, None).is_err());
+        recompress(path, &out, CompressionStrategy::Lz4, true, None).unwrap();
+        let layout = DruidSegment::open(&out)
+            .unwrap()
+            .column_layout("added")
+            .unwrap();
+        assert_eq!(layout.unwrap().compression, CompressionStrategy::Lz4);
+    }
+
+    #[test]
+    fn test_merge() {
+        let path = PathBuf::from("tests/fixtures/wikipedia-segment");
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("merged");
+        let options = MergeOptions::default();
+        let summary = merge(&[path.clone(), path.clone()], &out, &options, false).unwrap();
+        assert_eq!(summary.input_rows, 2 * 39244);
+        assert_eq!(summary.output_rows, 2 * 39244);
+
+        let original = DruidSegment::open(&path).unwrap();
+        let merged = DruidSegment::open(&out).unwrap();
+        assert_eq!(merged.metadata().dimensions, original.metadata().dimensions);
+        assert_eq!(
+            merged.dictionary_cardinality("channel").unwrap(),
+            original.dictionary_cardinality("channel").unwrap()
+        );
+
+        // The output is no longer empty
+        assert!(merge(std::slice::from_ref(&path), &out, &options, false).is_err());
+        merge(&[path], &out, &options, true).unwrap();
+        assert_eq!(DruidSegment::open(&out).unwrap().num_rows().unwrap(), 39244);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_create_from_parquet() {
+        use arrow::array::{Float64Array, Int64Array, TimestampMicrosecondArray};
+        use arrow::datatypes::TimestampMillisecondType;
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("edits.parquet");
+        let batch = RecordBatch::try_from_iter([
+            (
+                "ts",
+                Arc::new(TimestampMicrosecondArray::from(vec![
+                    2_000_000, 1_000_000, 2_000_000, 1_000_000,
+                ])) as ArrayRef,
+            ),
+            (
+                "page",
+                Arc::new(StringArray::from(vec!["b", "a", "b", "b"])),
+            ),
+            ("added", Arc::new(Int64Array::from(vec![4, 1, 6, 3]))),
+            (
+                "delta",
+                Arc::new(Float64Array::from(vec![0.5, 1.5, -2.0, 3.0])),
+            ),
+        ])
+        .unwrap();
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(&input).unwrap(), batch.schema(), None)
+                .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let out = dir.path().join("segment");
+        let cli = Cli::try_parse_from([
+            "druid-segment",
+            "create",
+            "--input",
+            input.to_str().unwrap(),
+            "--time-column",
+            "ts",
+            "--dimensions",
+            "page",
+            "--metrics",
+            "count:count,added:longSum,delta:doubleMax",
+            "--rollup",
+            "--output",
+            out.to_str().unwrap(),
+        ])
+        .unwrap();
+        let Commands::Create {
+            metrics,
+            dimensions,
+            rollup,
+            ..
+        } = cli.command
+        else {
+            panic!("expected the create command");
+        };
+        let mut options = CreateOptions::new("ts")
+            .with_dimensions(dimensions.unwrap())
+            .with_rollup(rollup);
+        for metric in &metrics {
+            let (name, aggregator) = parse_metric(metric).unwrap();
+            options = options.with_metric(name, aggregator);
+        }
+        let summary = cmd_create(&input, &out, &options, false).unwrap();
+        assert_eq!(summary.input_rows, 4);
+        assert_eq!(summary.output_rows, 3);
+
+        let segment = DruidSegment::open(&out).unwrap();
+        assert_eq!(segment.metadata().interval_start_ms, 1000);
+        assert_eq!(segment.metadata().interval_end_ms, 2001);
+        assert_eq!(segment.metadata().dimensions, vec!["page"]);
+        let metadata = segment.aggregate_metadata().unwrap();
+        assert_eq!(metadata.rollup, Some(true));
+        assert_eq!(metadata.aggregators.unwrap().len(), 3);
+
+        let batch = segment.read_all().unwrap();
+        let times = batch
+            .column_by_name("__time")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<arrow::array::PrimitiveArray<TimestampMillisecondType>>()
+            .unwrap();
+        assert_eq!(times.values().to_vec(), vec![1000, 1000, 2000]);
+        let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+        assert_eq!(
+            column("page").as_ref(),
+            &StringArray::from(vec!["a", "b", "b"]) as &dyn Array
+        );
+        assert_eq!(
+            column("count").as_ref(),
+            &Int64Array::from(vec![1, 1, 2]) as &dyn Array
+        );
+        assert_eq!(
+            column("added").as_ref(),
+            &Int64Array::from(vec![1, 3, 10]) as &dyn Array
+        );
+        assert_eq!(
+            column("delta").as_ref(),
+            &Float64Array::from(vec![1.5, 3.0, 0.5]) as &dyn Array
+        );
+
+        let err = cmd_create(&input, &out, &options, false).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+        let options = CreateOptions::new("timestamp");
+        let err = cmd_create(&input, &out, &options, true).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Time column 'timestamp' is not in the input"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_create_from_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("edits.csv");
+        std::fs::write(
+            &input,
+            "ts,page,added\n\
+             2024-01-01T00:00:02Z,b,4\n\
+             2024-01-01T00:00:01Z,a,1\n",
+        )
+        .unwrap();
+
+        let out = dir.path().join("segment");
+        let options = CreateOptions::new("ts").with_metric("added", "longSum");
+        let summary = cmd_create(&input, &out, &options, false).unwrap();
+        assert_eq!(summary.output_rows, 2);
+
+        let segment = DruidSegment::open(&out).unwrap();
+        assert_eq!(segment.metadata().interval_start_ms, 1_704_067_201_000);
+        assert_eq!(segment.metadata().dimensions, vec!["page"]);
+        let batch = segment.read_all().unwrap();
+        assert_eq!(
+            batch.column_by_name("page").unwrap().as_ref(),
+            &StringArray::from(vec!["a", "b"]) as &dyn Array
+        );
+
+        let err = cmd_create(&dir.path().join("edits.json"), &out, &options, true).unwrap_err();
+        assert!(
+            err.to_string().contains("cannot tell the format"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_parse_metric() {
+        assert_eq!(parse_metric("added:longSum").unwrap(), ("added", "longSum"));
+        assert!(parse_metric("added").is_err());
+        assert!(parse_metric(":longSum").is_err());
+    }
+
+    #[test]
+    fn test_quantiles_description() {
+        use druid_datafusion_bridge::column::generic_indexed::GenericIndexedWriter;
+        use druid_datafusion_bridge::testing::SegmentFixtureBuilder;
+
+        // Compact sketches holding only their preamble: n, min and max
+        let sketch = |n: u64, min: f64, max: f64| {
+            let mut buf = vec![2, 3, 8, 0x18, 128, 0, 0, 0];
+            buf.extend_from_slice(&n.to_le_bytes());
+            buf.extend_from_slice(&min.to_le_bytes());
+            buf.extend_from_slice(&max.to_le_bytes());
+            buf
+        };
+        let sketches = [sketch(3, 1.5, 9.0), sketch(2, -4.0, 2.0)];
+        let descriptor = r#"{"valueType":"COMPLEX","parts":[{"type":"complex","typeName":"quantilesDoublesSketch"}]}"#;
+        let mut file = (descriptor.len() as i32).to_be_bytes().to_vec();
+        file.extend_from_slice(descriptor.as_bytes());
+        file.extend(
+            GenericIndexedWriter::write(sketches.iter().map(|s| Some(s.as_slice())), false)
+                .unwrap(),
+        );
+        let segment = SegmentFixtureBuilder::new()
+            .with_times([0, 1])
+            .with_column_file("latency", file)
+            .build()
+            .unwrap();
+
+        let schema = segment.try_schema().unwrap();
+        let latency = schema.field_with_name("latency").unwrap();
+        assert_eq!(
+            quantiles_description(&segment, latency).unwrap(),
+            "quantiles sketch: n=5, min=-4, max=9"
+        );
+        let time = schema.field_with_name("__time").unwrap();
+        assert_eq!(quantiles_description(&segment, time), None);
+    }
+
+    #[test]
+    fn test_column_stats() {
+        let segment = DruidSegment::open(Path::new("tests/fixtures/wikipedia-segment")).unwrap();
+        let stats = column_stats(&segment, None).unwrap();
+        assert_eq!(stats.len(), segment.schema().fields().len());
+        let column = |name: &str| stats.iter().find(|s| s.name == name).unwrap();
+
+        let added = column("added");
+        assert_eq!(added.min, Some(StatValue::Long(0)));
+        assert_eq!(added.max, Some(StatValue::Long(199818)));
+        assert_eq!(added.null_count, 0);
+        assert_eq!(added.distinct_count, None);
+
+        let channel = column("channel");
+        assert_eq!(channel.distinct_count, Some(51));
+        assert_eq!(channel.min.as_ref().unwrap().to_string(), "#ar.wikipedia");
+        assert_eq!(channel.max.as_ref().unwrap().to_string(), "#zh.wikipedia");
+
+        let city = column("cityName");
+        assert_eq!(city.null_count, 37091);
+
+        let time = column("__time");
+        assert_eq!(time.null_count, 0);
+        assert!(
+            time.min
+                .as_ref()
+                .unwrap()
+                .to_string()
+                .starts_with("2015-09-12T")
+        );
+    }
+
+    #[test]
+    fn test_dump_limit() {
+        let path = Path::new("tests/fixtures/wikipedia-segment");
+        let columns = ["__time".to_string(), "channel".to_string()];
+        let dump = |limit, format| {
+            let mut out = Vec::new();
+            cmd_dump(path, Some(&columns), limit, format, None, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let csv = dump(3, &OutputFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", csv);
+        assert_eq!(lines[0], "__time,channel");
+        assert!(lines[1].starts_with("2015-09-12T"));
+
+        assert_eq!(dump(3, &OutputFormat::Json).lines().count(), 3);
+        // A limit past the first batch spans several
+        assert_eq!(dump(20_000, &OutputFormat::Csv).lines().count(), 20_001);
+        assert_eq!(dump(0, &OutputFormat::Csv).trim(), "__time,channel");
+
+        // A zipped segment dumps the same rows
+        let dir = tempfile::tempdir().unwrap();
+        let zipped = dir.path().join("index.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&zipped).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for file in ["version.bin", "meta.smoosh", "00000.smoosh"] {
+            writer.start_file(file, options).unwrap();
+            writer
+                .write_all(&std::fs::read(path.join(file)).unwrap())
+                .unwrap();
+        }
+        writer.finish().unwrap();
+        let mut out = Vec::new();
+        cmd_dump(
+            &zipped,
+            Some(&columns),
+            3,
+            &OutputFormat::Csv,
+            None,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), csv);
+    }
+
+    #[test]
+    fn test_schema_json() {
+        let segment = DruidSegment::open(Path::new("tests/fixtures/wikipedia-segment")).unwrap();
+        let json = schema_json(&segment).unwrap();
+        // The document must survive a round trip through its text form
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&json).unwrap()).unwrap();
+
+        assert_eq!(json["num_rows"], 39244);
+        assert_eq!(json["interval"]["start"], "2015-09-12T00:00:00.000Z");
+        let columns = json["columns"].as_array().unwrap();
+        let column = |name: &str| {
+            columns
+                .iter()
+                .find(|c| c["name"] == name)
+                .unwrap_or_else(|| panic!("no column {}", name))
+        };
+        assert_eq!(
+            column("__time")["arrow_type"],
+            "Timestamp(Millisecond, None)"
+        );
+        assert_eq!(column("__time")["value_type"], "LONG");
+        assert_eq!(column("channel")["arrow_type"], "Utf8");
+        assert_eq!(column("channel")["value_type"], "STRING");
+        assert_eq!(column("channel")["dimension"], true);
+        assert_eq!(column("channel")["multi_value"], false);
+        assert_eq!(column("channel")["serde_type"], "stringDictionary");
+        assert_eq!(column("added")["value_type"], "LONG");
+        assert_eq!(column("__time")["dimension"], false);
+        assert_eq!(column("__time")["role"], "time");
+        assert_eq!(column("channel")["role"], "dimension");
+        assert!(column("added")["complex_type"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_query_column_suggestion() {
+        let path = Path::new("tests/fixtures/wikipedia-segment");
+        let err = cmd_query(path, "SELECT cityName FROM segment", None, false)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown column 'cityname'; did you mean \"cityName\"?"
+        );
+        // With nothing close, DataFusion's error is kept as is
+        let err = cmd_query(path, "SELECT xyzzy FROM segment", None, false)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No field named xyzzy"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_tune_tiny_grid() {
+        let report = tune_read_options(
+            Path::new("tests/fixtures/wikipedia-segment"),
+            "SELECT count(*) FROM segment",
+            1,
+            &[1000, 8192],
+        )
+        .await
+        .unwrap();
+
+        // The report round-trips as JSON
+        let report: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        let results = report["results"].as_array().unwrap();
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|r| r["rows"] == 1));
+        let dictionary: Vec<_> = results
+            .iter()
+            .map(|r| r["strings_as_dictionary"].as_bool().unwrap())
+            .collect();
+        assert_eq!(dictionary, [false, true, false, true]);
+
+        let best = report["best"]["batch_size"].as_u64().unwrap();
+        assert!(best == 1000 || best == 8192);
+        let (flag, call) = match report["best"]["strings_as_dictionary"].as_bool().unwrap() {
+            true => (
+                " --strings-as-dictionary",
+                ".with_strings_as_dictionary(true)",
+            ),
+            false => ("", ""),
+        };
+        assert_eq!(
+            report["cli_flags"],
+            format!("--batch-size {}{}", best, flag)
+        );
+        assert_eq!(
+            report["read_options"],
+            format!("ReadOptions::default().with_batch_size({}){}", best, call)
+        );
+        Cli::try_parse_from(
+            format!(
+                "druid-segment query seg -s x {}",
+                report["cli_flags"].as_str().unwrap()
+            )
+            .split(' '),
+        )
+        .unwrap();
+    }
 }
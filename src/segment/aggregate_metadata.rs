@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// Mirrors Druid's segment `Metadata`, stored as JSON in the `metadata.drd`
+/// logical file: how the segment was ingested and rolled up.
+///
+/// Every field is optional, as older segments omit some of them; absent
+/// fields are also left out when writing.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateMetadata {
+    /// Free-form key/value pairs attached at ingestion.
+    #[serde(default)]
+    pub container: serde_json::Map<String, serde_json::Value>,
+    /// Aggregators that produced the metric columns at ingestion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregators: Option<Vec<AggregatorSpec>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_spec: Option<TimestampSpec>,
+    /// Granularity rows were truncated to, e.g. `{"type": "none"}` or a
+    /// period granularity object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_granularity: Option<serde_json::Value>,
+    /// Whether rows with equal dimensions and truncated time were merged.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollup: Option<bool>,
+    /// Sort order of the rows, `__time` first unless the segment was built
+    /// with a custom ordering.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ordering: Option<Vec<OrderBy>>,
+}
+
+/// One ingestion-time aggregator, such as `{"type": "longSum", "name":
+/// "added", "fieldName": "added"}`.
+///
+/// Aggregators of any type are accepted; fields beyond the common ones
+/// are kept as JSON in `extra` and written back out.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregatorSpec {
+    #[serde(rename = "type")]
+    pub aggregator_type: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_name: Option<String>,
+    /// Remaining type-specific fields.
+    #[serde(flatten)]
+    pub extra: serde_json::Value,
+}
+
+/// Mirrors Druid's `TimestampSpec`: the input column and format `__time`
+/// was parsed from.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimestampSpec {
+    pub column: Option<String>,
+    pub format: Option<String>,
+    pub missing_value: Option<serde_json::Value>,
+}
+
+/// One column of the segment's sort order.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderBy {
+    pub column_name: String,
+    pub order: String,
+}
+
+impl AggregateMetadata {
+    /// Parse the raw bytes of `metadata.drd`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Serialize to the JSON bytes of `metadata.drd`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup_segment() {
+        let json = br#"{
+            "container": {"source": "test"},
+            "aggregators": [
+                {"type": "count", "name": "count"},
+                {"type": "longSum", "name": "added", "fieldName": "added"},
+                {"type": "HLLSketchBuild", "name": "users", "fieldName": "user", "lgK": 12}
+            ],
+            "timestampSpec": {"column": "ts", "format": "millis", "missingValue": null},
+            "queryGranularity": {"type": "period", "period": "PT1H"},
+            "rollup": true
+        }"#;
+        let metadata = AggregateMetadata::from_bytes(json).unwrap();
+        assert_eq!(metadata.rollup, Some(true));
+        assert_eq!(metadata.container["source"], "test");
+        assert!(metadata.ordering.is_none());
+
+        let aggregators = metadata.aggregators.unwrap();
+        assert_eq!(aggregators.len(), 3);
+        assert_eq!(aggregators[0].aggregator_type, "count");
+        assert_eq!(aggregators[0].field_name, None);
+        assert_eq!(aggregators[1].field_name.as_deref(), Some("added"));
+        assert_eq!(aggregators[2].extra["lgK"], 12);
+
+        let timestamp_spec = metadata.timestamp_spec.unwrap();
+        assert_eq!(timestamp_spec.column.as_deref(), Some("ts"));
+        assert_eq!(metadata.query_granularity.unwrap()["period"], "PT1H");
+    }
+
+    #[test]
+    fn test_to_bytes_round_trips() {
+        let json = br#"{
+            "aggregators": [
+                {"type": "count", "name": "count"},
+                {"type": "myCustomSketch", "name": "s", "fieldName": "user", "k": 128, "nested": {"a": [1, 2]}}
+            ],
+            "rollup": true
+        }"#;
+        let metadata = AggregateMetadata::from_bytes(json).unwrap();
+        let written = metadata.to_bytes().unwrap();
+        assert_eq!(AggregateMetadata::from_bytes(&written).unwrap(), metadata);
+
+        // Unknown aggregator fields survive, and absent ones stay absent
+        let value: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(value["aggregators"][1]["nested"]["a"][1], 2);
+        assert_eq!(value["aggregators"][1]["k"], 128);
+        assert!(value["aggregators"][0].get("fieldName").is_none());
+        assert!(value.get("ordering").is_none());
+        assert_eq!(value["container"], serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_sparse_metadata() {
+        let metadata = AggregateMetadata::from_bytes(b"{}").unwrap();
+        assert!(metadata.aggregators.is_none());
+        assert!(metadata.rollup.is_none());
+        assert!(AggregateMetadata::from_bytes(b"not json").is_err());
+    }
+}
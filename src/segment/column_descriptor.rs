@@ -1,7 +1,11 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use super::metadata::BitmapSerdeFactory;
+use crate::compression::CompressionStrategy;
+use crate::error::Result;
 
 /// Mirrors Druid's ValueType enum.
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum ValueType {
     String,
@@ -11,9 +15,22 @@ pub enum ValueType {
     Complex,
 }
 
+impl ValueType {
+    /// The name Druid gives the type, e.g. `LONG`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::String => "STRING",
+            Self::Long => "LONG",
+            Self::Float => "FLOAT",
+            Self::Double => "DOUBLE",
+            Self::Complex => "COMPLEX",
+        }
+    }
+}
+
 /// Mirrors Druid's ColumnDescriptor, serialized as JSON at the start
 /// of each column's data within the smoosh archive.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ColumnDescriptor {
     pub value_type: ValueType,
@@ -25,7 +42,7 @@ pub struct ColumnDescriptor {
 /// One entry in the ColumnDescriptor's `parts` array.
 /// The `type` field identifies the serialization class.
 /// Additional fields vary by type and are parsed separately from binary data.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ColumnPartSerde {
     #[serde(rename = "type")]
     pub serde_type: String,
@@ -33,3 +50,107 @@ pub struct ColumnPartSerde {
     #[serde(flatten)]
     pub extra: serde_json::Value,
 }
+
+impl ColumnPartSerde {
+    /// The `type` of the part's `bitmapSerdeFactory`, e.g. `"roaring"`.
+    pub fn bitmap_type(&self) -> Option<&str> {
+        self.extra
+            .get("bitmapSerdeFactory")
+            .and_then(|f| f.get("type"))
+            .and_then(|t| t.as_str())
+    }
+
+    /// The part's `bitmapSerdeFactory`, or `None` if it declares none.
+    pub fn bitmap_serde_factory(&self) -> Result<Option<BitmapSerdeFactory>> {
+        self.typed_field("bitmapSerdeFactory")
+    }
+
+    /// The part's `byteOrder`, or `None` if it declares none.
+    pub fn byte_order(&self) -> Result<Option<ByteOrder>> {
+        self.typed_field("byteOrder")
+    }
+
+    /// The part's `compression`, or `None` if it declares none. Most part
+    /// serdes record their compression in the binary data instead.
+    pub fn compression_strategy(&self) -> Result<Option<CompressionStrategy>> {
+        self.typed_field("compression")
+    }
+
+    fn typed_field<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.extra.get(key) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// What a column can be read with, combining its descriptor, its index
+/// flags and the segment's bitmap format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnCapabilities {
+    pub value_type: ValueType,
+    pub has_multiple_values: bool,
+    /// Whether the column stores a bitmap index per dictionary value. Only
+    /// string dimensions written with indexes have them.
+    pub has_bitmap_indexes: bool,
+    /// Format of the segment's bitmap indexes.
+    pub bitmap_serde_factory: BitmapSerdeFactory,
+}
+
+impl ColumnCapabilities {
+    /// Whether filters on this column can be answered from its bitmap
+    /// indexes instead of by scanning its values.
+    pub fn supports_index_pushdown(&self) -> bool {
+        self.has_bitmap_indexes && self.bitmap_serde_factory.supports_index_reads()
+    }
+}
+
+/// Byte order of the values inside a column's decompressed blocks,
+/// mirroring the `byteOrder` field of numeric and string part serdes.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ByteOrder {
+    /// Java's default `ByteBuffer` order.
+    #[default]
+    BigEndian,
+    LittleEndian,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Descriptors of the wikipedia fixture's `added` and `channel` columns
+    const LONG_DESCRIPTOR: &str = r#"{"valueType":"LONG","hasMultipleValues":false,"parts":[{"type":"longV2","byteOrder":"LITTLE_ENDIAN","bitmapSerdeFactory":{"type":"roaring"}}]}"#;
+    const STRING_DESCRIPTOR: &str = r#"{"valueType":"STRING","hasMultipleValues":false,"parts":[{"type":"stringDictionary","bitmapSerdeFactory":{"type":"roaring"},"byteOrder":"LITTLE_ENDIAN"}]}"#;
+
+    #[test]
+    fn test_part_serde_accessors() {
+        for json in [LONG_DESCRIPTOR, STRING_DESCRIPTOR] {
+            let descriptor: ColumnDescriptor = serde_json::from_str(json).unwrap();
+            let part = &descriptor.parts[0];
+            assert_eq!(part.bitmap_type(), Some("roaring"));
+            assert_eq!(part.byte_order().unwrap(), Some(ByteOrder::LittleEndian));
+            assert_eq!(part.compression_strategy().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_part_serde_optional_fields() {
+        let part: ColumnPartSerde =
+            serde_json::from_str(r#"{"type":"complex","typeName":"hyperUnique"}"#).unwrap();
+        assert_eq!(part.bitmap_type(), None);
+        assert_eq!(part.byte_order().unwrap(), None);
+
+        let part: ColumnPartSerde =
+            serde_json::from_str(r#"{"type":"stringDictionary","compression":"zstd"}"#).unwrap();
+        assert_eq!(
+            part.compression_strategy().unwrap(),
+            Some(CompressionStrategy::Zstd)
+        );
+
+        let part: ColumnPartSerde =
+            serde_json::from_str(r#"{"type":"longV2","byteOrder":"MIDDLE_ENDIAN"}"#).unwrap();
+        assert!(part.byte_order().is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+
+use super::column_descriptor::{ColumnDescriptor, ValueType};
+use crate::column::string::StringColumnValues;
+use crate::error::{DruidSegmentError, Result};
+
+/// One column of a segment, with its header parsed and, for string
+/// columns, its dictionary located, so that lookups decode nothing else.
+///
+/// Returned by [`DruidSegment::column`](super::DruidSegment::column).
+pub struct ColumnHandle<'a> {
+    name: String,
+    descriptor: ColumnDescriptor,
+    num_rows: usize,
+    strings: Option<Strings<'a>>,
+}
+
+/// What a string column's handle looks values up in.
+pub(crate) enum Strings<'a> {
+    Dictionary(StringColumnValues<'a>),
+    /// A column written as a `nullColumn` part, whose only value is null.
+    AllNull,
+}
+
+impl std::fmt::Debug for ColumnHandle<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ColumnHandle")
+            .field("name", &self.name)
+            .field("descriptor", &self.descriptor)
+            .field("num_rows", &self.num_rows)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> ColumnHandle<'a> {
+    pub(crate) fn new(
+        name: &str,
+        descriptor: ColumnDescriptor,
+        num_rows: usize,
+        strings: Option<Strings<'a>>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            descriptor,
+            num_rows,
+            strings,
+        }
+    }
+
+    /// The column's name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The column's header: its value type and the serdes of its parts.
+    pub fn descriptor(&self) -> &ColumnDescriptor {
+        &self.descriptor
+    }
+
+    /// Number of rows in the column, which is the segment's.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Whether the column is a string dimension.
+    pub fn is_string(&self) -> bool {
+        self.descriptor.value_type == ValueType::String
+    }
+
+    /// Number of distinct values of a string column, null included if any
+    /// row is null, or `None` for other columns.
+    pub fn cardinality(&self) -> Option<usize> {
+        match self.strings.as_ref()? {
+            Strings::Dictionary(values) => Some(values.cardinality()),
+            Strings::AllNull => Some(1),
+        }
+    }
+
+    /// The dictionary of a string column, in sorted order with null first.
+    /// No row is decoded.
+    pub fn dictionary(&self) -> Result<impl Iterator<Item = Result<Option<Cow<'a, str>>>> + '_> {
+        let strings = self.strings()?;
+        let cardinality = self.cardinality().unwrap_or_default();
+        Ok((0..cardinality).map(move |id| match strings {
+            Strings::Dictionary(values) => values.dictionary_value(id),
+            Strings::AllNull => Ok(None),
+        }))
+    }
+
+    /// The value of row `row` of a single-value string column. Only the
+    /// row's id is decoded, along with the block holding it if the column
+    /// is compressed.
+    pub fn value_at(&self, row: usize) -> Result<Option<Cow<'a, str>>> {
+        if row >= self.num_rows {
+            return Err(DruidSegmentError::RowOutOfRange {
+                offset: row,
+                num_rows: self.num_rows,
+            });
+        }
+        match self.strings()? {
+            Strings::Dictionary(values) => values.value_at(row),
+            Strings::AllNull => Ok(None),
+        }
+    }
+
+    fn strings(&self) -> Result<&Strings<'a>> {
+        self.strings.as_ref().ok_or_else(|| {
+            DruidSegmentError::UnsupportedColumnType(format!(
+                "dictionary of {:?} column '{}'",
+                self.descriptor.value_type, self.name
+            ))
+        })
+    }
+}
@@ -0,0 +1,496 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, AsArray, Int64Array};
+use arrow::compute::kernels::cast::{CastOptions, cast_with_options};
+use arrow::compute::{max, min};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit, TimestampMillisecondType};
+use arrow::record_batch::RecordBatch;
+
+use super::TIME_COLUMN;
+use super::aggregate_metadata::{AggregateMetadata, AggregatorSpec, TimestampSpec};
+use super::merge::{roll_up, sort_rows};
+use super::writer::SegmentWriter;
+use crate::compression::CompressionStrategy;
+use crate::error::{DruidSegmentError, Result};
+
+/// Options controlling how [`create_segment`] builds a segment from a
+/// batch of input rows.
+#[derive(Debug, Clone)]
+pub struct CreateOptions {
+    /// Input column holding each row's timestamp, written as `__time`.
+    pub time_column: String,
+    /// Input columns to store as dimensions, in order; `None` for every
+    /// column that is neither the time column nor a metric's input.
+    pub dimensions: Option<Vec<String>>,
+    /// Aggregators producing the metric columns, each reading the input
+    /// column named by its `field_name`.
+    pub metrics: Vec<AggregatorSpec>,
+    /// Roll up rows with the same `__time` and dimension values into one,
+    /// combining each metric with its aggregator.
+    pub rollup: bool,
+    /// Block compression of the segment's numeric columns.
+    pub compression: CompressionStrategy,
+}
+
+impl CreateOptions {
+    /// Options for input rows timestamped by `time_column`, with every
+    /// other column a dimension and no rollup.
+    pub fn new(time_column: impl Into<String>) -> Self {
+        Self {
+            time_column: time_column.into(),
+            dimensions: None,
+            metrics: Vec::new(),
+            rollup: false,
+            compression: CompressionStrategy::Lz4,
+        }
+    }
+
+    /// Store only `dimensions` as dimensions; see
+    /// [`CreateOptions::dimensions`].
+    pub fn with_dimensions(mut self, dimensions: Vec<String>) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Add a metric `name` aggregated with `aggregator_type` from the input
+    /// column of the same name, such as `added` with `longSum`. A `count`
+    /// metric reads no column.
+    pub fn with_metric(
+        mut self,
+        name: impl Into<String>,
+        aggregator_type: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        let aggregator_type = aggregator_type.into();
+        self.metrics.push(AggregatorSpec {
+            field_name: (aggregator_type != "count").then(|| name.clone()),
+            aggregator_type,
+            name,
+            extra: serde_json::json!({}),
+        });
+        self
+    }
+
+    /// Roll up rows; see [`CreateOptions::rollup`].
+    pub fn with_rollup(mut self, rollup: bool) -> Self {
+        self.rollup = rollup;
+        self
+    }
+
+    /// Compress the numeric columns with `compression`.
+    pub fn with_compression(mut self, compression: CompressionStrategy) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
+/// Row counts of a created segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateSummary {
+    /// Rows of the input.
+    pub input_rows: usize,
+    /// Rows in the segment, fewer than the input's when rolled up.
+    pub output_rows: usize,
+}
+
+/// Build a segment from the rows of `input` and write it to `output`,
+/// which is created if missing.
+///
+/// Column types are taken from the Arrow schema:
+/// - the time column may be a timestamp or date, an integer of epoch
+///   millis, or a string Arrow can parse as a timestamp; it must have no
+///   nulls
+/// - dimensions are kept as strings, longs (any integer or boolean type)
+///   or doubles and floats
+/// - each metric is cast to its aggregator's type: `count` and `long*`
+///   to longs, `double*` to doubles and `float*` to floats
+///
+/// Only the `count`, `*Sum`, `*Min` and `*Max` aggregators are supported.
+/// Rows are sorted by `__time` and then the dimensions, and the segment
+/// covers the millisecond range of its rows' times.
+pub fn create_segment(
+    input: &RecordBatch,
+    output: &Path,
+    options: &CreateOptions,
+) -> Result<CreateSummary> {
+    if input.num_rows() == 0 {
+        return Err(DruidSegmentError::InvalidData(
+            "Cannot create a segment without rows".to_string(),
+        ));
+    }
+    let time = time_column(input, &options.time_column)?;
+
+    let metric_inputs: Vec<&str> = options
+        .metrics
+        .iter()
+        .filter_map(|m| m.field_name.as_deref())
+        .collect();
+    let dimensions = match &options.dimensions {
+        Some(dimensions) => dimensions.clone(),
+        None => input
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .filter(|name| name != &options.time_column && !metric_inputs.contains(&name.as_str()))
+            .collect(),
+    };
+
+    let mut fields = vec![Field::new(TIME_COLUMN, time.data_type().clone(), false)];
+    let mut columns = vec![time];
+    for name in &dimensions {
+        if name == &options.time_column || name == TIME_COLUMN {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "The time column '{}' cannot also be a dimension",
+                name
+            )));
+        }
+        let column = dimension_column(input, name)?;
+        fields.push(Field::new(name, column.data_type().clone(), true));
+        columns.push(column);
+    }
+    for metric in &options.metrics {
+        if fields.iter().any(|f| f.name() == &metric.name) {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "Metric '{}' has the name of another column",
+                metric.name
+            )));
+        }
+        let column = metric_column(input, metric)?;
+        fields.push(Field::new(&metric.name, column.data_type().clone(), true));
+        columns.push(column);
+    }
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+
+    let mut batch = sort_rows(&batch, &dimensions)?;
+    if options.rollup {
+        batch = roll_up(&batch, &dimensions, &options.metrics)?;
+    }
+
+    let times = batch.column(0).as_primitive::<TimestampMillisecondType>();
+    let interval = min(times).unwrap_or_default()..max(times).unwrap_or_default() + 1;
+    let metadata = AggregateMetadata {
+        aggregators: Some(options.metrics.clone()),
+        timestamp_spec: Some(TimestampSpec {
+            column: Some(options.time_column.clone()),
+            format: Some("auto".to_string()),
+            missing_value: None,
+        }),
+        query_granularity: Some(serde_json::json!({"type": "none"})),
+        rollup: Some(options.rollup),
+        ..AggregateMetadata::default()
+    };
+    SegmentWriter::new()
+        .with_compression(options.compression)
+        .with_dimensions(dimensions)
+        .with_aggregate_metadata(metadata)
+        .write(&batch, interval, output)?;
+
+    Ok(CreateSummary {
+        input_rows: input.num_rows(),
+        output_rows: batch.num_rows(),
+    })
+}
+
+/// Casts that fail on values they cannot convert instead of making them
+/// null.
+const STRICT: CastOptions<'static> = CastOptions {
+    safe: false,
+    format_options: arrow::util::display::FormatOptions::new(),
+};
+
+/// The input column `name`, or an error naming the columns there are.
+fn input_column<'a>(input: &'a RecordBatch, name: &str, role: &str) -> Result<&'a ArrayRef> {
+    input.column_by_name(name).ok_or_else(|| {
+        let available: Vec<_> = input
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        DruidSegmentError::InvalidData(format!(
+            "{} '{}' is not in the input, whose columns are: {}",
+            role,
+            name,
+            available.join(", ")
+        ))
+    })
+}
+
+/// The time column as millisecond timestamps without a time zone.
+fn time_column(input: &RecordBatch, name: &str) -> Result<ArrayRef> {
+    let column = input_column(input, name, "Time column")?;
+    let millis = DataType::Timestamp(TimeUnit::Millisecond, None);
+    let times = match column.data_type() {
+        // Keep the instant; casting away the zone would shift it to wall
+        // clock time
+        DataType::Timestamp(_, Some(tz)) => {
+            let zoned = DataType::Timestamp(TimeUnit::Millisecond, Some(tz.clone()));
+            let times = cast_with_options(column, &zoned, &STRICT)?;
+            Arc::new(
+                times
+                    .as_primitive::<TimestampMillisecondType>()
+                    .clone()
+                    .with_timezone_opt(None::<String>),
+            )
+        }
+        DataType::Timestamp(_, None) | DataType::Date32 | DataType::Date64 => {
+            cast_with_options(column, &millis, &STRICT)?
+        }
+        t if t.is_integer() => {
+            let times = cast_with_options(column, &DataType::Int64, &STRICT)?;
+            cast_with_options(&times, &millis, &STRICT)?
+        }
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => {
+            cast_with_options(column, &millis, &STRICT).map_err(|e| {
+                DruidSegmentError::InvalidData(format!(
+                    "Time column '{}' has a value that is not a timestamp: {}",
+                    name, e
+                ))
+            })?
+        }
+        other => {
+            return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                "time column '{}' is of type {}; expected a timestamp, date, integer or string",
+                name, other
+            )));
+        }
+    };
+    if times.null_count() > 0 {
+        return Err(DruidSegmentError::InvalidData(format!(
+            "Time column '{}' has {} null values",
+            name,
+            times.null_count()
+        )));
+    }
+    Ok(times)
+}
+
+/// A dimension as a string, long, double or float column.
+fn dimension_column(input: &RecordBatch, name: &str) -> Result<ArrayRef> {
+    let column = input_column(input, name, "Dimension")?;
+    let to = match column.data_type() {
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => DataType::Utf8,
+        DataType::Dictionary(_, values)
+            if matches!(
+                values.as_ref(),
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+            ) =>
+        {
+            DataType::Utf8
+        }
+        DataType::Boolean => DataType::Int64,
+        t if t.is_integer() => DataType::Int64,
+        DataType::Float32 => DataType::Float32,
+        DataType::Float16 | DataType::Float64 => DataType::Float64,
+        other => {
+            return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                "dimension '{}' is of type {}; expected a string, integer, boolean or float",
+                name, other
+            )));
+        }
+    };
+    Ok(cast_with_options(column, &to, &STRICT)?)
+}
+
+/// A metric's input cast to its aggregator's type, or ones for `count`.
+fn metric_column(input: &RecordBatch, metric: &AggregatorSpec) -> Result<ArrayRef> {
+    let to = metric_type(metric)?;
+    let Some(field_name) = &metric.field_name else {
+        return Ok(Arc::new(Int64Array::from_value(1, input.num_rows())));
+    };
+    let column = input_column(input, field_name, "Metric column")?;
+    if !column.data_type().is_numeric() && column.data_type() != &DataType::Boolean {
+        return Err(DruidSegmentError::UnsupportedColumnType(format!(
+            "metric '{}' reads column '{}' of type {}, which is not numeric",
+            metric.name,
+            field_name,
+            column.data_type()
+        )));
+    }
+    Ok(cast_with_options(column, &to, &STRICT)?)
+}
+
+/// The column type an aggregator produces.
+fn metric_type(metric: &AggregatorSpec) -> Result<DataType> {
+    let aggregator = metric.aggregator_type.as_str();
+    let prefix = ["Sum", "Min", "Max"]
+        .iter()
+        .find_map(|suffix| aggregator.strip_suffix(suffix));
+    match (aggregator, prefix) {
+        ("count", _) | (_, Some("long")) => Ok(DataType::Int64),
+        (_, Some("double")) => Ok(DataType::Float64),
+        (_, Some("float")) => Ok(DataType::Float32),
+        _ => Err(DruidSegmentError::UnsupportedColumnType(format!(
+            "cannot create metric '{}' with a {} aggregator; expected count or a \
+             long, double or float Sum, Min or Max",
+            metric.name, aggregator
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::DruidSegment;
+    use arrow::array::{
+        Float64Array, Int32Array, ListArray, StringArray, TimestampNanosecondArray,
+    };
+    use arrow::datatypes::Int32Type;
+
+    fn input(columns: Vec<(&str, ArrayRef)>) -> RecordBatch {
+        RecordBatch::try_from_iter(columns).unwrap()
+    }
+
+    #[test]
+    fn test_create_sorts_and_infers_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch = input(vec![
+            (
+                "ts",
+                Arc::new(
+                    TimestampNanosecondArray::from(vec![30_000_000, 10_000_000, 20_000_000])
+                        .with_timezone("+02:00"),
+                ),
+            ),
+            ("page", Arc::new(StringArray::from(vec!["c", "a", "b"]))),
+            ("user_id", Arc::new(Int32Array::from(vec![3, 1, 2]))),
+            ("added", Arc::new(Int32Array::from(vec![30, 10, 20]))),
+        ]);
+        let options = CreateOptions::new("ts").with_metric("added", "doubleSum");
+
+        let summary = create_segment(&batch, dir.path(), &options).unwrap();
+        assert_eq!(
+            summary,
+            CreateSummary {
+                input_rows: 3,
+                output_rows: 3
+            }
+        );
+
+        let segment = DruidSegment::open(dir.path()).unwrap();
+        assert_eq!(segment.metadata().interval_start_ms, 10);
+        assert_eq!(segment.metadata().interval_end_ms, 31);
+        assert_eq!(segment.metadata().dimensions, vec!["page", "user_id"]);
+        let batch = segment.read_all().unwrap();
+        let times = batch.column(0).as_primitive::<TimestampMillisecondType>();
+        assert_eq!(times.values().to_vec(), vec![10, 20, 30]);
+        let page = batch.column_by_name("page").unwrap().as_string::<i32>();
+        assert_eq!(page, &StringArray::from(vec!["a", "b", "c"]));
+        let user_id = batch.column_by_name("user_id").unwrap();
+        assert_eq!(
+            user_id.as_ref(),
+            &Int64Array::from(vec![1, 2, 3]) as &dyn Array
+        );
+        let added = batch.column_by_name("added").unwrap();
+        assert_eq!(
+            added.as_ref(),
+            &Float64Array::from(vec![10.0, 20.0, 30.0]) as &dyn Array
+        );
+        let metadata = segment.aggregate_metadata().unwrap();
+        assert_eq!(metadata.aggregators, Some(options.metrics));
+        assert_eq!(metadata.rollup, Some(false));
+    }
+
+    #[test]
+    fn test_create_rollup() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch = input(vec![
+            ("ts", Arc::new(Int64Array::from(vec![5, 0, 5, 0]))),
+            (
+                "page",
+                Arc::new(StringArray::from(vec!["x", "x", "x", "y"])),
+            ),
+            ("added", Arc::new(Int64Array::from(vec![1, 2, 4, 8]))),
+        ]);
+        let options = CreateOptions::new("ts")
+            .with_dimensions(vec!["page".to_string()])
+            .with_metric("count", "count")
+            .with_metric("added", "longMax")
+            .with_rollup(true);
+
+        let summary = create_segment(&batch, dir.path(), &options).unwrap();
+        assert_eq!(summary.output_rows, 3);
+
+        let batch = DruidSegment::open(dir.path()).unwrap().read_all().unwrap();
+        let times = batch.column(0).as_primitive::<TimestampMillisecondType>();
+        assert_eq!(times.values().to_vec(), vec![0, 0, 5]);
+        let count = batch.column_by_name("count").unwrap();
+        assert_eq!(
+            count.as_ref(),
+            &Int64Array::from(vec![1, 1, 2]) as &dyn Array
+        );
+        let added = batch.column_by_name("added").unwrap();
+        assert_eq!(
+            added.as_ref(),
+            &Int64Array::from(vec![2, 8, 4]) as &dyn Array
+        );
+    }
+
+    #[test]
+    fn test_create_missing_time_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch = input(vec![("page", Arc::new(StringArray::from(vec!["x"])))]);
+        let err = create_segment(&batch, dir.path(), &CreateOptions::new("ts")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid binary data: Time column 'ts' is not in the input, whose columns are: page"
+        );
+        assert!(std::fs::read_dir(dir.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_create_unsupported_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let tags = ListArray::from_iter_primitive::<Int32Type, _, _>([Some([Some(1)])]);
+        let batch = input(vec![
+            (
+                "ts",
+                Arc::new(StringArray::from(vec!["2024-01-01T00:00:00Z"])),
+            ),
+            ("tags", Arc::new(tags)),
+            ("page", Arc::new(StringArray::from(vec!["x"]))),
+        ]);
+
+        let err = create_segment(&batch, dir.path(), &CreateOptions::new("ts")).unwrap_err();
+        assert!(matches!(err, DruidSegmentError::UnsupportedColumnType(_)));
+        assert!(
+            err.to_string()
+                .contains("dimension 'tags' is of type List("),
+            "{}",
+            err
+        );
+
+        let options = CreateOptions::new("ts")
+            .with_dimensions(Vec::new())
+            .with_metric("page", "longSum");
+        let err = create_segment(&batch, dir.path(), &options).unwrap_err();
+        assert!(err.to_string().contains("not numeric"), "{}", err);
+
+        let options = CreateOptions::new("page");
+        let err = create_segment(&batch, dir.path(), &options).unwrap_err();
+        assert!(err.to_string().contains("not a timestamp"), "{}", err);
+
+        let options = CreateOptions::new("ts")
+            .with_dimensions(vec!["page".to_string()])
+            .with_metric("uniques", "hyperUnique");
+        let err = create_segment(&batch, dir.path(), &options).unwrap_err();
+        assert!(
+            err.to_string().contains("hyperUnique aggregator"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_create_null_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let batch = input(vec![(
+            "ts",
+            Arc::new(Int64Array::from(vec![Some(1), None])),
+        )]);
+        let err = create_segment(&batch, dir.path(), &CreateOptions::new("ts")).unwrap_err();
+        assert!(err.to_string().contains("1 null values"), "{}", err);
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat};
+
+/// Identity of a segment: the datasource, interval, version and partition
+/// that Druid only records in the path a segment is stored under.
+///
+/// Deep storage and the segment cache lay segments out as
+///
+/// ```text
+/// <datasource>/<start>_<end>/<version>/<partition_num>/
+/// ```
+///
+/// with variants recognized by [`parse_from_path`](Self::parse_from_path):
+///
+/// - the interval split into `<start>/<end>` directories, as its ISO 8601
+///   form `<start>/<end>` makes it when used as a path;
+/// - colons in timestamps replaced with underscores, as HDFS deep storage
+///   writes them;
+/// - zipped segments, stored as `<partition_num>/index.zip` or as
+///   `<partition_num>_index.zip` in the version directory.
+///
+/// Segment ids order by datasource, interval, version, then partition, so
+/// sorting puts a datasource's segments in time order with newer versions
+/// of an interval after older ones.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SegmentId {
+    pub datasource: String,
+    pub interval_start_ms: i64,
+    pub interval_end_ms: i64,
+    pub version: String,
+    pub partition_num: u32,
+    /// The segment's shard spec as JSON, from the `descriptor.json` Druid
+    /// writes next to segments in deep storage, if there is one.
+    pub shard_spec: Option<String>,
+}
+
+impl SegmentId {
+    /// Parse the id of the segment stored at `path`, a segment directory
+    /// or zip file, from the directories above it. Returns `None` if the
+    /// path does not follow Druid's layout.
+    ///
+    /// The shard spec is not part of the path and is left `None`.
+    pub fn parse_from_path(path: &Path) -> Option<Self> {
+        let mut names = path
+            .components()
+            .rev()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .filter(|name| !name.is_empty() && *name != ".")
+            .peekable();
+
+        let mut last = names.next()?;
+        if last == "index.zip" {
+            last = names.next()?;
+        }
+        let partition_num = match last.strip_suffix("_index.zip") {
+            Some(partition) => partition.parse().ok()?,
+            None => last.parse().ok()?,
+        };
+        let version = normalize_timestamp(names.next()?);
+
+        let interval = names.next()?;
+        let (interval_start_ms, interval_end_ms) = match split_interval(interval) {
+            Some(interval) => interval,
+            None => {
+                let end = parse_timestamp(interval)?;
+                (parse_timestamp(names.next()?)?, end)
+            }
+        };
+        let datasource = names.next()?.to_string();
+
+        Some(Self {
+            datasource,
+            interval_start_ms,
+            interval_end_ms,
+            version,
+            partition_num,
+            shard_spec: None,
+        })
+    }
+}
+
+impl fmt::Display for SegmentId {
+    /// Druid's segment identifier: `<datasource>_<start>_<end>_<version>`,
+    /// followed by `_<partition_num>` for partitions other than 0.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{}_{}",
+            self.datasource,
+            format_timestamp(self.interval_start_ms),
+            format_timestamp(self.interval_end_ms),
+            self.version
+        )?;
+        if self.partition_num != 0 {
+            write!(f, "_{}", self.partition_num)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a `<start>_<end>` interval directory into its bounds. Druid's
+/// timestamps are UTC and end in `Z`, which tells the separator apart from
+/// underscores standing in for colons.
+fn split_interval(name: &str) -> Option<(i64, i64)> {
+    let (start, end) = name.split_once("Z_")?;
+    let start = parse_timestamp(&format!("{}Z", start))?;
+    Some((start, parse_timestamp(end)?))
+}
+
+/// Parse an ISO 8601 timestamp in epoch millis, in extended
+/// (`2016-06-27T00:00:00.000Z`) or basic (`20160627T000000.000Z`) form,
+/// with colons possibly replaced by underscores.
+fn parse_timestamp(name: &str) -> Option<i64> {
+    let name = name.replace('_', ":");
+    if let Ok(time) = DateTime::parse_from_rfc3339(&name) {
+        return Some(time.timestamp_millis());
+    }
+    NaiveDateTime::parse_from_str(&name, "%Y%m%dT%H%M%S%.fZ")
+        .ok()
+        .map(|time| time.and_utc().timestamp_millis())
+}
+
+/// A version directory's name with the colons of a timestamp version
+/// restored, or unchanged if it is not one.
+fn normalize_timestamp(name: &str) -> String {
+    let restored = name.replace('_', ":");
+    if name.contains('_') && DateTime::parse_from_rfc3339(&restored).is_ok() {
+        restored
+    } else {
+        name.to_string()
+    }
+}
+
+/// Format epoch millis as Druid does in ids and intervals, e.g.
+/// `2016-06-27T00:00:00.000Z`.
+pub(crate) fn format_timestamp(millis: i64) -> String {
+    match DateTime::from_timestamp_millis(millis) {
+        Some(time) => time.to_rfc3339_opts(SecondsFormat::Millis, true),
+        None => millis.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: i64 = 1_466_985_600_000;
+    const END: i64 = 1_467_072_000_000;
+
+    fn parse(path: &str) -> Option<SegmentId> {
+        SegmentId::parse_from_path(Path::new(path))
+    }
+
+    #[test]
+    fn test_parse_segment_cache_layout() {
+        let id = parse(
+            "/var/druid/segment-cache/wikipedia/2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/2024-01-01T00:00:00.000Z/3/",
+        )
+        .unwrap();
+        assert_eq!(
+            id,
+            SegmentId {
+                datasource: "wikipedia".into(),
+                interval_start_ms: START,
+                interval_end_ms: END,
+                version: "2024-01-01T00:00:00.000Z".into(),
+                partition_num: 3,
+                shard_spec: None,
+            }
+        );
+        assert_eq!(
+            id.to_string(),
+            "wikipedia_2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z_2024-01-01T00:00:00.000Z_3"
+        );
+    }
+
+    #[test]
+    fn test_parse_layout_variants() {
+        let expected = parse(
+            "wikipedia/2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/2024-01-01T00:00:00.000Z/0",
+        )
+        .unwrap();
+        for path in [
+            // Interval split at its ISO 8601 slash
+            "wikipedia/2016-06-27T00:00:00.000Z/2016-06-28T00:00:00.000Z/2024-01-01T00:00:00.000Z/0",
+            // HDFS: colons replaced with underscores, partition in the zip name
+            "wikipedia/2016-06-27T00_00_00.000Z_2016-06-28T00_00_00.000Z/2024-01-01T00_00_00.000Z/0_index.zip",
+            // Basic ISO 8601 timestamps, zip in the partition directory
+            "wikipedia/20160627T000000.000Z_20160628T000000.000Z/2024-01-01T00:00:00.000Z/0/index.zip",
+        ] {
+            assert_eq!(parse(path).as_ref(), Some(&expected), "{}", path);
+        }
+        assert_eq!(
+            expected.to_string(),
+            "wikipedia_2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z_2024-01-01T00:00:00.000Z"
+        );
+
+        // Versions that are not timestamps are kept as they are
+        let id = parse("ds/2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/v_2/1").unwrap();
+        assert_eq!(id.version, "v_2");
+    }
+
+    #[test]
+    fn test_parse_rejects_other_paths() {
+        assert_eq!(parse("tests/fixtures/wikipedia-segment"), None);
+        assert_eq!(parse("2024-01-01T00:00:00.000Z/0"), None);
+        assert_eq!(
+            parse("wikipedia/2016-06-27_2016-06-28/2024-01-01T00:00:00.000Z/0"),
+            None
+        );
+        // No datasource above the interval
+        assert_eq!(
+            parse("2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/v1/0"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ids_sort_by_interval_then_version() {
+        let mut ids: Vec<SegmentId> = [
+            "ds/2016-06-28T00:00:00.000Z_2016-06-29T00:00:00.000Z/v1/0",
+            "ds/2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/v2/0",
+            "ds/2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/v1/1",
+            "ds/2016-06-27T00:00:00.000Z_2016-06-28T00:00:00.000Z/v1/0",
+        ]
+        .into_iter()
+        .map(|path| parse(path).unwrap())
+        .collect();
+        ids.sort();
+        let order: Vec<_> = ids
+            .iter()
+            .map(|id| (id.interval_start_ms, id.version.as_str(), id.partition_num))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (START, "v1", 0),
+                (START, "v1", 1),
+                (START, "v2", 0),
+                (END, "v1", 0),
+            ]
+        );
+    }
+}
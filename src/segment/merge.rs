@@ -0,0 +1,609 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, ArrowNumericType, AsArray, PrimitiveArray, UInt32Array, new_null_array,
+};
+use arrow::compute::{
+    SortColumn, SortOptions, concat_batches, lexsort_to_indices, max, min, partition, sum, take,
+    take_record_batch,
+};
+use arrow::datatypes::{DataType, Field, Float32Type, Float64Type, Int64Type, Schema};
+use arrow::record_batch::RecordBatch;
+
+use super::aggregate_metadata::{AggregateMetadata, AggregatorSpec};
+use super::column_descriptor::ByteOrder;
+use super::progress::{ProgressReport, ProgressSink};
+use super::writer::SegmentWriter;
+use super::{DruidSegment, TIME_COLUMN};
+use crate::column::complex::nested::write_json_column;
+use crate::column::complex::{EXTENSION_NAME_KEY, JSON_EXTENSION_NAME};
+use crate::compression::CompressionStrategy;
+use crate::error::{DruidSegmentError, Result};
+
+/// Options controlling how [`merge_segments`] combines segments.
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// Roll up rows with the same `__time` and dimension values into one,
+    /// combining each metric with its aggregator from `metadata.drd`.
+    pub rollup: bool,
+    /// Block compression of the merged segment's numeric columns.
+    pub compression: CompressionStrategy,
+    /// Sink told about each input segment as it is read.
+    pub progress: Option<Arc<dyn ProgressSink>>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            rollup: false,
+            compression: CompressionStrategy::Lz4,
+            progress: None,
+        }
+    }
+}
+
+impl MergeOptions {
+    /// Roll up rows; see [`MergeOptions::rollup`].
+    pub fn with_rollup(mut self, rollup: bool) -> Self {
+        self.rollup = rollup;
+        self
+    }
+
+    /// Compress the merged numeric columns with `compression`.
+    pub fn with_compression(mut self, compression: CompressionStrategy) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Report the merge's progress to `sink`, one unit per input segment.
+    pub fn with_progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress = Some(sink);
+        self
+    }
+}
+
+/// Row counts of a merge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Rows across all the inputs.
+    pub input_rows: usize,
+    /// Rows in the merged segment, fewer than the inputs' when rolled up.
+    pub output_rows: usize,
+}
+
+/// The columns, interval and metadata the inputs have in common.
+struct MergedLayout {
+    fields: Vec<Field>,
+    /// Columns that are nested `COMPLEX<json>` columns, read as JSON text.
+    json_columns: Vec<String>,
+    dimensions: Vec<String>,
+    interval: Range<i64>,
+    aggregators: Option<Vec<AggregatorSpec>>,
+    metadata: Option<AggregateMetadata>,
+}
+
+/// Merge the segments at `inputs` into one segment written to `output`,
+/// which is created if missing.
+///
+/// Rows are sorted by `__time` and then by the dimensions, and written
+/// with [`SegmentWriter`], which rebuilds every string column's dictionary
+/// and bitmap indexes. The merged segment covers the union of the inputs'
+/// intervals and has every column of any input; rows of inputs without a
+/// column are null in it. A column must have the same type in every input
+/// that has it. Nested `COMPLEX<json>` columns are kept as nested columns.
+///
+/// With [`MergeOptions::rollup`], rows with the same `__time` and
+/// dimension values are combined. This needs every input to have the same
+/// aggregators in `metadata.drd`, one per metric, of the `count`, `*Sum`,
+/// `*Min` or `*Max` types.
+pub fn merge_segments(
+    inputs: &[PathBuf],
+    output: &Path,
+    options: &MergeOptions,
+) -> Result<MergeSummary> {
+    if inputs.is_empty() {
+        return Err(DruidSegmentError::InvalidData(
+            "No segments to merge".to_string(),
+        ));
+    }
+
+    let segments = inputs
+        .iter()
+        .map(|path| DruidSegment::open(path))
+        .collect::<Result<Vec<_>>>()?;
+    let size = |segment: &DruidSegment| {
+        segment
+            .smoosh()
+            .entries()
+            .map(|entry| entry.size() as u64)
+            .sum::<u64>()
+    };
+    let report = options.progress.as_ref().map(|sink| {
+        let total = segments.iter().map(size).sum();
+        ProgressReport::start(sink, segments.len(), total)
+    });
+
+    let mut batches = Vec::with_capacity(inputs.len());
+    let mut layout: Option<MergedLayout> = None;
+    for (path, segment) in inputs.iter().zip(&segments) {
+        let batch = segment.read_all()?;
+        if let Some(report) = &report {
+            report.advance(&path.display().to_string(), batch.num_rows(), size(segment));
+        }
+        let metadata = if segment.smoosh().has_file("metadata.drd") {
+            Some(segment.aggregate_metadata()?)
+        } else {
+            None
+        };
+        match &mut layout {
+            None => layout = Some(MergedLayout::new(segment, &batch, metadata)),
+            Some(layout) => layout.add(segment, &batch, metadata, path)?,
+        }
+        batches.push(batch);
+    }
+    let layout = layout.expect("at least one input");
+
+    let schema = Arc::new(Schema::new(layout.fields.clone()));
+    let aligned = batches
+        .iter()
+        .map(|batch| align(batch, &schema))
+        .collect::<Result<Vec<_>>>()?;
+    let merged = concat_batches(&schema, &aligned)?;
+    let input_rows = merged.num_rows();
+    let mut merged = sort_rows(&merged, &layout.dimensions)?;
+
+    let mut metadata = layout.metadata.clone().unwrap_or_default();
+    metadata.aggregators = layout.aggregators.clone();
+    if options.rollup {
+        let aggregators = layout.aggregators.as_deref().ok_or_else(|| {
+            DruidSegmentError::InvalidData(
+                "Cannot roll up segments without the same aggregators".to_string(),
+            )
+        })?;
+        merged = roll_up(&merged, &layout.dimensions, aggregators)?;
+    }
+    metadata.rollup = Some(options.rollup);
+
+    let mut writer = SegmentWriter::new()
+        .with_compression(options.compression)
+        .with_dimensions(layout.dimensions.clone())
+        .with_aggregate_metadata(metadata);
+    for name in &layout.json_columns {
+        let index = merged.schema().index_of(name)?;
+        let rows = merged
+            .column(index)
+            .as_string::<i32>()
+            .iter()
+            .map(|text| text.map(serde_json::from_str).transpose())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let files = write_json_column(name, &rows, options.compression, ByteOrder::LittleEndian)?;
+        writer = writer.with_column_file(name.as_str(), files.column);
+        for (file_name, file) in files.internal_files {
+            writer = writer.with_internal_file(file_name, file);
+        }
+        merged.remove_column(index);
+    }
+    writer.write(&merged, layout.interval.clone(), output)?;
+
+    Ok(MergeSummary {
+        input_rows,
+        output_rows: merged.num_rows(),
+    })
+}
+
+impl MergedLayout {
+    /// The layout of the first input.
+    fn new(
+        segment: &DruidSegment,
+        batch: &RecordBatch,
+        metadata: Option<AggregateMetadata>,
+    ) -> Self {
+        let segment_metadata = segment.metadata();
+        let mut layout = Self {
+            fields: Vec::new(),
+            json_columns: Vec::new(),
+            dimensions: segment_metadata.dimensions.clone(),
+            interval: segment_metadata.interval_start_ms..segment_metadata.interval_end_ms,
+            aggregators: metadata.as_ref().and_then(|m| m.aggregators.clone()),
+            metadata,
+        };
+        for field in batch.schema().fields() {
+            layout.push_field(field);
+        }
+        layout
+    }
+
+    /// Widen the layout to cover another input.
+    fn add(
+        &mut self,
+        segment: &DruidSegment,
+        batch: &RecordBatch,
+        metadata: Option<AggregateMetadata>,
+        path: &Path,
+    ) -> Result<()> {
+        for field in batch.schema().fields() {
+            match self.fields.iter().find(|f| f.name() == field.name()) {
+                Some(existing) if existing.data_type() != field.data_type() => {
+                    return Err(DruidSegmentError::InvalidData(format!(
+                        "Column '{}' of {} is {}, but {} in the segments before it",
+                        field.name(),
+                        path.display(),
+                        field.data_type(),
+                        existing.data_type()
+                    )));
+                }
+                Some(_) => {}
+                None => self.push_field(field),
+            }
+        }
+
+        let segment_metadata = segment.metadata();
+        for dimension in &segment_metadata.dimensions {
+            if !self.dimensions.contains(dimension) {
+                self.dimensions.push(dimension.clone());
+            }
+        }
+        self.interval = self.interval.start.min(segment_metadata.interval_start_ms)
+            ..self.interval.end.max(segment_metadata.interval_end_ms);
+
+        let aggregators = metadata.as_ref().and_then(|m| m.aggregators.as_ref());
+        if self.aggregators.as_ref() != aggregators {
+            self.aggregators = None;
+        }
+        if self.metadata.is_none() {
+            self.metadata = metadata;
+        }
+        Ok(())
+    }
+
+    fn push_field(&mut self, field: &Field) {
+        if field.metadata().get(EXTENSION_NAME_KEY).map(String::as_str) == Some(JSON_EXTENSION_NAME)
+        {
+            self.json_columns.push(field.name().clone());
+        }
+        self.fields
+            .push(Field::new(field.name(), field.data_type().clone(), true));
+    }
+}
+
+/// `batch` with the columns of `schema`, null where it has none.
+fn align(batch: &RecordBatch, schema: &Arc<Schema>) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) => column.clone(),
+            None => new_null_array(field.data_type(), batch.num_rows()),
+        })
+        .collect();
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// The columns rows are ordered and rolled up by: `__time`, then the
+/// dimensions.
+fn key_columns(batch: &RecordBatch, dimensions: &[String]) -> Vec<ArrayRef> {
+    std::iter::once(TIME_COLUMN)
+        .chain(dimensions.iter().map(String::as_str))
+        .filter_map(|name| batch.column_by_name(name).cloned())
+        .collect()
+}
+
+/// `batch` sorted by `__time` and then the dimensions, nulls first.
+pub(super) fn sort_rows(batch: &RecordBatch, dimensions: &[String]) -> Result<RecordBatch> {
+    let sort_columns: Vec<_> = key_columns(batch, dimensions)
+        .into_iter()
+        .map(|values| SortColumn {
+            values,
+            options: Some(SortOptions {
+                descending: false,
+                nulls_first: true,
+            }),
+        })
+        .collect();
+    let indices = lexsort_to_indices(&sort_columns, None)?;
+    Ok(take_record_batch(batch, &indices)?)
+}
+
+/// How an aggregator combines the values of rows rolled up together.
+#[derive(Debug, Clone, Copy)]
+enum Combine {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Combine {
+    fn of(aggregator: &AggregatorSpec) -> Result<Self> {
+        match aggregator.aggregator_type.as_str() {
+            "count" | "longSum" | "doubleSum" | "floatSum" => Ok(Self::Sum),
+            "longMin" | "doubleMin" | "floatMin" => Ok(Self::Min),
+            "longMax" | "doubleMax" | "floatMax" => Ok(Self::Max),
+            other => Err(DruidSegmentError::UnsupportedColumnType(format!(
+                "cannot roll up '{}' with a {} aggregator",
+                aggregator.name, other
+            ))),
+        }
+    }
+}
+
+/// Combine the rows of sorted `batch` with the same `__time` and dimension
+/// values, each metric with its aggregator.
+pub(super) fn roll_up(
+    batch: &RecordBatch,
+    dimensions: &[String],
+    aggregators: &[AggregatorSpec],
+) -> Result<RecordBatch> {
+    if batch.num_rows() == 0 {
+        return Ok(batch.clone());
+    }
+    let groups = partition(&key_columns(batch, dimensions))?.ranges();
+    let firsts = UInt32Array::from_iter_values(groups.iter().map(|g| g.start as u32));
+
+    let mut columns = Vec::with_capacity(batch.num_columns());
+    for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+        let name = field.name();
+        if name == TIME_COLUMN || dimensions.contains(name) {
+            columns.push(take(column.as_ref(), &firsts, None)?);
+            continue;
+        }
+        let aggregator = aggregators
+            .iter()
+            .find(|a| &a.name == name)
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!(
+                    "Cannot roll up metric '{}' without an aggregator",
+                    name
+                ))
+            })?;
+        let combine = Combine::of(aggregator)?;
+        columns.push(match column.data_type() {
+            DataType::Int64 => combine_groups::<Int64Type>(column.as_ref(), &groups, combine),
+            DataType::Float64 => combine_groups::<Float64Type>(column.as_ref(), &groups, combine),
+            DataType::Float32 => combine_groups::<Float32Type>(column.as_ref(), &groups, combine),
+            other => {
+                return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                    "cannot roll up metric '{}' of type {}",
+                    name, other
+                )));
+            }
+        });
+    }
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/// One value per group of rows of `array`; null when all of them are.
+fn combine_groups<T: ArrowNumericType>(
+    array: &dyn Array,
+    groups: &[Range<usize>],
+    combine: Combine,
+) -> ArrayRef {
+    let array = array.as_primitive::<T>();
+    let combined: PrimitiveArray<T> = groups
+        .iter()
+        .map(|group| {
+            let rows = array.slice(group.start, group.len());
+            match combine {
+                Combine::Sum => sum(&rows),
+                Combine::Min => min(&rows),
+                Combine::Max => max(&rows),
+            }
+        })
+        .collect();
+    Arc::new(combined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::SegmentFixtureBuilder;
+    use arrow::array::{Float64Array, Int64Array, StringArray};
+
+    fn rollup_metadata() -> AggregateMetadata {
+        let aggregator = |aggregator_type: &str, name: &str| AggregatorSpec {
+            aggregator_type: aggregator_type.to_string(),
+            name: name.to_string(),
+            field_name: Some(name.to_string()),
+            extra: serde_json::json!({}),
+        };
+        AggregateMetadata {
+            aggregators: Some(vec![
+                aggregator("count", "count"),
+                aggregator("doubleSum", "added"),
+                aggregator("longMax", "peak"),
+            ]),
+            rollup: Some(true),
+            ..AggregateMetadata::default()
+        }
+    }
+
+    fn rollup_segment(
+        dir: &Path,
+        times: Vec<i64>,
+        pages: &[Option<&str>],
+        added: Vec<Option<f64>>,
+        peak: Vec<Option<i64>>,
+    ) {
+        let count = vec![Some(1); times.len()];
+        SegmentFixtureBuilder::new()
+            .interval(0, 1000)
+            .with_writer(SegmentWriter::new().with_aggregate_metadata(rollup_metadata()))
+            .with_times(times)
+            .with_string_column("page", pages.iter().copied())
+            .with_long_column("count", count)
+            .with_double_column("added", added)
+            .with_long_column("peak", peak)
+            .build_in(dir)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_merge_sorts_rows_and_rebuilds_dictionaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, out) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("out"),
+        );
+        SegmentFixtureBuilder::new()
+            .interval(0, 100)
+            .with_times([10, 30])
+            .with_string_column("page", [Some("zebra"), Some("apple")])
+            .with_long_column("delta", [Some(1), Some(3)])
+            .build_in(&a)
+            .unwrap();
+        SegmentFixtureBuilder::new()
+            .interval(100, 200)
+            .with_times([20, 40].map(|t| t + 100))
+            .with_string_column("page", [None, Some("mango")])
+            .with_string_column("user", [Some("u1"), Some("u2")])
+            .build_in(&b)
+            .unwrap();
+
+        let summary = merge_segments(&[b, a], &out, &MergeOptions::default()).unwrap();
+        assert_eq!(
+            summary,
+            MergeSummary {
+                input_rows: 4,
+                output_rows: 4
+            }
+        );
+
+        let merged = DruidSegment::open(&out).unwrap();
+        assert_eq!(merged.metadata().interval_start_ms, 0);
+        assert_eq!(merged.metadata().interval_end_ms, 200);
+        assert_eq!(merged.metadata().dimensions, vec!["page", "user"]);
+        let batch = merged.read_all().unwrap();
+        let times = batch
+            .column_by_name(TIME_COLUMN)
+            .unwrap()
+            .as_primitive::<arrow::datatypes::TimestampMillisecondType>();
+        assert_eq!(times.values().to_vec(), vec![10, 30, 120, 140]);
+        let page = batch.column_by_name("page").unwrap().as_string::<i32>();
+        assert_eq!(
+            page.iter().collect::<Vec<_>>(),
+            vec![Some("zebra"), Some("apple"), None, Some("mango")]
+        );
+        let delta = batch.column_by_name("delta").unwrap();
+        assert_eq!(
+            delta.as_ref(),
+            &Int64Array::from(vec![Some(1), Some(3), None, None]) as &dyn Array
+        );
+        let user = batch.column_by_name("user").unwrap().as_string::<i32>();
+        assert_eq!(
+            user.iter().collect::<Vec<_>>(),
+            vec![None, None, Some("u1"), Some("u2")]
+        );
+        let dictionary = merged
+            .column("page")
+            .unwrap()
+            .dictionary()
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            dictionary,
+            vec![
+                None,
+                Some("apple".into()),
+                Some("mango".into()),
+                Some("zebra".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_rollup() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, out) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("out"),
+        );
+        rollup_segment(
+            &a,
+            vec![0, 0, 5],
+            &[Some("x"), Some("y"), Some("x")],
+            vec![Some(1.0), Some(2.0), None],
+            vec![Some(4), Some(1), None],
+        );
+        rollup_segment(
+            &b,
+            vec![0, 5],
+            &[Some("x"), Some("x")],
+            vec![Some(0.5), None],
+            vec![Some(9), None],
+        );
+
+        let options = MergeOptions::default().with_rollup(true);
+        let summary = merge_segments(&[a, b], &out, &options).unwrap();
+        assert_eq!(summary.input_rows, 5);
+        assert_eq!(summary.output_rows, 3);
+
+        let merged = DruidSegment::open(&out).unwrap();
+        assert_eq!(merged.aggregate_metadata().unwrap().rollup, Some(true));
+        let batch = merged.read_all().unwrap();
+        let page = batch.column_by_name("page").unwrap().as_string::<i32>();
+        assert_eq!(page, &StringArray::from(vec!["x", "y", "x"]));
+        let count = batch.column_by_name("count").unwrap();
+        assert_eq!(
+            count.as_ref(),
+            &Int64Array::from(vec![2, 1, 2]) as &dyn Array
+        );
+        let added = batch.column_by_name("added").unwrap();
+        assert_eq!(
+            added.as_ref(),
+            &Float64Array::from(vec![Some(1.5), Some(2.0), None]) as &dyn Array
+        );
+        let peak = batch.column_by_name("peak").unwrap();
+        assert_eq!(
+            peak.as_ref(),
+            &Int64Array::from(vec![Some(9), Some(1), None]) as &dyn Array
+        );
+    }
+
+    #[test]
+    fn test_merge_rollup_needs_aggregators() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, out) = (dir.path().join("a"), dir.path().join("out"));
+        SegmentFixtureBuilder::new()
+            .with_string_column("page", [Some("x"), Some("x")])
+            .with_long_column("delta", [Some(1), Some(2)])
+            .build_in(&a)
+            .unwrap();
+
+        let options = MergeOptions::default().with_rollup(true);
+        let err = merge_segments(&[a], &out, &options).unwrap_err();
+        assert!(err.to_string().contains("without an aggregator"), "{}", err);
+    }
+
+    #[test]
+    fn test_merge_type_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b, out) = (
+            dir.path().join("a"),
+            dir.path().join("b"),
+            dir.path().join("out"),
+        );
+        SegmentFixtureBuilder::new()
+            .with_long_column("value", [Some(1)])
+            .build_in(&a)
+            .unwrap();
+        SegmentFixtureBuilder::new()
+            .with_string_column("value", [Some("one")])
+            .build_in(&b)
+            .unwrap();
+
+        let err = merge_segments(&[a, b], &out, &MergeOptions::default()).unwrap_err();
+        assert!(matches!(err, DruidSegmentError::InvalidData(_)), "{}", err);
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn test_merge_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = merge_segments(&[], dir.path(), &MergeOptions::default()).unwrap_err();
+        assert!(matches!(err, DruidSegmentError::InvalidData(_)));
+    }
+}
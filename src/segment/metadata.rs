@@ -1,9 +1,13 @@
 use std::io::Cursor;
 
 use byteorder::{BigEndian, ReadBytesExt};
+use serde::Deserialize;
 
-use crate::column::generic_indexed::GenericIndexedV1;
+use crate::column::generic_indexed::{GenericIndexed, GenericIndexedWriter};
+use crate::column::read_len;
 use crate::error::{DruidSegmentError, Result};
+use crate::segment::id::{SegmentId, format_timestamp};
+use crate::segment::smoosh::SmooshReader;
 
 /// Segment metadata parsed from the `index.drd` logical file.
 ///
@@ -13,23 +17,72 @@ use crate::error::{DruidSegmentError, Result};
 /// [dimensions: GenericIndexed<String>] -- list of dimension names
 /// [interval_start: i64]                -- interval start in epoch millis
 /// [interval_end: i64]                  -- interval end in epoch millis
-/// [bitmap_serde_factory: optional]
+/// [bitmap_serde_factory: optional]   -- [json_len: i32][json]
 /// ```
-#[derive(Debug, Clone)]
+///
+/// Segments written with `storeEmptyColumns` carry two more name lists
+/// after the bitmap serde factory, for columns that are entirely null.
+/// They are not read, and [`to_bytes`](Self::to_bytes) does not write them.
+#[derive(Debug, Clone, PartialEq)]
 pub struct SegmentMetadata {
     pub columns: Vec<String>,
     pub dimensions: Vec<String>,
     pub interval_start_ms: i64,
     pub interval_end_ms: i64,
+    /// Format of the segment's bitmap indexes.
+    pub bitmap_serde_factory: BitmapSerdeFactory,
+    /// The segment's datasource, interval, version and partition, parsed
+    /// from its path by [`DruidSegment::open`](super::DruidSegment::open).
+    /// Not part of index.drd, so `None` when parsed from bytes.
+    pub segment_id: Option<SegmentId>,
+}
+
+/// Mirrors Druid's BitmapSerdeFactory: the bitmap format every index in a
+/// segment is written with, e.g. `{"type": "roaring"}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BitmapSerdeFactory {
+    Roaring,
+    /// Druid's legacy format, assumed when index.drd names no factory.
+    Concise,
+    #[serde(other)]
+    Unknown,
+}
+
+impl BitmapSerdeFactory {
+    /// Whether bitmap indexes in this format can be read, and so used to
+    /// answer filters without decoding column values.
+    pub fn supports_index_reads(&self) -> bool {
+        matches!(self, BitmapSerdeFactory::Roaring)
+    }
+}
+
+impl std::fmt::Display for BitmapSerdeFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BitmapSerdeFactory::Roaring => "roaring",
+            BitmapSerdeFactory::Concise => "concise",
+            BitmapSerdeFactory::Unknown => "unknown",
+        })
+    }
 }
 
 impl SegmentMetadata {
     /// Parse segment metadata from the raw bytes of `index.drd`.
     pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_smoosh(data, None)
+    }
+
+    /// Parse segment metadata from the raw bytes of `index.drd`, mapping
+    /// the external files of GenericIndexed V2 name lists from `smoosh`.
+    pub fn from_bytes_with_smoosh(data: &[u8], smoosh: Option<&SmooshReader>) -> Result<Self> {
+        if data.is_empty() {
+            return Err(DruidSegmentError::EmptyLogicalFile("index.drd".into()));
+        }
         let mut offset = 0;
 
         // Read column names (GenericIndexed<String>)
-        let columns_gi = GenericIndexedV1::from_bytes(&data[offset..])?;
+        let columns_gi = GenericIndexed::from_bytes(&data[offset..], smoosh)?;
         let mut columns = Vec::with_capacity(columns_gi.len());
         for i in 0..columns_gi.len() {
             let name = columns_gi.get_str(i)?.ok_or_else(|| {
@@ -43,7 +96,7 @@ impl SegmentMetadata {
         offset += columns_gi.total_size()?;
 
         // Read dimension names (GenericIndexed<String>)
-        let dimensions_gi = GenericIndexedV1::from_bytes(&data[offset..])?;
+        let dimensions_gi = GenericIndexed::from_bytes(&data[offset..], smoosh)?;
         let mut dimensions = Vec::with_capacity(dimensions_gi.len());
         for i in 0..dimensions_gi.len() {
             let name = dimensions_gi.get_str(i)?.ok_or_else(|| {
@@ -65,12 +118,192 @@ impl SegmentMetadata {
         let mut cursor = Cursor::new(&data[offset..]);
         let interval_start_ms = cursor.read_i64::<BigEndian>()?;
         let interval_end_ms = cursor.read_i64::<BigEndian>()?;
+        offset += 16;
+
+        // Read the bitmap serde factory (length-prefixed JSON), which
+        // segments written before it existed omit
+        let bitmap_serde_factory = if data.len() < offset + 4 {
+            BitmapSerdeFactory::Concise
+        } else {
+            let mut cursor = Cursor::new(&data[offset..]);
+            let json_len = read_len(&mut cursor, "index.drd: bitmap serde factory length")?;
+            let json = data.get(offset + 4..offset + 4 + json_len).ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!(
+                    "index.drd: bitmap serde factory length {} exceeds remaining {} bytes",
+                    json_len,
+                    data.len() - offset - 4
+                ))
+            })?;
+            serde_json::from_slice(json)?
+        };
 
         Ok(Self {
             columns,
             dimensions,
             interval_start_ms,
             interval_end_ms,
+            bitmap_serde_factory,
+            segment_id: None,
         })
     }
+
+    /// Whether the segment's interval shares any instant with the
+    /// half-open query window `[start_ms, end_ms)`. Like Druid's
+    /// intervals, both are half-open, so windows that only touch an end of
+    /// the interval do not overlap it.
+    pub fn overlaps(&self, start_ms: i64, end_ms: i64) -> bool {
+        start_ms < self.interval_end_ms && end_ms > self.interval_start_ms
+    }
+
+    /// Whether `ts_ms` falls in the segment's half-open interval
+    /// `[interval_start_ms, interval_end_ms)`.
+    pub fn contains(&self, ts_ms: i64) -> bool {
+        (self.interval_start_ms..self.interval_end_ms).contains(&ts_ms)
+    }
+
+    /// The segment's interval in ISO 8601, as Druid writes it:
+    /// `<start>/<end>` in UTC with milliseconds.
+    pub fn interval_iso(&self) -> String {
+        format!(
+            "{}/{}",
+            format_timestamp(self.interval_start_ms),
+            format_timestamp(self.interval_end_ms)
+        )
+    }
+
+    /// Serialize to the bytes of `index.drd`, as read by
+    /// [`from_bytes`](Self::from_bytes).
+    ///
+    /// Name lists are written as GenericIndexed V1, flagged sorted when
+    /// their names are strictly ascending as Druid does. Fails for an
+    /// [`Unknown`](BitmapSerdeFactory::Unknown) bitmap serde factory, whose
+    /// name was not kept.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let json = match self.bitmap_serde_factory {
+            BitmapSerdeFactory::Unknown => {
+                return Err(DruidSegmentError::InvalidData(
+                    "index.drd: cannot write an unknown bitmap serde factory".into(),
+                ));
+            }
+            factory => format!(r#"{{"type":"{}"}}"#, factory),
+        };
+
+        let mut buf = write_names(&self.columns)?;
+        buf.extend(write_names(&self.dimensions)?);
+        buf.extend_from_slice(&self.interval_start_ms.to_be_bytes());
+        buf.extend_from_slice(&self.interval_end_ms.to_be_bytes());
+        buf.extend_from_slice(&(json.len() as i32).to_be_bytes());
+        buf.extend_from_slice(json.as_bytes());
+        Ok(buf)
+    }
+}
+
+/// Write a list of names as a GenericIndexed V1 of strings, flagged sorted
+/// if the names strictly ascend in Java's string order (by UTF-16 code
+/// unit).
+fn write_names(names: &[String]) -> Result<Vec<u8>> {
+    let sorted = names
+        .windows(2)
+        .all(|pair| pair[0].encode_utf16().lt(pair[1].encode_utf16()));
+    GenericIndexedWriter::write_strings(names.iter().map(|n| Some(n.as_str())), sorted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_bytes_round_trips() {
+        let metadata = SegmentMetadata {
+            columns: vec!["added".into(), "channel".into(), "page".into()],
+            dimensions: vec!["page".into(), "channel".into()],
+            interval_start_ms: 1_442_016_000_000,
+            interval_end_ms: 1_442_102_400_000,
+            bitmap_serde_factory: BitmapSerdeFactory::Concise,
+            segment_id: None,
+        };
+        let bytes = metadata.to_bytes().unwrap();
+        assert_eq!(SegmentMetadata::from_bytes(&bytes).unwrap(), metadata);
+        // Sorted column names are flagged, unsorted dimensions are not
+        assert_eq!(&bytes[..2], [0x01, 0x01]);
+        let columns = GenericIndexed::from_bytes(&bytes, None).unwrap();
+        assert_eq!(bytes[columns.total_size().unwrap() + 1], 0x00);
+
+        let empty = SegmentMetadata {
+            columns: vec![],
+            dimensions: vec![],
+            ..metadata
+        };
+        let bytes = empty.to_bytes().unwrap();
+        assert_eq!(SegmentMetadata::from_bytes(&bytes).unwrap(), empty);
+    }
+
+    #[test]
+    fn test_to_bytes_rejects_unknown_factory() {
+        let metadata = SegmentMetadata {
+            columns: vec![],
+            dimensions: vec![],
+            interval_start_ms: 0,
+            interval_end_ms: 1,
+            bitmap_serde_factory: BitmapSerdeFactory::Unknown,
+            segment_id: None,
+        };
+        assert!(metadata.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_bitmap_serde_factory_fallbacks() {
+        let metadata = SegmentMetadata {
+            columns: vec!["added".into()],
+            dimensions: vec![],
+            interval_start_ms: 0,
+            interval_end_ms: 1,
+            bitmap_serde_factory: BitmapSerdeFactory::Roaring,
+            segment_id: None,
+        };
+        let bytes = metadata.to_bytes().unwrap();
+        let json = br#"{"type":"roaring"}"#;
+        let factory_start = bytes.len() - json.len() - 4;
+
+        // Segments older than the factory section are Concise
+        let legacy = SegmentMetadata::from_bytes(&bytes[..factory_start]).unwrap();
+        assert_eq!(legacy.bitmap_serde_factory, BitmapSerdeFactory::Concise);
+
+        let mut other = bytes[..factory_start].to_vec();
+        let json = br#"{"type":"someFutureFormat"}"#;
+        other.extend_from_slice(&(json.len() as i32).to_be_bytes());
+        other.extend_from_slice(json);
+        let other = SegmentMetadata::from_bytes(&other).unwrap();
+        assert_eq!(other.bitmap_serde_factory, BitmapSerdeFactory::Unknown);
+        assert!(!other.bitmap_serde_factory.supports_index_reads());
+
+        // A length running past the data is an error, not a guess
+        let mut truncated = bytes.clone();
+        truncated.pop();
+        assert!(SegmentMetadata::from_bytes(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_interval_overlap_and_containment() {
+        let metadata = SegmentMetadata {
+            columns: vec![],
+            dimensions: vec![],
+            interval_start_ms: 1_000,
+            interval_end_ms: 2_000,
+            bitmap_serde_factory: BitmapSerdeFactory::Roaring,
+            segment_id: None,
+        };
+        // Windows touching either end do not overlap
+        assert!(!metadata.overlaps(0, 1_000));
+        assert!(!metadata.overlaps(2_000, 3_000));
+        assert!(metadata.overlaps(0, 1_001));
+        assert!(metadata.overlaps(1_999, 3_000));
+        assert!(metadata.overlaps(1_200, 1_300));
+        assert!(metadata.overlaps(0, 3_000));
+
+        assert!(metadata.contains(1_000));
+        assert!(metadata.contains(1_999));
+        assert!(!metadata.contains(2_000));
+        assert!(!metadata.contains(999));
+    }
 }
@@ -1,75 +1,399 @@
+pub mod aggregate_metadata;
 pub mod column_descriptor;
+pub mod column_handle;
+pub mod create;
+pub mod id;
+pub mod merge;
 pub mod metadata;
+pub mod progress;
+pub mod read_options;
+pub mod rows;
 pub mod smoosh;
+pub mod stats;
+pub mod validate;
 pub mod version;
+pub mod writer;
 
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::time::Instant;
 
-use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
-use arrow::record_batch::RecordBatch;
+use arrow::array::{
+    ArrayRef, BooleanArray, ListArray, StringArray, TimestampMillisecondArray, UInt32Array,
+};
+use arrow::compute::{filter_record_batch, take_record_batch};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::{RecordBatch, RecordBatchOptions};
+use roaring::RoaringBitmap;
+#[cfg(feature = "async")]
+use tokio::io::AsyncReadExt;
 
-use self::column_descriptor::{ColumnDescriptor, ValueType};
+use self::aggregate_metadata::AggregateMetadata;
+use self::column_descriptor::{ColumnCapabilities, ColumnDescriptor, ValueType};
+use self::column_handle::{ColumnHandle, Strings};
+use self::id::SegmentId;
 use self::metadata::SegmentMetadata;
+use self::progress::ProgressReport;
+use self::read_options::{ReadOptions, TimeRange};
+use self::rows::{BatchIter, RowIter, SegmentBatchReader};
+#[cfg(feature = "async")]
+use self::smoosh::AsyncSmooshSource;
 use self::smoosh::SmooshReader;
+use self::stats::{ColumnStats, StatValue};
+use self::validate::{ColumnTiming, ValidationReport};
 use self::version::read_version;
 use crate::column;
-use crate::error::Result;
+use crate::column::block_layout::BlockLayout;
+use crate::column::string::{DictionaryCache, StringColumnIndex};
+use crate::compression::DecompressionTimer;
+use crate::error::{DruidSegmentError, Result};
+
+/// Name of the timestamp column every Druid segment carries.
+pub const TIME_COLUMN: &str = "__time";
+
+/// Field metadata key holding a column's Druid value type, e.g. `LONG`.
+pub const VALUE_TYPE_KEY: &str = "druid.value_type";
+
+/// Field metadata key holding `true` for the segment's dimensions and
+/// `false` for `__time` and metrics.
+pub const IS_DIMENSION_KEY: &str = "druid.is_dimension";
+
+/// Field metadata key holding a column's role in the segment: `time` for
+/// `__time`, `dimension` for the segment's dimensions, and `metric` for
+/// the other columns.
+pub const COLUMN_ROLE_KEY: &str = "druid.column_role";
+
+/// Field metadata key holding the type of the aggregator that produced a
+/// metric at ingestion, e.g. `longSum`, if `metadata.drd` lists one.
+pub const AGGREGATOR_KEY: &str = "druid.aggregator";
+
+/// Field metadata key holding `true` for multi-value dimensions.
+pub const HAS_MULTIPLE_VALUES_KEY: &str = "druid.has_multiple_values";
+
+/// Schema metadata key holding the segment's interval in ISO 8601, e.g.
+/// `2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z`.
+pub const INTERVAL_KEY: &str = "druid.interval";
+
+/// Schema metadata key holding the segment's dimensions as a JSON array.
+pub const DIMENSIONS_KEY: &str = "druid.dimensions";
 
 /// A fully opened Druid v9 segment, ready for reading.
 pub struct DruidSegment {
     smoosh: SmooshReader,
     metadata: SegmentMetadata,
-    schema: Arc<Schema>,
+    /// The Arrow schema, supplied by the caller or built from every column
+    /// header on first use.
+    schema: OnceLock<SchemaRef>,
+    /// Row count, read from the `__time` header on first use.
+    num_rows: OnceLock<usize>,
+    /// Statistics of the columns they have been computed for.
+    stats: Mutex<HashMap<String, ColumnStats>>,
+    /// Columns whose headers have been parsed and checked against `schema`.
+    parsed_columns: Mutex<BTreeSet<String>>,
+    /// Guards the warning logged when an index lookup cannot use indexes.
+    pushdown_warning: Once,
+    /// Dictionaries of the string columns read as dictionary arrays, so
+    /// every batch of a column shares one.
+    dictionaries: DictionaryCache,
+    /// Type of the ingestion aggregator of each metric, from `metadata.drd`
+    /// on first use.
+    aggregators: OnceLock<HashMap<String, String>>,
 }
 
 impl std::fmt::Debug for DruidSegment {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("DruidSegment")
             .field("metadata", &self.metadata)
-            .field("schema", &self.schema)
+            .field("schema", &self.schema.get())
             .finish_non_exhaustive()
     }
 }
 
 impl DruidSegment {
     /// Open a segment directory, validating version and parsing metadata.
+    ///
+    /// No column header is parsed here: reads only parse the headers of the
+    /// columns they touch, and the schema is built from every header the
+    /// first time it is asked for (see [`try_schema`](Self::try_schema)).
+    ///
+    /// If the directory is laid out as in deep storage or the segment
+    /// cache, the segment's id is recorded in
+    /// [`SegmentMetadata::segment_id`].
+    ///
+    /// A path to a `.zip` file is opened with [`open_zip`](Self::open_zip).
     pub fn open(path: &Path) -> Result<Self> {
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+            && path.is_file()
+        {
+            return Self::open_zip(path);
+        }
+
         // 1. Validate version.bin
         let version_data = std::fs::read(path.join("version.bin"))?;
         read_version(&version_data)?;
 
-        // 2. Open smoosh archive
+        // 2. Open smoosh archive, then parse metadata
         let smoosh = SmooshReader::open(path)?;
+        let mut segment = Self::from_reader(smoosh)?;
+        segment.metadata.segment_id = segment_id_at(path);
+        Ok(segment)
+    }
+
+    /// Open a segment from its files held in memory, keyed by file name:
+    /// `version.bin`, `meta.smoosh` and the chunk files such as
+    /// `00000.smoosh`, e.g. as fetched from object storage. Other files are
+    /// ignored. A missing file fails with a not-found I/O error.
+    pub fn open_in_memory(mut files: HashMap<String, Vec<u8>>) -> Result<Self> {
+        let mut take = |name: &str| {
+            files.remove(name).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{} not among the segment's files", name),
+                )
+            })
+        };
+        read_version(&take("version.bin")?)?;
+        let meta = String::from_utf8(take("meta.smoosh")?)
+            .map_err(|_| DruidSegmentError::InvalidSmooshMeta("meta.smoosh is not UTF-8".into()))?;
+        let num_chunks = smoosh::num_chunks(&meta)?;
+        let chunks = (0..num_chunks)
+            .map(|i| take(&format!("{:05}.smoosh", i)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        Self::from_reader(SmooshReader::from_parts(&meta, chunks)?)
+    }
+
+    /// Open a segment zipped as deep storage keeps them, e.g. in an
+    /// `index.zip`, reading its files into memory instead of extracting
+    /// them.
+    ///
+    /// The segment's files may sit under a directory inside the archive,
+    /// as long as they all share it. An archive holding no segment, or
+    /// more than one, fails with [`DruidSegmentError::InvalidData`].
+    pub fn open_zip(path: &Path) -> Result<Self> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let mut archive = zip::ZipArchive::new(file)?;
+        let prefix = zip_segment_prefix(&archive, path)?;
 
-        // 3. Parse index.drd metadata
-        let index_data = smoosh.map_file("index.drd")?;
-        let metadata = SegmentMetadata::from_bytes(index_data)?;
+        let version_data = smoosh::read_zip_entry(&mut archive, &format!("{}version.bin", prefix))?;
+        read_version(&version_data)?;
+        let smoosh = SmooshReader::from_zip(&mut archive, &prefix)?;
+        let mut segment = Self::from_reader(smoosh)?;
+        segment.metadata.segment_id = segment_id_at(path);
+        Ok(segment)
+    }
+
+    /// Open a segment through an async source, such as an object store,
+    /// without blocking on I/O or memory-mapping: `version.bin` and the
+    /// chunks are read into memory by [`SmooshReader::open_async`].
+    #[cfg(feature = "async")]
+    pub async fn open_async<S: AsyncSmooshSource + ?Sized>(source: &S) -> Result<Self> {
+        let mut version_data = Vec::new();
+        source
+            .open("version.bin")
+            .await?
+            .read_to_end(&mut version_data)
+            .await?;
+        read_version(&version_data)?;
+
+        let smoosh = SmooshReader::open_async(source).await?;
+        Self::from_reader(smoosh)
+    }
+
+    /// Open the segment stored under `prefix` in `store`, such as an S3
+    /// bucket, without downloading its chunks.
+    ///
+    /// Only `version.bin`, `meta.smoosh`, `index.drd`, `metadata.drd`,
+    /// `__time` and the headers of the other columns are fetched, so the
+    /// schema and row count are known. Other columns must be fetched with
+    /// [`fetch_columns`](Self::fetch_columns) before they are read;
+    /// reading one that is not fails with
+    /// [`DruidSegmentError::LogicalFileNotFetched`].
+    #[cfg(feature = "object_store")]
+    pub async fn open_remote(
+        store: Arc<dyn object_store::ObjectStore>,
+        prefix: &object_store::path::Path,
+    ) -> Result<Self> {
+        let version_data = store
+            .get(&prefix.child("version.bin"))
+            .await?
+            .bytes()
+            .await?;
+        read_version(&version_data)?;
+
+        let smoosh = SmooshReader::open_remote(store, prefix).await?;
+        let eager = ["index.drd", "metadata.drd", TIME_COLUMN];
+        let eager: Vec<&str> = eager
+            .iter()
+            .flat_map(|&name| companion_files(&smoosh, name))
+            .collect();
+        smoosh.fetch_files(eager).await?;
 
-        // 4. Build Arrow schema
-        let schema = Self::build_schema(&smoosh, &metadata)?;
+        let segment = Self::from_reader(smoosh)?;
+        let columns = segment.metadata.columns.iter().map(String::as_str);
+        segment.smoosh.fetch_heads(columns).await?;
+        Ok(segment)
+    }
+
+    /// Fetch the files of `columns` for a segment from
+    /// [`open_remote`](Self::open_remote), so that they can be read. Columns
+    /// fetched before are not fetched again, and a segment opened any other
+    /// way has nothing to fetch.
+    #[cfg(feature = "object_store")]
+    pub async fn fetch_columns(&self, columns: &[&str]) -> Result<()> {
+        self.check_columns(columns)?;
+        let files: Vec<&str> = columns
+            .iter()
+            .flat_map(|&c| companion_files(&self.smoosh, c))
+            .collect();
+        self.smoosh.fetch_files(files).await
+    }
+
+    /// Open a segment from an already-built smoosh archive, such as one
+    /// held in memory with [`SmooshReader::from_parts`].
+    ///
+    /// There is no `version.bin` to check here; callers that have it can
+    /// validate it with [`version::read_version`].
+    pub fn from_reader(smoosh: SmooshReader) -> Result<Self> {
+        // Parse index.drd metadata
+        let index_data = smoosh.map_non_empty_file("index.drd")?;
+        let metadata = SegmentMetadata::from_bytes_with_smoosh(index_data, Some(&smoosh))?;
 
         Ok(Self {
             smoosh,
             metadata,
-            schema,
+            schema: OnceLock::new(),
+            parsed_columns: Mutex::default(),
+            num_rows: OnceLock::new(),
+            stats: Mutex::default(),
+            pushdown_warning: Once::new(),
+            dictionaries: DictionaryCache::default(),
+            aggregators: OnceLock::new(),
         })
     }
 
-    fn build_schema(smoosh: &SmooshReader, metadata: &SegmentMetadata) -> Result<Arc<Schema>> {
+    /// Open a segment directory with a caller-supplied schema instead of
+    /// one built from every column header.
+    ///
+    /// Only the existence of each schema column is checked up front, so
+    /// opening a wide segment does not parse any column header. Each
+    /// column's type is checked against `schema` when it is first read, and
+    /// a mismatch fails that read with
+    /// [`DruidSegmentError::SchemaMismatch`]. The schema may list any subset
+    /// of the segment's columns.
+    pub fn open_with_schema(path: &Path, schema: SchemaRef) -> Result<Self> {
+        let mut segment = Self::open(path)?;
+        if let Some(missing) = schema
+            .fields()
+            .iter()
+            .find(|f| !segment.smoosh.has_file(f.name()))
+        {
+            return Err(DruidSegmentError::LogicalFileNotFound(
+                missing.name().clone(),
+            ));
+        }
+        segment.schema = OnceLock::from(schema);
+        Ok(segment)
+    }
+
+    /// Build the schema: `__time` first, then the columns listed in
+    /// index.drd (which does not include `__time`).
+    ///
+    /// Fields carry the Druid semantics of their columns in their metadata
+    /// (see [`druid_field`]), and the schema carries the segment's interval
+    /// and dimensions under [`INTERVAL_KEY`] and [`DIMENSIONS_KEY`].
+    fn build_schema(&self) -> Result<Arc<Schema>> {
+        let metadata = &self.metadata;
         let mut fields = Vec::new();
+        if self.smoosh.has_file(TIME_COLUMN) && !metadata.columns.iter().any(|c| c == TIME_COLUMN) {
+            fields.push(time_field());
+        }
         for col_name in &metadata.columns {
-            let col_data = smoosh.map_file(col_name)?;
-            let (descriptor, _) = column::parse_column_header(col_data)?;
-            let arrow_type = druid_type_to_arrow(&descriptor, col_name);
-            fields.push(Field::new(col_name, arrow_type, true));
+            fields.push(self.header_field(col_name)?);
+        }
+        let schema_metadata = HashMap::from([
+            (INTERVAL_KEY.to_string(), metadata.interval_iso()),
+            (
+                DIMENSIONS_KEY.to_string(),
+                serde_json::to_string(&metadata.dimensions)?,
+            ),
+        ]);
+        Ok(Arc::new(Schema::new_with_metadata(fields, schema_metadata)))
+    }
+
+    /// The field of a column as its header describes it.
+    fn header_field(&self, name: &str) -> Result<Field> {
+        Ok(self.column_field(
+            &self.column_descriptor(name)?,
+            name,
+            &ReadOptions::default(),
+        ))
+    }
+
+    /// The field a column described by `descriptor` is read into, with the
+    /// type of its ingestion aggregator if it is a metric that has one.
+    fn column_field(
+        &self,
+        descriptor: &ColumnDescriptor,
+        name: &str,
+        options: &ReadOptions,
+    ) -> Field {
+        let is_dimension = self.metadata.dimensions.iter().any(|d| d == name);
+        let field = druid_field(descriptor, name, is_dimension, options);
+        match self.aggregator_types().get(name) {
+            Some(aggregator) if !is_dimension && name != TIME_COLUMN => {
+                let mut metadata = field.metadata().clone();
+                metadata.insert(AGGREGATOR_KEY.to_string(), aggregator.clone());
+                field.with_metadata(metadata)
+            }
+            _ => field,
         }
-        Ok(Arc::new(Schema::new(fields)))
     }
 
-    /// Return the Arrow schema for this segment.
+    /// Type of the aggregator of each metric named in `metadata.drd`, or
+    /// none if it is missing or lists no aggregators.
+    fn aggregator_types(&self) -> &HashMap<String, String> {
+        self.aggregators.get_or_init(|| {
+            let aggregators = self.aggregate_metadata().ok().and_then(|m| m.aggregators);
+            aggregators
+                .into_iter()
+                .flatten()
+                .map(|spec| (spec.name, spec.aggregator_type))
+                .collect()
+        })
+    }
+
+    /// Parse a column's header: its value type and the serdes of its
+    /// parts, without reading any of its data.
+    pub fn column_descriptor(&self, column: &str) -> Result<ColumnDescriptor> {
+        let col_data = self.smoosh.map_file_head(column)?;
+        let (descriptor, _) = column::parse_column_header(col_data)?;
+        self.mark_parsed(column);
+        Ok(descriptor)
+    }
+
+    /// Return the Arrow schema for this segment, building it on first use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the schema has to be built and a column header cannot be
+    /// parsed; [`try_schema`](Self::try_schema) returns the error instead.
     pub fn schema(&self) -> Arc<Schema> {
-        self.schema.clone()
+        self.try_schema()
+            .expect("column headers could not be parsed into a schema")
+    }
+
+    /// Return the Arrow schema for this segment. The first call on a
+    /// segment from [`open`](Self::open) parses every column header.
+    pub fn try_schema(&self) -> Result<Arc<Schema>> {
+        if let Some(schema) = self.schema.get() {
+            return Ok(schema.clone());
+        }
+        let schema = self.build_schema()?;
+        Ok(self.schema.get_or_init(|| schema).clone())
     }
 
     /// Get the segment metadata.
@@ -77,35 +401,901 @@ impl DruidSegment {
         &self.metadata
     }
 
+    /// Parse the segment's `metadata.drd`: its aggregators, timestamp spec,
+    /// query granularity, rollup flag and row ordering.
+    pub fn aggregate_metadata(&self) -> Result<AggregateMetadata> {
+        AggregateMetadata::from_bytes(self.smoosh.map_non_empty_file("metadata.drd")?)
+    }
+
+    /// Whether rows are sorted by `__time`, as they are unless
+    /// `metadata.drd`'s ordering puts another column first or sorts `__time`
+    /// descending. Segments without a readable ordering are sorted by time.
+    pub fn is_time_ordered(&self) -> bool {
+        let ordering = self.aggregate_metadata().ok().and_then(|m| m.ordering);
+        match ordering.as_deref() {
+            Some([first, ..]) => {
+                first.column_name == TIME_COLUMN && !first.order.eq_ignore_ascii_case("descending")
+            }
+            _ => true,
+        }
+    }
+
+    /// Names of the columns whose headers have been parsed so far, sorted.
+    ///
+    /// Opening a segment parses no header; reads parse those of the columns
+    /// they touch, and building the schema parses all of them.
+    pub fn parsed_columns(&self) -> Vec<String> {
+        self.parsed_columns
+            .lock()
+            .expect("parsed_columns lock poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Records that `column`'s header has been parsed.
+    fn mark_parsed(&self, column: &str) {
+        self.parsed_columns
+            .lock()
+            .expect("parsed_columns lock poisoned")
+            .insert(column.to_string());
+    }
+
     /// Read all columns into a single RecordBatch.
+    ///
+    /// Rows come back in storage order: sorted by `__time`, then by the
+    /// dimensions in the order the segment was built with. This matches the
+    /// order of a Druid scan query over the segment.
     pub fn read_all(&self) -> Result<RecordBatch> {
-        let col_names: Vec<&str> = self.metadata.columns.iter().map(|s| s.as_str()).collect();
-        self.read_columns(&col_names)
+        self.read_all_with_options(&ReadOptions::default())
     }
 
-    /// Read specific columns by name into a RecordBatch.
+    /// Read all columns into a single RecordBatch with the given options.
+    pub fn read_all_with_options(&self, options: &ReadOptions) -> Result<RecordBatch> {
+        let schema = self.try_schema()?;
+        let col_names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        self.read_columns_with_options(&col_names, options)
+    }
+
+    /// Read specific columns by name into a RecordBatch, in storage order.
     pub fn read_columns(&self, columns: &[&str]) -> Result<RecordBatch> {
+        self.read_columns_with_options(columns, &ReadOptions::default())
+    }
+
+    /// Read `len` rows of specific columns starting at row `offset`, in
+    /// storage order, e.g. to page through a segment.
+    ///
+    /// Compressed columns decode only the blocks overlapping the window.
+    /// A window reaching past the last row is cut short.
+    pub fn read_columns_range(
+        &self,
+        columns: &[&str],
+        offset: usize,
+        len: usize,
+    ) -> Result<RecordBatch> {
+        let options = ReadOptions::default().with_offset(offset).with_limit(len);
+        self.read_columns_with_options(columns, &options)
+    }
+
+    /// Like [`read_columns_range`](Self::read_columns_range), but an
+    /// `offset` past the last row fails with
+    /// [`DruidSegmentError::RowOutOfRange`] instead of yielding an empty
+    /// batch. A window starting at the last row's end is empty, and one
+    /// reaching past it is still cut short.
+    pub fn read_row_range(
+        &self,
+        columns: &[&str],
+        offset: usize,
+        len: usize,
+    ) -> Result<RecordBatch> {
+        let num_rows = self.num_rows()?;
+        if offset > num_rows {
+            return Err(DruidSegmentError::RowOutOfRange { offset, num_rows });
+        }
+        self.read_columns_range(columns, offset, len)
+    }
+
+    /// The rows whose `__time` falls in `start_ms..end_ms`.
+    ///
+    /// In a time-ordered segment they are found by binary search: first over
+    /// the first row of each `__time` block, then within the one block each
+    /// bound falls in, so only a few blocks are decompressed. Otherwise
+    /// `__time` is scanned, and the range returned is the smallest holding
+    /// every row in the time range, which may hold others too.
+    pub fn time_range_rows(&self, start_ms: i64, end_ms: i64) -> Result<Range<usize>> {
+        if !self.is_time_ordered() {
+            let time = self.read_time_values(0, self.num_rows()?)?;
+            let range = TimeRange::new(Some(start_ms), Some(end_ms));
+            let first = time.iter().position(|&t| range.contains(t));
+            let last = time.iter().rposition(|&t| range.contains(t));
+            return Ok(match (first, last) {
+                (Some(first), Some(last)) => first..last + 1,
+                _ => 0..0,
+            });
+        }
+        let start = self.time_lower_bound(start_ms)?;
+        let end = self.time_lower_bound(end_ms)?.max(start);
+        Ok(start..end)
+    }
+
+    /// Read the rows of specific columns whose `__time` falls in
+    /// `start_ms..end_ms`, in storage order.
+    ///
+    /// In a time-ordered segment only the blocks holding those rows are
+    /// decoded, via [`time_range_rows`](Self::time_range_rows) and
+    /// [`read_row_range`](Self::read_row_range). Otherwise the columns are
+    /// read whole and filtered by `__time`.
+    pub fn read_time_range(
+        &self,
+        columns: &[&str],
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<RecordBatch> {
+        if !self.is_time_ordered() {
+            let range = TimeRange::new(Some(start_ms), Some(end_ms));
+            let options = ReadOptions::default().with_time_range(range);
+            return self.read_columns_with_options(columns, &options);
+        }
+        self.check_columns(columns)?;
+        let rows = self.time_range_rows(start_ms, end_ms)?;
+        self.read_row_range(columns, rows.start, rows.len())
+    }
+
+    /// The first row whose `__time` is at least `time_ms`, or the row count
+    /// if there is none, in a time-ordered segment.
+    fn time_lower_bound(&self, time_ms: i64) -> Result<usize> {
+        let num_rows = self.num_rows()?;
+        let block_size = match self.column_layout(TIME_COLUMN)? {
+            Some(layout) if layout.size_per > 0 => layout.size_per,
+            _ => num_rows.max(1),
+        };
+        let num_blocks = num_rows.div_ceil(block_size);
+
+        // Blocks starting before `time_ms`; the bound is in the last of them
+        let mut lo = 0;
+        let mut hi = num_blocks;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.read_time_values(mid * block_size, 1)?[0] < time_ms {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == 0 {
+            return Ok(0);
+        }
+        let block_start = (lo - 1) * block_size;
+        let block = self.read_time_values(block_start, block_size)?;
+        Ok(block_start + block.partition_point(|&t| t < time_ms))
+    }
+
+    /// Decode `len` values of `__time` starting at row `offset`.
+    fn read_time_values(&self, offset: usize, len: usize) -> Result<Vec<i64>> {
+        let batch = self.read_columns_range(&[TIME_COLUMN], offset, len)?;
+        let time = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData("__time column is not a timestamp".into())
+            })?;
+        Ok(time.values().to_vec())
+    }
+
+    /// Read specific columns by name into a RecordBatch with the given options.
+    ///
+    /// If `options` carries a cancellation token, it is checked before each
+    /// column and each compressed block; a cancelled read returns
+    /// [`DruidSegmentError::Cancelled`](crate::error::DruidSegmentError::Cancelled).
+    ///
+    /// If `options` carries a time range, a segment whose interval does not
+    /// overlap it yields an empty batch without decoding any column, and
+    /// otherwise only rows whose `__time` falls in the range are returned.
+    /// A limit keeps the first rows in storage order.
+    ///
+    /// If `options` carries a progress sink, it is started once, told about
+    /// each column as it is read, and finished even if the read fails.
+    pub fn read_columns_with_options(
+        &self,
+        columns: &[&str],
+        options: &ReadOptions,
+    ) -> Result<RecordBatch> {
+        self.check_columns(columns)?;
+        let report = options.progress.as_ref().map(|sink| {
+            let total_bytes = columns.iter().map(|&c| self.stored_size(c)).sum();
+            ProgressReport::start(sink, columns.len(), total_bytes)
+        });
+        self.read_columns_inner(columns, options, report.as_ref())
+    }
+
+    /// Read checked `columns`, reporting each to `report` rather than to the
+    /// options' sink, so windowed reads can share one report.
+    fn read_columns_inner(
+        &self,
+        columns: &[&str],
+        options: &ReadOptions,
+        report: Option<&ProgressReport>,
+    ) -> Result<RecordBatch> {
+        let interval = (
+            self.metadata.interval_start_ms,
+            self.metadata.interval_end_ms,
+        );
+        if options
+            .time_range
+            .is_some_and(|range| !range.overlaps(interval.0, interval.1))
+        {
+            return self.empty_batch(columns, options);
+        }
+
+        if columns.is_empty() {
+            return self.read_row_count(options);
+        }
+
+        // A limit counts rows that pass the time range, so columns can only
+        // stop decoding early when there is no range to filter by.
+        let column_options = match options.time_range {
+            Some(_) => ReadOptions {
+                limit: None,
+                ..options.clone()
+            },
+            None => options.clone(),
+        };
+
         let mut arrays = Vec::new();
         let mut fields = Vec::new();
 
         for &col_name in columns {
-            let col_data = self.smoosh.map_file(col_name)?;
-            let (descriptor, array) = column::read_column(col_name, col_data)?;
-            let arrow_type = druid_type_to_arrow(&descriptor, col_name);
-            fields.push(Field::new(col_name, arrow_type, true));
+            let (field, array) = self.read_column(col_name, &column_options)?;
+            if let Some(report) = report {
+                report.advance(col_name, array.len(), self.stored_size(col_name));
+            }
+            fields.push(field);
             arrays.push(array);
         }
 
-        let schema = Arc::new(Schema::new(fields));
-        Ok(RecordBatch::try_new(schema, arrays)?)
+        let batch = RecordBatch::try_new(self.batch_schema(fields), arrays)?;
+        let batch = match &options.time_range {
+            Some(range) => self.filter_time_range(batch, columns, range, &column_options)?,
+            None => batch,
+        };
+        Ok(match options.limit {
+            Some(limit) if batch.num_rows() > limit => batch.slice(0, limit),
+            _ => batch,
+        })
+    }
+
+    /// Build the zero-column batch of an empty projection (e.g. `count(*)`
+    /// or `SELECT 1`), which only needs the number of rows selected.
+    ///
+    /// Without a time range the count comes from the `__time` header and
+    /// nothing is decoded; with one, only `__time` is decoded to count the
+    /// rows in range.
+    fn read_row_count(&self, options: &ReadOptions) -> Result<RecordBatch> {
+        options.check_cancelled()?;
+        let mut num_rows = match &options.time_range {
+            None => options.rows_to_decode(self.num_rows()?),
+            Some(range) => {
+                let time_options = ReadOptions {
+                    limit: None,
+                    ..options.clone()
+                };
+                let (_, time) = self.read_column(TIME_COLUMN, &time_options)?;
+                let time = time
+                    .as_any()
+                    .downcast_ref::<TimestampMillisecondArray>()
+                    .ok_or_else(|| {
+                        DruidSegmentError::InvalidData("__time column is not a timestamp".into())
+                    })?;
+                time.iter()
+                    .filter(|t| t.is_some_and(|t| range.contains(t)))
+                    .count()
+            }
+        };
+        if let Some(limit) = options.limit {
+            num_rows = num_rows.min(limit);
+        }
+        let batch_options = RecordBatchOptions::new().with_row_count(Some(num_rows));
+        Ok(RecordBatch::try_new_with_options(
+            self.batch_schema(Vec::new()),
+            vec![],
+            &batch_options,
+        )?)
+    }
+
+    /// Read specific columns as a sequence of batches of at most
+    /// [`ReadOptions::batch_size`] rows, in storage order.
+    ///
+    /// The columns are decoded once and the batches are zero-copy slices of
+    /// them. An empty result is returned as a single empty batch so callers
+    /// still see its schema.
+    pub fn read_batches_with_options(
+        &self,
+        columns: &[&str],
+        options: &ReadOptions,
+    ) -> Result<Vec<RecordBatch>> {
+        let batch = self.read_columns_with_options(columns, options)?;
+        Ok(split_batch(&batch, options.effective_batch_size()))
+    }
+
+    /// Iterate over batches of at most `batch_size` rows of `columns`, or
+    /// of every column if `None`, in storage order.
+    ///
+    /// Unlike [`read_batches_with_options`](Self::read_batches_with_options),
+    /// each batch is decoded only when the iterator reaches it, so memory
+    /// use does not grow with the segment. The last batch holds the rows
+    /// left over; an empty segment yields no batches.
+    pub fn batches(&self, columns: Option<&[&str]>, batch_size: usize) -> Result<BatchIter<'_>> {
+        let options = ReadOptions::default().with_batch_size(batch_size);
+        match columns {
+            Some(columns) => self.batches_with_options(columns, &options),
+            None => {
+                let schema = self.try_schema()?;
+                let columns: Vec<&str> =
+                    schema.fields().iter().map(|f| f.name().as_str()).collect();
+                self.batches_with_options(&columns, &options)
+            }
+        }
+    }
+
+    /// Iterate over batches of specific columns with the given options.
+    ///
+    /// The offset, limit, time range and cancellation token apply as they
+    /// do for [`read_columns_with_options`](Self::read_columns_with_options).
+    pub fn batches_with_options(
+        &self,
+        columns: &[&str],
+        options: &ReadOptions,
+    ) -> Result<BatchIter<'_>> {
+        BatchIter::new(self, columns, options)
+    }
+
+    /// Turn the segment into an Arrow
+    /// [`RecordBatchReader`](arrow::record_batch::RecordBatchReader) over every
+    /// column, yielding batches of at most `batch_size` rows decoded one
+    /// at a time, for tools that consume Arrow readers.
+    pub fn into_batch_reader(self, batch_size: usize) -> Result<SegmentBatchReader> {
+        SegmentBatchReader::new(self, batch_size)
+    }
+
+    /// Iterate over the rows of specific columns, in storage order.
+    ///
+    /// Unlike [`read_columns`](Self::read_columns), columns are decoded one
+    /// window of [`DEFAULT_BATCH_SIZE`](read_options::DEFAULT_BATCH_SIZE)
+    /// rows at a time, so memory use does not grow with the segment.
+    pub fn row_iter(&self, columns: &[&str]) -> Result<RowIter<'_>> {
+        self.row_iter_with_options(columns, &ReadOptions::default())
+    }
+
+    /// Iterate over rows with the given options.
+    ///
+    /// Windows hold [`ReadOptions::batch_size`] stored rows. The offset,
+    /// limit, time range and cancellation token apply as they do for
+    /// [`read_columns_with_options`](Self::read_columns_with_options).
+    pub fn row_iter_with_options(
+        &self,
+        columns: &[&str],
+        options: &ReadOptions,
+    ) -> Result<RowIter<'_>> {
+        RowIter::new(self, columns, options)
+    }
+
+    /// Keep only the rows of `batch` whose `__time` falls in `range`,
+    /// reading `__time` separately if it was not among `columns`.
+    fn filter_time_range(
+        &self,
+        batch: RecordBatch,
+        columns: &[&str],
+        range: &TimeRange,
+        options: &ReadOptions,
+    ) -> Result<RecordBatch> {
+        let time: ArrayRef = match columns.iter().position(|&c| c == TIME_COLUMN) {
+            Some(idx) => batch.column(idx).clone(),
+            None => self.read_column(TIME_COLUMN, options)?.1,
+        };
+        let time = time
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData("__time column is not a timestamp".into())
+            })?;
+
+        let mask: BooleanArray = time
+            .iter()
+            .map(|t| Some(t.is_some_and(|t| range.contains(t))))
+            .collect();
+        Ok(filter_record_batch(&batch, &mask)?)
+    }
+
+    /// Look up the rows of string dimension `column` whose value is `value`,
+    /// using the column's bitmap index instead of decoding its values.
+    ///
+    /// Returns `None` if `value` is not in the column's dictionary. For a
+    /// multi-value dimension, a row matches if any of its values is `value`.
+    ///
+    /// If the column has no bitmap indexes, or the segment's bitmap format
+    /// cannot be read, the lookup fails and a warning is logged the first
+    /// time this happens for the segment.
+    pub fn dimension_index(&self, column: &str, value: &str) -> Result<Option<RoaringBitmap>> {
+        let capabilities = self.column_capabilities(column)?;
+        if !capabilities.supports_index_pushdown() {
+            self.pushdown_warning.call_once(|| {
+                tracing::warn!(
+                    column,
+                    bitmap = %capabilities.bitmap_serde_factory,
+                    has_bitmap_indexes = capabilities.has_bitmap_indexes,
+                    "Index pushdown unavailable for this segment, filters fall back to scanning"
+                );
+            });
+        }
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        let bitmap = column::read_dimension_bitmap(col_data, Some(&self.smoosh), value)?;
+        self.mark_parsed(column);
+        Ok(bitmap)
+    }
+
+    /// The rows where string dimension `column` equals `value`, computed
+    /// from its bitmap index without decoding any values.
+    ///
+    /// Like [`dimension_index`](Self::dimension_index), but a value missing
+    /// from the dictionary matches no rows instead of returning `None`.
+    pub fn filter_rows(&self, column: &str, value: &str) -> Result<RoaringBitmap> {
+        Ok(self.dimension_index(column, value)?.unwrap_or_default())
+    }
+
+    /// The rows where string dimension `column` equals `value`.
+    ///
+    /// Uses the column's bitmap index when the segment has a readable one,
+    /// so no row is decoded; otherwise the column is decoded and scanned.
+    /// For a multi-value dimension, a row matches if any of its values is
+    /// `value`.
+    pub fn rows_matching(&self, column: &str, value: &str) -> Result<RoaringBitmap> {
+        self.rows_matching_any(column, &[value])
+    }
+
+    /// The rows where string dimension `column` equals any of `values`,
+    /// looked up like [`rows_matching`](Self::rows_matching).
+    pub fn rows_matching_any(&self, column: &str, values: &[&str]) -> Result<RoaringBitmap> {
+        let capabilities = self.column_capabilities(column)?;
+        if capabilities.value_type != ValueType::String {
+            return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                "row lookup on {:?} column '{}'",
+                capabilities.value_type, column
+            )));
+        }
+        if !capabilities.supports_index_pushdown() {
+            return self.scan_matching(column, values);
+        }
+        let index = self.string_index(column)?;
+        let mut rows = RoaringBitmap::new();
+        for value in values {
+            if let Some(bitmap) = index.bitmap_for_value(value)? {
+                rows |= bitmap;
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Decode string dimension `column` and collect the rows holding any
+    /// of `values`.
+    fn scan_matching(&self, column: &str, values: &[&str]) -> Result<RoaringBitmap> {
+        let batch = self.read_columns(&[column])?;
+        let array = batch.column(0);
+        let matches = |strings: &StringArray| {
+            strings
+                .iter()
+                .any(|s| s.is_some_and(|s| values.contains(&s)))
+        };
+        let mut rows = RoaringBitmap::new();
+        if let Some(strings) = array.as_any().downcast_ref::<StringArray>() {
+            for (row, value) in strings.iter().enumerate() {
+                if value.is_some_and(|v| values.contains(&v)) {
+                    rows.insert(row as u32);
+                }
+            }
+        } else if let Some(lists) = array.as_any().downcast_ref::<ListArray>() {
+            for (row, list) in lists.iter().enumerate() {
+                if let Some(list) = list
+                    && let Some(strings) = list.as_any().downcast_ref::<StringArray>()
+                    && matches(strings)
+                {
+                    rows.insert(row as u32);
+                }
+            }
+        } else {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "string column '{}' decoded as {}",
+                column,
+                array.data_type()
+            )));
+        }
+        Ok(rows)
+    }
+
+    /// Read only the rows in `rows` of specific columns, in storage order,
+    /// e.g. those found by [`rows_matching`](Self::rows_matching).
+    ///
+    /// Compressed columns decode only the blocks between the first and last
+    /// row. A row past the segment's last fails with
+    /// [`DruidSegmentError::RowOutOfRange`].
+    pub fn read_rows(&self, rows: &RoaringBitmap, columns: &[&str]) -> Result<RecordBatch> {
+        let num_rows = self.num_rows()?;
+        let (Some(first), Some(last)) = (rows.min(), rows.max()) else {
+            return self.empty_batch(columns, &ReadOptions::default());
+        };
+        if last as usize >= num_rows {
+            return Err(DruidSegmentError::RowOutOfRange {
+                offset: last as usize,
+                num_rows,
+            });
+        }
+        if columns.is_empty() {
+            let options = RecordBatchOptions::new().with_row_count(Some(rows.len() as usize));
+            return Ok(RecordBatch::try_new_with_options(
+                self.batch_schema(Vec::new()),
+                vec![],
+                &options,
+            )?);
+        }
+        let window =
+            self.read_columns_range(columns, first as usize, (last - first) as usize + 1)?;
+        let indices: UInt32Array = rows.iter().map(|row| row - first).collect();
+        Ok(take_record_batch(&window, &indices)?)
+    }
+
+    /// Open the inverted indexes of a string dimension: its dictionary and
+    /// one bitmap of rows per value, deserialized on lookup.
+    pub fn string_index(&self, column: &str) -> Result<StringColumnIndex<'_>> {
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        let index = column::read_string_index(col_data, Some(&self.smoosh))?;
+        self.mark_parsed(column);
+        Ok(index)
+    }
+
+    /// Open a handle on `column` that parses its header, and the dictionary
+    /// of a string column, once for any number of lookups.
+    pub fn column(&self, column: &str) -> Result<ColumnHandle<'_>> {
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        let (descriptor, _) = column::parse_column_header(col_data)?;
+        let all_null = descriptor
+            .parts
+            .first()
+            .is_some_and(|part| part.serde_type == "nullColumn");
+        let strings = match descriptor.value_type {
+            ValueType::String if all_null => Some(Strings::AllNull),
+            ValueType::String => Some(Strings::Dictionary(column::read_string_values(
+                col_data,
+                Some(&self.smoosh),
+            )?)),
+            _ => None,
+        };
+        let num_rows = self.num_rows()?;
+        self.mark_parsed(column);
+        Ok(ColumnHandle::new(column, descriptor, num_rows, strings))
+    }
+
+    /// Describe what `column` can be read with, from its header and the
+    /// segment's bitmap format.
+    pub fn column_capabilities(&self, column: &str) -> Result<ColumnCapabilities> {
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        let (descriptor, _) = column::parse_column_header(col_data)?;
+        Ok(ColumnCapabilities {
+            value_type: descriptor.value_type,
+            has_multiple_values: descriptor.has_multiple_values,
+            has_bitmap_indexes: column::read_has_bitmap_index(col_data, Some(&self.smoosh))?,
+            bitmap_serde_factory: self.metadata.bitmap_serde_factory,
+        })
+    }
+
+    /// Number of distinct values of a string column, from its dictionary
+    /// (null counts as a value), or `None` for non-string columns.
+    pub fn dictionary_cardinality(&self, column: &str) -> Result<Option<usize>> {
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        column::read_dictionary_cardinality(col_data, Some(&self.smoosh))
+    }
+
+    /// Min, max, null count and distinct count of `column`, computed on the
+    /// first call as cheaply as its encoding allows (see [`ColumnStats`])
+    /// and cached after.
+    pub fn column_stats(&self, column: &str) -> Result<ColumnStats> {
+        if let Some(stats) = self.stats.lock().expect("stats lock poisoned").get(column) {
+            return Ok(stats.clone());
+        }
+        let stats = self.compute_column_stats(column)?;
+        self.stats
+            .lock()
+            .expect("stats lock poisoned")
+            .insert(column.to_string(), stats.clone());
+        Ok(stats)
+    }
+
+    /// [`column_stats`](Self::column_stats) of every column, in schema
+    /// order.
+    pub fn all_column_stats(&self) -> Result<Vec<ColumnStats>> {
+        self.try_schema()?
+            .fields()
+            .iter()
+            .map(|field| self.column_stats(field.name()))
+            .collect()
+    }
+
+    /// Decode every column in full, without the dictionary cache, checking
+    /// that each holds the segment's number of rows, and time each
+    /// column's decoding by phase.
+    pub fn validate(&self) -> Result<ValidationReport> {
+        self.validate_with_options(&ReadOptions::default())
+    }
+
+    /// [`validate`](Self::validate), reporting each column to the options'
+    /// progress sink and stopping once their cancellation token is
+    /// cancelled. Every column is still decoded in full: the other options
+    /// are ignored.
+    pub fn validate_with_options(&self, options: &ReadOptions) -> Result<ValidationReport> {
+        let num_rows = self.num_rows()?;
+        let schema = self.try_schema()?;
+        let report = options.progress.as_ref().map(|sink| {
+            let total_bytes = schema
+                .fields()
+                .iter()
+                .map(|f| self.stored_size(f.name()))
+                .sum();
+            ProgressReport::start(sink, schema.fields().len(), total_bytes)
+        });
+        let options = ReadOptions {
+            cancellation: options.cancellation.clone(),
+            ..ReadOptions::default()
+        };
+        let mut columns = Vec::with_capacity(schema.fields().len());
+        for field in schema.fields() {
+            let name = field.name();
+            let started = Instant::now();
+            let data = self.smoosh.map_non_empty_file(name)?;
+            let map = started.elapsed();
+
+            let building = Instant::now();
+            let timer = DecompressionTimer::start();
+            let (_, array) =
+                column::read_column_cached(name, data, Some(&self.smoosh), &options, None)?;
+            let decompress = timer.stop();
+            let build = building.elapsed().saturating_sub(decompress);
+            let total = started.elapsed();
+
+            if array.len() != num_rows {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "Column '{}' has {} rows, but the segment has {}",
+                    name,
+                    array.len(),
+                    num_rows
+                )));
+            }
+            if let Some(report) = &report {
+                report.advance(name, array.len(), self.stored_size(name));
+            }
+            columns.push(ColumnTiming {
+                name: name.clone(),
+                map,
+                decompress,
+                build,
+                total,
+            });
+        }
+        Ok(ValidationReport { num_rows, columns })
+    }
+
+    fn compute_column_stats(&self, column: &str) -> Result<ColumnStats> {
+        let handle = self.column(column)?;
+        let mut stats = ColumnStats {
+            name: column.to_string(),
+            min: None,
+            max: None,
+            null_count: 0,
+            distinct_count: None,
+        };
+        if column == TIME_COLUMN && self.is_time_ordered() {
+            // Rows are sorted by time, so only the first and last are read
+            let num_rows = handle.num_rows();
+            if num_rows > 0 {
+                let first = self.read_columns_range(&[column], 0, 1)?;
+                let last = self.read_columns_range(&[column], num_rows - 1, 1)?;
+                stats.min = stats::min_max(first.column(0).as_ref()).map(|(min, _)| min);
+                stats.max = stats::min_max(last.column(0).as_ref()).map(|(_, max)| max);
+            }
+            return Ok(stats);
+        }
+        match handle.descriptor().value_type {
+            ValueType::String => {
+                let mut values = Vec::new();
+                for value in handle.dictionary()? {
+                    values.extend(value?);
+                }
+                stats.min = values
+                    .iter()
+                    .min()
+                    .map(|v| StatValue::String(v.to_string()));
+                stats.max = values
+                    .iter()
+                    .max()
+                    .map(|v| StatValue::String(v.to_string()));
+                stats.distinct_count = Some(values.len());
+                stats.null_count = self.column_null_count(column)?;
+            }
+            // Includes `__time` when rows are not sorted by it
+            ValueType::Long | ValueType::Float | ValueType::Double => {
+                let batch = self.read_columns(&[column])?;
+                let array = batch.column(0);
+                (stats.min, stats.max) = stats::min_max(array.as_ref()).unzip();
+                stats.null_count = array.null_count();
+            }
+            _ => stats.null_count = self.column_null_count(column)?,
+        }
+        Ok(stats)
+    }
+
+    /// Whether filters can use the segment's bitmap indexes at all, which
+    /// depends on the format they are written in.
+    pub fn index_pushdown_enabled(&self) -> bool {
+        self.metadata.bitmap_serde_factory.supports_index_reads()
+    }
+
+    /// The segment's column names, `__time` first, as index.drd lists
+    /// them.
+    pub fn column_names(&self) -> Vec<String> {
+        let time = self.smoosh.has_file(TIME_COLUMN)
+            && !self.metadata.columns.iter().any(|c| c == TIME_COLUMN);
+        time.then(|| TIME_COLUMN.to_string())
+            .into_iter()
+            .chain(self.metadata.columns.iter().cloned())
+            .collect()
+    }
+
+    /// Fail with [`DruidSegmentError::UnknownColumn`] on the first of
+    /// `columns` the segment does not have, before any is decoded.
+    pub(crate) fn check_columns(&self, columns: &[&str]) -> Result<()> {
+        let known = |name: &str| {
+            self.metadata.columns.iter().any(|c| c == name)
+                || (name == TIME_COLUMN && self.smoosh.has_file(TIME_COLUMN))
+        };
+        match columns.iter().find(|&&c| !known(c)) {
+            Some(&requested) => Err(DruidSegmentError::UnknownColumn {
+                requested: requested.to_string(),
+                available: self.column_names(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Read one column, checking its stored type against the schema.
+    ///
+    /// Returns the schema's field for the column, or one built from the
+    /// column header if the schema does not list it.
+    fn read_column(&self, name: &str, options: &ReadOptions) -> Result<(Field, ArrayRef)> {
+        let col_data = self.smoosh.map_non_empty_file(name)?;
+        let (descriptor, array) = column::read_column_cached(
+            name,
+            col_data,
+            Some(&self.smoosh),
+            options,
+            Some(&self.dictionaries),
+        )?;
+        let actual = self.column_field(&descriptor, name, options);
+        self.mark_parsed(name);
+
+        let expected = self
+            .known_field(name)
+            .map(|field| read_field(field, options));
+        match expected {
+            Some(field) if field.data_type() != actual.data_type() => {
+                Err(DruidSegmentError::SchemaMismatch {
+                    column: name.to_string(),
+                    expected: field.data_type().to_string(),
+                    actual: actual.data_type().to_string(),
+                })
+            }
+            Some(field) => Ok((field, array)),
+            None => Ok((actual, array)),
+        }
+    }
+
+    /// The schema's field for a column, if the schema has been supplied or
+    /// built and lists it. Never builds the schema.
+    fn known_field(&self, name: &str) -> Option<&Field> {
+        self.schema.get()?.field_with_name(name).ok()
+    }
+
+    /// An empty batch with the types the requested columns would have.
+    fn empty_batch(&self, columns: &[&str], options: &ReadOptions) -> Result<RecordBatch> {
+        let fields = columns
+            .iter()
+            .map(|&name| match self.known_field(name) {
+                Some(field) => Ok(read_field(field, options)),
+                None => Ok(read_field(&self.header_field(name)?, options)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RecordBatch::new_empty(self.batch_schema(fields)))
     }
 
-    /// Return the number of rows in the segment.
+    /// The schema of a batch of `fields`, with the segment schema's metadata
+    /// if it has been supplied or built, so batches match the schema.
+    fn batch_schema(&self, fields: Vec<Field>) -> SchemaRef {
+        match self.schema.get() {
+            Some(schema) => Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+            None => Arc::new(Schema::new(fields)),
+        }
+    }
+
+    /// Size in bytes of a column's logical file, or 0 if there is none.
+    fn stored_size(&self, column: &str) -> u64 {
+        self.smoosh
+            .entry(column)
+            .map_or(0, |entry| entry.size() as u64)
+    }
+
+    /// Return the number of rows in the segment, read from the header of
+    /// the `__time` column's values on the first call, without
+    /// decompressing any block, and cached after.
+    ///
+    /// If `__time` cannot be read, the count comes from the header of the
+    /// first other column that holds one, and a warning is logged.
     pub fn num_rows(&self) -> Result<usize> {
-        // Determine row count from the __time column
-        let time_data = self.smoosh.map_file("__time")?;
-        let (_, array) = column::read_column("__time", time_data)?;
-        Ok(array.len())
+        if let Some(&num_rows) = self.num_rows.get() {
+            return Ok(num_rows);
+        }
+        let num_rows = match self.time_row_count() {
+            Ok(num_rows) => num_rows,
+            Err(err) => self.fallback_row_count().ok_or(err)?,
+        };
+        Ok(*self.num_rows.get_or_init(|| num_rows))
+    }
+
+    fn time_row_count(&self) -> Result<usize> {
+        let col_data = self.smoosh.map_non_empty_file(TIME_COLUMN)?;
+        let num_rows = column::read_time_row_count(col_data)?;
+        self.mark_parsed(TIME_COLUMN);
+        Ok(num_rows)
+    }
+
+    /// The row count of the first column other than `__time` whose header
+    /// holds one.
+    fn fallback_row_count(&self) -> Option<usize> {
+        self.metadata
+            .columns
+            .iter()
+            .filter(|&c| c != TIME_COLUMN)
+            .find_map(|name| {
+                let col_data = self.smoosh.map_non_empty_file(name).ok()?;
+                let num_rows = column::read_row_count(col_data, Some(&self.smoosh)).ok()??;
+                tracing::warn!(
+                    column = name.as_str(),
+                    num_rows,
+                    "__time is unreadable, taking the row count from another column"
+                );
+                Some(num_rows)
+            })
+    }
+
+    /// Return the block layout of a numeric column's compressed values, or
+    /// `None` for other column types. No block is decompressed.
+    pub fn column_layout(&self, column: &str) -> Result<Option<BlockLayout>> {
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        column::read_block_layout(col_data)
+    }
+
+    /// Return the number of null rows in `column` if it can be read without
+    /// decoding values: from the null bitmap of a numeric column, or from
+    /// the null dictionary entry of a single-value string column.
+    pub fn null_count(&self, column: &str) -> Result<Option<usize>> {
+        let col_data = self.smoosh.map_non_empty_file(column)?;
+        column::read_null_count(col_data, Some(&self.smoosh))
+    }
+
+    /// Return the number of null rows in `column`, decoding it only when
+    /// [`null_count`](Self::null_count) cannot tell, as for multi-value
+    /// and complex columns.
+    pub fn column_null_count(&self, column: &str) -> Result<usize> {
+        match self.null_count(column)? {
+            Some(count) => Ok(count),
+            None => Ok(self.read_columns(&[column])?.column(0).null_count()),
+        }
     }
 
     /// Get a reference to the smoosh reader for direct file access.
@@ -114,16 +1304,354 @@ impl DruidSegment {
     }
 }
 
+/// The id of the segment in directory `path`, parsed from its absolute
+/// path, with the shard spec of the `descriptor.json` beside its files if
+/// there is one.
+fn segment_id_at(path: &Path) -> Option<SegmentId> {
+    let mut id = SegmentId::parse_from_path(&std::path::absolute(path).ok()?)?;
+    // A zipped segment's descriptor sits next to it, as `descriptor.json`
+    // or, beside `<partition>_index.zip`, as `<partition>_descriptor.json`
+    let descriptor = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if path.is_file() => {
+            let partition = name.strip_suffix("index.zip").unwrap_or("");
+            path.with_file_name(format!("{}descriptor.json", partition))
+        }
+        _ => path.join("descriptor.json"),
+    };
+    id.shard_spec = std::fs::read(descriptor)
+        .ok()
+        .and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok())
+        .and_then(|descriptor| descriptor.get("shardSpec").map(|spec| spec.to_string()));
+    Some(id)
+}
+
+/// The directory prefix, such as `""` or `"wikipedia/"`, of the one segment
+/// in a zip archive, found from its `meta.smoosh` entry.
+fn zip_segment_prefix<R: std::io::Read + std::io::Seek>(
+    archive: &zip::ZipArchive<R>,
+    path: &Path,
+) -> Result<String> {
+    let prefixes: Vec<&str> = archive
+        .file_names()
+        .filter_map(|name| name.strip_suffix("meta.smoosh"))
+        .filter(|prefix| prefix.is_empty() || prefix.ends_with('/'))
+        .collect();
+    match prefixes.as_slice() {
+        [prefix] => Ok(prefix.to_string()),
+        [] => Err(DruidSegmentError::InvalidData(format!(
+            "{} holds no segment: no meta.smoosh entry",
+            path.display()
+        ))),
+        _ => Err(DruidSegmentError::InvalidData(format!(
+            "{} holds {} segments, under {}",
+            path.display(),
+            prefixes.len(),
+            prefixes
+                .iter()
+                .map(|prefix| format!("'{}'", prefix))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// The logical files read for `name`: the file itself, if the archive has
+/// it, the external files of any GenericIndexed V2 in it, which Druid
+/// names after the column (`<name>.<part>_header`, `<name>_value_0`, ...),
+/// and the internal files of a nested column (`<name>.__raw`, ...).
+#[cfg(feature = "object_store")]
+fn companion_files<'s>(smoosh: &'s SmooshReader, name: &'s str) -> impl Iterator<Item = &'s str> {
+    smoosh.file_names().filter(move |&file| {
+        if file == name
+            || file
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.starts_with(".__"))
+        {
+            return true;
+        }
+        let base = file.strip_suffix("_header").or_else(|| {
+            let (base, n) = file.rsplit_once("_value_")?;
+            n.bytes().all(|b| b.is_ascii_digit()).then_some(base)
+        });
+        base.and_then(|base| base.strip_prefix(name))
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '_']))
+    })
+}
+
+/// Split `batch` into consecutive slices of at most `batch_size` rows.
+fn split_batch(batch: &RecordBatch, batch_size: usize) -> Vec<RecordBatch> {
+    if batch.num_rows() == 0 {
+        return vec![batch.clone()];
+    }
+    (0..batch.num_rows())
+        .step_by(batch_size)
+        .map(|offset| batch.slice(offset, batch_size.min(batch.num_rows() - offset)))
+        .collect()
+}
+
+/// Build the Arrow field for a column.
+///
+/// The field metadata records what the Arrow type loses: the Druid value
+/// type, the column's role, whether it is a dimension and has multiple
+/// values, and the serde types of its parts. Complex columns also carry
+/// their complex type name so consumers can interpret the bytes, and known
+/// sketches are marked with an extension name (see
+/// [`extension_name`](column::complex::extension_name)).
+fn druid_field(
+    descriptor: &ColumnDescriptor,
+    col_name: &str,
+    is_dimension: bool,
+    options: &ReadOptions,
+) -> Field {
+    if col_name == TIME_COLUMN {
+        return time_field();
+    }
+    let field = Field::new(
+        col_name,
+        druid_type_to_arrow(descriptor, col_name, options),
+        true,
+    );
+
+    let serde_types: Vec<&str> = descriptor
+        .parts
+        .iter()
+        .map(|part| part.serde_type.as_str())
+        .collect();
+    let mut metadata = HashMap::from([
+        (
+            VALUE_TYPE_KEY.to_string(),
+            descriptor.value_type.as_str().to_string(),
+        ),
+        (
+            COLUMN_ROLE_KEY.to_string(),
+            if is_dimension { "dimension" } else { "metric" }.to_string(),
+        ),
+        (IS_DIMENSION_KEY.to_string(), is_dimension.to_string()),
+        (
+            HAS_MULTIPLE_VALUES_KEY.to_string(),
+            descriptor.has_multiple_values.to_string(),
+        ),
+        (
+            column::complex::SERDE_TYPE_KEY.to_string(),
+            serde_types.join(","),
+        ),
+    ]);
+    if descriptor.value_type != ValueType::Complex {
+        return field.with_metadata(metadata);
+    }
+
+    if let Some(type_name) = column::complex::complex_type_name(descriptor) {
+        metadata.insert(
+            column::complex::COMPLEX_TYPE_KEY.to_string(),
+            type_name.to_string(),
+        );
+        let extension = if column::complex::is_nested_json(descriptor) {
+            Some(column::complex::JSON_EXTENSION_NAME)
+        } else {
+            column::complex::extension_name(type_name)
+        };
+        if let Some(extension) = extension {
+            metadata.insert(
+                column::complex::EXTENSION_NAME_KEY.to_string(),
+                extension.to_string(),
+            );
+        }
+    }
+    field.with_metadata(metadata)
+}
+
+/// The field of `__time`, whose header is not parsed to build the schema
+/// so that a segment with an unreadable `__time` can still be opened.
+fn time_field() -> Field {
+    let metadata = HashMap::from([
+        (VALUE_TYPE_KEY.to_string(), "LONG".to_string()),
+        (COLUMN_ROLE_KEY.to_string(), "time".to_string()),
+        (IS_DIMENSION_KEY.to_string(), "false".to_string()),
+        (HAS_MULTIPLE_VALUES_KEY.to_string(), "false".to_string()),
+    ]);
+    Field::new(
+        TIME_COLUMN,
+        DataType::Timestamp(TimeUnit::Millisecond, None),
+        true,
+    )
+    .with_metadata(metadata)
+}
+
+/// `field` with the type reads under `options` return for it.
+fn read_field(field: &Field, options: &ReadOptions) -> Field {
+    field
+        .clone()
+        .with_data_type(options.read_type(field.data_type().clone()))
+}
+
 /// Map a Druid ValueType to an Arrow DataType.
-fn druid_type_to_arrow(descriptor: &ColumnDescriptor, col_name: &str) -> DataType {
-    if col_name == "__time" {
+///
+/// Single-value strings and nested JSON map to `Utf8`, or to
+/// `Dictionary(Int32, Utf8)` if `options` reads strings as dictionaries.
+pub(crate) fn druid_type_to_arrow(
+    descriptor: &ColumnDescriptor,
+    col_name: &str,
+    options: &ReadOptions,
+) -> DataType {
+    if col_name == TIME_COLUMN {
         return DataType::Timestamp(TimeUnit::Millisecond, None);
     }
-    match descriptor.value_type {
+    let data_type = match descriptor.value_type {
+        ValueType::String if descriptor.has_multiple_values => {
+            DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true)))
+        }
         ValueType::String => DataType::Utf8,
         ValueType::Long => DataType::Int64,
         ValueType::Float => DataType::Float32,
         ValueType::Double => DataType::Float64,
+        ValueType::Complex if column::complex::is_nested_json(descriptor) => DataType::Utf8,
         ValueType::Complex => DataType::Binary,
+    };
+    options.read_type(data_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE_PATH: &str = "tests/fixtures/wikipedia-segment";
+
+    #[test]
+    fn test_open_parses_only_read_columns() {
+        let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+        assert!(segment.parsed_columns().is_empty());
+
+        let batch = segment.read_columns(&["channel", "added"]).unwrap();
+        assert_eq!(batch.num_rows(), 39244);
+        assert_eq!(batch.schema().field(1).data_type(), &DataType::Int64);
+        assert_eq!(segment.parsed_columns(), vec!["added", "channel"]);
+
+        // An out-of-range read builds its empty batch from the headers too
+        let options = ReadOptions::default().with_time_range(TimeRange::new(Some(0), Some(1)));
+        let empty = segment
+            .read_columns_with_options(&["delta"], &options)
+            .unwrap();
+        assert_eq!(empty.schema().field(0).data_type(), &DataType::Int64);
+        assert_eq!(segment.parsed_columns(), vec!["added", "channel", "delta"]);
+
+        let schema = segment.try_schema().unwrap();
+        assert_eq!(segment.parsed_columns().len(), schema.fields().len() - 1);
+        assert!(Arc::ptr_eq(&schema, &segment.schema()));
+    }
+
+    #[test]
+    fn test_column_descriptor() {
+        let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+        let descriptor = segment.column_descriptor("channel").unwrap();
+        assert_eq!(descriptor.value_type, ValueType::String);
+        assert!(!descriptor.has_multiple_values);
+        assert_eq!(descriptor.parts[0].serde_type, "stringDictionary");
+        assert_eq!(segment.parsed_columns(), vec!["channel"]);
+
+        assert!(matches!(
+            segment.column_descriptor("nope"),
+            Err(DruidSegmentError::LogicalFileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_projection_row_count() {
+        let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+        let all = segment.read_columns(&[]).unwrap();
+        assert_eq!((all.num_columns(), all.num_rows()), (0, 39244));
+
+        let options = ReadOptions::default().with_offset(39240).with_limit(10);
+        assert_eq!(
+            segment
+                .read_columns_with_options(&[], &options)
+                .unwrap()
+                .num_rows(),
+            4
+        );
+
+        let range = TimeRange::new(Some(1_442_019_600_000), Some(1_442_023_200_000));
+        let options = ReadOptions::default().with_time_range(range);
+        let in_range = segment
+            .read_columns_with_options(&[], &options)
+            .unwrap()
+            .num_rows();
+        let time = segment
+            .read_columns_with_options(&["__time"], &options)
+            .unwrap();
+        assert_eq!(in_range, time.num_rows());
+        let limited = segment
+            .read_columns_with_options(&[], &options.with_limit(5))
+            .unwrap();
+        assert_eq!(limited.num_rows(), 5);
+
+        // Batches of the streaming reader the table scan uses
+        let options = ReadOptions::default().with_batch_size(10_000);
+        let sizes: Vec<usize> = segment
+            .batches_with_options(&[], &options)
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .collect();
+        assert_eq!(sizes, vec![10_000, 10_000, 10_000, 9244]);
+
+        let range = TimeRange::new(Some(1_442_019_600_000), Some(1_442_023_200_000));
+        let options = options.with_time_range(range);
+        let in_range: usize = segment
+            .batches_with_options(&[], &options)
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum();
+        let expected = segment
+            .read_columns_with_options(&[], &options)
+            .unwrap()
+            .num_rows();
+        assert!(in_range > 0);
+        assert_eq!(in_range, expected);
+    }
+
+    #[test]
+    fn test_complex_field_metadata() {
+        let descriptor: ColumnDescriptor = serde_json::from_str(
+            r#"{"valueType":"COMPLEX","parts":[{"type":"complex","typeName":"hyperUnique"}]}"#,
+        )
+        .unwrap();
+        let field = druid_field(&descriptor, "unique_users", false, &ReadOptions::default());
+        assert_eq!(field.data_type(), &DataType::Binary);
+        assert_eq!(
+            field
+                .metadata()
+                .get(column::complex::EXTENSION_NAME_KEY)
+                .map(String::as_str),
+            Some(column::complex::HLL_EXTENSION_NAME)
+        );
+
+        assert_eq!(field.metadata()[column::complex::SERDE_TYPE_KEY], "complex");
+        assert_eq!(
+            field.metadata()[column::complex::COMPLEX_TYPE_KEY],
+            "hyperUnique"
+        );
+
+        let descriptor: ColumnDescriptor = serde_json::from_str(
+            r#"{"valueType":"COMPLEX","parts":[{"type":"complex","typeName":"thetaSketch"}]}"#,
+        )
+        .unwrap();
+        let field = druid_field(&descriptor, "sketch", false, &ReadOptions::default());
+        assert_eq!(
+            field.metadata()[column::complex::COMPLEX_TYPE_KEY],
+            "thetaSketch"
+        );
+        assert!(
+            !field
+                .metadata()
+                .contains_key(column::complex::EXTENSION_NAME_KEY)
+        );
+
+        let descriptor: ColumnDescriptor =
+            serde_json::from_str(r#"{"valueType":"LONG","parts":[{"type":"longV2"}]}"#).unwrap();
+        let metadata = druid_field(&descriptor, "added", false, &ReadOptions::default())
+            .metadata()
+            .clone();
+        assert_eq!(metadata[column::complex::SERDE_TYPE_KEY], "longV2");
+        assert!(!metadata.contains_key(column::complex::COMPLEX_TYPE_KEY));
+        assert!(!metadata.contains_key(column::complex::EXTENSION_NAME_KEY));
     }
 }
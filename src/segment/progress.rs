@@ -0,0 +1,48 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Receives progress events from long-running reads.
+///
+/// The library never reports progress on its own: a sink is attached to a
+/// read with [`ReadOptions::with_progress`](super::read_options::ReadOptions::with_progress),
+/// and it is up to the sink to display or record the events. Every method
+/// defaults to doing nothing.
+pub trait ProgressSink: Debug + Send + Sync {
+    /// Work is starting on `units` columns holding `total_bytes` bytes of
+    /// stored data.
+    fn start(&self, _units: usize, _total_bytes: u64) {}
+
+    /// Column `unit` has been read: `rows` rows decoded from `bytes` bytes
+    /// of stored data.
+    fn advance(&self, _unit: &str, _rows: usize, _bytes: u64) {}
+
+    /// The read is over, whether it succeeded or failed. Called once per
+    /// [`start`](Self::start).
+    fn finish(&self) {}
+}
+
+/// One read's report to a sink: started when created and finished when
+/// dropped, so a read that returns early with an error still finishes it.
+#[derive(Debug)]
+pub struct ProgressReport {
+    sink: Arc<dyn ProgressSink>,
+}
+
+impl ProgressReport {
+    /// Tell `sink` that work is starting; see [`ProgressSink::start`].
+    pub fn start(sink: &Arc<dyn ProgressSink>, units: usize, total_bytes: u64) -> Self {
+        sink.start(units, total_bytes);
+        Self { sink: sink.clone() }
+    }
+
+    /// Tell the sink that `unit` is done; see [`ProgressSink::advance`].
+    pub fn advance(&self, unit: &str, rows: usize, bytes: u64) {
+        self.sink.advance(unit, rows, bytes);
+    }
+}
+
+impl Drop for ProgressReport {
+    fn drop(&mut self) {
+        self.sink.finish();
+    }
+}
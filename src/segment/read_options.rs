@@ -0,0 +1,348 @@
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+
+use super::progress::ProgressSink;
+use crate::error::{DruidSegmentError, Result};
+
+/// Options controlling how column data is read from a segment.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Token checked between columns and between compressed blocks.
+    /// Once cancelled, reads return [`DruidSegmentError::Cancelled`].
+    pub cancellation: Option<CancellationToken>,
+    /// Only rows whose `__time` falls in this range are returned. Segments
+    /// whose interval does not overlap it are skipped without decoding.
+    pub time_range: Option<TimeRange>,
+    /// Keep rows in storage order even when a read is split across
+    /// partitions or segments. Reading a single segment in a single
+    /// partition always preserves storage order.
+    pub preserve_order: bool,
+    /// Return at most this many rows. Column readers stop decompressing
+    /// after the block that holds the last row needed.
+    pub limit: Option<usize>,
+    /// Skip this many stored rows before reading, counted before any time
+    /// range is applied. Column readers skip the compressed blocks that lie
+    /// entirely before the first row read.
+    pub offset: usize,
+    /// Split results into batches of at most this many rows. `None` uses
+    /// [`DEFAULT_BATCH_SIZE`].
+    pub batch_size: Option<usize>,
+    /// Sink told about each column as it is read.
+    pub progress: Option<Arc<dyn ProgressSink>>,
+    /// Return single-value string columns as `Dictionary(Int32, Utf8)`
+    /// arrays, keeping the segment's dictionary encoding, instead of
+    /// `Utf8` arrays with every row's string copied out.
+    pub strings_as_dictionary: bool,
+    /// Whether empty strings in string columns read as themselves or as
+    /// null.
+    pub null_handling: NullHandling,
+}
+
+/// How string columns' empty strings relate to null, mirroring Druid's
+/// `druid.generic.useDefaultValueForNull`.
+///
+/// Druid stores null as its own dictionary entry, the first. Segments
+/// written in legacy mode have no empty strings, which were stored as
+/// null; segments written in SQL-compatible mode can have both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullHandling {
+    /// The null entry reads as null and the empty string as itself, as in
+    /// Druid's SQL-compatible mode.
+    #[default]
+    SqlCompatible,
+    /// Both the null entry and the empty string read as null, as in
+    /// Druid's legacy mode.
+    Legacy,
+}
+
+impl NullHandling {
+    /// `value` as reads return it under this mode.
+    pub fn apply<S: AsRef<str>>(self, value: Option<S>) -> Option<S> {
+        match self {
+            NullHandling::SqlCompatible => value,
+            NullHandling::Legacy => value.filter(|v| !v.as_ref().is_empty()),
+        }
+    }
+}
+
+/// Rows per batch when [`ReadOptions::batch_size`] is not set.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+impl ReadOptions {
+    /// Attach a cancellation token to these options.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Restrict reads to rows whose `__time` falls in `range`.
+    pub fn with_time_range(mut self, range: TimeRange) -> Self {
+        self.time_range = Some(range);
+        self
+    }
+
+    /// Require results in storage order; see [`ReadOptions::preserve_order`].
+    pub fn with_preserve_order(mut self, preserve_order: bool) -> Self {
+        self.preserve_order = preserve_order;
+        self
+    }
+
+    /// Return at most `limit` rows.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skip the first `offset` stored rows.
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Split results into batches of at most `batch_size` rows.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Report the read's progress to `sink`.
+    pub fn with_progress(mut self, sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress = Some(sink);
+        self
+    }
+
+    /// Return single-value string columns as dictionary arrays; see
+    /// [`ReadOptions::strings_as_dictionary`].
+    pub fn with_strings_as_dictionary(mut self, strings_as_dictionary: bool) -> Self {
+        self.strings_as_dictionary = strings_as_dictionary;
+        self
+    }
+
+    /// Read empty strings as `null_handling` says.
+    pub fn with_null_handling(mut self, null_handling: NullHandling) -> Self {
+        self.null_handling = null_handling;
+        self
+    }
+
+    /// The type reads return for a column stored as `data_type`.
+    pub fn read_type(&self, data_type: DataType) -> DataType {
+        match data_type {
+            DataType::Utf8 if self.strings_as_dictionary => {
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+            }
+            other => other,
+        }
+    }
+
+    /// The schema of the batches read from a segment whose schema is
+    /// `schema`, which is returned as is unless a type changes.
+    pub fn read_schema(&self, schema: SchemaRef) -> SchemaRef {
+        if !self.strings_as_dictionary {
+            return schema;
+        }
+        let fields: Vec<Field> = schema
+            .fields()
+            .iter()
+            .map(|f| {
+                f.as_ref()
+                    .clone()
+                    .with_data_type(self.read_type(f.data_type().clone()))
+            })
+            .collect();
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone()))
+    }
+
+    /// The batch size to split results by, never zero.
+    pub fn effective_batch_size(&self) -> usize {
+        self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1)
+    }
+
+    /// How many of a column's `total` rows need decoding under the offset
+    /// and limit.
+    pub fn rows_to_decode(&self, total: usize) -> usize {
+        self.row_range(total).len()
+    }
+
+    /// The rows of a column with `total` rows that need decoding under the
+    /// offset and limit.
+    pub fn row_range(&self, total: usize) -> Range<usize> {
+        let start = self.offset.min(total);
+        let end = self
+            .limit
+            .map_or(total, |limit| start.saturating_add(limit).min(total));
+        start..end
+    }
+
+    /// Return `Err(Cancelled)` if the attached token has been cancelled.
+    pub fn check_cancelled(&self) -> Result<()> {
+        check_cancelled(self.cancellation.as_ref())
+    }
+}
+
+/// A half-open range `[start_ms, end_ms)` of `__time` values in epoch
+/// milliseconds. A missing bound is unbounded on that side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start_ms: Option<i64>,
+    pub end_ms: Option<i64>,
+}
+
+impl TimeRange {
+    /// Create a range from an inclusive start and an exclusive end.
+    pub fn new(start_ms: Option<i64>, end_ms: Option<i64>) -> Self {
+        Self { start_ms, end_ms }
+    }
+
+    /// The range covered by both `self` and `other`.
+    pub fn intersect(self, other: TimeRange) -> Self {
+        Self {
+            start_ms: match (self.start_ms, other.start_ms) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            },
+            end_ms: match (self.end_ms, other.end_ms) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+
+    /// Whether `timestamp_ms` falls inside the range.
+    pub fn contains(&self, timestamp_ms: i64) -> bool {
+        self.start_ms.is_none_or(|start| timestamp_ms >= start)
+            && self.end_ms.is_none_or(|end| timestamp_ms < end)
+    }
+
+    /// Whether the range shares any instant with the half-open interval
+    /// `[start_ms, end_ms)`, such as a segment's interval.
+    pub fn overlaps(&self, start_ms: i64, end_ms: i64) -> bool {
+        self.start_ms.is_none_or(|start| start < end_ms)
+            && self.end_ms.is_none_or(|end| end > start_ms)
+    }
+}
+
+/// A cheaply cloneable handle used to abort long-running reads.
+///
+/// Clones share the same state. A child token is cancelled when either it
+/// or its parent is cancelled, so one query can be stopped without
+/// affecting others sharing the parent.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    /// Create a new, uncancelled token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a token that is cancelled along with this one, but whose own
+    /// cancellation does not propagate back.
+    pub fn child_token(&self) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    /// Request cancellation of every read observing this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether this token or any of its ancestors has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.parent.as_ref().is_some_and(|p| p.is_cancelled())
+    }
+}
+
+/// Return `Err(Cancelled)` if `token` is present and cancelled.
+pub fn check_cancelled(token: Option<&CancellationToken>) -> Result<()> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(DruidSegmentError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_state() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+
+    #[test]
+    fn test_child_token() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let other = parent.child_token();
+        parent.cancel();
+        assert!(other.is_cancelled());
+    }
+
+    #[test]
+    fn test_time_range() {
+        let range = TimeRange::new(Some(10), None).intersect(TimeRange::new(Some(5), Some(20)));
+        assert_eq!(range, TimeRange::new(Some(10), Some(20)));
+        assert!(range.contains(10));
+        assert!(!range.contains(20));
+        assert!(range.overlaps(0, 11));
+        assert!(!range.overlaps(0, 10));
+        assert!(!range.overlaps(20, 30));
+        assert!(TimeRange::default().overlaps(i64::MIN, i64::MAX));
+    }
+
+    #[test]
+    fn test_null_handling() {
+        assert_eq!(NullHandling::SqlCompatible.apply(Some("")), Some(""));
+        assert_eq!(NullHandling::Legacy.apply(Some("")), None);
+        assert_eq!(NullHandling::Legacy.apply(Some("a")), Some("a"));
+        assert_eq!(NullHandling::Legacy.apply(None::<&str>), None);
+        assert_eq!(
+            ReadOptions::default().null_handling,
+            NullHandling::SqlCompatible
+        );
+    }
+
+    #[test]
+    fn test_row_range() {
+        let options = ReadOptions::default();
+        assert_eq!(options.row_range(10), 0..10);
+        let options = options.with_offset(4);
+        assert_eq!(options.row_range(10), 4..10);
+        assert_eq!(options.clone().with_limit(3).row_range(10), 4..7);
+        assert_eq!(options.clone().with_limit(30).row_range(10), 4..10);
+        assert_eq!(options.rows_to_decode(2), 0);
+    }
+
+    #[test]
+    fn test_check_cancelled() {
+        let options = ReadOptions::default();
+        assert!(options.check_cancelled().is_ok());
+
+        let token = CancellationToken::new();
+        let options = options.with_cancellation(token.clone());
+        assert!(options.check_cancelled().is_ok());
+        token.cancel();
+        assert!(matches!(
+            options.check_cancelled(),
+            Err(DruidSegmentError::Cancelled)
+        ));
+    }
+}
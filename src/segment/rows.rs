@@ -0,0 +1,380 @@
+use arrow::array::{
+    Array, Float32Array, Float64Array, Int64Array, ListArray, StringArray,
+    TimestampMillisecondArray,
+};
+use arrow::datatypes::{DataType, Field, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+
+use super::DruidSegment;
+use super::progress::ProgressReport;
+use super::read_options::ReadOptions;
+use crate::error::{DruidSegmentError, Result};
+
+/// One row of a segment, with typed accessors by column name.
+///
+/// A row shares the decoded window of rows it came from, so cloning it is
+/// cheap. Accessors return `None` for a null value and fail if the column
+/// was not requested or has a different type.
+#[derive(Debug, Clone)]
+pub struct Row {
+    window: RecordBatch,
+    index: usize,
+}
+
+impl Row {
+    /// Names of the row's columns, in the order they were requested.
+    pub fn column_names(&self) -> impl Iterator<Item = &str> {
+        self.window
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+    }
+
+    /// Number of columns in the row.
+    pub fn num_columns(&self) -> usize {
+        self.window.num_columns()
+    }
+
+    /// Whether the value of `column` is null.
+    pub fn is_null(&self, column: &str) -> Result<bool> {
+        Ok(self.array(column)?.is_null(self.index))
+    }
+
+    /// The value of a timestamp column such as `__time`, in epoch
+    /// milliseconds.
+    pub fn get_timestamp_ms(&self, column: &str) -> Result<Option<i64>> {
+        let array: &TimestampMillisecondArray =
+            self.typed(column, &DataType::Timestamp(TimeUnit::Millisecond, None))?;
+        Ok(self.value(array, |a, i| a.value(i)))
+    }
+
+    /// The value of a long column.
+    pub fn get_i64(&self, column: &str) -> Result<Option<i64>> {
+        let array: &Int64Array = self.typed(column, &DataType::Int64)?;
+        Ok(self.value(array, |a, i| a.value(i)))
+    }
+
+    /// The value of a float column.
+    pub fn get_f32(&self, column: &str) -> Result<Option<f32>> {
+        let array: &Float32Array = self.typed(column, &DataType::Float32)?;
+        Ok(self.value(array, |a, i| a.value(i)))
+    }
+
+    /// The value of a double column.
+    pub fn get_f64(&self, column: &str) -> Result<Option<f64>> {
+        let array: &Float64Array = self.typed(column, &DataType::Float64)?;
+        Ok(self.value(array, |a, i| a.value(i)))
+    }
+
+    /// The value of a single-value string column.
+    pub fn get_str(&self, column: &str) -> Result<Option<&str>> {
+        let array: &StringArray = self.typed(column, &DataType::Utf8)?;
+        Ok(self.value(array, |a, i| a.value(i)))
+    }
+
+    /// The values of a multi-value string column. Null dictionary entries
+    /// become `None` elements.
+    pub fn get_list(&self, column: &str) -> Result<Option<Vec<Option<String>>>> {
+        let list_type = DataType::List(Field::new_list_field(DataType::Utf8, true).into());
+        let array: &ListArray = self.typed(column, &list_type)?;
+        if array.is_null(self.index) {
+            return Ok(None);
+        }
+        let values = array.value(self.index);
+        let values = values
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!(
+                    "Column '{}' does not hold a list of strings",
+                    column
+                ))
+            })?;
+        Ok(Some(values.iter().map(|v| v.map(str::to_string)).collect()))
+    }
+
+    fn array(&self, column: &str) -> Result<&dyn Array> {
+        self.window
+            .column_by_name(column)
+            .map(|a| a.as_ref())
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidData(format!("Row has no column '{}'", column))
+            })
+    }
+
+    fn typed<A: Array + 'static>(&self, column: &str, expected: &DataType) -> Result<&A> {
+        let array = self.array(column)?;
+        array
+            .as_any()
+            .downcast_ref::<A>()
+            .ok_or_else(|| DruidSegmentError::SchemaMismatch {
+                column: column.to_string(),
+                expected: expected.to_string(),
+                actual: array.data_type().to_string(),
+            })
+    }
+
+    fn value<'a, A: Array, T>(&self, array: &'a A, get: impl Fn(&'a A, usize) -> T) -> Option<T> {
+        (!array.is_null(self.index)).then(|| get(array, self.index))
+    }
+}
+
+/// Iterator over a segment's rows in batches, returned by
+/// [`DruidSegment::batches`].
+///
+/// Each batch is decoded when the iterator reaches it, from a window of
+/// [`ReadOptions::batch_size`] stored rows, so only one window of each
+/// column is held in memory and only the compressed blocks overlapping it
+/// are decompressed. Batches hold at most that many rows, fewer where the
+/// time range drops rows; windows left empty by it are skipped. Iteration
+/// stops after the first error.
+///
+/// A progress sink in the options is started once, when the first window
+/// is read, and each window advances every column by its share of the
+/// column's stored bytes. It is finished when iteration ends, fails, or
+/// the iterator is dropped.
+#[derive(Debug)]
+pub struct BatchIter<'a> {
+    segment: SegmentRef<'a>,
+    columns: Vec<String>,
+    options: ReadOptions,
+    /// Rows stored in the segment.
+    num_rows: usize,
+    /// First stored row of the next window.
+    next_offset: usize,
+    /// Rows still to yield under the options' limit.
+    remaining: Option<usize>,
+    started: bool,
+    /// The report to the options' progress sink, once started.
+    report: Option<ProgressReport>,
+    done: bool,
+}
+
+/// A segment a [`BatchIter`] reads from, borrowed or owned.
+#[derive(Debug)]
+enum SegmentRef<'a> {
+    Borrowed(&'a DruidSegment),
+    Owned(Box<DruidSegment>),
+}
+
+impl std::ops::Deref for SegmentRef<'_> {
+    type Target = DruidSegment;
+
+    fn deref(&self) -> &DruidSegment {
+        match self {
+            SegmentRef::Borrowed(segment) => segment,
+            SegmentRef::Owned(segment) => segment,
+        }
+    }
+}
+
+impl<'a> BatchIter<'a> {
+    pub(super) fn new(
+        segment: &'a DruidSegment,
+        columns: &[&str],
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        Self::from_ref(SegmentRef::Borrowed(segment), columns, options)
+    }
+
+    fn from_ref(segment: SegmentRef<'a>, columns: &[&str], options: &ReadOptions) -> Result<Self> {
+        segment.check_columns(columns)?;
+        let skipped = options.time_range.is_some_and(|range| {
+            !range.overlaps(
+                segment.metadata.interval_start_ms,
+                segment.metadata.interval_end_ms,
+            )
+        });
+        let num_rows = segment.num_rows()?;
+        Ok(Self {
+            segment,
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            options: options.clone(),
+            num_rows,
+            next_offset: options.offset,
+            remaining: options.limit,
+            started: false,
+            report: None,
+            done: skipped,
+        })
+    }
+
+    /// Decode the next window of stored rows, filtered by the time range.
+    fn read_window(&mut self) -> Result<RecordBatch> {
+        let mut window_size = self.options.effective_batch_size();
+        if self.options.time_range.is_none()
+            && let Some(remaining) = self.remaining
+        {
+            window_size = window_size.min(remaining);
+        }
+        let window_options = ReadOptions {
+            time_range: None,
+            offset: self.next_offset,
+            limit: Some(window_size),
+            ..self.options.clone()
+        };
+        self.next_offset += window_size;
+
+        let columns: Vec<&str> = self.columns.iter().map(String::as_str).collect();
+        let window = self
+            .segment
+            .read_columns_inner(&columns, &window_options, None)?;
+        if let Some(report) = &self.report {
+            for &column in &columns {
+                let share = self.segment.stored_size(column) * window.num_rows() as u64
+                    / self.num_rows.max(1) as u64;
+                report.advance(column, window.num_rows(), share);
+            }
+        }
+        match &self.options.time_range {
+            Some(range) => self
+                .segment
+                .filter_time_range(window, &columns, range, &window_options),
+            None => Ok(window),
+        }
+    }
+
+    fn start_progress(&mut self) {
+        self.started = true;
+        if let Some(sink) = &self.options.progress {
+            let rows = self.options.row_range(self.num_rows).len() as u64;
+            let total_bytes = self
+                .columns
+                .iter()
+                .map(|c| self.segment.stored_size(c) * rows / self.num_rows.max(1) as u64)
+                .sum();
+            self.report = Some(ProgressReport::start(sink, self.columns.len(), total_bytes));
+        }
+    }
+}
+
+impl Iterator for BatchIter<'_> {
+    type Item = Result<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.start_progress();
+        }
+        while !self.done && self.remaining != Some(0) && self.next_offset < self.num_rows {
+            let window = match self.read_window() {
+                Ok(window) => window,
+                Err(e) => {
+                    self.done = true;
+                    self.report = None;
+                    return Some(Err(e));
+                }
+            };
+            let batch = match self.remaining {
+                Some(remaining) if window.num_rows() > remaining => window.slice(0, remaining),
+                _ => window,
+            };
+            if let Some(remaining) = &mut self.remaining {
+                *remaining -= batch.num_rows();
+            }
+            if batch.num_rows() > 0 {
+                return Some(Ok(batch));
+            }
+        }
+        self.report = None;
+        self.done = true;
+        None
+    }
+}
+
+/// An Arrow [`RecordBatchReader`] over every column of a segment it owns,
+/// returned by [`DruidSegment::into_batch_reader`].
+///
+/// Batches are decoded one at a time as by [`BatchIter`], and carry the
+/// segment's schema. Errors are returned as
+/// [`ArrowError::ExternalError`] unless they are Arrow errors already.
+#[derive(Debug)]
+pub struct SegmentBatchReader {
+    schema: SchemaRef,
+    batches: BatchIter<'static>,
+}
+
+impl SegmentBatchReader {
+    pub(super) fn new(segment: DruidSegment, batch_size: usize) -> Result<Self> {
+        let schema = segment.try_schema()?;
+        let columns: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        let options = ReadOptions::default().with_batch_size(batch_size);
+        let batches =
+            BatchIter::from_ref(SegmentRef::Owned(Box::new(segment)), &columns, &options)?;
+        Ok(Self { schema, batches })
+    }
+}
+
+impl Iterator for SegmentBatchReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.batches.next()?.map_err(|e| match e {
+            DruidSegmentError::ArrowError(e) => e,
+            e => ArrowError::ExternalError(Box::new(e)),
+        }))
+    }
+}
+
+impl RecordBatchReader for SegmentBatchReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Iterator over the rows of a segment, returned by
+/// [`DruidSegment::row_iter`].
+///
+/// Rows are decoded a [`BatchIter`] batch at a time, so only one window of
+/// each column is held in memory. Iteration stops after the first error.
+#[derive(Debug)]
+pub struct RowIter<'a> {
+    batches: BatchIter<'a>,
+    window: Option<RecordBatch>,
+    index: usize,
+}
+
+impl<'a> RowIter<'a> {
+    pub(super) fn new(
+        segment: &'a DruidSegment,
+        columns: &[&str],
+        options: &ReadOptions,
+    ) -> Result<Self> {
+        Ok(Self {
+            batches: BatchIter::new(segment, columns, options)?,
+            window: None,
+            index: 0,
+        })
+    }
+}
+
+impl Iterator for RowIter<'_> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(window) = &self.window
+                && self.index < window.num_rows()
+            {
+                let row = Row {
+                    window: window.clone(),
+                    index: self.index,
+                };
+                self.index += 1;
+                return Some(Ok(row));
+            }
+
+            match self.batches.next()? {
+                Ok(window) => {
+                    self.window = Some(window);
+                    self.index = 0;
+                }
+                Err(e) => {
+                    self.window = None;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,22 @@
 use std::collections::BTreeMap;
 use std::fs::File;
+use std::io::{Read, Seek, Write};
 use std::path::Path;
 
+#[cfg(feature = "async")]
+use std::io::SeekFrom;
+#[cfg(feature = "object_store")]
+use std::sync::{Arc, OnceLock};
+
+#[cfg(feature = "async")]
+use async_trait::async_trait;
+use bytes::Bytes;
 use memmap2::Mmap;
+#[cfg(feature = "object_store")]
+use object_store::{ObjectStore, path::Path as ObjectPath};
+#[cfg(feature = "async")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use zip::ZipArchive;
 
 use crate::error::{DruidSegmentError, Result};
 
@@ -18,24 +32,77 @@ pub struct SmooshEntry {
 impl SmooshEntry {
     /// Size in bytes of this logical file.
     pub fn size(&self) -> usize {
-        self.end_offset - self.start_offset
+        self.end_offset.saturating_sub(self.start_offset)
     }
 }
 
-/// Memory-mapped smoosh archive reader.
+/// The bytes of one physical chunk: a memory-mapped file, a buffer
+/// already held in memory, or the part of the chunk from `start` on that
+/// was read into memory.
+enum Chunk {
+    Mapped(Mmap),
+    Shared(Bytes),
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    Window {
+        start: usize,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Chunk {
+    /// Offset in the chunk file of the first byte of [`bytes`](Self::bytes).
+    fn start(&self) -> usize {
+        match self {
+            Chunk::Window { start, .. } => *start,
+            Chunk::Mapped(_) | Chunk::Shared(_) => 0,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Chunk::Mapped(mmap) => mmap,
+            Chunk::Shared(bytes) => bytes,
+            Chunk::Window { bytes, .. } => bytes,
+        }
+    }
+}
+
+/// Smoosh archive reader.
 ///
 /// Druid's smoosh format packs multiple logical files into a small number
 /// of physical chunk files (max 2GB each). `meta.smoosh` is a text index
 /// that maps logical file names to chunk number + byte range.
 ///
+/// Chunks are memory-mapped when opened from a directory, or held in memory
+/// when built with [`from_parts`](Self::from_parts) or
+/// [`from_bytes`](Self::from_bytes) or, with the `async` feature, read
+/// through an `AsyncSmooshSource` by `open_async`.
+///
+/// Every chunk holding a logical file is mapped by [`open`](Self::open),
+/// so a reader sees one consistent version of the segment: if the
+/// directory is later swapped out atomically (renamed over, as Druid
+/// historicals do), the mappings keep the old files alive and reads never
+/// mix old and new chunks. Modifying or truncating a chunk file in place
+/// while it is mapped is not supported. Chunk files that no logical file
+/// lives in are not required, so a segment missing such a trailing chunk
+/// still opens.
+///
 /// This mirrors Druid's Java `SmooshedFileMapper`.
 pub struct SmooshReader {
+    max_chunk_size: usize,
     entries: BTreeMap<String, SmooshEntry>,
-    mmaps: Vec<Mmap>,
+    /// One slot per declared chunk; `None` for a chunk no entry is in.
+    chunks: Vec<Option<Chunk>>,
+    /// Where the logical files of a reader from
+    /// [`open_remote`](Self::open_remote) are fetched from, which then has
+    /// no chunks.
+    #[cfg(feature = "object_store")]
+    remote: Option<RemoteFiles>,
 }
 
 impl SmooshReader {
-    /// Open a segment directory, parse `meta.smoosh`, and mmap all chunk files.
+    /// Open a segment directory, parse `meta.smoosh`, and mmap the chunk
+    /// files that hold logical files.
     pub fn open(segment_dir: &Path) -> Result<Self> {
         let meta_path = segment_dir.join("meta.smoosh");
         let meta_content = std::fs::read_to_string(&meta_path).map_err(|e| {
@@ -45,80 +112,25 @@ impl SmooshReader {
                 e
             ))
         })?;
+        let SmooshMeta {
+            max_chunk_size,
+            num_chunks,
+            entries,
+        } = parse_meta(&meta_content)?;
 
-        let mut lines = meta_content.lines();
-
-        // First line: v1,<max_chunk_size>,<num_chunks>
-        let header = lines
-            .next()
-            .ok_or_else(|| DruidSegmentError::InvalidSmooshMeta("meta.smoosh is empty".into()))?;
-        let header_parts: Vec<&str> = header.split(',').collect();
-        if header_parts.len() < 3 || header_parts[0] != "v1" {
-            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
-                "Invalid header line: '{}'",
-                header
-            )));
-        }
-        let num_chunks: usize = header_parts[2].trim().parse().map_err(|e| {
-            DruidSegmentError::InvalidSmooshMeta(format!(
-                "Invalid num_chunks '{}': {}",
-                header_parts[2], e
-            ))
-        })?;
-
-        // Parse entry lines: <name>,<chunk>,<start>,<end>
-        let mut entries = BTreeMap::new();
-        for line in lines {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-            let parts: Vec<&str> = line.split(',').collect();
-            if parts.len() < 4 {
-                return Err(DruidSegmentError::InvalidSmooshMeta(format!(
-                    "Invalid entry line: '{}'",
-                    line
-                )));
-            }
-            let name = parts[0].to_string();
-            let chunk_number: usize = parts[1].parse().map_err(|e| {
-                DruidSegmentError::InvalidSmooshMeta(format!(
-                    "Invalid chunk number '{}': {}",
-                    parts[1], e
-                ))
-            })?;
-            let start_offset: usize = parts[2].parse().map_err(|e| {
-                DruidSegmentError::InvalidSmooshMeta(format!(
-                    "Invalid start offset '{}': {}",
-                    parts[2], e
-                ))
-            })?;
-            let end_offset: usize = parts[3].parse().map_err(|e| {
-                DruidSegmentError::InvalidSmooshMeta(format!(
-                    "Invalid end offset '{}': {}",
-                    parts[3], e
-                ))
-            })?;
-
-            entries.insert(
-                name.clone(),
-                SmooshEntry {
-                    name,
-                    chunk_number,
-                    start_offset,
-                    end_offset,
-                },
-            );
-        }
-
-        // Memory-map each physical chunk file
-        let mut mmaps = Vec::with_capacity(num_chunks);
+        // Memory-map each physical chunk file some entry lives in
+        let mut chunks = Vec::with_capacity(num_chunks);
         for i in 0..num_chunks {
+            let Some(entry) = entries.values().find(|e| e.chunk_number == i) else {
+                chunks.push(None);
+                continue;
+            };
             let chunk_path = segment_dir.join(format!("{:05}.smoosh", i));
             let file = File::open(&chunk_path).map_err(|e| {
                 DruidSegmentError::InvalidSmooshMeta(format!(
-                    "Failed to open {}: {}",
+                    "Failed to open {}, which holds '{}': {}",
                     chunk_path.display(),
+                    entry.name,
                     e
                 ))
             })?;
@@ -127,39 +139,136 @@ impl SmooshReader {
             // mapped is undefined behavior, but this matches Druid's own
             // usage pattern with MappedByteBuffer.
             let mmap = unsafe { Mmap::map(&file)? };
-            mmaps.push(mmap);
+            chunks.push(Some(Chunk::Mapped(mmap)));
+        }
+
+        Ok(Self {
+            max_chunk_size,
+            entries,
+            chunks,
+            #[cfg(feature = "object_store")]
+            remote: None,
+        })
+    }
+
+    /// Build a reader from the contents of `meta.smoosh` and the bytes of
+    /// each chunk file, in chunk order, e.g. for a segment fetched from
+    /// object storage without writing it to disk.
+    pub fn from_parts(meta: &str, chunks: Vec<Vec<u8>>) -> Result<Self> {
+        Self::from_bytes(meta, chunks.into_iter().map(Bytes::from).collect())
+    }
+
+    /// Like [`from_parts`](Self::from_parts), but taking chunks as
+    /// [`Bytes`], which can share one buffer or come straight from an HTTP
+    /// or object store client without a copy.
+    pub fn from_bytes(meta: &str, chunks: Vec<Bytes>) -> Result<Self> {
+        let SmooshMeta {
+            max_chunk_size,
+            num_chunks,
+            entries,
+        } = parse_meta(meta)?;
+        if chunks.len() != num_chunks {
+            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+                "meta.smoosh declares {} chunks but {} were given",
+                num_chunks,
+                chunks.len()
+            )));
         }
+        Ok(Self {
+            max_chunk_size,
+            entries,
+            chunks: chunks.into_iter().map(|c| Some(Chunk::Shared(c))).collect(),
+            #[cfg(feature = "object_store")]
+            remote: None,
+        })
+    }
 
-        Ok(Self { entries, mmaps })
+    /// Read the smoosh archive of a zipped segment from the entries of
+    /// `archive` whose names start with `prefix`, such as `""` or
+    /// `"wikipedia/"`: its `meta.smoosh` and each chunk, read into memory.
+    pub fn from_zip<R: Read + Seek>(archive: &mut ZipArchive<R>, prefix: &str) -> Result<Self> {
+        let meta = read_zip_entry(archive, &format!("{}meta.smoosh", prefix))?;
+        let meta = String::from_utf8(meta)
+            .map_err(|_| DruidSegmentError::InvalidSmooshMeta("meta.smoosh is not UTF-8".into()))?;
+        let num_chunks = num_chunks(&meta)?;
+        let chunks = (0..num_chunks)
+            .map(|i| read_zip_entry(archive, &format!("{}{:05}.smoosh", prefix, i)))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_parts(&meta, chunks)
     }
 
     /// Return a byte slice for the named logical file.
+    ///
+    /// A reader from [`open_remote`](Self::open_remote) only maps files
+    /// already fetched with [`fetch_files`](Self::fetch_files), and fails
+    /// with [`DruidSegmentError::LogicalFileNotFetched`] for the others.
     pub fn map_file(&self, name: &str) -> Result<&[u8]> {
         let entry = self
             .entries
             .get(name)
             .ok_or_else(|| DruidSegmentError::LogicalFileNotFound(name.to_string()))?;
-
-        if entry.chunk_number >= self.mmaps.len() {
-            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
-                "Chunk {} for file '{}' is out of range (have {} chunks)",
-                entry.chunk_number,
-                name,
-                self.mmaps.len()
-            )));
+        #[cfg(feature = "object_store")]
+        if let Some(remote) = &self.remote {
+            return remote.files[name]
+                .whole
+                .get()
+                .map(|bytes| &bytes[..])
+                .ok_or_else(|| DruidSegmentError::LogicalFileNotFetched(name.to_string()));
         }
 
-        let mmap = &self.mmaps[entry.chunk_number];
-        if entry.end_offset > mmap.len() {
+        let chunk = self
+            .chunks
+            .get(entry.chunk_number)
+            .and_then(Option::as_ref)
+            .ok_or_else(|| {
+                DruidSegmentError::InvalidSmooshMeta(format!(
+                    "Chunk {} for file '{}' is out of range (have {} chunks)",
+                    entry.chunk_number,
+                    name,
+                    self.chunks.len()
+                ))
+            })?;
+        let (start, bytes) = (chunk.start(), chunk.bytes());
+        if entry.end_offset > start + bytes.len() {
             return Err(DruidSegmentError::InvalidSmooshMeta(format!(
                 "File '{}' end offset {} exceeds chunk size {}",
                 name,
                 entry.end_offset,
-                mmap.len()
+                start + bytes.len()
             )));
         }
 
-        Ok(&mmap[entry.start_offset..entry.end_offset])
+        // A window starts at or before every file in its chunk
+        Ok(&bytes[entry.start_offset - start..entry.end_offset - start])
+    }
+
+    /// Like [`map_file`](Self::map_file), but fail with
+    /// [`DruidSegmentError::EmptyLogicalFile`] for a zero-length file.
+    ///
+    /// Zero-length logical files are valid in a smoosh archive, but none of
+    /// the structures parsed from them (index.drd, columns) can be empty.
+    pub fn map_non_empty_file(&self, name: &str) -> Result<&[u8]> {
+        let data = self.map_file(name)?;
+        if data.is_empty() {
+            return Err(DruidSegmentError::EmptyLogicalFile(name.to_string()));
+        }
+        Ok(data)
+    }
+
+    /// Like [`map_non_empty_file`](Self::map_non_empty_file), but a remote
+    /// reader whose file is not fetched may return only the start of it,
+    /// as fetched by [`fetch_heads`](Self::fetch_heads). That is enough to
+    /// parse a column's header.
+    pub(crate) fn map_file_head(&self, name: &str) -> Result<&[u8]> {
+        #[cfg(feature = "object_store")]
+        if let Some(remote) = &self.remote
+            && let Some(file) = remote.files.get(name)
+            && file.whole.get().is_none()
+            && let Some(head) = file.head.get().filter(|h| !h.is_empty())
+        {
+            return Ok(head);
+        }
+        self.map_non_empty_file(name)
     }
 
     /// Iterate over all logical file names (sorted).
@@ -172,6 +281,19 @@ impl SmooshReader {
         self.entries.contains_key(name)
     }
 
+    /// Whether the named logical file can be mapped without fetching it:
+    /// always, unless the reader is from [`open_remote`](Self::open_remote).
+    pub fn is_fetched(&self, name: &str) -> bool {
+        #[cfg(feature = "object_store")]
+        if let Some(remote) = &self.remote {
+            return remote
+                .files
+                .get(name)
+                .is_some_and(|f| f.whole.get().is_some());
+        }
+        self.has_file(name)
+    }
+
     /// Get the entry metadata for a logical file.
     pub fn entry(&self, name: &str) -> Option<&SmooshEntry> {
         self.entries.get(name)
@@ -191,4 +313,807 @@ impl SmooshReader {
     pub fn is_empty(&self) -> bool {
         self.entries.is_empty()
     }
+
+    /// The chunk size limit declared in the `meta.smoosh` header, which
+    /// no logical file ends past.
+    pub fn max_chunk_size(&self) -> usize {
+        self.max_chunk_size
+    }
+
+    /// Number of physical chunk files declared in the `meta.smoosh` header,
+    /// including any that hold no logical file.
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+/// Where [`SmooshReader::open_async`] reads a segment's files from, such as
+/// an object store: each file of the segment directory (`meta.smoosh`,
+/// `00000.smoosh`, ...) is opened by name as an async, seekable reader.
+///
+/// Implemented for [`Path`], reading a local directory with
+/// [`tokio::fs::File`].
+#[cfg(feature = "async")]
+#[async_trait]
+pub trait AsyncSmooshSource: Send + Sync {
+    type Reader: AsyncRead + AsyncSeek + Unpin + Send;
+
+    /// Open the file `name` of the segment directory.
+    async fn open(&self, name: &str) -> std::io::Result<Self::Reader>;
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl AsyncSmooshSource for Path {
+    type Reader = tokio::fs::File;
+
+    async fn open(&self, name: &str) -> std::io::Result<Self::Reader> {
+        tokio::fs::File::open(self.join(name)).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl SmooshReader {
+    /// Open a segment through `source` without blocking or memory-mapping:
+    /// parse `meta.smoosh`, then read each chunk that holds logical files
+    /// into memory, from its first file to the end of its last one.
+    ///
+    /// The bytes are cached, so the reader behaves like one opened with
+    /// [`open`](Self::open) and does no further I/O.
+    pub async fn open_async<S: AsyncSmooshSource + ?Sized>(source: &S) -> Result<Self> {
+        let read_meta = async {
+            let mut content = String::new();
+            let mut reader = source.open("meta.smoosh").await?;
+            reader.read_to_string(&mut content).await?;
+            Ok::<_, std::io::Error>(content)
+        };
+        let meta_content = read_meta.await.map_err(|e| {
+            DruidSegmentError::InvalidSmooshMeta(format!("Failed to read meta.smoosh: {}", e))
+        })?;
+        let SmooshMeta {
+            max_chunk_size,
+            num_chunks,
+            entries,
+        } = parse_meta(&meta_content)?;
+
+        let mut chunks = Vec::with_capacity(num_chunks);
+        for i in 0..num_chunks {
+            let in_chunk = || entries.values().filter(|e| e.chunk_number == i);
+            let (Some(first), Some(end)) = (
+                in_chunk().min_by_key(|e| e.start_offset),
+                in_chunk().map(|e| e.end_offset).max(),
+            ) else {
+                chunks.push(None);
+                continue;
+            };
+            let chunk_name = format!("{:05}.smoosh", i);
+            // A short chunk is read as far as it goes; map_file reports the
+            // files it cuts off, as it does for a short mapped chunk
+            let read_chunk = async {
+                let mut reader = source.open(&chunk_name).await?;
+                reader
+                    .seek(SeekFrom::Start(first.start_offset as u64))
+                    .await?;
+                let mut bytes = Vec::with_capacity(end - first.start_offset);
+                reader
+                    .take((end - first.start_offset) as u64)
+                    .read_to_end(&mut bytes)
+                    .await?;
+                Ok::<_, std::io::Error>(bytes)
+            };
+            let bytes = read_chunk.await.map_err(|e| {
+                DruidSegmentError::InvalidSmooshMeta(format!(
+                    "Failed to read {}, which holds '{}': {}",
+                    chunk_name, first.name, e
+                ))
+            })?;
+            chunks.push(Some(Chunk::Window {
+                start: first.start_offset,
+                bytes,
+            }));
+        }
+
+        Ok(Self {
+            max_chunk_size,
+            entries,
+            chunks,
+            #[cfg(feature = "object_store")]
+            remote: None,
+        })
+    }
+}
+
+/// How many bytes of a column file [`SmooshReader::fetch_heads`] fetches
+/// first, which holds the header of all but the widest columns.
+#[cfg(feature = "object_store")]
+const HEAD_SIZE: usize = 4096;
+
+/// The logical files of a segment in an object store, each fetched once
+/// and kept.
+#[cfg(feature = "object_store")]
+struct RemoteFiles {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    /// One slot per entry, so that mapped files borrow from the reader.
+    files: BTreeMap<String, RemoteFile>,
+}
+
+#[cfg(feature = "object_store")]
+#[derive(Default)]
+struct RemoteFile {
+    /// The start of the file, holding its column header.
+    head: OnceLock<Bytes>,
+    whole: OnceLock<Bytes>,
+}
+
+#[cfg(feature = "object_store")]
+impl SmooshReader {
+    /// Open the segment stored under `prefix` in `store`, e.g. a directory
+    /// in S3 holding `meta.smoosh` and `00000.smoosh`, ..., fetching only
+    /// `meta.smoosh`.
+    ///
+    /// Logical files are fetched by range GETs of their chunks with
+    /// [`fetch_files`](Self::fetch_files), and kept for later reads;
+    /// mapping a file not fetched yet fails with
+    /// [`DruidSegmentError::LogicalFileNotFetched`]. Chunks are never
+    /// fetched whole.
+    pub async fn open_remote(store: Arc<dyn ObjectStore>, prefix: &ObjectPath) -> Result<Self> {
+        let meta = store
+            .get(&prefix.child("meta.smoosh"))
+            .await?
+            .bytes()
+            .await?;
+        let meta = std::str::from_utf8(&meta)
+            .map_err(|_| DruidSegmentError::InvalidSmooshMeta("meta.smoosh is not UTF-8".into()))?;
+        let SmooshMeta {
+            max_chunk_size,
+            num_chunks,
+            entries,
+        } = parse_meta(meta)?;
+        let files = entries
+            .keys()
+            .map(|name| (name.clone(), RemoteFile::default()))
+            .collect();
+
+        Ok(Self {
+            max_chunk_size,
+            entries,
+            chunks: (0..num_chunks).map(|_| None).collect(),
+            remote: Some(RemoteFiles {
+                store,
+                prefix: prefix.clone(),
+                files,
+            }),
+        })
+    }
+
+    /// Fetch the named logical files, skipping those already fetched, with
+    /// one request per chunk that coalesces nearby ranges. Does nothing
+    /// for a reader not from [`open_remote`](Self::open_remote).
+    pub async fn fetch_files<'n>(&self, names: impl IntoIterator<Item = &'n str>) -> Result<()> {
+        let Some(remote) = &self.remote else {
+            return Ok(());
+        };
+        let mut ranges = Vec::new();
+        for name in names {
+            let entry = self
+                .entries
+                .get(name)
+                .ok_or_else(|| DruidSegmentError::LogicalFileNotFound(name.to_string()))?;
+            if remote.files[name].whole.get().is_none() {
+                ranges.push((entry, entry.start_offset..entry.end_offset));
+            }
+        }
+        for (entry, bytes) in remote.fetch(ranges).await? {
+            // A concurrent fetch of the same file may have won; either is fine
+            let _ = remote.files[&entry.name].whole.set(bytes);
+        }
+        Ok(())
+    }
+
+    /// Fetch the start of each named logical file, enough for
+    /// [`map_file_head`](Self::map_file_head) to return its column header,
+    /// without fetching the rest. Files already fetched whole are skipped.
+    pub(crate) async fn fetch_heads<'n>(
+        &self,
+        names: impl IntoIterator<Item = &'n str>,
+    ) -> Result<()> {
+        let Some(remote) = &self.remote else {
+            return Ok(());
+        };
+        let mut ranges = Vec::new();
+        for name in names {
+            let entry = self
+                .entries
+                .get(name)
+                .ok_or_else(|| DruidSegmentError::LogicalFileNotFound(name.to_string()))?;
+            let file = &remote.files[name];
+            if file.whole.get().is_none() && file.head.get().is_none() {
+                let end = entry.end_offset.min(entry.start_offset + HEAD_SIZE);
+                ranges.push((entry, entry.start_offset..end));
+            }
+        }
+        let mut heads = remote.fetch(ranges).await?;
+
+        // A header longer than the first fetch is fetched again, whole
+        let longer: Vec<_> = heads
+            .iter()
+            .filter_map(|(entry, head)| {
+                let len = i32::from_be_bytes(head.get(..4)?.try_into().ok()?);
+                let end = entry.start_offset + 4 + usize::try_from(len).ok()?;
+                (end > entry.start_offset + head.len())
+                    .then(|| (*entry, entry.start_offset..end.min(entry.end_offset)))
+            })
+            .collect();
+        heads.extend(remote.fetch(longer).await?);
+        // Longer heads come last, and win
+        for (entry, head) in heads.into_iter().rev() {
+            let _ = remote.files[&entry.name].head.set(head);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "object_store")]
+impl RemoteFiles {
+    /// Fetch each byte range of its entry's chunk, concurrently across
+    /// chunks.
+    async fn fetch<'e>(
+        &self,
+        ranges: Vec<(&'e SmooshEntry, std::ops::Range<usize>)>,
+    ) -> Result<Vec<(&'e SmooshEntry, Bytes)>> {
+        let mut by_chunk: BTreeMap<usize, Vec<_>> = BTreeMap::new();
+        for (entry, range) in ranges {
+            by_chunk
+                .entry(entry.chunk_number)
+                .or_default()
+                .push((entry, range));
+        }
+        let requests = by_chunk.into_iter().map(|(chunk, ranges)| async move {
+            let location = self.prefix.child(format!("{:05}.smoosh", chunk));
+            let bytes = self
+                .store
+                .get_ranges(
+                    &location,
+                    &ranges.iter().map(|(_, r)| r.clone()).collect::<Vec<_>>(),
+                )
+                .await?;
+            Ok::<_, DruidSegmentError>(ranges.into_iter().map(|(e, _)| e).zip(bytes))
+        });
+        let fetched = futures::future::try_join_all(requests).await?;
+        Ok(fetched.into_iter().flatten().collect())
+    }
+}
+
+/// Largest chunk Druid writes: chunks are addressed with Java ints.
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = i32::MAX as usize;
+
+/// Smoosh archive writer, the counterpart of [`SmooshReader`].
+///
+/// Logical files are appended to the current chunk in the order they are
+/// added; a file that would overflow it starts a new chunk. Chunks are
+/// held in memory until [`write_to`](Self::write_to) writes them as
+/// `00000.smoosh`, `00001.smoosh`, ... next to `meta.smoosh`, or
+/// [`into_parts`](Self::into_parts) hands them to
+/// [`SmooshReader::from_parts`].
+///
+/// This mirrors Druid's Java `FileSmoosher`.
+#[derive(Debug)]
+pub struct SmooshWriter {
+    max_chunk_size: usize,
+    entries: BTreeMap<String, SmooshEntry>,
+    chunks: Vec<Vec<u8>>,
+}
+
+impl Default for SmooshWriter {
+    fn default() -> Self {
+        Self {
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            entries: BTreeMap::new(),
+            chunks: Vec::new(),
+        }
+    }
+}
+
+impl SmooshWriter {
+    /// A writer with Druid's default chunk size limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit each chunk to `max_chunk_size` bytes, at most
+    /// [`DEFAULT_MAX_CHUNK_SIZE`].
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = max_chunk_size.min(DEFAULT_MAX_CHUNK_SIZE);
+        self
+    }
+
+    /// Add a logical file holding `bytes`.
+    ///
+    /// Fails if a file of that name was already added, if the name cannot
+    /// be written to `meta.smoosh` (it contains a comma or line break), or
+    /// if the file is larger than the chunk size limit.
+    pub fn add(&mut self, name: &str, bytes: &[u8]) -> Result<()> {
+        if self.entries.contains_key(name) {
+            return Err(DruidSegmentError::DuplicateLogicalFile(name.to_string()));
+        }
+        if name.is_empty() || name.contains([',', '\n', '\r']) {
+            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+                "Invalid logical file name '{}'",
+                name
+            )));
+        }
+        if bytes.len() > self.max_chunk_size {
+            return Err(DruidSegmentError::LogicalFileTooLarge {
+                name: name.to_string(),
+                size: bytes.len(),
+                max: self.max_chunk_size,
+            });
+        }
+
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|chunk| chunk.len() + bytes.len() <= self.max_chunk_size);
+        if !fits {
+            self.chunks.push(Vec::new());
+        }
+        let chunk_number = self.chunks.len() - 1;
+        let chunk = &mut self.chunks[chunk_number];
+        let start_offset = chunk.len();
+        chunk.extend_from_slice(bytes);
+        self.entries.insert(
+            name.to_string(),
+            SmooshEntry {
+                name: name.to_string(),
+                chunk_number,
+                start_offset,
+                end_offset: chunk.len(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Add a logical file whose contents `write` produces.
+    pub fn add_with(
+        &mut self,
+        name: &str,
+        write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    ) -> Result<()> {
+        let mut bytes = Vec::new();
+        write(&mut bytes)?;
+        self.add(name, &bytes)
+    }
+
+    /// The `meta.smoosh` index of the files added so far, listed by name.
+    pub fn meta(&self) -> String {
+        let mut meta = format!("v1,{},{}\n", self.max_chunk_size, self.chunks.len());
+        for entry in self.entries.values() {
+            meta.push_str(&format!(
+                "{},{},{},{}\n",
+                entry.name, entry.chunk_number, entry.start_offset, entry.end_offset
+            ));
+        }
+        meta
+    }
+
+    /// The contents of `meta.smoosh` and of each chunk, in chunk order.
+    pub fn into_parts(self) -> (String, Vec<Vec<u8>>) {
+        (self.meta(), self.chunks)
+    }
+
+    /// Write `meta.smoosh` and the chunk files into `dir`, which must
+    /// exist.
+    pub fn write_to(self, dir: &Path) -> Result<()> {
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            std::fs::write(dir.join(format!("{:05}.smoosh", i)), chunk)?;
+        }
+        std::fs::write(dir.join("meta.smoosh"), self.meta())?;
+        Ok(())
+    }
+}
+
+/// Parse `meta.smoosh` into its chunk count and entries.
+/// The parsed contents of `meta.smoosh`.
+/// Read the whole of the zip entry `name` into memory. A missing entry
+/// fails with a not-found I/O error, as a missing file of a segment
+/// directory does.
+pub(crate) fn read_zip_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} not found in zip archive", name),
+            )
+            .into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The number of chunk files `meta.smoosh` declares.
+pub(crate) fn num_chunks(meta: &str) -> Result<usize> {
+    Ok(parse_meta(meta)?.num_chunks)
+}
+
+struct SmooshMeta {
+    max_chunk_size: usize,
+    num_chunks: usize,
+    entries: BTreeMap<String, SmooshEntry>,
+}
+
+fn parse_meta(meta_content: &str) -> Result<SmooshMeta> {
+    let mut lines = meta_content.lines();
+
+    // First line: v1,<max_chunk_size>,<num_chunks>
+    let header = lines
+        .next()
+        .ok_or_else(|| DruidSegmentError::InvalidSmooshMeta("meta.smoosh is empty".into()))?;
+    let header_parts: Vec<&str> = header.split(',').collect();
+    if header_parts.len() < 3 || header_parts[0] != "v1" {
+        return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+            "Invalid header line: '{}'",
+            header
+        )));
+    }
+    let max_chunk_size = parse_meta_number(header_parts[1].trim(), "max_chunk_size")?;
+    let num_chunks = parse_meta_number(header_parts[2].trim(), "num_chunks")?;
+
+    // Parse entry lines: <name>,<chunk>,<start>,<end>
+    let mut entries = BTreeMap::new();
+    for (line_idx, line) in lines.enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = line.split(',').collect();
+        if parts.len() < 4 {
+            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+                "Invalid entry line: '{}'",
+                line
+            )));
+        }
+        let name = parts[0].to_string();
+        let chunk_number = parse_meta_number(parts[1], "chunk number")?;
+        let start_offset = parse_meta_number(parts[2], "start offset")?;
+        let end_offset = parse_meta_number(parts[3], "end offset")?;
+
+        if chunk_number >= num_chunks {
+            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+                "Entry '{}' on line {} is in chunk {}, but the header declares only {} chunks",
+                name,
+                line_idx + 2,
+                chunk_number,
+                num_chunks
+            )));
+        }
+        if start_offset > end_offset {
+            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+                "Entry '{}' on line {} has start offset {} after end offset {}",
+                name,
+                line_idx + 2, // 1-based, after the header line
+                start_offset,
+                end_offset
+            )));
+        }
+        if end_offset > max_chunk_size {
+            return Err(DruidSegmentError::InvalidSmooshMeta(format!(
+                "Entry '{}' on line {} ends at offset {}, past the max chunk size {}",
+                name,
+                line_idx + 2,
+                end_offset,
+                max_chunk_size
+            )));
+        }
+
+        entries.insert(
+            name.clone(),
+            SmooshEntry {
+                name,
+                chunk_number,
+                start_offset,
+                end_offset,
+            },
+        );
+    }
+
+    Ok(SmooshMeta {
+        max_chunk_size,
+        num_chunks,
+        entries,
+    })
+}
+
+/// Parse a number of `meta.smoosh`, which may be past `i32::MAX` when the
+/// header allows chunks that large, as a u64 that must also fit a usize.
+fn parse_meta_number(text: &str, what: &str) -> Result<usize> {
+    let number: u64 = text.parse().map_err(|e| {
+        DruidSegmentError::InvalidSmooshMeta(format!("Invalid {} '{}': {}", what, text, e))
+    })?;
+    usize::try_from(number).map_err(|_| {
+        DruidSegmentError::InvalidSmooshMeta(format!(
+            "{} {} does not fit in this platform's address space",
+            what, number
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const META: &str = "v1,2147483647,2\nb,1,1,3\na,0,2,7\nempty,0,0,0\n";
+
+    fn chunks() -> Vec<Vec<u8>> {
+        vec![b"..hello".to_vec(), b"xok".to_vec()]
+    }
+
+    #[test]
+    fn test_from_bytes_shares_one_buffer() {
+        let buffer = Bytes::from_static(b"..helloxok");
+        let reader =
+            SmooshReader::from_bytes(META, vec![buffer.slice(..7), buffer.slice(7..)]).unwrap();
+        assert_eq!(reader.map_file("a").unwrap(), b"hello");
+        assert_eq!(reader.map_file("b").unwrap(), b"ok");
+        // The chunks are views of the buffer, not copies
+        assert_eq!(reader.map_file("a").unwrap().as_ptr(), buffer[2..].as_ptr());
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let reader = SmooshReader::from_parts(META, chunks()).unwrap();
+        assert_eq!(reader.file_names().collect::<Vec<_>>(), ["a", "b", "empty"]);
+        assert_eq!(reader.map_file("a").unwrap(), b"hello");
+        assert_eq!(reader.map_file("b").unwrap(), b"ok");
+        assert!(reader.map_file("empty").unwrap().is_empty());
+        assert!(matches!(
+            reader.map_non_empty_file("empty"),
+            Err(DruidSegmentError::EmptyLogicalFile(_))
+        ));
+        assert!(matches!(
+            reader.map_file("missing"),
+            Err(DruidSegmentError::LogicalFileNotFound(_))
+        ));
+    }
+
+    /// Segment files held in memory, read through async cursors.
+    #[cfg(feature = "async")]
+    struct MemorySource(BTreeMap<&'static str, Vec<u8>>);
+
+    #[cfg(feature = "async")]
+    #[async_trait]
+    impl AsyncSmooshSource for MemorySource {
+        type Reader = std::io::Cursor<Vec<u8>>;
+
+        async fn open(&self, name: &str) -> std::io::Result<Self::Reader> {
+            let bytes = self.0.get(name).ok_or(std::io::ErrorKind::NotFound)?;
+            Ok(std::io::Cursor::new(bytes.clone()))
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_open_async() {
+        let [chunk0, chunk1] = chunks().try_into().unwrap();
+        let mut source = MemorySource(BTreeMap::from([
+            ("meta.smoosh", META.as_bytes().to_vec()),
+            ("00000.smoosh", chunk0),
+            ("00001.smoosh", chunk1),
+        ]));
+        let reader = SmooshReader::open_async(&source).await.unwrap();
+        assert_eq!(reader.file_names().collect::<Vec<_>>(), ["a", "b", "empty"]);
+        assert_eq!(reader.map_file("a").unwrap(), b"hello");
+        assert_eq!(reader.map_file("b").unwrap(), b"ok");
+        assert!(reader.map_file("empty").unwrap().is_empty());
+        // Only the bytes from the first file of a chunk on are read
+        assert_eq!(reader.chunks[1].as_ref().unwrap().bytes(), b"ok");
+
+        // A short chunk still opens, failing reads of the files it cuts off
+        source.0.insert("00000.smoosh", b"..hel".to_vec());
+        let reader = SmooshReader::open_async(&source).await.unwrap();
+        assert!(reader.map_file("empty").unwrap().is_empty());
+        assert!(matches!(
+            reader.map_file("a"),
+            Err(DruidSegmentError::InvalidSmooshMeta(_))
+        ));
+
+        source.0.remove("00001.smoosh");
+        let err = SmooshReader::open_async(&source).await.err().unwrap();
+        let err = err.to_string();
+        assert!(
+            err.contains("00001.smoosh") && err.contains("'b'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_writer_round_trips_through_files() {
+        let mut writer = SmooshWriter::new().with_max_chunk_size(10);
+        writer.add("index.drd", b"abcd").unwrap();
+        writer.add("__time", b"efg").unwrap();
+        writer.add("empty", b"").unwrap();
+        // Does not fit after the 7 bytes already in chunk 0
+        writer.add("added", b"hijklm").unwrap();
+        writer
+            .add_with("channel", |out| out.write_all(b"nop"))
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        writer.write_to(dir.path()).unwrap();
+        let meta = std::fs::read_to_string(dir.path().join("meta.smoosh")).unwrap();
+        assert!(meta.starts_with("v1,10,2\n__time,0,4,7\n"), "{}", meta);
+
+        let reader = SmooshReader::open(dir.path()).unwrap();
+        assert_eq!(reader.len(), 5);
+        assert_eq!(reader.map_file("index.drd").unwrap(), b"abcd");
+        assert_eq!(reader.map_file("__time").unwrap(), b"efg");
+        assert!(reader.map_file("empty").unwrap().is_empty());
+        assert_eq!(reader.map_file("added").unwrap(), b"hijklm");
+        assert_eq!(reader.map_file("channel").unwrap(), b"nop");
+        let chunk = |name: &str| reader.entry(name).unwrap().chunk_number;
+        assert_eq!(
+            ["index.drd", "__time", "empty", "added", "channel"].map(chunk),
+            [0, 0, 0, 1, 1]
+        );
+        assert_eq!(reader.entry("channel").unwrap().start_offset, 6);
+    }
+
+    #[test]
+    fn test_missing_unreferenced_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("00000.smoosh"), b"abcdefg").unwrap();
+        // Two chunks declared, but only chunk 0 holds files or exists
+        let meta = "v1,10,2\n__time,0,4,7\nindex.drd,0,0,4\n";
+        std::fs::write(dir.path().join("meta.smoosh"), meta).unwrap();
+        let reader = SmooshReader::open(dir.path()).unwrap();
+        assert_eq!(reader.map_file("__time").unwrap(), b"efg");
+
+        // A missing chunk that holds a file is still an error, naming both
+        std::fs::write(
+            dir.path().join("meta.smoosh"),
+            format!("{}added,1,0,3\n", meta),
+        )
+        .unwrap();
+        let err = SmooshReader::open(dir.path()).err().unwrap().to_string();
+        assert!(
+            err.contains("00001.smoosh") && err.contains("'added'"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_header_limits() {
+        let reader = SmooshReader::from_parts(META, chunks()).unwrap();
+        assert_eq!(reader.max_chunk_size(), DEFAULT_MAX_CHUNK_SIZE);
+        assert_eq!(reader.num_chunks(), 2);
+
+        // An entry ending past the declared chunk size limit
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("00000.smoosh"), b"abcdefgh").unwrap();
+        std::fs::write(
+            dir.path().join("meta.smoosh"),
+            "v1,4,1\nindex.drd,0,0,4\n__time,0,4,8\n",
+        )
+        .unwrap();
+        let err = SmooshReader::open(dir.path()).err().unwrap();
+        assert!(
+            matches!(&err, DruidSegmentError::InvalidSmooshMeta(msg) if msg.contains("'__time'")),
+            "{}",
+            err
+        );
+
+        std::fs::write(dir.path().join("meta.smoosh"), "v1,8,1\n__time,0,4,8\n").unwrap();
+        let reader = SmooshReader::open(dir.path()).unwrap();
+        assert_eq!(reader.max_chunk_size(), 8);
+        assert_eq!(reader.num_chunks(), 1);
+
+        assert!(SmooshReader::from_parts("v1,big,0\n", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_offsets_around_i32_boundary() {
+        // Druid's default limit is i32::MAX: an entry may end there, not past
+        let meta = parse_meta("v1,2147483647,1\na,0,2147483640,2147483647\n").unwrap();
+        assert_eq!(meta.entries["a"].size(), 7);
+        let err = parse_meta("v1,2147483647,1\na,0,2147483640,2147483648\n")
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("past the max chunk size"),
+            "{}",
+            err
+        );
+
+        // A header allowing larger chunks keeps offsets past i32::MAX intact
+        let meta = parse_meta("v1,4294967296,1\nbig,0,2147483646,2147483650\n").unwrap();
+        let big = &meta.entries["big"];
+        assert_eq!((big.start_offset, big.end_offset), (2147483646, 2147483650));
+
+        for (bad, message) in [
+            ("v1,2147483647,1\na,0,-1,4\n", "Invalid start offset '-1'"),
+            (
+                "v1,2147483647,1\na,0,0,18446744073709551616\n",
+                "Invalid end offset",
+            ),
+            ("v1,2147483647,1\na,1,0,4\n", "declares only 1 chunks"),
+        ] {
+            let err = parse_meta(bad).err().unwrap();
+            assert!(err.to_string().contains(message), "{}: {}", bad, err);
+        }
+    }
+
+    /// A logical file straddling the 2 GiB mark of a sparse chunk, which
+    /// takes no disk space beyond the bytes written.
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    #[test]
+    fn test_map_file_past_2gb() {
+        use std::io::SeekFrom;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut chunk = File::create(dir.path().join("00000.smoosh")).unwrap();
+        chunk.set_len((1 << 31) + 16).unwrap();
+        chunk.seek(SeekFrom::Start((1 << 31) - 4)).unwrap();
+        chunk.write_all(b"boundary").unwrap();
+        drop(chunk);
+        std::fs::write(
+            dir.path().join("meta.smoosh"),
+            "v1,4294967296,1\nbig,0,2147483644,2147483652\nzero,0,0,4\n",
+        )
+        .unwrap();
+
+        let reader = SmooshReader::open(dir.path()).unwrap();
+        assert_eq!(reader.map_file("big").unwrap(), b"boundary");
+        assert_eq!(reader.map_file("zero").unwrap(), [0; 4]);
+    }
+
+    #[test]
+    fn test_writer_rejects_bad_files() {
+        let mut writer = SmooshWriter::new().with_max_chunk_size(4);
+        writer.add("a", b"1234").unwrap();
+        assert!(matches!(
+            writer.add("a", b"5"),
+            Err(DruidSegmentError::DuplicateLogicalFile(name)) if name == "a"
+        ));
+        let err = writer.add("big", b"12345").unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                DruidSegmentError::LogicalFileTooLarge { name, size: 5, max: 4 } if name == "big"
+            ),
+            "{}",
+            err
+        );
+        assert!(writer.add("a,b", b"").is_err());
+        assert!(writer.add("", b"").is_err());
+
+        // Failed adds leave the archive untouched
+        let (meta, chunks) = writer.into_parts();
+        let reader = SmooshReader::from_parts(&meta, chunks).unwrap();
+        assert_eq!(reader.file_names().collect::<Vec<_>>(), ["a"]);
+    }
+
+    #[test]
+    fn test_from_parts_checks_chunks() {
+        let err = SmooshReader::from_parts(META, vec![b"..hello".to_vec()])
+            .err()
+            .expect("missing chunk should be rejected");
+        assert!(err.to_string().contains("declares 2 chunks"), "{}", err);
+
+        let reader =
+            SmooshReader::from_parts("v1,2147483647,1\na,0,2,9\n", vec![b"short".to_vec()])
+                .unwrap();
+        assert!(matches!(
+            reader.map_file("a"),
+            Err(DruidSegmentError::InvalidSmooshMeta(_))
+        ));
+    }
 }
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use arrow::array::{Array, AsArray};
+use arrow::compute::{max, min};
+use arrow::datatypes::{
+    DataType, Float32Type, Float64Type, Int64Type, TimeUnit, TimestampMillisecondType,
+};
+use chrono::DateTime;
+use serde::Serialize;
+
+/// A minimum or maximum value of a column.
+///
+/// Serialized as the bare value: numbers, timestamps as epoch millis, and
+/// strings.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum StatValue {
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// Epoch milliseconds, as `__time` stores them.
+    Timestamp(i64),
+    String(String),
+}
+
+impl fmt::Display for StatValue {
+    /// The value as text, timestamps in RFC 3339 with milliseconds.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Long(v) => write!(f, "{}", v),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::Double(v) => write!(f, "{}", v),
+            Self::Timestamp(millis) => match DateTime::from_timestamp_millis(*millis) {
+                Some(time) => {
+                    f.write_str(&time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+                }
+                None => write!(f, "{}", millis),
+            },
+            Self::String(v) => f.write_str(v),
+        }
+    }
+}
+
+/// Statistics of one column, each computed the cheapest way its encoding
+/// allows:
+///
+/// - `__time` is sorted, so its min and max are its first and last rows.
+/// - String columns take min, max and distinct count from their
+///   dictionary, and their null count from its null entry, without
+///   decoding rows.
+/// - Numeric columns are decoded once.
+///
+/// Complex columns only have a null count.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    /// Smallest non-null value, or `None` if every row is null or the
+    /// column's type has no order.
+    pub min: Option<StatValue>,
+    /// Largest non-null value, like `min`.
+    pub max: Option<StatValue>,
+    pub null_count: usize,
+    /// Number of distinct non-null values, known only for string columns.
+    pub distinct_count: Option<usize>,
+}
+
+/// The min and max of a decoded numeric or time column, or `None` if it
+/// is all nulls or of another type.
+pub(crate) fn min_max(array: &dyn Array) -> Option<(StatValue, StatValue)> {
+    fn pair<T>(
+        min: Option<T>,
+        max: Option<T>,
+        value: fn(T) -> StatValue,
+    ) -> Option<(StatValue, StatValue)> {
+        Some((value(min?), value(max?)))
+    }
+    match array.data_type() {
+        DataType::Int64 => {
+            let a = array.as_primitive::<Int64Type>();
+            pair(min(a), max(a), StatValue::Long)
+        }
+        DataType::Float32 => {
+            let a = array.as_primitive::<Float32Type>();
+            pair(min(a), max(a), StatValue::Float)
+        }
+        DataType::Float64 => {
+            let a = array.as_primitive::<Float64Type>();
+            pair(min(a), max(a), StatValue::Double)
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            let a = array.as_primitive::<TimestampMillisecondType>();
+            pair(min(a), max(a), StatValue::Timestamp)
+        }
+        _ => None,
+    }
+}
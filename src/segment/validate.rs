@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+/// The outcome of [`DruidSegment::validate`](super::DruidSegment::validate):
+/// every column decoded in full, with the time each took.
+#[derive(Debug, Clone)]
+pub struct ValidationReport {
+    pub num_rows: usize,
+    /// One entry per column, in schema order.
+    pub columns: Vec<ColumnTiming>,
+}
+
+/// Wall time spent decoding one column, split by phase.
+///
+/// The phases are measured separately from the total, so they add up to
+/// slightly less than it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnTiming {
+    pub name: String,
+    /// Mapping the column's logical file, or fetching it for segments in
+    /// an object store.
+    pub map: Duration,
+    /// Decompressing the column's blocks.
+    pub decompress: Duration,
+    /// Parsing the column's header and building its Arrow array from the
+    /// decompressed blocks.
+    pub build: Duration,
+    /// The whole column, from mapping to the finished array.
+    pub total: Duration,
+}
+
+impl ValidationReport {
+    /// The columns' timings, slowest first.
+    pub fn slowest_columns(&self) -> Vec<&ColumnTiming> {
+        let mut columns: Vec<_> = self.columns.iter().collect();
+        columns.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        columns
+    }
+}
@@ -24,10 +24,21 @@ pub fn read_version(data: &[u8]) -> Result<i32> {
     Ok(version)
 }
 
+/// The contents of version.bin for a v9 segment: 9 as a big-endian i32.
+pub fn write_version() -> [u8; 4] {
+    SEGMENT_VERSION_V9.to_be_bytes()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_write_version() {
+        assert_eq!(write_version(), [0x00, 0x00, 0x00, 0x09]);
+        assert_eq!(read_version(&write_version()).unwrap(), 9);
+    }
+
     #[test]
     fn test_valid_v9() {
         let data = [0x00, 0x00, 0x00, 0x09];
@@ -0,0 +1,534 @@
+use std::ops::Range;
+use std::path::Path;
+
+use arrow::array::{Array, AsArray};
+use arrow::datatypes::{
+    DataType, Float32Type, Float64Type, Int64Type, TimeUnit, TimestampMillisecondType,
+};
+use arrow::record_batch::RecordBatch;
+use roaring::RoaringBitmap;
+
+use super::TIME_COLUMN;
+use super::aggregate_metadata::AggregateMetadata;
+use super::column_descriptor::{ByteOrder, ColumnDescriptor, ColumnPartSerde, ValueType};
+use super::metadata::{BitmapSerdeFactory, SegmentMetadata};
+use super::smoosh::{DEFAULT_MAX_CHUNK_SIZE, SmooshWriter};
+use super::version::write_version;
+use crate::column::block_writer::{BlockValue, CompressedBlockWriter};
+use crate::column::string::write_string_column;
+use crate::column::{NumericPart, write_column_file};
+use crate::compression::CompressionStrategy;
+use crate::error::{DruidSegmentError, Result};
+
+/// Writes a [`RecordBatch`] as a Druid v9 segment directory: `version.bin`,
+/// `meta.smoosh` and its chunk files, holding `index.drd`, `metadata.drd`
+/// and one logical file per column.
+///
+/// Columns are written by Arrow type:
+/// - `__time`, which must be a millisecond timestamp without nulls, as
+///   compressed longs
+/// - `Utf8` as single-value dictionary-encoded string dimensions, with
+///   roaring bitmap indexes
+/// - `Int64`, `Float64` and `Float32` as `longV2`, `doubleV2` and
+///   `floatV2` metrics, with a null bitmap when any row is null
+///
+/// Rows are stored in the order given; Druid expects them sorted by
+/// `__time`, so sort the batch first if it is not.
+#[derive(Debug, Clone)]
+pub struct SegmentWriter {
+    compression: CompressionStrategy,
+    byte_order: ByteOrder,
+    max_chunk_size: usize,
+    aggregate_metadata: AggregateMetadata,
+    /// Dimensions to list in `index.drd` instead of the string columns.
+    dimensions: Option<Vec<String>>,
+    /// Columns added already serialized, written after the batch's.
+    column_files: Vec<(String, Vec<u8>)>,
+    /// Logical files that are not columns, written last.
+    internal_files: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for SegmentWriter {
+    fn default() -> Self {
+        Self {
+            compression: CompressionStrategy::Lz4,
+            byte_order: ByteOrder::LittleEndian,
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            aggregate_metadata: AggregateMetadata {
+                aggregators: Some(Vec::new()),
+                query_granularity: Some(serde_json::json!({"type": "none"})),
+                rollup: Some(false),
+                ..AggregateMetadata::default()
+            },
+            dimensions: None,
+            column_files: Vec::new(),
+            internal_files: Vec::new(),
+        }
+    }
+}
+
+impl SegmentWriter {
+    /// A writer of LZ4-compressed, little-endian numeric columns, as Druid
+    /// writes them, for a segment that was not rolled up.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the block compression of numeric columns; only LZ4 and
+    /// uncompressed are supported.
+    pub fn with_compression(mut self, compression: CompressionStrategy) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the byte order of values inside numeric columns' blocks.
+    pub fn with_byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// Set the size limit of each smoosh chunk file.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+
+    /// Set the contents of `metadata.drd`.
+    pub fn with_aggregate_metadata(mut self, metadata: AggregateMetadata) -> Self {
+        self.aggregate_metadata = metadata;
+        self
+    }
+
+    /// List `dimensions` in `index.drd` instead of the batch's string
+    /// columns, e.g. to keep the long dimensions of a segment being
+    /// rewritten. Each must be a column of the batch other than `__time`.
+    pub fn with_dimensions(mut self, dimensions: Vec<String>) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    /// Add a column already serialized as a column's logical file (see
+    /// [`write_column_file`]), written as-is after the batch's columns.
+    /// This is how columns the writer cannot encode, such as complex
+    /// metrics, get into a segment.
+    pub fn with_column_file(mut self, name: impl Into<String>, file: Vec<u8>) -> Self {
+        self.column_files.push((name.into(), file));
+        self
+    }
+
+    /// Add a logical file that is not a column, such as one of the internal
+    /// files a nested column's header refers to (see
+    /// [`write_json_column`](crate::column::complex::nested::write_json_column)).
+    /// It is not listed in `index.drd`.
+    pub fn with_internal_file(mut self, name: impl Into<String>, file: Vec<u8>) -> Self {
+        self.internal_files.push((name.into(), file));
+        self
+    }
+
+    /// Write `batch` as a segment covering `interval` (epoch millis, end
+    /// exclusive) into `out_dir`, which is created if missing.
+    pub fn write(&self, batch: &RecordBatch, interval: Range<i64>, out_dir: &Path) -> Result<()> {
+        let smoosh = self.smoosh(batch, interval)?;
+        std::fs::create_dir_all(out_dir)?;
+        std::fs::write(out_dir.join("version.bin"), write_version())?;
+        smoosh.write_to(out_dir)
+    }
+
+    /// Serialize `batch` into the logical files of a segment covering
+    /// `interval`, without writing them anywhere.
+    pub fn smoosh(&self, batch: &RecordBatch, interval: Range<i64>) -> Result<SmooshWriter> {
+        if interval.start > interval.end {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "Segment interval {}..{} ends before it starts",
+                interval.start, interval.end
+            )));
+        }
+
+        let mut smoosh = SmooshWriter::new().with_max_chunk_size(self.max_chunk_size);
+        let mut has_time = false;
+        let mut columns = Vec::new();
+        let mut dimensions = Vec::new();
+        for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+            let name = field.name();
+            let file = if name == TIME_COLUMN {
+                has_time = true;
+                self.time_column(array.as_ref(), &interval)?
+            } else {
+                columns.push(name.clone());
+                match array.data_type() {
+                    DataType::Utf8 => {
+                        dimensions.push(name.clone());
+                        string_column(array.as_ref(), self.byte_order)?
+                    }
+                    DataType::Int64 => self.numeric_column(
+                        ValueType::Long,
+                        "longV2",
+                        array.as_primitive::<Int64Type>().values(),
+                        array.as_ref(),
+                    )?,
+                    DataType::Float64 => self.numeric_column(
+                        ValueType::Double,
+                        "doubleV2",
+                        array.as_primitive::<Float64Type>().values(),
+                        array.as_ref(),
+                    )?,
+                    DataType::Float32 => self.numeric_column(
+                        ValueType::Float,
+                        "floatV2",
+                        array.as_primitive::<Float32Type>().values(),
+                        array.as_ref(),
+                    )?,
+                    other => {
+                        return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                            "cannot write column '{}' of type {}",
+                            name, other
+                        )));
+                    }
+                }
+            };
+            smoosh.add(name, &file)?;
+        }
+        for (name, file) in &self.column_files {
+            columns.push(name.clone());
+            smoosh.add(name, file)?;
+        }
+        for (name, file) in &self.internal_files {
+            smoosh.add(name, file)?;
+        }
+        if !has_time {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "Cannot write a segment without a {} column",
+                TIME_COLUMN
+            )));
+        }
+
+        if let Some(listed) = &self.dimensions {
+            if let Some(missing) = listed.iter().find(|d| !columns.contains(d)) {
+                return Err(DruidSegmentError::InvalidData(format!(
+                    "Dimension '{}' is not a column of the batch",
+                    missing
+                )));
+            }
+            dimensions = listed.clone();
+        }
+
+        let metadata = SegmentMetadata {
+            columns,
+            dimensions,
+            interval_start_ms: interval.start,
+            interval_end_ms: interval.end,
+            bitmap_serde_factory: BitmapSerdeFactory::Roaring,
+            segment_id: None,
+        };
+        smoosh.add("index.drd", &metadata.to_bytes()?)?;
+        smoosh.add("metadata.drd", &self.aggregate_metadata.to_bytes()?)?;
+        Ok(smoosh)
+    }
+
+    /// The `__time` column: compressed longs, every one inside `interval`.
+    fn time_column(&self, array: &dyn Array, interval: &Range<i64>) -> Result<Vec<u8>> {
+        let DataType::Timestamp(TimeUnit::Millisecond, _) = array.data_type() else {
+            return Err(DruidSegmentError::UnsupportedColumnType(format!(
+                "{} must be a millisecond timestamp, not {}",
+                TIME_COLUMN,
+                array.data_type()
+            )));
+        };
+        if array.null_count() > 0 {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "{} has {} null rows",
+                TIME_COLUMN,
+                array.null_count()
+            )));
+        }
+        let times = array.as_primitive::<TimestampMillisecondType>();
+        if let Some(t) = times.values().iter().find(|t| !interval.contains(t)) {
+            return Err(DruidSegmentError::InvalidData(format!(
+                "{} value {} is outside the segment interval {}..{}",
+                TIME_COLUMN, t, interval.start, interval.end
+            )));
+        }
+        self.numeric_column(ValueType::Long, "longV2", times.values(), array)
+    }
+
+    /// A numeric column of `values`, with the nulls of `array`. Null rows
+    /// are stored as zero, as Druid does.
+    fn numeric_column<T: BlockValue + Default>(
+        &self,
+        value_type: ValueType,
+        serde_type: &str,
+        values: &[T],
+        array: &dyn Array,
+    ) -> Result<Vec<u8>> {
+        let mut nulls = RoaringBitmap::new();
+        let compressed = match array.logical_nulls() {
+            Some(validity) if validity.null_count() > 0 => {
+                nulls.extend(
+                    validity
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(row, valid)| (!valid).then_some(row as u32)),
+                );
+                let zeroed: Vec<T> = values
+                    .iter()
+                    .zip(validity.iter())
+                    .map(|(&v, valid)| if valid { v } else { T::default() })
+                    .collect();
+                self.block_writer().write(&zeroed)?
+            }
+            _ => self.block_writer().write(values)?,
+        };
+        let descriptor = descriptor(
+            value_type,
+            serde_type,
+            serde_json::json!({
+                "byteOrder": self.byte_order,
+                "bitmapSerdeFactory": {"type": "roaring"},
+            }),
+        );
+        write_column_file(&descriptor, &NumericPart::write(&compressed, &nulls))
+    }
+
+    fn block_writer<T: BlockValue>(&self) -> CompressedBlockWriter<T> {
+        CompressedBlockWriter::new()
+            .with_compression(self.compression)
+            .with_byte_order(self.byte_order)
+    }
+}
+
+/// A single-value string dimension with bitmap indexes.
+fn string_column(array: &dyn Array, byte_order: ByteOrder) -> Result<Vec<u8>> {
+    let descriptor = descriptor(
+        ValueType::String,
+        "stringDictionary",
+        serde_json::json!({
+            "bitmapSerdeFactory": {"type": "roaring"},
+            "byteOrder": byte_order,
+        }),
+    );
+    write_column_file(&descriptor, &write_string_column(array.as_string::<i32>())?)
+}
+
+/// The descriptor of a single-value column with one part serde.
+fn descriptor(
+    value_type: ValueType,
+    serde_type: &str,
+    extra: serde_json::Value,
+) -> ColumnDescriptor {
+    ColumnDescriptor {
+        value_type,
+        has_multiple_values: false,
+        parts: vec![ColumnPartSerde {
+            serde_type: serde_type.to_string(),
+            extra,
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{
+        ArrayRef, BooleanArray, Float32Array, Float64Array, Int64Array, StringArray,
+        TimestampMillisecondArray,
+    };
+    use arrow::datatypes::{Field, Schema};
+
+    use super::*;
+    use crate::segment::DruidSegment;
+    use crate::segment::smoosh::SmooshReader;
+    use crate::testing::SegmentFixtureBuilder;
+
+    const DAY: Range<i64> = 1_442_016_000_000..1_442_102_400_000;
+
+    fn batch(columns: Vec<(&str, ArrayRef)>) -> RecordBatch {
+        let fields: Vec<Field> = columns
+            .iter()
+            .map(|(name, array)| Field::new(*name, array.data_type().clone(), true))
+            .collect();
+        let arrays = columns.into_iter().map(|(_, array)| array).collect();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).unwrap()
+    }
+
+    fn times(n: i64) -> ArrayRef {
+        Arc::new(TimestampMillisecondArray::from_iter_values(
+            (0..n).map(|i| DAY.start + i * 1000),
+        ))
+    }
+
+    fn open(writer: &SegmentWriter, batch: &RecordBatch) -> DruidSegment {
+        let (meta, chunks) = writer.smoosh(batch, DAY).unwrap().into_parts();
+        DruidSegment::from_reader(SmooshReader::from_parts(&meta, chunks).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_with_nulls() {
+        let batch = batch(vec![
+            (TIME_COLUMN, times(5)),
+            (
+                "page",
+                Arc::new(StringArray::from(vec![
+                    Some("b"),
+                    None,
+                    Some(""),
+                    Some("\u{1F600}"),
+                    Some("\u{FF61}"),
+                ])),
+            ),
+            (
+                "added",
+                Arc::new(Int64Array::from(vec![
+                    Some(1),
+                    None,
+                    Some(-3),
+                    None,
+                    Some(i64::MAX),
+                ])),
+            ),
+            (
+                "ratio",
+                Arc::new(Float64Array::from(vec![
+                    Some(0.5),
+                    Some(f64::NAN),
+                    None,
+                    Some(-0.0),
+                    Some(1e300),
+                ])),
+            ),
+            (
+                "score",
+                Arc::new(Float32Array::from(vec![
+                    None,
+                    Some(1.5),
+                    Some(2.5),
+                    Some(f32::INFINITY),
+                    Some(0.0),
+                ])),
+            ),
+        ]);
+
+        for writer in [
+            SegmentWriter::new(),
+            SegmentWriter::new()
+                .with_compression(CompressionStrategy::Uncompressed)
+                .with_byte_order(ByteOrder::BigEndian),
+        ] {
+            let segment = open(&writer, &batch);
+            assert_eq!(segment.read_all().unwrap().columns(), batch.columns());
+            assert_eq!(segment.metadata().dimensions, ["page"]);
+            assert_eq!(
+                segment.metadata().columns,
+                ["page", "added", "ratio", "score"]
+            );
+            assert_eq!(segment.aggregate_metadata().unwrap().rollup, Some(false));
+
+            // The dictionary sorts like Java strings, so the surrogate pair
+            // of U+1F600 comes before U+FF61, unlike in UTF-8 byte order
+            assert_eq!(segment.dictionary_cardinality("page").unwrap(), Some(5));
+            let index = segment.string_index("page").unwrap();
+            let values: Vec<_> = (0..index.cardinality())
+                .map(|id| index.value(id).unwrap().map(|v| v.into_owned()))
+                .collect();
+            assert_eq!(
+                values,
+                [
+                    None,
+                    Some(""),
+                    Some("b"),
+                    Some("\u{1F600}"),
+                    Some("\u{FF61}")
+                ]
+                .map(|v| v.map(String::from))
+            );
+            assert_eq!(
+                segment
+                    .dimension_index("page", "b")
+                    .unwrap()
+                    .unwrap()
+                    .iter()
+                    .collect::<Vec<_>>(),
+                [0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_listed_dimensions() {
+        let fixture = SegmentFixtureBuilder::new()
+            .interval(DAY.start, DAY.end)
+            .with_string_column("page", [Some("a")])
+            .with_long_column("added", [Some(1)]);
+        let segment = fixture
+            .clone()
+            .with_writer(SegmentWriter::new().with_dimensions(vec!["added".into(), "page".into()]))
+            .build()
+            .unwrap();
+        assert_eq!(segment.metadata().dimensions, ["added", "page"]);
+
+        let unknown = SegmentWriter::new().with_dimensions(vec![TIME_COLUMN.into()]);
+        assert!(unknown.smoosh(&fixture.batch().unwrap(), DAY).is_err());
+    }
+
+    #[test]
+    fn test_empty_batch() {
+        let fixture = SegmentFixtureBuilder::new()
+            .interval(DAY.start, DAY.end)
+            .with_string_column("page", Vec::<Option<&str>>::new())
+            .with_long_column("added", []);
+        let segment = fixture.build().unwrap();
+        assert_eq!(
+            segment.read_all().unwrap().columns(),
+            fixture.batch().unwrap().columns()
+        );
+    }
+
+    #[test]
+    fn test_rejects_unwritable_batches() {
+        let writer = SegmentWriter::new();
+        let no_time = batch(vec![("added", Arc::new(Int64Array::from(vec![1])))]);
+        assert!(matches!(
+            writer.smoosh(&no_time, DAY),
+            Err(DruidSegmentError::InvalidData(_))
+        ));
+
+        let outside = batch(vec![(
+            TIME_COLUMN,
+            Arc::new(TimestampMillisecondArray::from(vec![DAY.end])) as ArrayRef,
+        )]);
+        assert!(matches!(
+            writer.smoosh(&outside, DAY),
+            Err(DruidSegmentError::InvalidData(_))
+        ));
+
+        let null_time = batch(vec![(
+            TIME_COLUMN,
+            Arc::new(TimestampMillisecondArray::from(vec![None, Some(DAY.start)])) as ArrayRef,
+        )]);
+        assert!(writer.smoosh(&null_time, DAY).is_err());
+
+        let boolean = batch(vec![
+            (TIME_COLUMN, times(1)),
+            ("flag", Arc::new(BooleanArray::from(vec![true]))),
+        ]);
+        assert!(matches!(
+            writer.smoosh(&boolean, DAY),
+            Err(DruidSegmentError::UnsupportedColumnType(_))
+        ));
+
+        let lzf = SegmentWriter::new().with_compression(CompressionStrategy::Lzf);
+        assert!(
+            lzf.smoosh(&batch(vec![(TIME_COLUMN, times(1))]), DAY)
+                .is_err()
+        );
+        let reversed = Range {
+            start: DAY.end,
+            end: DAY.start,
+        };
+        assert!(
+            writer
+                .smoosh(&batch(vec![(TIME_COLUMN, times(1))]), reversed)
+                .is_err()
+        );
+    }
+}
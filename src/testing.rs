@@ -0,0 +1,213 @@
+//! Builders for synthetic segments, for testing code that reads segments
+//! without depending on a checked-in fixture.
+//!
+//! Enabled by the `testing` feature.
+
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, Float32Array, Float64Array, Int64Array, StringArray, TimestampMillisecondArray,
+};
+use arrow::datatypes::{Field, Schema};
+use arrow::record_batch::RecordBatch;
+use tempfile::TempDir;
+
+use crate::column::complex::nested::write_json_column;
+use crate::compression::CompressionStrategy;
+use crate::error::Result;
+use crate::segment::column_descriptor::ByteOrder;
+use crate::segment::smoosh::SmooshReader;
+use crate::segment::writer::SegmentWriter;
+use crate::segment::{DruidSegment, TIME_COLUMN};
+
+/// Interval of a fixture whose interval is not set: the first day of 1970.
+pub const DEFAULT_INTERVAL: Range<i64> = 0..86_400_000;
+
+/// Builds a segment from columns of values, written with [`SegmentWriter`]:
+///
+/// ```
+/// # use druid_datafusion_bridge::testing::SegmentFixtureBuilder;
+/// let dir = SegmentFixtureBuilder::new()
+///     .with_string_column("d1", [Some("a"), None, Some("b")])
+///     .with_long_column("m1", [Some(1), Some(2), None])
+///     .build_temp_dir()
+///     .unwrap();
+/// let segment = druid_datafusion_bridge::segment::DruidSegment::open(dir.path()).unwrap();
+/// assert_eq!(segment.num_rows().unwrap(), 3);
+/// ```
+///
+/// Unless set with [`with_times`](Self::with_times), row `i` is at
+/// `interval.start + i` milliseconds.
+#[derive(Debug, Clone)]
+pub struct SegmentFixtureBuilder {
+    writer: SegmentWriter,
+    interval: Range<i64>,
+    times: Option<Vec<i64>>,
+    columns: Vec<(String, ArrayRef)>,
+    json_columns: Vec<(String, Vec<Option<serde_json::Value>>)>,
+}
+
+impl Default for SegmentFixtureBuilder {
+    fn default() -> Self {
+        Self {
+            writer: SegmentWriter::new(),
+            interval: DEFAULT_INTERVAL,
+            times: None,
+            columns: Vec::new(),
+            json_columns: Vec::new(),
+        }
+    }
+}
+
+impl SegmentFixtureBuilder {
+    /// A builder of an empty segment over [`DEFAULT_INTERVAL`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the segment's interval, in epoch millis with the end exclusive.
+    pub fn interval(mut self, start_ms: i64, end_ms: i64) -> Self {
+        self.interval = start_ms..end_ms;
+        self
+    }
+
+    /// Set the `__time` of each row, which must fall in the interval.
+    pub fn with_times(mut self, times: impl IntoIterator<Item = i64>) -> Self {
+        self.times = Some(times.into_iter().collect());
+        self
+    }
+
+    /// Add a single-value string dimension.
+    pub fn with_string_column<S: AsRef<str>>(
+        self,
+        name: &str,
+        values: impl IntoIterator<Item = Option<S>>,
+    ) -> Self {
+        self.with_column(name, Arc::new(StringArray::from_iter(values)))
+    }
+
+    /// Add a long column.
+    pub fn with_long_column(
+        self,
+        name: &str,
+        values: impl IntoIterator<Item = Option<i64>>,
+    ) -> Self {
+        self.with_column(name, Arc::new(Int64Array::from_iter(values)))
+    }
+
+    /// Add a double column.
+    pub fn with_double_column(
+        self,
+        name: &str,
+        values: impl IntoIterator<Item = Option<f64>>,
+    ) -> Self {
+        self.with_column(name, Arc::new(Float64Array::from_iter(values)))
+    }
+
+    /// Add a float column.
+    pub fn with_float_column(
+        self,
+        name: &str,
+        values: impl IntoIterator<Item = Option<f32>>,
+    ) -> Self {
+        self.with_column(name, Arc::new(Float32Array::from_iter(values)))
+    }
+
+    /// Add a column of any type [`SegmentWriter`] can write.
+    pub fn with_column(mut self, name: &str, values: ArrayRef) -> Self {
+        self.columns.push((name.to_string(), values));
+        self
+    }
+
+    /// Add a nested `COMPLEX<json>` column holding `values`, written with
+    /// [`write_json_column`](crate::column::complex::nested::write_json_column).
+    pub fn with_json_column(
+        mut self,
+        name: &str,
+        values: impl IntoIterator<Item = Option<serde_json::Value>>,
+    ) -> Self {
+        let values: Vec<_> = values.into_iter().collect();
+        self.json_columns.push((name.to_string(), values));
+        self
+    }
+
+    /// Add a column already serialized as a column's logical file, e.g. a
+    /// complex metric or deliberately corrupt data.
+    pub fn with_column_file(mut self, name: &str, file: Vec<u8>) -> Self {
+        self.writer = self.writer.with_column_file(name, file);
+        self
+    }
+
+    /// Write with `writer`, e.g. to set its compression or chunk size.
+    /// Column files already added are dropped.
+    pub fn with_writer(mut self, writer: SegmentWriter) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    /// The rows the segment holds, `__time` first.
+    pub fn batch(&self) -> Result<RecordBatch> {
+        let num_rows = match (&self.times, self.columns.first()) {
+            (Some(times), _) => times.len(),
+            (None, Some((_, values))) => values.len(),
+            (None, None) => 0,
+        };
+        let times = TimestampMillisecondArray::from(match &self.times {
+            Some(times) => times.clone(),
+            None => (0..num_rows as i64)
+                .map(|row| self.interval.start + row)
+                .collect(),
+        });
+
+        let mut fields = vec![Field::new(TIME_COLUMN, times.data_type().clone(), true)];
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(times)];
+        for (name, values) in &self.columns {
+            fields.push(Field::new(name, values.data_type().clone(), true));
+            arrays.push(values.clone());
+        }
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+    }
+
+    /// Build the segment in memory.
+    pub fn build(&self) -> Result<DruidSegment> {
+        let smoosh = self
+            .writer()?
+            .smoosh(&self.batch()?, self.interval.clone())?;
+        let (meta, chunks) = smoosh.into_parts();
+        DruidSegment::from_reader(SmooshReader::from_parts(&meta, chunks)?)
+    }
+
+    /// Write the segment into `dir`, creating it if missing.
+    pub fn build_in(&self, dir: &Path) -> Result<()> {
+        self.writer()?
+            .write(&self.batch()?, self.interval.clone(), dir)
+    }
+
+    /// The writer with the nested columns' files added.
+    fn writer(&self) -> Result<SegmentWriter> {
+        let mut writer = self.writer.clone();
+        for (name, values) in &self.json_columns {
+            let files = write_json_column(
+                name,
+                values,
+                CompressionStrategy::Lz4,
+                ByteOrder::LittleEndian,
+            )?;
+            writer = writer.with_column_file(name.as_str(), files.column);
+            for (file_name, file) in files.internal_files {
+                writer = writer.with_internal_file(file_name, file);
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Write the segment into a new temporary directory, removed when the
+    /// returned [`TempDir`] is dropped.
+    pub fn build_temp_dir(&self) -> Result<TempDir> {
+        let dir = tempfile::tempdir()?;
+        self.build_in(dir.path())?;
+        Ok(dir)
+    }
+}
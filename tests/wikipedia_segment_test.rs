@@ -1,10 +1,43 @@
 //! Integration tests using the real Wikipedia segment fixture.
 
+#[cfg(feature = "df-43")]
+extern crate datafusion_43 as datafusion;
+
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use druid_datafusion_bridge::column::generic_indexed::GenericIndexedV1;
+use arrow::array::{Array, AsArray, Int64Array, StringArray, TimestampMillisecondArray};
+use arrow::compute::concat_batches;
+use arrow::datatypes::DataType;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use datafusion::prelude::SessionContext;
+use druid_datafusion_bridge::column;
+use druid_datafusion_bridge::column::complex;
+use druid_datafusion_bridge::column::generic_indexed::{GenericIndexedV1, GenericIndexedWriter};
+use druid_datafusion_bridge::column::string::StringColumn;
+use druid_datafusion_bridge::compression::CompressionStrategy;
+use druid_datafusion_bridge::datafusion_ext::execution_plan::DruidSegmentExec;
+use druid_datafusion_bridge::datafusion_ext::table_provider::{
+    DruidSegmentTable, DruidSegmentsTable,
+};
+use druid_datafusion_bridge::error::DruidSegmentError;
+use druid_datafusion_bridge::segment::aggregate_metadata::{AggregateMetadata, OrderBy};
 use druid_datafusion_bridge::segment::column_descriptor::ColumnDescriptor;
+use druid_datafusion_bridge::segment::merge::{MergeOptions, merge_segments};
+use druid_datafusion_bridge::segment::metadata::{BitmapSerdeFactory, SegmentMetadata};
+use druid_datafusion_bridge::segment::progress::ProgressSink;
+use druid_datafusion_bridge::segment::read_options::{
+    CancellationToken, NullHandling, ReadOptions, TimeRange,
+};
 use druid_datafusion_bridge::segment::smoosh::SmooshReader;
+use druid_datafusion_bridge::segment::stats::StatValue;
+use druid_datafusion_bridge::segment::version::write_version;
+use druid_datafusion_bridge::segment::writer::SegmentWriter;
+use druid_datafusion_bridge::segment::{
+    AGGREGATOR_KEY, COLUMN_ROLE_KEY, DIMENSIONS_KEY, DruidSegment, HAS_MULTIPLE_VALUES_KEY,
+    INTERVAL_KEY, IS_DIMENSION_KEY, VALUE_TYPE_KEY,
+};
+use druid_datafusion_bridge::testing::SegmentFixtureBuilder;
 
 const FIXTURE_PATH: &str = "tests/fixtures/wikipedia-segment";
 
@@ -110,6 +143,33 @@ fn test_read_metadata_drd() {
     assert_eq!(metadata.get("rollup"), Some(&serde_json::json!(false)));
 }
 
+#[test]
+fn test_aggregate_metadata() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let metadata = segment
+        .aggregate_metadata()
+        .expect("Failed to parse metadata.drd");
+
+    // Writing it back gives the file Druid wrote
+    let written = metadata.to_bytes().unwrap();
+    assert_eq!(written, segment.smoosh().map_file("metadata.drd").unwrap());
+
+    assert_eq!(metadata.rollup, Some(false));
+    // The fixture was ingested without rollup and lists no aggregators
+    assert!(metadata.aggregators.as_ref().unwrap().is_empty());
+    assert_eq!(
+        metadata.timestamp_spec.unwrap().column.as_deref(),
+        Some("time")
+    );
+    assert_eq!(metadata.query_granularity.unwrap()["type"], "none");
+
+    let ordering = metadata.ordering.unwrap();
+    assert_eq!(ordering.len(), 20);
+    assert_eq!(ordering[0].column_name, "__time");
+    assert_eq!(ordering[1].column_name, "channel");
+    assert!(ordering.iter().all(|o| o.order == "ascending"));
+}
+
 #[test]
 fn test_read_index_drd_structure() {
     let path = Path::new(FIXTURE_PATH);
@@ -324,3 +384,2428 @@ fn test_list_all_files() {
         assert!(reader.has_file(col), "Missing column: {}", col);
     }
 }
+
+#[test]
+fn test_read_long_metric_column() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment
+        .read_columns(&["added"])
+        .expect("Failed to read added");
+
+    let added = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .expect("added should be Int64");
+
+    assert_eq!(added.len(), 39244);
+    // The fixture's metrics carry no null bitmap, so no row may be null.
+    assert_eq!(added.null_count(), 0);
+    assert_eq!(&added.values()[..5], &[36, 17, 0, 18, 18]);
+    assert_eq!(added.value(added.len() - 1), 182);
+}
+
+#[test]
+fn test_cancelled_read_all() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let token = CancellationToken::new();
+    token.cancel();
+    let options = ReadOptions::default().with_cancellation(token);
+
+    let err = segment
+        .read_all_with_options(&options)
+        .expect_err("cancelled read should fail");
+    assert!(matches!(err, DruidSegmentError::Cancelled));
+}
+
+#[test]
+fn test_read_string_column() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment
+        .read_columns(&["channel", "cityName"])
+        .expect("Failed to read string columns");
+    assert_eq!(batch.num_rows(), 39244);
+
+    let channel = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .expect("channel should be Utf8");
+    assert_eq!(channel.value(0), "#en.wikipedia");
+    assert_eq!(channel.value(1), "#ca.wikipedia");
+
+    // cityName's dictionary starts with a null entry for rows without a city
+    let city = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .expect("cityName should be Utf8");
+    assert!(city.is_null(0));
+    assert_eq!(city.value(2), "Auburn");
+}
+
+#[test]
+fn test_dimension_index() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment.read_columns(&["channel", "cityName"]).unwrap();
+    let column_rows = |idx: usize, value: &str| -> Vec<u32> {
+        let values = batch
+            .column(idx)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        (0..values.len())
+            .filter(|&i| values.is_valid(i) && values.value(i) == value)
+            .map(|i| i as u32)
+            .collect()
+    };
+
+    let en = segment
+        .dimension_index("channel", "#en.wikipedia")
+        .expect("Failed to read bitmap index")
+        .expect("#en.wikipedia should be in the dictionary");
+    assert!(en.contains(0));
+    assert_eq!(
+        en.iter().collect::<Vec<_>>(),
+        column_rows(0, "#en.wikipedia")
+    );
+
+    let auburn = segment
+        .dimension_index("cityName", "Auburn")
+        .unwrap()
+        .unwrap();
+    assert!(auburn.contains(2));
+    assert_eq!(auburn.iter().collect::<Vec<_>>(), column_rows(1, "Auburn"));
+
+    assert!(
+        segment
+            .dimension_index("channel", "#nope.wikipedia")
+            .unwrap()
+            .is_none()
+    );
+    assert!(segment.dimension_index("added", "1").is_err());
+}
+
+/// First and last (__time, channel, added) rows of the fixture in storage order.
+const FIRST_ROW: (i64, &str, i64) = (1442018818771, "#en.wikipedia", 36);
+const LAST_ROW: (i64, &str, i64) = (1442102399200, "#en.wikipedia", 182);
+
+fn row(batch: &RecordBatch, idx: usize) -> (i64, String, i64) {
+    let time = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<TimestampMillisecondArray>()
+        .expect("__time should be a timestamp");
+    let channel = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .expect("channel should be Utf8");
+    let added = batch
+        .column(2)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .expect("added should be Int64");
+    (
+        time.value(idx),
+        channel.value(idx).to_string(),
+        added.value(idx),
+    )
+}
+
+fn assert_storage_order(batch: &RecordBatch) {
+    assert_eq!(batch.num_rows(), 39244);
+    let first = row(batch, 0);
+    let last = row(batch, batch.num_rows() - 1);
+    assert_eq!((first.0, first.1.as_str(), first.2), FIRST_ROW);
+    assert_eq!((last.0, last.1.as_str(), last.2), LAST_ROW);
+}
+
+async fn sql_rows(table: DruidSegmentTable) -> RecordBatch {
+    let ctx = SessionContext::new();
+    ctx.register_table("segment", Arc::new(table)).unwrap();
+    let df = ctx
+        .sql("SELECT __time, channel, added FROM segment")
+        .await
+        .unwrap();
+    let schema = Arc::new(df.schema().as_arrow().clone());
+    let batches = df.collect().await.unwrap();
+    concat_batches(&schema, &batches).unwrap()
+}
+
+/// The number of rows of `segment` where `column IS NULL`, read with
+/// `options` through DataFusion.
+async fn sql_null_count(segment: DruidSegment, column: &str, options: ReadOptions) -> i64 {
+    let ctx = SessionContext::new();
+    let table = DruidSegmentTable::new(segment)
+        .unwrap()
+        .with_options(options);
+    ctx.register_table("segment", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql(&format!(
+            r#"SELECT count(*) FROM segment WHERE "{}" IS NULL"#,
+            column
+        ))
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap()
+        .value(0)
+}
+
+#[tokio::test]
+async fn test_null_handling_modes() {
+    // Rows of the fixture without a city point at the null entry and none
+    // is empty, so both modes agree
+    for null_handling in [NullHandling::SqlCompatible, NullHandling::Legacy] {
+        let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+        let options = ReadOptions::default().with_null_handling(null_handling);
+        assert_eq!(sql_null_count(segment, "cityName", options).await, 37091);
+    }
+
+    let values = [Some("a"), Some(""), None, Some(""), Some("b")];
+    let builder = SegmentFixtureBuilder::new().with_string_column("dim", values);
+    for (null_handling, nulls, expected) in [
+        (NullHandling::SqlCompatible, 1, values.to_vec()),
+        (
+            NullHandling::Legacy,
+            3,
+            vec![Some("a"), None, None, None, Some("b")],
+        ),
+    ] {
+        let options = ReadOptions::default().with_null_handling(null_handling);
+        let segment = builder.build().unwrap();
+        let batch = segment
+            .read_columns_with_options(&["dim"], &options)
+            .unwrap();
+        let dim = batch.column(0).as_string::<i32>();
+        assert_eq!(dim.iter().collect::<Vec<_>>(), expected);
+
+        // Dictionary arrays share one dictionary and null the keys instead
+        let dictionary = segment
+            .read_columns_with_options(&["dim"], &options.clone().with_strings_as_dictionary(true))
+            .unwrap();
+        let dictionary = arrow::compute::cast(dictionary.column(0), &DataType::Utf8).unwrap();
+        assert_eq!(dictionary.as_string::<i32>(), dim);
+
+        assert_eq!(sql_null_count(segment, "dim", options).await, nulls);
+    }
+}
+
+#[tokio::test]
+async fn test_nested_json_column() {
+    let rows = [
+        Some(serde_json::json!({"user": {"name": "ada"}, "tags": ["a", "b"]})),
+        None,
+        Some(serde_json::json!({"user": {"name": "bob"}, "score": 2.5})),
+    ];
+    let dir = SegmentFixtureBuilder::new()
+        .with_string_column("page", ["x", "y", "z"].map(Some))
+        .with_json_column("attrs", rows.clone())
+        .build_temp_dir()
+        .unwrap();
+    let segment = DruidSegment::open(dir.path()).unwrap();
+
+    let schema = segment.try_schema().unwrap();
+    let attrs = schema.field_with_name("attrs").unwrap();
+    assert_eq!(attrs.data_type(), &DataType::Utf8);
+    assert_eq!(attrs.metadata()[VALUE_TYPE_KEY], "COMPLEX");
+    assert_eq!(attrs.metadata()[complex::COMPLEX_TYPE_KEY], "json");
+    assert_eq!(
+        attrs.metadata()[complex::EXTENSION_NAME_KEY],
+        complex::JSON_EXTENSION_NAME
+    );
+    // The column's internal files are not columns
+    assert_eq!(segment.column_names().len(), 3);
+
+    let batch = segment.read_columns(&["attrs"]).unwrap();
+    let json: Vec<Option<serde_json::Value>> = batch
+        .column(0)
+        .as_string::<i32>()
+        .iter()
+        .map(|row| row.map(|row| serde_json::from_str(row).unwrap()))
+        .collect();
+    assert_eq!(json, rows);
+
+    let ctx = SessionContext::new();
+    ctx.register_table(
+        "segment",
+        Arc::new(DruidSegmentTable::new(segment).unwrap()),
+    )
+    .unwrap();
+    let batches = ctx
+        .sql(r#"SELECT page FROM segment WHERE attrs LIKE '%"name":"bob"%'"#)
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+    let pages = batch.column(0).as_string::<i32>();
+    assert_eq!(pages.iter().collect::<Vec<_>>(), vec![Some("z")]);
+}
+
+#[test]
+fn test_validate_timing() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    let report = segment.validate().unwrap();
+    assert_eq!(report.num_rows, 39244);
+    let names: Vec<_> = report.columns.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, segment.column_names());
+
+    for timing in &report.columns {
+        let phases = timing.map + timing.decompress + timing.build;
+        assert!(phases <= timing.total, "{:?}", timing);
+        // Only the gaps between phases are not counted
+        assert!(
+            timing.total - phases < std::time::Duration::from_millis(50),
+            "{:?}",
+            timing
+        );
+        assert!(timing.total > std::time::Duration::ZERO, "{:?}", timing);
+    }
+    // The fixture's numeric columns are LZ4 compressed
+    let added = report.columns.iter().find(|t| t.name == "added").unwrap();
+    assert!(added.decompress > std::time::Duration::ZERO);
+
+    let slowest = report.slowest_columns();
+    assert_eq!(slowest.len(), report.columns.len());
+    assert!(slowest.windows(2).all(|w| w[0].total >= w[1].total));
+}
+
+#[tokio::test]
+async fn test_merge_segments_queryable() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b, out) = (
+        dir.path().join("a"),
+        dir.path().join("b"),
+        dir.path().join("merged"),
+    );
+    SegmentFixtureBuilder::new()
+        .with_string_column("page", ["b", "a", "b"].map(Some))
+        .with_long_column("delta", [1, 2, 3].map(Some))
+        .with_json_column(
+            "attrs",
+            [Some(serde_json::json!({"bot": true})), None, None],
+        )
+        .build_in(&a)
+        .unwrap();
+    SegmentFixtureBuilder::new()
+        .with_string_column("page", ["c", "b"].map(Some))
+        .with_long_column("delta", [10, 20].map(Some))
+        .build_in(&b)
+        .unwrap();
+
+    let summary = merge_segments(&[a, b], &out, &MergeOptions::default()).unwrap();
+    assert_eq!(summary.output_rows, 5);
+    let segment = DruidSegment::open(&out).unwrap();
+    assert_eq!(segment.num_rows().unwrap(), 5);
+    assert_eq!(segment.dictionary_cardinality("page").unwrap(), Some(3));
+    let attrs = segment.try_schema().unwrap();
+    let attrs = attrs.field_with_name("attrs").unwrap();
+    assert_eq!(attrs.metadata()[complex::COMPLEX_TYPE_KEY], "json");
+
+    let ctx = SessionContext::new();
+    ctx.register_table(
+        "segment",
+        Arc::new(DruidSegmentTable::new(segment).unwrap()),
+    )
+    .unwrap();
+    let batches = ctx
+        .sql("SELECT page, SUM(delta) AS delta, COUNT(attrs) AS bots FROM segment GROUP BY page ORDER BY page")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+    let pages = batch.column(0).as_string::<i32>();
+    assert_eq!(
+        pages.iter().collect::<Vec<_>>(),
+        vec![Some("a"), Some("b"), Some("c")]
+    );
+    assert_eq!(
+        batch.column(1).as_ref(),
+        &Int64Array::from(vec![2, 24, 10]) as &dyn Array
+    );
+    assert_eq!(
+        batch.column(2).as_ref(),
+        &Int64Array::from(vec![0, 1, 0]) as &dyn Array
+    );
+}
+
+#[test]
+fn test_read_columns_storage_order() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment
+        .read_columns(&["__time", "channel", "added"])
+        .expect("Failed to read columns");
+    assert_storage_order(&batch);
+
+    let all = segment.read_all().expect("Failed to read all columns");
+    let batch = all
+        .project(&[
+            all.schema().index_of("__time").unwrap(),
+            all.schema().index_of("channel").unwrap(),
+            all.schema().index_of("added").unwrap(),
+        ])
+        .unwrap();
+    assert_storage_order(&batch);
+}
+
+#[tokio::test]
+async fn test_sql_storage_order() {
+    let table = DruidSegmentTable::open(Path::new(FIXTURE_PATH)).expect("Failed to open table");
+    assert_storage_order(&sql_rows(table).await);
+
+    let table = DruidSegmentTable::open(Path::new(FIXTURE_PATH))
+        .expect("Failed to open table")
+        .with_options(ReadOptions::default().with_preserve_order(true));
+    assert_storage_order(&sql_rows(table).await);
+}
+
+/// Copy the fixture into a temporary directory, rewriting meta.smoosh with
+/// `edit` applied to each entry line.
+fn fixture_with_meta(edit: impl Fn(&str) -> String) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for file in ["00000.smoosh", "version.bin", "factory.json"] {
+        std::fs::copy(Path::new(FIXTURE_PATH).join(file), dir.path().join(file)).unwrap();
+    }
+    let meta = std::fs::read_to_string(Path::new(FIXTURE_PATH).join("meta.smoosh")).unwrap();
+    let mut lines = meta.lines();
+    let mut rewritten = format!("{}\n", lines.next().unwrap());
+    for line in lines {
+        rewritten.push_str(&edit(line));
+        rewritten.push('\n');
+    }
+    std::fs::write(dir.path().join("meta.smoosh"), rewritten).unwrap();
+    dir
+}
+
+/// Rewrite the entry for `name` to cover `[start, end)` of chunk 0.
+fn set_range(line: &str, name: &str, start: usize, end: usize) -> String {
+    if line.split(',').next() == Some(name) {
+        format!("{},0,{},{}", name, start, end)
+    } else {
+        line.to_string()
+    }
+}
+
+#[test]
+fn test_smoosh_inverted_range() {
+    let dir = fixture_with_meta(|line| set_range(line, "channel", 300, 200));
+    let err = SmooshReader::open(dir.path())
+        .err()
+        .expect("inverted range should be rejected");
+    assert!(
+        matches!(err, DruidSegmentError::InvalidSmooshMeta(_)),
+        "{}",
+        err
+    );
+    let message = err.to_string();
+    assert!(message.contains("'channel'"), "{}", message);
+    assert!(message.contains("line 4"), "{}", message);
+}
+
+#[test]
+fn test_zero_length_index_drd() {
+    let dir = fixture_with_meta(|line| set_range(line, "index.drd", 0, 0));
+    let smoosh = SmooshReader::open(dir.path()).unwrap();
+    assert_eq!(smoosh.entry("index.drd").unwrap().size(), 0);
+    assert!(smoosh.map_file("index.drd").unwrap().is_empty());
+
+    let err = DruidSegment::open(dir.path()).unwrap_err();
+    assert!(
+        matches!(&err, DruidSegmentError::EmptyLogicalFile(name) if name == "index.drd"),
+        "{}",
+        err
+    );
+    assert_eq!(err.to_string(), "Logical file 'index.drd' is empty");
+}
+
+#[test]
+fn test_num_rows_falls_back_when_time_is_unreadable() {
+    // __time's entry points at channel's bytes, a string column
+    let dir = fixture_with_meta(|line| set_range(line, "__time", 193299, 276187));
+    let segment = DruidSegment::open(dir.path()).expect("Failed to open segment");
+    assert_eq!(segment.num_rows().unwrap(), 39244);
+    let err = segment.read_columns(&["__time"]).unwrap_err();
+    assert!(
+        err.to_string().contains("__time stored as a String column"),
+        "{}",
+        err
+    );
+    assert_eq!(segment.read_columns(&["added"]).unwrap().num_rows(), 39244);
+}
+
+#[test]
+fn test_rolled_up_time_column() {
+    const HOUR: i64 = 3_600_000;
+    let start = 1_442_016_000_000;
+    // Druid truncates __time to the query granularity before rolling up,
+    // so a rolled-up segment stores the bucket starts
+    let times: Vec<i64> = [0, 0, 1, 5, 23].iter().map(|h| start + h * HOUR).collect();
+    let metadata = AggregateMetadata {
+        query_granularity: Some(serde_json::json!({"type": "period", "period": "PT1H"})),
+        rollup: Some(true),
+        ..Default::default()
+    };
+    let segment = SegmentFixtureBuilder::new()
+        .interval(start, start + 24 * HOUR)
+        .with_writer(SegmentWriter::new().with_aggregate_metadata(metadata.clone()))
+        .with_times(times.clone())
+        .with_string_column("page", ["a", "b", "a", "a", "c"].map(Some))
+        .with_long_column("count", [3, 1, 2, 7, 1].map(Some))
+        .build()
+        .unwrap();
+
+    assert_eq!(segment.aggregate_metadata().unwrap(), metadata);
+    assert_eq!(segment.num_rows().unwrap(), 5);
+    let batch = segment.read_columns(&["__time"]).unwrap();
+    let time = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<TimestampMillisecondArray>()
+        .unwrap();
+    assert_eq!(time.values().to_vec(), times);
+    assert!(time.values().iter().all(|t| (t - start) % HOUR == 0));
+}
+
+#[test]
+fn test_schema_druid_metadata() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    let schema = segment.try_schema().unwrap();
+    assert_eq!(
+        schema.metadata()[INTERVAL_KEY],
+        "2015-09-12T00:00:00.000Z/2015-09-13T00:00:00.000Z"
+    );
+    let dimensions: Vec<String> = serde_json::from_str(&schema.metadata()[DIMENSIONS_KEY]).unwrap();
+    assert_eq!(dimensions, segment.metadata().dimensions);
+
+    let channel = schema.field_with_name("channel").unwrap().metadata();
+    assert_eq!(channel[VALUE_TYPE_KEY], "STRING");
+    assert_eq!(channel[IS_DIMENSION_KEY], "true");
+    assert_eq!(channel[HAS_MULTIPLE_VALUES_KEY], "false");
+    assert_eq!(channel[complex::SERDE_TYPE_KEY], "stringDictionary");
+
+    // The fixture was ingested with its long columns as dimensions
+    let added = schema.field_with_name("added").unwrap().metadata();
+    assert_eq!(added[VALUE_TYPE_KEY], "LONG");
+    assert_eq!(added[IS_DIMENSION_KEY], "true");
+    assert_eq!(added[complex::SERDE_TYPE_KEY], "longV2");
+    let time = schema.field_with_name("__time").unwrap().metadata();
+    assert_eq!(time[VALUE_TYPE_KEY], "LONG");
+    assert_eq!(time[IS_DIMENSION_KEY], "false");
+
+    // Batches carry the schema's fields, metadata included
+    let batch = segment.read_columns(&["channel", "added"]).unwrap();
+    assert_eq!(batch.schema().field(0).metadata(), channel);
+    assert_eq!(batch.schema().metadata(), schema.metadata());
+
+    // The writer stores long columns as metrics
+    let written = SegmentFixtureBuilder::new()
+        .with_string_column("channel", [Some("#en")])
+        .with_long_column("added", [Some(1)])
+        .build()
+        .unwrap();
+    let schema = written.try_schema().unwrap();
+    let added = schema.field_with_name("added").unwrap().metadata();
+    assert_eq!(added[VALUE_TYPE_KEY], "LONG");
+    assert_eq!(added[IS_DIMENSION_KEY], "false");
+    assert_eq!(schema.metadata()[DIMENSIONS_KEY], r#"["channel"]"#);
+}
+
+#[test]
+fn test_schema_column_roles() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    let schema = segment.try_schema().unwrap();
+    let role =
+        |name: &str| schema.field_with_name(name).unwrap().metadata()[COLUMN_ROLE_KEY].clone();
+    assert_eq!(role("__time"), "time");
+    assert_eq!(role("channel"), "dimension");
+    // The fixture was ingested without aggregators, its long columns as
+    // dimensions
+    assert_eq!(role("added"), "dimension");
+    assert!(
+        segment
+            .aggregate_metadata()
+            .unwrap()
+            .aggregators
+            .unwrap()
+            .is_empty()
+    );
+
+    // A rolled-up segment's metrics name their aggregators
+    let aggregators = serde_json::from_value(serde_json::json!([
+        {"type": "longSum", "name": "added", "fieldName": "added"},
+    ]))
+    .unwrap();
+    let metadata = AggregateMetadata {
+        aggregators: Some(aggregators),
+        rollup: Some(true),
+        ..Default::default()
+    };
+    let segment = SegmentFixtureBuilder::new()
+        .with_writer(SegmentWriter::new().with_aggregate_metadata(metadata))
+        .with_string_column("channel", [Some("#en")])
+        .with_long_column("added", [Some(1)])
+        .with_long_column("deleted", [Some(0)])
+        .build()
+        .unwrap();
+    let schema = segment.try_schema().unwrap();
+    let channel = schema.field_with_name("channel").unwrap().metadata();
+    assert_eq!(channel[COLUMN_ROLE_KEY], "dimension");
+    assert!(!channel.contains_key(AGGREGATOR_KEY));
+    let added = schema.field_with_name("added").unwrap().metadata();
+    assert_eq!(added[COLUMN_ROLE_KEY], "metric");
+    assert_eq!(added[AGGREGATOR_KEY], "longSum");
+    // A metric without an aggregator is still a metric
+    let deleted = schema.field_with_name("deleted").unwrap().metadata();
+    assert_eq!(deleted[COLUMN_ROLE_KEY], "metric");
+    assert!(!deleted.contains_key(AGGREGATOR_KEY));
+
+    // Columns read before the schema is built carry the same metadata
+    let segment = SegmentFixtureBuilder::new()
+        .with_long_column("added", [Some(1)])
+        .build()
+        .unwrap();
+    let batch = segment.read_columns(&["added"]).unwrap();
+    assert_eq!(
+        batch.schema().field(0).metadata()[COLUMN_ROLE_KEY],
+        "metric"
+    );
+}
+
+#[test]
+fn test_time_range_slicing() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    assert!(segment.is_time_ordered());
+    let hour = 3_600_000;
+    let start = segment.metadata().interval_start_ms;
+    let end = segment.metadata().interval_end_ms;
+    let time = segment.read_columns(&["__time"]).unwrap();
+    let time = time
+        .column(0)
+        .as_primitive::<arrow::datatypes::TimestampMillisecondType>();
+
+    for (lo, hi) in [
+        (start + 12 * hour, start + 13 * hour),
+        (start, start + hour),
+        (end - hour, end),
+        (start - hour, end + hour),
+        (end, end + hour),
+        (start + 5 * hour, start + 5 * hour),
+    ] {
+        let rows = segment.time_range_rows(lo, hi).unwrap();
+        let expected: Vec<usize> = (0..time.len())
+            .filter(|&i| (lo..hi).contains(&time.value(i)))
+            .collect();
+        assert_eq!(rows.len(), expected.len(), "{}..{}", lo, hi);
+        if let Some(&first) = expected.first() {
+            assert_eq!(rows.start, first);
+        }
+
+        let batch = segment
+            .read_time_range(&["__time", "page"], lo, hi)
+            .unwrap();
+        let filtered = segment
+            .read_columns_with_options(
+                &["__time", "page"],
+                &ReadOptions::default().with_time_range(TimeRange::new(Some(lo), Some(hi))),
+            )
+            .unwrap();
+        assert_eq!(batch, filtered, "{}..{}", lo, hi);
+    }
+    assert_eq!(segment.time_range_rows(start, end).unwrap(), 0..39244);
+
+    let err = segment.read_time_range(&["pages"], start, end).unwrap_err();
+    assert!(matches!(err, DruidSegmentError::UnknownColumn { .. }));
+}
+
+#[test]
+fn test_time_range_slicing_unordered_segment() {
+    // Sorted by page first, so __time is not in order
+    let metadata = AggregateMetadata {
+        ordering: Some(vec![
+            OrderBy {
+                column_name: "page".into(),
+                order: "ascending".into(),
+            },
+            OrderBy {
+                column_name: "__time".into(),
+                order: "ascending".into(),
+            },
+        ]),
+        ..Default::default()
+    };
+    let segment = SegmentFixtureBuilder::new()
+        .with_writer(SegmentWriter::new().with_aggregate_metadata(metadata))
+        .with_times([5, 30, 10, 20, 40])
+        .with_string_column("page", ["a", "a", "b", "b", "c"].map(Some))
+        .build()
+        .unwrap();
+    assert!(!segment.is_time_ordered());
+
+    // The smallest range holding every row in time, and one other row
+    assert_eq!(segment.time_range_rows(10, 31).unwrap(), 1..4);
+    assert_eq!(segment.time_range_rows(100, 200).unwrap(), 0..0);
+    let batch = segment.read_time_range(&["page"], 10, 31).unwrap();
+    let pages = batch.column(0).as_string::<i32>();
+    assert_eq!(
+        pages.iter().collect::<Vec<_>>(),
+        [Some("a"), Some("b"), Some("b")]
+    );
+}
+
+#[test]
+fn test_time_stats_unordered_segment() {
+    // Sorted by page first, so the first and last rows are not the
+    // earliest and latest
+    let metadata = AggregateMetadata {
+        ordering: Some(vec![OrderBy {
+            column_name: "page".into(),
+            order: "ascending".into(),
+        }]),
+        ..Default::default()
+    };
+    let segment = SegmentFixtureBuilder::new()
+        .with_writer(SegmentWriter::new().with_aggregate_metadata(metadata))
+        .with_times([20, 5, 40, 10])
+        .with_string_column("page", ["a", "b", "c", "d"].map(Some))
+        .build()
+        .unwrap();
+    assert!(!segment.is_time_ordered());
+
+    let stats = segment.column_stats("__time").unwrap();
+    assert_eq!(stats.min, Some(StatValue::Timestamp(5)));
+    assert_eq!(stats.max, Some(StatValue::Timestamp(40)));
+    assert_eq!(stats.null_count, 0);
+}
+
+#[test]
+fn test_zero_length_column_file() {
+    let dir = fixture_with_meta(|line| set_range(line, "added", 100, 100));
+    // Opening parses no column header, so the empty file is only reported
+    // once the schema is built or the column read
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    for err in [
+        segment.try_schema().unwrap_err(),
+        segment.read_columns(&["added"]).unwrap_err(),
+    ] {
+        assert!(
+            matches!(&err, DruidSegmentError::EmptyLogicalFile(name) if name == "added"),
+            "{}",
+            err
+        );
+    }
+    assert!(DruidSegmentTable::open(dir.path()).is_err());
+
+    // Every constructor that needs the schema fails instead of panicking
+    let segment = Arc::new(DruidSegment::open(dir.path()).unwrap());
+    assert!(DruidSegmentTable::from_arc(segment.clone()).is_err());
+    assert!(DruidSegmentExec::new(segment.clone(), None).is_err());
+    let fixture = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    assert!(DruidSegmentsTable::new(vec![fixture, segment]).is_err());
+    assert!(matches!(
+        DruidSegmentExec::with_segments(Vec::new(), None, ReadOptions::default()),
+        Err(DruidSegmentError::NoSegments(_))
+    ));
+}
+
+#[test]
+fn test_row_iter_matches_read_columns() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let columns = ["added", "__time", "cityName", "channel"];
+    let batch = segment.read_columns(&columns).unwrap();
+    let added = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    let time = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<TimestampMillisecondArray>()
+        .unwrap();
+    let city = batch
+        .column(2)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let channel = batch
+        .column(3)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+
+    // Small windows so the first rows span several of them
+    let options = ReadOptions::default().with_batch_size(700);
+    let rows = segment.row_iter_with_options(&columns, &options).unwrap();
+    let mut count = 0;
+    for (i, row) in rows.take(2500).enumerate() {
+        let row = row.unwrap();
+        assert_eq!(row.column_names().collect::<Vec<_>>(), columns);
+        assert_eq!(
+            row.get_i64("added").unwrap(),
+            Some(added.value(i)),
+            "row {i}"
+        );
+        assert_eq!(row.get_timestamp_ms("__time").unwrap(), Some(time.value(i)));
+        assert_eq!(
+            row.get_str("cityName").unwrap(),
+            city.is_valid(i).then(|| city.value(i))
+        );
+        assert_eq!(row.get_str("channel").unwrap(), Some(channel.value(i)));
+        count += 1;
+    }
+    assert_eq!(count, 2500);
+
+    let row = segment.row_iter(&columns).unwrap().next().unwrap().unwrap();
+    assert!(matches!(
+        row.get_str("added"),
+        Err(DruidSegmentError::SchemaMismatch { .. })
+    ));
+    assert!(row.get_i64("delta").is_err());
+    assert!(segment.row_iter(&["no_such_column"]).is_err());
+}
+
+#[test]
+fn test_row_iter_offset_and_limit() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    assert_eq!(segment.row_iter(&["added"]).unwrap().count(), 39244);
+
+    let expected = segment.read_columns(&["added"]).unwrap();
+    let expected = expected
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    let options = ReadOptions::default()
+        .with_offset(39000)
+        .with_limit(300)
+        .with_batch_size(100);
+    let added: Vec<Option<i64>> = segment
+        .row_iter_with_options(&["added"], &options)
+        .unwrap()
+        .map(|row| row.unwrap().get_i64("added").unwrap())
+        .collect();
+    assert_eq!(added.len(), 244);
+    assert_eq!(added, expected.slice(39000, 244).iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_batches_stream_segment() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let all = segment.read_all().unwrap();
+
+    // 39244 rows is not a multiple of the batch size
+    let batches: Vec<RecordBatch> = segment
+        .batches(None, 10_000)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let sizes: Vec<usize> = batches.iter().map(|b| b.num_rows()).collect();
+    assert_eq!(sizes, [10_000, 10_000, 10_000, 9_244]);
+    assert_eq!(concat_batches(&all.schema(), &batches).unwrap(), all);
+
+    // A batch size past the segment's rows yields one batch
+    let batches: Vec<RecordBatch> = segment
+        .batches(Some(&["channel", "added"]), 100_000)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(
+        batches[0],
+        segment.read_columns(&["channel", "added"]).unwrap()
+    );
+
+    assert!(segment.batches(Some(&["no_such_column"]), 10).is_err());
+}
+
+#[test]
+fn test_into_batch_reader() {
+    let all = DruidSegment::open(Path::new(FIXTURE_PATH))
+        .unwrap()
+        .read_all()
+        .unwrap();
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let reader = segment.into_batch_reader(15_000).unwrap();
+    assert_eq!(RecordBatchReader::schema(&reader), all.schema());
+
+    // Drive it as any Arrow consumer would, through a boxed reader
+    let reader: Box<dyn RecordBatchReader + Send> = Box::new(reader);
+    let schema = reader.schema();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    let sizes: Vec<usize> = batches.iter().map(|b| b.num_rows()).collect();
+    assert_eq!(sizes, [15_000, 15_000, 9_244]);
+    assert_eq!(concat_batches(&schema, &batches).unwrap(), all);
+}
+
+#[test]
+fn test_batches_decode_lazily() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let token = CancellationToken::new();
+    let options = ReadOptions::default()
+        .with_batch_size(8192)
+        .with_cancellation(token.clone());
+    let mut batches = segment.batches_with_options(&["added"], &options).unwrap();
+    assert_eq!(batches.next().unwrap().unwrap().num_rows(), 8192);
+
+    // Later batches are only decoded when reached
+    token.cancel();
+    assert!(matches!(
+        batches.next(),
+        Some(Err(DruidSegmentError::Cancelled))
+    ));
+    assert!(batches.next().is_none());
+
+    let empty = SegmentFixtureBuilder::new()
+        .with_long_column("added", [])
+        .build()
+        .unwrap();
+    assert_eq!(empty.batches(None, 10).unwrap().count(), 0);
+}
+
+#[test]
+fn test_filter_rows_matches_scan() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let rows = segment.filter_rows("channel", "#en.wikipedia").unwrap();
+    // Only the index was read, not the values
+    assert_eq!(segment.parsed_columns(), vec!["channel".to_string()]);
+
+    let batch = segment.read_columns(&["channel"]).unwrap();
+    let channel = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let scanned: Vec<u32> = (0..channel.len())
+        .filter(|&i| channel.is_valid(i) && channel.value(i) == "#en.wikipedia")
+        .map(|i| i as u32)
+        .collect();
+    assert!(!scanned.is_empty());
+    assert_eq!(rows.len() as usize, scanned.len());
+    assert_eq!(rows.iter().collect::<Vec<_>>(), scanned);
+
+    assert!(
+        segment
+            .filter_rows("channel", "#xx.wikipedia")
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_rows_matching_and_read_rows() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let en = segment.rows_matching("channel", "#en.wikipedia").unwrap();
+    let de = segment.rows_matching("channel", "#de.wikipedia").unwrap();
+    let any = segment
+        .rows_matching_any("channel", &["#en.wikipedia", "#de.wikipedia", "#xx"])
+        .unwrap();
+    assert_eq!(any, &en | &de);
+    assert_eq!(segment.parsed_columns(), vec!["channel".to_string()]);
+
+    let batch = segment.read_rows(&any, &["__time", "channel"]).unwrap();
+    assert_eq!(batch.num_rows() as u64, any.len());
+    let channel = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    assert!(
+        channel
+            .iter()
+            .all(|c| matches!(c, Some("#en.wikipedia" | "#de.wikipedia")))
+    );
+    let first = any.min().unwrap() as usize;
+    assert_eq!(
+        batch.slice(0, 1),
+        segment
+            .read_columns_range(&["__time", "channel"], first, 1)
+            .unwrap()
+    );
+
+    assert_eq!(
+        segment
+            .read_rows(&Default::default(), &["channel"])
+            .unwrap()
+            .num_rows(),
+        0
+    );
+    assert_eq!(
+        segment.read_rows(&any, &[]).unwrap().num_rows() as u64,
+        any.len()
+    );
+    let past_end = [39244u32].into_iter().collect();
+    assert!(matches!(
+        segment.read_rows(&past_end, &["channel"]),
+        Err(DruidSegmentError::RowOutOfRange { offset: 39244, .. })
+    ));
+    assert!(matches!(
+        segment.rows_matching("added", "1"),
+        Err(DruidSegmentError::UnsupportedColumnType(_))
+    ));
+
+    // Without readable indexes the column is scanned instead
+    let dir = fixture_with_bitmap_factory("concise");
+    let concise = DruidSegment::open(dir.path()).expect("Failed to open segment");
+    assert_eq!(
+        concise
+            .rows_matching_any("channel", &["#en.wikipedia", "#de.wikipedia"])
+            .unwrap(),
+        any
+    );
+}
+
+#[test]
+fn test_open_records_segment_id() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    assert_eq!(segment.metadata().segment_id, None);
+
+    let root = tempfile::tempdir().unwrap();
+    let dir = root.path().join(
+        "wikipedia/2015-09-12T00:00:00.000Z_2015-09-13T00:00:00.000Z/2024-01-01T00:00:00.000Z/2",
+    );
+    std::fs::create_dir_all(&dir).unwrap();
+    for file in ["00000.smoosh", "meta.smoosh", "version.bin"] {
+        std::fs::copy(Path::new(FIXTURE_PATH).join(file), dir.join(file)).unwrap();
+    }
+    std::fs::write(
+        dir.join("descriptor.json"),
+        r#"{"dataSource":"wikipedia","shardSpec":{"type":"numbered","partitionNum":2,"partitions":3}}"#,
+    )
+    .unwrap();
+
+    let segment = DruidSegment::open(&dir).expect("Failed to open segment");
+    let id = segment.metadata().segment_id.as_ref().unwrap();
+    assert_eq!(id.datasource, "wikipedia");
+    assert_eq!(
+        (id.interval_start_ms, id.interval_end_ms),
+        (
+            segment.metadata().interval_start_ms,
+            segment.metadata().interval_end_ms
+        )
+    );
+    assert_eq!(id.version, "2024-01-01T00:00:00.000Z");
+    assert_eq!(id.partition_num, 2);
+    let shard_spec: serde_json::Value =
+        serde_json::from_str(id.shard_spec.as_deref().unwrap()).unwrap();
+    assert_eq!(shard_spec["partitions"], 3);
+
+    let with_schema = DruidSegment::open_with_schema(&dir, segment.schema()).unwrap();
+    assert_eq!(with_schema.metadata(), segment.metadata());
+}
+
+#[test]
+fn test_open_in_memory_from_files() {
+    let fixture = SegmentFixtureBuilder::new()
+        .with_writer(SegmentWriter::new().with_max_chunk_size(4096))
+        .with_string_column("page", (0..300).map(|i| Some(format!("page-{}", i % 7))))
+        .with_long_column("added", (0..300).map(Some));
+    let dir = fixture.build_temp_dir().unwrap();
+    let mut files: std::collections::HashMap<String, Vec<u8>> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            (name, std::fs::read(&path).unwrap())
+        })
+        .collect();
+    assert!(
+        files.contains_key("00001.smoosh"),
+        "expected several chunks"
+    );
+    drop(dir);
+
+    let segment = DruidSegment::open_in_memory(files.clone()).unwrap();
+    assert_eq!(segment.num_rows().unwrap(), 300);
+    assert_eq!(
+        segment.read_all().unwrap().columns(),
+        fixture.batch().unwrap().columns()
+    );
+    assert_eq!(
+        segment.rows_matching("page", "page-3").unwrap().len(),
+        (0..300).filter(|i| i % 7 == 3).count() as u64
+    );
+
+    files.remove("00001.smoosh");
+    match DruidSegment::open_in_memory(files.clone()).unwrap_err() {
+        DruidSegmentError::Io(err) => {
+            assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+            assert!(err.to_string().contains("00001.smoosh"), "{}", err);
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+    files.insert("version.bin".into(), 8i32.to_be_bytes().to_vec());
+    assert!(matches!(
+        DruidSegment::open_in_memory(files).unwrap_err(),
+        DruidSegmentError::InvalidVersion(8)
+    ));
+}
+
+/// Zip the segment files of `dir` into `zip_path`, each under `prefix`.
+fn zip_segment(dir: &Path, zip_path: &Path, prefix: &str, method: zip::CompressionMethod) {
+    use std::io::Write;
+
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(zip_path).unwrap());
+    let options = zip::write::SimpleFileOptions::default().compression_method(method);
+    if !prefix.is_empty() {
+        writer.add_directory(prefix, options).unwrap();
+    }
+    let mut names: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    for name in names {
+        writer
+            .start_file(format!("{}{}", prefix, name), options)
+            .unwrap();
+        writer
+            .write_all(&std::fs::read(dir.join(&name)).unwrap())
+            .unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[tokio::test]
+async fn test_open_zip() {
+    let root = tempfile::tempdir().unwrap();
+    let zipped = root.path().join("wikipedia.zip");
+    zip_segment(
+        Path::new(FIXTURE_PATH),
+        &zipped,
+        "wikipedia/",
+        zip::CompressionMethod::Stored,
+    );
+
+    let segment = DruidSegment::open(&zipped).unwrap();
+    let expected = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    assert_eq!(segment.num_rows().unwrap(), 39244);
+    assert_eq!(segment.metadata(), expected.metadata());
+    assert_eq!(
+        segment
+            .read_columns(&["__time", "channel", "added"])
+            .unwrap(),
+        expected
+            .read_columns(&["__time", "channel", "added"])
+            .unwrap()
+    );
+
+    // Tables and queries take zip paths too
+    let ctx = SessionContext::new();
+    let table = DruidSegmentTable::open(&zipped).unwrap();
+    ctx.register_table("wikipedia", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("SELECT count(*) FROM wikipedia WHERE channel = '#en.wikipedia'")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(
+        batches[0]
+            .column(0)
+            .as_primitive::<arrow::datatypes::Int64Type>()
+            .value(0),
+        11549
+    );
+}
+
+#[test]
+fn test_open_zip_deep_storage_layout() {
+    let segment_dir = SegmentFixtureBuilder::new()
+        .interval(1_442_016_000_000, 1_442_102_400_000)
+        .with_times([1_442_016_000_000, 1_442_016_000_001])
+        .with_string_column("page", [Some("a"), Some("b")])
+        .build_temp_dir()
+        .unwrap();
+    let root = tempfile::tempdir().unwrap();
+    let version_dir = root
+        .path()
+        .join("wikipedia/2015-09-12T00:00:00.000Z_2015-09-13T00:00:00.000Z/v1");
+
+    // Druid's layout, with a partition directory holding index.zip
+    let partition_dir = version_dir.join("2");
+    std::fs::create_dir_all(&partition_dir).unwrap();
+    let zipped = partition_dir.join("index.zip");
+    zip_segment(
+        segment_dir.path(),
+        &zipped,
+        "",
+        zip::CompressionMethod::Deflated,
+    );
+    std::fs::write(
+        partition_dir.join("descriptor.json"),
+        r#"{"shardSpec":{"type":"numbered","partitionNum":2,"partitions":3}}"#,
+    )
+    .unwrap();
+    let segment = DruidSegment::open_zip(&zipped).unwrap();
+    let id = segment.metadata().segment_id.clone().unwrap();
+    assert_eq!((id.datasource.as_str(), id.partition_num), ("wikipedia", 2));
+    assert!(id.shard_spec.unwrap().contains(r#""partitions":3"#));
+    let pages = segment.read_columns(&["page"]).unwrap();
+    assert_eq!(pages.column(0).as_string::<i32>().value(1), "b");
+
+    // HDFS's layout, with the partition number in the file names
+    let zipped = version_dir.join("4_index.zip");
+    zip_segment(
+        segment_dir.path(),
+        &zipped,
+        "",
+        zip::CompressionMethod::Deflated,
+    );
+    std::fs::write(
+        version_dir.join("4_descriptor.json"),
+        r#"{"shardSpec":{"type":"numbered","partitionNum":4,"partitions":5}}"#,
+    )
+    .unwrap();
+    let id = DruidSegment::open(&zipped)
+        .unwrap()
+        .metadata()
+        .segment_id
+        .clone()
+        .unwrap();
+    assert_eq!(id.partition_num, 4);
+    assert!(id.shard_spec.unwrap().contains(r#""partitions":5"#));
+}
+
+#[test]
+fn test_open_zip_errors() {
+    let segment_dir = SegmentFixtureBuilder::new()
+        .with_long_column("added", [Some(1)])
+        .build_temp_dir()
+        .unwrap();
+    let root = tempfile::tempdir().unwrap();
+
+    // No segment in the archive
+    let empty_dir = tempfile::tempdir().unwrap();
+    let zipped = root.path().join("empty.zip");
+    zip_segment(
+        empty_dir.path(),
+        &zipped,
+        "",
+        zip::CompressionMethod::Stored,
+    );
+    let err = DruidSegment::open_zip(&zipped).unwrap_err();
+    assert!(matches!(err, DruidSegmentError::InvalidData(_)), "{}", err);
+
+    // A chunk missing from the archive
+    let partial = tempfile::tempdir().unwrap();
+    for file in ["meta.smoosh", "version.bin"] {
+        std::fs::copy(segment_dir.path().join(file), partial.path().join(file)).unwrap();
+    }
+    let zipped = root.path().join("partial.zip");
+    zip_segment(
+        partial.path(),
+        &zipped,
+        "seg/",
+        zip::CompressionMethod::Stored,
+    );
+    match DruidSegment::open_zip(&zipped).unwrap_err() {
+        DruidSegmentError::Io(err) => {
+            assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+            assert!(err.to_string().contains("seg/00000.smoosh"), "{}", err);
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+
+    // Not a zip archive at all
+    let zipped = root.path().join("bogus.zip");
+    std::fs::write(&zipped, b"not a zip").unwrap();
+    assert!(matches!(
+        DruidSegment::open(&zipped).unwrap_err(),
+        DruidSegmentError::Zip(_)
+    ));
+}
+
+#[test]
+fn test_column_handle_dictionary() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let handle = segment.column("channel").unwrap();
+    assert!(handle.is_string());
+    assert_eq!(handle.num_rows(), 39244);
+    assert_eq!(
+        handle.cardinality(),
+        segment.dictionary_cardinality("channel").unwrap()
+    );
+    let dictionary = handle
+        .dictionary()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(Some(dictionary.len()), handle.cardinality());
+    assert!(dictionary.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let batch = segment.read_columns(&["channel"]).unwrap();
+    let channel = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let mut distinct: Vec<&str> = channel.iter().flatten().collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+    assert_eq!(
+        dictionary
+            .iter()
+            .flatten()
+            .map(|v| v.as_ref())
+            .collect::<Vec<_>>(),
+        distinct
+    );
+    for row in [0, 1, 20_000, 39_243] {
+        assert_eq!(
+            handle.value_at(row).unwrap().as_deref(),
+            channel.iter().nth(row).unwrap()
+        );
+    }
+    assert!(matches!(
+        handle.value_at(39_244),
+        Err(DruidSegmentError::RowOutOfRange { .. })
+    ));
+
+    let added = segment.column("added").unwrap();
+    assert!(!added.is_string());
+    assert_eq!(added.cardinality(), None);
+    assert!(added.dictionary().is_err());
+    assert!(added.value_at(0).is_err());
+
+    let dir = SegmentFixtureBuilder::new()
+        .with_string_column("empty", [None::<&str>; 3])
+        .build_temp_dir()
+        .unwrap();
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    let empty = segment.column("empty").unwrap();
+    assert_eq!(empty.cardinality(), Some(1));
+    assert_eq!(empty.value_at(2).unwrap(), None);
+}
+
+#[test]
+fn test_num_rows_reads_only_the_header() {
+    // Overwrite the last compressed block of __time, keeping its headers
+    let dir = fixture_with_meta(str::to_string);
+    let smoosh = SmooshReader::open(dir.path()).unwrap();
+    let entry = smoosh.entry("__time").unwrap().clone();
+    let layout = column::read_block_layout(smoosh.map_file("__time").unwrap())
+        .unwrap()
+        .unwrap();
+    drop(smoosh);
+    let last_block = *layout.block_sizes.last().unwrap();
+    let path = dir.path().join("00000.smoosh");
+    let mut data = std::fs::read(&path).unwrap();
+    data[entry.end_offset - last_block..entry.end_offset].fill(0xFF);
+    std::fs::write(&path, data).unwrap();
+
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    assert!(segment.read_columns(&["__time"]).is_err());
+    assert_eq!(segment.num_rows().unwrap(), 39244);
+    // Cached after the first call
+    assert_eq!(segment.num_rows().unwrap(), 39244);
+}
+
+#[test]
+fn test_column_stats_match_decoded_values() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment
+        .read_columns(&["__time", "cityName", "added"])
+        .unwrap();
+
+    let time = batch
+        .column(0)
+        .as_any()
+        .downcast_ref::<TimestampMillisecondArray>()
+        .unwrap();
+    let stats = segment.column_stats("__time").unwrap();
+    assert_eq!(
+        stats.min,
+        Some(StatValue::Timestamp(arrow::compute::min(time).unwrap()))
+    );
+    assert_eq!(
+        stats.max,
+        Some(StatValue::Timestamp(arrow::compute::max(time).unwrap()))
+    );
+    assert_eq!(stats.distinct_count, None);
+
+    let city = batch
+        .column(1)
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .unwrap();
+    let stats = segment.column_stats("cityName").unwrap();
+    let mut distinct: Vec<&str> = city.iter().flatten().collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+    assert_eq!(stats.distinct_count, Some(distinct.len()));
+    assert_eq!(stats.null_count, city.null_count());
+    assert_eq!(stats.min, Some(StatValue::String(distinct[0].to_string())));
+    assert_eq!(
+        stats.max,
+        Some(StatValue::String(distinct.last().unwrap().to_string()))
+    );
+
+    let added = batch
+        .column(2)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    let stats = segment.column_stats("added").unwrap();
+    assert_eq!(
+        stats.min,
+        Some(StatValue::Long(arrow::compute::min(added).unwrap()))
+    );
+    assert_eq!(
+        stats.max,
+        Some(StatValue::Long(arrow::compute::max(added).unwrap()))
+    );
+    // Cached
+    assert_eq!(segment.column_stats("added").unwrap(), stats);
+
+    let all = segment.all_column_stats().unwrap();
+    assert_eq!(all.len(), segment.schema().fields().len());
+    let json = serde_json::to_value(&all[0]).unwrap();
+    assert_eq!(json["name"], "__time");
+    assert_eq!(json["min"], 1_442_018_818_771i64);
+    assert_eq!(json["null_count"], 0);
+    assert!(json["distinct_count"].is_null());
+}
+
+/// Copy the fixture, rewriting the bitmap serde factory recorded in
+/// index.drd to `factory`, which must be as long as "roaring".
+fn fixture_with_bitmap_factory(factory: &str) -> tempfile::TempDir {
+    let dir = fixture_with_meta(str::to_string);
+    let smoosh = SmooshReader::open(dir.path()).unwrap();
+    let entry = smoosh.entry("index.drd").unwrap().clone();
+    drop(smoosh);
+
+    let path = dir.path().join("00000.smoosh");
+    let mut data = std::fs::read(&path).unwrap();
+    let original = br#"{"type":"roaring"}"#;
+    let index = &mut data[entry.start_offset..entry.end_offset];
+    let pos = index
+        .windows(original.len())
+        .position(|w| w == original)
+        .expect("index.drd names the roaring factory");
+    let replacement = format!(r#"{{"type":"{}"}}"#, factory);
+    assert_eq!(replacement.len(), original.len());
+    index[pos..pos + original.len()].copy_from_slice(replacement.as_bytes());
+    std::fs::write(&path, data).unwrap();
+    dir
+}
+
+async fn explain(table: DruidSegmentTable) -> String {
+    let ctx = SessionContext::new();
+    ctx.register_table("segment", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("EXPLAIN SELECT channel FROM segment")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    arrow::util::pretty::pretty_format_batches(&batches)
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_bitmap_factory_roaring() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    assert_eq!(
+        segment.metadata().bitmap_serde_factory,
+        BitmapSerdeFactory::Roaring
+    );
+    let capabilities = segment.column_capabilities("channel").unwrap();
+    assert!(capabilities.has_bitmap_indexes);
+    assert!(capabilities.supports_index_pushdown());
+    assert!(
+        !segment
+            .column_capabilities("added")
+            .unwrap()
+            .has_bitmap_indexes
+    );
+
+    let table = DruidSegmentTable::new(segment).unwrap();
+    let plan = explain(table).await;
+    assert!(
+        plan.contains("bitmap=roaring, pushdown=enabled"),
+        "{}",
+        plan
+    );
+}
+
+#[tokio::test]
+async fn test_bitmap_factory_concise() {
+    let dir = fixture_with_bitmap_factory("concise");
+    let segment = DruidSegment::open(dir.path()).expect("Failed to open segment");
+    assert_eq!(
+        segment.metadata().bitmap_serde_factory,
+        BitmapSerdeFactory::Concise
+    );
+    let capabilities = segment.column_capabilities("channel").unwrap();
+    assert_eq!(
+        capabilities.bitmap_serde_factory,
+        BitmapSerdeFactory::Concise
+    );
+    assert!(capabilities.has_bitmap_indexes);
+    assert!(!capabilities.supports_index_pushdown());
+    assert!(!segment.index_pushdown_enabled());
+
+    let table = DruidSegmentTable::new(segment).unwrap();
+    let plan = explain(table).await;
+    assert!(
+        plan.contains("bitmap=concise, pushdown=disabled"),
+        "{}",
+        plan
+    );
+}
+
+#[test]
+fn test_open_in_memory_segment() {
+    let meta = std::fs::read_to_string(Path::new(FIXTURE_PATH).join("meta.smoosh")).unwrap();
+    let chunk = std::fs::read(Path::new(FIXTURE_PATH).join("00000.smoosh")).unwrap();
+    let reader = SmooshReader::from_parts(&meta, vec![chunk]).unwrap();
+    let segment = DruidSegment::from_reader(reader).expect("Failed to open in-memory segment");
+
+    let on_disk = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    assert_eq!(segment.schema(), on_disk.schema());
+    assert_eq!(
+        segment.read_columns(&["added", "channel"]).unwrap(),
+        on_disk.read_columns(&["added", "channel"]).unwrap()
+    );
+}
+
+#[test]
+fn test_time_column_block_layout() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let layout = segment
+        .column_layout("__time")
+        .unwrap()
+        .expect("__time is a long column");
+
+    assert_eq!(layout.total_size, 39244);
+    assert!(layout.size_per > 0);
+    assert_eq!(
+        layout.block_count(),
+        layout.total_size.div_ceil(layout.size_per)
+    );
+    let values: usize = (0..layout.block_count())
+        .map(|i| layout.block_value_count(i).unwrap())
+        .sum();
+    assert_eq!(values, layout.total_size);
+    assert_eq!(
+        layout.block_value_count(layout.block_count() - 1),
+        Some(layout.total_size - (layout.block_count() - 1) * layout.size_per)
+    );
+    assert_eq!(layout.block_value_count(layout.block_count()), None);
+
+    assert!(layout.block_sizes.iter().all(|&size| size > 0));
+    assert!(layout.compressed_size() < segment.smoosh().entry("__time").unwrap().size());
+
+    assert!(segment.column_layout("channel").unwrap().is_none());
+}
+
+/// A datasource directory holding `copies` copies of the fixture segment.
+fn fixture_datasource(copies: usize) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..copies {
+        let segment_dir = dir.path().join(format!("segment-{}", i));
+        std::fs::create_dir(&segment_dir).unwrap();
+        for file in ["00000.smoosh", "meta.smoosh", "version.bin", "factory.json"] {
+            std::fs::copy(Path::new(FIXTURE_PATH).join(file), segment_dir.join(file)).unwrap();
+        }
+    }
+    // Not a segment: no version.bin
+    std::fs::create_dir(dir.path().join("tmp")).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn test_segments_table_open_dir() {
+    let dir = fixture_datasource(2);
+    let table = DruidSegmentsTable::open_dir(dir.path()).expect("Failed to open datasource");
+    assert_eq!(table.segments().len(), 2);
+
+    let ctx = SessionContext::new();
+    ctx.register_table("datasource", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("SELECT count(*), sum(added) FROM datasource")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let count = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(count.value(0), 2 * 39244);
+
+    let single = DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap();
+    let added = single.read_columns(&["added"]).unwrap();
+    let added = added
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    let sum = batches[0]
+        .column(1)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(sum.value(0), 2 * added.values().iter().sum::<i64>());
+
+    let plan = ctx
+        .sql("EXPLAIN SELECT channel FROM datasource")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let plan = arrow::util::pretty::pretty_format_batches(&plan)
+        .unwrap()
+        .to_string();
+    assert!(plan.contains("segments=2"), "{}", plan);
+}
+
+#[tokio::test]
+async fn test_segments_table_preserve_order() {
+    let dir = fixture_datasource(2);
+    let table = DruidSegmentsTable::open_dir(dir.path())
+        .unwrap()
+        .with_options(ReadOptions::default().with_preserve_order(true));
+    let expected = table.segments()[0].read_columns(&["__time"]).unwrap();
+
+    let ctx = SessionContext::new_with_config(
+        datafusion::prelude::SessionConfig::new().with_target_partitions(4),
+    );
+    ctx.register_table("datasource", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("SELECT __time FROM datasource LIMIT 39250")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+    assert_eq!(batch.num_rows(), 39250);
+    // The first segment's rows in storage order, then the second's
+    assert_eq!(batch.slice(0, 39244).column(0), expected.column(0));
+    assert_eq!(
+        batch.slice(39244, 6).column(0),
+        &expected.column(0).slice(0, 6)
+    );
+}
+
+#[test]
+fn test_segments_table_rejects_empty_and_mismatched() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(matches!(
+        DruidSegmentsTable::open_dir(dir.path()),
+        Err(DruidSegmentError::NoSegments(_))
+    ));
+
+    let narrow = Arc::new(arrow::datatypes::Schema::new(vec![
+        arrow::datatypes::Field::new(
+            "__time",
+            arrow::datatypes::DataType::Timestamp(arrow::datatypes::TimeUnit::Millisecond, None),
+            false,
+        ),
+        arrow::datatypes::Field::new("added", arrow::datatypes::DataType::Float64, true),
+    ]));
+    let segments = vec![
+        DruidSegment::open_with_schema(Path::new(FIXTURE_PATH), narrow).unwrap(),
+        DruidSegment::open(Path::new(FIXTURE_PATH)).unwrap(),
+    ];
+    let err = DruidSegmentsTable::new(segments).unwrap_err();
+    assert!(
+        matches!(&err, DruidSegmentError::SchemaMismatch { column, .. } if column == "added"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_string_index_partitions_rows() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let index = segment.string_index("channel").unwrap();
+    assert!(index.cardinality() > 1);
+
+    let mut union = roaring::RoaringBitmap::new();
+    let mut total = 0;
+    for id in 0..index.cardinality() {
+        let bitmap = index.bitmap(id).unwrap();
+        total += bitmap.len();
+        union |= bitmap;
+    }
+    // A single-value dimension's bitmaps partition the rows
+    assert_eq!(total, 39244);
+    assert_eq!(union.len(), 39244);
+    assert_eq!(union.max(), Some(39243));
+
+    let en = index.bitmap_for_value("#en.wikipedia").unwrap().unwrap();
+    assert_eq!(
+        Some(en),
+        segment.dimension_index("channel", "#en.wikipedia").unwrap()
+    );
+    assert!(index.bitmap_for_value("#nowhere").unwrap().is_none());
+    assert_eq!(index.spatial_index().unwrap(), None);
+    assert!(segment.string_index("added").is_err());
+}
+
+#[derive(Debug, PartialEq)]
+enum ProgressEvent {
+    Start(usize, u64),
+    Advance(String, usize, u64),
+    Finish,
+}
+
+#[derive(Debug, Default)]
+struct RecordingSink(Mutex<Vec<ProgressEvent>>);
+
+impl ProgressSink for RecordingSink {
+    fn start(&self, units: usize, total_bytes: u64) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(ProgressEvent::Start(units, total_bytes));
+    }
+
+    fn advance(&self, unit: &str, rows: usize, bytes: u64) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(ProgressEvent::Advance(unit.to_string(), rows, bytes));
+    }
+
+    fn finish(&self) {
+        self.0.lock().unwrap().push(ProgressEvent::Finish);
+    }
+}
+
+#[test]
+fn test_progress_reports_each_column() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let sink = Arc::new(RecordingSink::default());
+    let options = ReadOptions::default().with_progress(sink.clone());
+    let columns = ["__time", "channel", "added"];
+    segment
+        .read_columns_with_options(&columns, &options)
+        .unwrap();
+
+    let events = sink.0.lock().unwrap();
+    assert_eq!(events.len(), columns.len() + 2);
+    let ProgressEvent::Start(units, total_bytes) = events[0] else {
+        panic!("expected a start event first, got {:?}", events[0]);
+    };
+    assert_eq!(units, columns.len());
+    assert_eq!(events.last(), Some(&ProgressEvent::Finish));
+
+    let mut done = 0;
+    for (event, column) in events[1..events.len() - 1].iter().zip(columns) {
+        let ProgressEvent::Advance(unit, rows, bytes) = event else {
+            panic!("expected an advance event, got {:?}", event);
+        };
+        assert_eq!(unit, column);
+        assert_eq!(*rows, 39244);
+        assert!(*bytes > 0);
+        done += bytes;
+        assert!(done <= total_bytes);
+    }
+    assert_eq!(done, total_bytes);
+}
+
+#[test]
+fn test_validate_progress() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let sink = Arc::new(RecordingSink::default());
+    let options = ReadOptions::default().with_progress(sink.clone());
+    let report = segment.validate_with_options(&options).unwrap();
+
+    let events = sink.0.lock().unwrap();
+    assert_eq!(events.len(), report.columns.len() + 2);
+    assert!(matches!(events[0], ProgressEvent::Start(units, _) if units == report.columns.len()));
+    for (event, timing) in events[1..events.len() - 1].iter().zip(&report.columns) {
+        assert!(
+            matches!(event, ProgressEvent::Advance(unit, 39244, _) if *unit == timing.name),
+            "{:?}",
+            event
+        );
+    }
+    assert_eq!(events.last(), Some(&ProgressEvent::Finish));
+}
+
+#[test]
+fn test_merge_progress() {
+    let dir = tempfile::tempdir().unwrap();
+    let inputs: Vec<_> = ["a", "b"].map(|name| dir.path().join(name)).into();
+    for (path, rows) in inputs.iter().zip([3, 2]) {
+        SegmentFixtureBuilder::new()
+            .with_long_column("delta", (0..rows).map(Some))
+            .build_in(path)
+            .unwrap();
+    }
+    let sink = Arc::new(RecordingSink::default());
+    let options = MergeOptions::default().with_progress(sink.clone());
+    merge_segments(&inputs, &dir.path().join("merged"), &options).unwrap();
+
+    let events = sink.0.lock().unwrap();
+    assert_eq!(events.len(), 4);
+    let ProgressEvent::Start(2, total_bytes) = events[0] else {
+        panic!(
+            "expected a start event for two segments, got {:?}",
+            events[0]
+        );
+    };
+    let mut done = 0;
+    for (event, (path, rows)) in events[1..3].iter().zip(inputs.iter().zip([3, 2])) {
+        let ProgressEvent::Advance(unit, read, bytes) = event else {
+            panic!("expected an advance event, got {:?}", event);
+        };
+        assert_eq!(unit, &path.display().to_string());
+        assert_eq!(*read, rows);
+        done += bytes;
+    }
+    assert_eq!(done, total_bytes);
+    assert_eq!(events[3], ProgressEvent::Finish);
+
+    // A failed merge still finishes its report
+    let sink = Arc::new(RecordingSink::default());
+    let options = MergeOptions::default()
+        .with_rollup(true)
+        .with_progress(sink.clone());
+    assert!(merge_segments(&inputs, &dir.path().join("rolled"), &options).is_err());
+    assert_eq!(sink.0.lock().unwrap().last(), Some(&ProgressEvent::Finish));
+}
+
+#[test]
+fn test_progress_finished_on_error() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let sink = Arc::new(RecordingSink::default());
+    let token = CancellationToken::new();
+    token.cancel();
+    let options = ReadOptions::default()
+        .with_progress(sink.clone())
+        .with_cancellation(token);
+    assert!(
+        segment
+            .read_columns_with_options(&["channel", "added"], &options)
+            .is_err()
+    );
+    assert!(
+        segment
+            .batches_with_options(&["channel"], &options)
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err()
+    );
+
+    let events = sink.0.lock().unwrap();
+    assert_eq!(events.len(), 4);
+    assert!(matches!(events[0], ProgressEvent::Start(2, _)));
+    assert_eq!(events[1], ProgressEvent::Finish);
+    assert!(matches!(events[2], ProgressEvent::Start(1, _)));
+    assert_eq!(events[3], ProgressEvent::Finish);
+    drop(events);
+
+    // Unknown columns fail before the read starts
+    let sink = Arc::new(RecordingSink::default());
+    let options = ReadOptions::default().with_progress(sink.clone());
+    assert!(
+        segment
+            .read_columns_with_options(&["channel", "missing"], &options)
+            .is_err()
+    );
+    assert!(sink.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_progress_started_once_per_iteration() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let sink = Arc::new(RecordingSink::default());
+    let options = ReadOptions::default()
+        .with_batch_size(10_000)
+        .with_progress(sink.clone());
+    let rows = segment
+        .row_iter_with_options(&["channel", "added"], &options)
+        .unwrap()
+        .count();
+    assert_eq!(rows, 39244);
+
+    let events = sink.0.lock().unwrap();
+    let starts = events
+        .iter()
+        .filter(|e| matches!(e, ProgressEvent::Start(..)))
+        .count();
+    let finishes = events
+        .iter()
+        .filter(|e| **e == ProgressEvent::Finish)
+        .count();
+    assert_eq!((starts, finishes), (1, 1));
+    assert!(matches!(events[0], ProgressEvent::Start(2, _)));
+    assert_eq!(events.last(), Some(&ProgressEvent::Finish));
+    // Four windows, each advancing both columns
+    assert_eq!(events.len(), 2 + 4 * 2);
+}
+
+#[test]
+fn test_unknown_column_suggestion() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let err = segment.read_columns(&["channel", "usr"]).unwrap_err();
+    assert!(
+        matches!(&err, DruidSegmentError::UnknownColumn { requested, available }
+            if requested == "usr" && available.len() == 20 && available[0] == "__time")
+    );
+    assert_eq!(err.suggestion(), Some("user"));
+    assert_eq!(
+        err.to_string(),
+        "Unknown column 'usr'; did you mean 'user'?"
+    );
+    // Validated before any column is decoded
+    assert!(segment.parsed_columns().is_empty());
+
+    assert!(matches!(
+        segment.batches(Some(&["cityname"]), 100),
+        Err(DruidSegmentError::UnknownColumn { .. })
+    ));
+    assert!(matches!(
+        segment.row_iter(&["__time", "nope"]),
+        Err(DruidSegmentError::UnknownColumn { .. })
+    ));
+}
+
+#[test]
+fn test_string_column_consumes_its_indexes() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let data = segment.smoosh().map_non_empty_file("channel").unwrap();
+    let (descriptor, binary) = column::parse_column_header(data).unwrap();
+    let byte_order = descriptor.parts[0]
+        .byte_order()
+        .unwrap()
+        .unwrap_or_default();
+
+    let column = StringColumn::from_bytes(
+        binary,
+        byte_order,
+        Some(segment.smoosh()),
+        &ReadOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(column.values().len(), 39244);
+    assert_eq!(column.total_consumed(), binary.len());
+
+    let index = column.index().expect("channel has bitmap indexes");
+    assert_eq!(index.bitmap_count(), index.cardinality());
+    assert_eq!(
+        index.bitmap_count(),
+        segment.string_index("channel").unwrap().cardinality()
+    );
+}
+
+/// Prefix a column's binary data with its JSON descriptor.
+fn column_file(descriptor: &str, binary: &[u8]) -> Vec<u8> {
+    let mut file = (descriptor.len() as i32).to_be_bytes().to_vec();
+    file.extend_from_slice(descriptor.as_bytes());
+    file.extend_from_slice(binary);
+    file
+}
+
+#[test]
+fn test_rolled_up_complex_metric() {
+    let sketches: [&[u8]; 3] = [b"\x01\x02\x03", b"", b"\xff\x00"];
+    let metric = column_file(
+        r#"{"valueType":"COMPLEX","hasMultipleValues":false,"parts":[{"type":"complex","typeName":"hyperUnique"}]}"#,
+        &GenericIndexedWriter::write(sketches.iter().map(|s| Some(*s)), false).unwrap(),
+    );
+
+    let segment = SegmentFixtureBuilder::new()
+        .interval(1_442_016_000_000, 1_442_102_400_000)
+        .with_times([1_442_016_000_000, 1_442_019_600_000, 1_442_023_200_000])
+        .with_column_file("unique_users", metric)
+        .build()
+        .unwrap();
+
+    let schema = segment.schema();
+    let field = schema.field_with_name("unique_users").unwrap();
+    assert_eq!(field.data_type(), &arrow::datatypes::DataType::Binary);
+    assert_eq!(field.metadata()[complex::SERDE_TYPE_KEY], "complex");
+    assert_eq!(field.metadata()[complex::COMPLEX_TYPE_KEY], "hyperUnique");
+
+    let batch = segment.read_all().unwrap();
+    assert_eq!(batch.num_rows(), 3);
+    let values = batch
+        .column_by_name("unique_users")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<arrow::array::BinaryArray>()
+        .unwrap();
+    for (i, sketch) in sketches.iter().enumerate() {
+        assert_eq!(values.value(i), *sketch);
+    }
+}
+
+#[test]
+fn test_open_does_not_parse_unread_columns() {
+    // A column whose header is not even JSON
+    let broken = column_file("{not json", b"");
+    let segment = SegmentFixtureBuilder::new()
+        .with_times([0, 1000])
+        .with_column_file("broken", broken)
+        .build()
+        .unwrap();
+    assert!(segment.parsed_columns().is_empty());
+
+    let batch = segment.read_columns(&["__time"]).unwrap();
+    assert_eq!(batch.num_rows(), 2);
+    assert_eq!(segment.parsed_columns(), vec!["__time".to_string()]);
+
+    // Only building the full schema reaches the broken header
+    assert!(segment.try_schema().is_err());
+    assert!(segment.read_columns(&["broken"]).is_err());
+}
+
+#[test]
+fn test_metadata_files_match_fixture() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let version = std::fs::read(Path::new(FIXTURE_PATH).join("version.bin")).unwrap();
+    assert_eq!(version, write_version());
+
+    let index = segment.smoosh().map_file("index.drd").unwrap();
+    let metadata = SegmentMetadata::from_bytes(index).unwrap();
+    let written = metadata.to_bytes().unwrap();
+    assert_eq!(SegmentMetadata::from_bytes(&written).unwrap(), metadata);
+    // Byte for byte up to the bitmap serde factory; the fixture goes on
+    // with its (unread) lists of all-null columns
+    assert!(index.len() > written.len());
+    assert_eq!(&index[..written.len()], &written[..]);
+}
+
+#[test]
+fn test_segment_writer_round_trips_fixture() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment.read_all().unwrap();
+    let metadata = segment.metadata();
+    let interval = metadata.interval_start_ms..metadata.interval_end_ms;
+
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("segment");
+    SegmentWriter::new()
+        .with_aggregate_metadata(segment.aggregate_metadata().unwrap())
+        .write(&batch, interval, &out)
+        .unwrap();
+    for file in ["version.bin", "meta.smoosh", "00000.smoosh"] {
+        assert!(out.join(file).is_file(), "missing {}", file);
+    }
+
+    let written = DruidSegment::open(&out).unwrap();
+    assert_eq!(written.read_all().unwrap().columns(), batch.columns());
+    assert_eq!(written.metadata().columns, metadata.columns);
+    assert_eq!(written.metadata().interval_end_ms, metadata.interval_end_ms);
+    assert_eq!(
+        written.aggregate_metadata().unwrap(),
+        segment.aggregate_metadata().unwrap()
+    );
+    // Long dimensions are written as metrics, leaving only the strings
+    assert_eq!(written.metadata().dimensions.len(), 16);
+    assert_eq!(
+        written.dimension_index("channel", "#en.wikipedia").unwrap(),
+        segment.dimension_index("channel", "#en.wikipedia").unwrap()
+    );
+}
+
+#[test]
+fn test_truncated_column_error_names_column() {
+    let fixture = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let added = fixture.smoosh().map_file("added").unwrap();
+    let header_size = 4 + i32::from_be_bytes(added[..4].try_into().unwrap()) as usize;
+
+    let segment = SegmentFixtureBuilder::new()
+        .with_times(0..1000)
+        .with_column_file("added", added[..added.len() / 2].to_vec())
+        .build()
+        .unwrap();
+    let err = segment.read_columns(&["added"]).unwrap_err();
+    assert!(err.to_string().contains("column 'added'"), "{}", err);
+    match err {
+        DruidSegmentError::ColumnReadError {
+            column,
+            offset,
+            source,
+        } => {
+            assert_eq!(column, "added");
+            // The failure is in the longV2 part, right after the descriptor
+            assert_eq!(offset, header_size);
+            assert!(
+                matches!(*source, DruidSegmentError::InvalidData(_)),
+                "{}",
+                source
+            );
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn test_read_columns_range_matches_slice() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let all = segment.read_all().unwrap();
+    let schema = all.schema();
+    let columns: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+
+    // Windows inside one block, across block boundaries, and past the end
+    for (offset, len) in [
+        (0, 10),
+        (8190, 10),
+        (16_000, 20_000),
+        (39_240, 100),
+        (50_000, 5),
+        (7, 0),
+    ] {
+        let batch = segment.read_columns_range(&columns, offset, len).unwrap();
+        let start = offset.min(all.num_rows());
+        let expected = all.slice(start, len.min(all.num_rows() - start));
+        assert_eq!(batch, expected, "rows {}..+{}", offset, len);
+    }
+}
+
+#[test]
+fn test_read_row_range() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let all = segment
+        .read_columns(&["__time", "channel", "added"])
+        .unwrap();
+    let columns = ["__time", "channel", "added"];
+
+    let batch = segment.read_row_range(&columns, 20_000, 500).unwrap();
+    assert_eq!(batch, all.slice(20_000, 500));
+    let batch = segment.read_row_range(&columns, 39_000, 1000).unwrap();
+    assert_eq!(batch, all.slice(39_000, 244));
+    assert_eq!(
+        segment
+            .read_row_range(&columns, 39_244, 1)
+            .unwrap()
+            .num_rows(),
+        0
+    );
+
+    let err = segment.read_row_range(&columns, 39_245, 1).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            DruidSegmentError::RowOutOfRange {
+                offset: 39_245,
+                num_rows: 39_244
+            }
+        ),
+        "{}",
+        err
+    );
+    assert!(err.to_string().contains("39244 rows"), "{}", err);
+}
+
+#[test]
+fn test_column_null_count_matches_decoded() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let batch = segment.read_all().unwrap();
+    for (field, array) in batch.schema().fields().iter().zip(batch.columns()) {
+        let name = field.name();
+        // Every fixture column is a numeric or single-value string one,
+        // so none needs decoding
+        assert_eq!(
+            segment.null_count(name).unwrap(),
+            Some(array.null_count()),
+            "{}",
+            name
+        );
+        assert_eq!(
+            segment.column_null_count(name).unwrap(),
+            array.null_count(),
+            "{}",
+            name
+        );
+    }
+    assert_eq!(segment.column_null_count("cityName").unwrap(), 37091);
+}
+
+#[test]
+fn test_fixture_all_null_string_column() {
+    let dir = SegmentFixtureBuilder::new()
+        .with_string_column("empty", [None::<&str>; 5])
+        .build_temp_dir()
+        .unwrap();
+    let segment = DruidSegment::open(dir.path()).unwrap();
+
+    // The dictionary holds nothing but null
+    assert_eq!(segment.dictionary_cardinality("empty").unwrap(), Some(1));
+    assert_eq!(segment.column_null_count("empty").unwrap(), 5);
+    let batch = segment.read_columns(&["empty"]).unwrap();
+    assert_eq!(batch.column(0).null_count(), 5);
+    assert!(segment.dimension_index("empty", "a").unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_fixture_zero_rows() {
+    let dir = SegmentFixtureBuilder::new()
+        .with_string_column("page", Vec::<Option<&str>>::new())
+        .with_long_column("added", [])
+        .build_temp_dir()
+        .unwrap();
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    assert_eq!(segment.num_rows().unwrap(), 0);
+    assert_eq!(segment.read_all().unwrap().num_rows(), 0);
+
+    let ctx = SessionContext::new();
+    let table = DruidSegmentTable::open(dir.path()).unwrap();
+    ctx.register_table("segment", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("SELECT count(*), sum(added) FROM segment")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let count = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(count.value(0), 0);
+    assert!(batches[0].column(1).is_null(0));
+}
+
+#[tokio::test]
+async fn test_fixture_null_heavy_long_column() {
+    let values: Vec<Option<i64>> = (0..10_000).map(|i| (i % 97 == 0).then_some(i)).collect();
+    let nulls = values.iter().filter(|v| v.is_none()).count();
+    let dir = SegmentFixtureBuilder::new()
+        .with_long_column("sparse", values.clone())
+        .build_temp_dir()
+        .unwrap();
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    assert_eq!(segment.column_null_count("sparse").unwrap(), nulls);
+    let batch = segment.read_columns(&["sparse"]).unwrap();
+    assert_eq!(batch.column(0).as_ref(), &Int64Array::from(values));
+
+    let ctx = SessionContext::new();
+    let table = DruidSegmentTable::open(dir.path()).unwrap();
+    ctx.register_table("segment", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("SELECT count(*) FROM segment WHERE sparse IS NULL")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let count = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(count.value(0) as usize, nulls);
+}
+
+#[test]
+fn test_fixture_multi_chunk_smoosh() {
+    // Each column fits in a chunk on its own, but not both together
+    let fixture = SegmentFixtureBuilder::new()
+        .with_writer(
+            SegmentWriter::new()
+                .with_compression(CompressionStrategy::Uncompressed)
+                .with_max_chunk_size(4096),
+        )
+        .with_long_column("a", (0..400).map(Some))
+        .with_long_column("b", (0..400).map(|i| Some(-i)));
+    let dir = fixture.build_temp_dir().unwrap();
+    assert!(dir.path().join("00001.smoosh").is_file());
+
+    let segment = DruidSegment::open(dir.path()).unwrap();
+    assert_eq!(
+        segment.read_all().unwrap().columns(),
+        fixture.batch().unwrap().columns()
+    );
+}
+
+#[test]
+fn test_fixture_multi_block_long_column() {
+    // Well past the 8192 values of one compressed block
+    let rows = 20_000;
+    let fixture =
+        SegmentFixtureBuilder::new().with_long_column("added", (0..rows).map(|i| Some(i * 3)));
+    let segment = fixture.build().unwrap();
+    let batch = fixture.batch().unwrap();
+
+    assert_eq!(segment.read_all().unwrap().columns(), batch.columns());
+    for (offset, len) in [(0, 10), (8190, 5), (16_000, 4000), (19_999, 1)] {
+        let range = segment
+            .read_columns_range(&["__time", "added"], offset, len)
+            .unwrap();
+        assert_eq!(
+            range.columns(),
+            batch.slice(offset, len).columns(),
+            "{}..+{}",
+            offset,
+            len
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_open_async_matches_mmap() {
+    let path = Path::new(FIXTURE_PATH);
+    let mapped = DruidSegment::open(path).expect("Failed to open segment");
+    let segment = DruidSegment::open_async(path).await.unwrap();
+    assert_eq!(segment.metadata().columns, mapped.metadata().columns);
+    assert_eq!(segment.read_all().unwrap(), mapped.read_all().unwrap());
+
+    let dir = tempfile::tempdir().unwrap();
+    assert!(DruidSegment::open_async(dir.path()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_strings_as_dictionary() {
+    let segment = DruidSegment::open(Path::new(FIXTURE_PATH)).expect("Failed to open segment");
+    let plain = segment.read_all().unwrap();
+    let options = ReadOptions::default().with_strings_as_dictionary(true);
+    let batch = segment.read_all_with_options(&options).unwrap();
+    assert_eq!(batch.schema(), options.read_schema(segment.schema()));
+
+    let dictionary_type = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+    for (field, column) in plain.schema().fields().iter().zip(batch.columns()) {
+        if field.data_type() != &DataType::Utf8 {
+            assert_eq!(column.data_type(), field.data_type());
+            continue;
+        }
+        assert_eq!(column.data_type(), &dictionary_type, "{}", field.name());
+        let cast = arrow::compute::cast(column, &DataType::Utf8).unwrap();
+        assert_eq!(&cast, plain.column_by_name(field.name()).unwrap());
+    }
+    // The dictionary is the column's own, not one entry per row
+    let channel = batch
+        .column_by_name("channel")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<arrow::array::DictionaryArray<arrow::datatypes::Int32Type>>()
+        .unwrap();
+    assert_eq!(
+        Some(channel.values().len()),
+        segment.dictionary_cardinality("channel").unwrap()
+    );
+
+    // GROUP BY over dictionary columns gives the same groups
+    let sql = "SELECT channel, count(*) AS n, sum(added) AS added FROM segment \
+               GROUP BY channel ORDER BY channel";
+    let mut results = Vec::new();
+    for strings_as_dictionary in [false, true] {
+        let table = DruidSegmentTable::open(Path::new(FIXTURE_PATH))
+            .unwrap()
+            .with_options(ReadOptions::default().with_strings_as_dictionary(strings_as_dictionary));
+        let ctx = SessionContext::new();
+        ctx.register_table("segment", Arc::new(table)).unwrap();
+        let df = ctx.sql(sql).await.unwrap();
+        let schema = Arc::new(df.schema().as_arrow().clone());
+        let batch = concat_batches(&schema, &df.collect().await.unwrap()).unwrap();
+        let channel = arrow::compute::cast(batch.column(0), &DataType::Utf8).unwrap();
+        results.push((channel, batch.column(1).clone(), batch.column(2).clone()));
+    }
+    assert_eq!(results[0], results[1]);
+}
+
+/// An in-memory object store holding the files of the segment directory
+/// `dir` under `segments/wikipedia`.
+async fn remote_segment(
+    dir: &Path,
+) -> (Arc<dyn object_store::ObjectStore>, object_store::path::Path) {
+    let store = object_store::memory::InMemory::new();
+    let prefix = object_store::path::Path::from("segments/wikipedia");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        object_store::ObjectStore::put(&store, &prefix.child(name), bytes.into())
+            .await
+            .unwrap();
+    }
+    (Arc::new(store), prefix)
+}
+
+#[tokio::test]
+async fn test_open_remote_fetches_columns_on_demand() {
+    let fixture = SegmentFixtureBuilder::new()
+        .with_writer(SegmentWriter::new().with_max_chunk_size(4096))
+        .with_string_column("page", (0..300).map(|i| Some(format!("page-{}", i % 7))))
+        .with_long_column("added", (0..300).map(Some));
+    let dir = fixture.build_temp_dir().unwrap();
+    let (store, prefix) = remote_segment(dir.path()).await;
+
+    let segment = DruidSegment::open_remote(store.clone(), &prefix)
+        .await
+        .unwrap();
+    assert_eq!(segment.num_rows().unwrap(), 300);
+    let local = DruidSegment::open(dir.path()).unwrap();
+    assert_eq!(segment.try_schema().unwrap(), local.try_schema().unwrap());
+
+    // Headers were fetched for the schema, but not the values
+    assert!(!segment.smoosh().is_fetched("page"));
+    let err = segment.read_columns(&["page"]).unwrap_err();
+    assert!(
+        matches!(err, DruidSegmentError::LogicalFileNotFetched(ref name) if name == "page"),
+        "{err}"
+    );
+
+    segment.fetch_columns(&["page"]).await.unwrap();
+    assert!(segment.smoosh().is_fetched("page"));
+    assert!(!segment.smoosh().is_fetched("added"));
+    assert_eq!(
+        segment.read_columns(&["page"]).unwrap().columns(),
+        local.read_columns(&["page"]).unwrap().columns()
+    );
+
+    segment.fetch_columns(&["added"]).await.unwrap();
+    assert_eq!(
+        segment.read_all().unwrap().columns(),
+        fixture.batch().unwrap().columns()
+    );
+    assert!(segment.fetch_columns(&["missing"]).await.is_err());
+
+    let empty = object_store::path::Path::from("segments/none");
+    assert!(DruidSegment::open_remote(store, &empty).await.is_err());
+}
+
+#[tokio::test]
+async fn test_remote_table_fetches_projected_columns() {
+    let (store, prefix) = remote_segment(Path::new(FIXTURE_PATH)).await;
+    let table = DruidSegmentTable::open_remote(store, &prefix)
+        .await
+        .unwrap();
+    let segment = table.segment().clone();
+
+    let ctx = SessionContext::new();
+    ctx.register_table("wikipedia", Arc::new(table)).unwrap();
+    let batches = ctx
+        .sql("SELECT count(*) FROM wikipedia WHERE channel = '#en.wikipedia'")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let count = batches[0]
+        .column(0)
+        .as_primitive::<arrow::datatypes::Int64Type>();
+    assert_eq!(count.value(0), 11549);
+
+    assert!(segment.smoosh().is_fetched("channel"));
+    for column in ["page", "comment", "added"] {
+        assert!(!segment.smoosh().is_fetched(column), "{column}");
+    }
+}